@@ -1,6 +1,14 @@
+use std::{ops::Range, path::PathBuf};
+
 use ash::vk::{self};
 use modelz::AlphaMode;
-use vent_rendering::{image::VulkanImage, mesh::Mesh3D};
+use vent_rendering::{
+    geometry::{Bounds, MeshRange},
+    image::VulkanImage,
+    mesh::Mesh3D,
+    pipeline::RenderingFormats,
+    MaterialPipelineInfo,
+};
 
 mod image;
 pub mod io;
@@ -14,16 +22,39 @@ pub struct Model3D {
     pub pipelines: Vec<ModelPipeline>,
     pub materials: Vec<Material>,
 
-    pub descriptor_pool: vk::DescriptorPool,
+    pub shaders: ModelShaders,
 
     pub position: [f32; 3], // Default: 0.0, 0.0, 0.0
     pub rotation: [f32; 4], // Default: 0.0, 0.0, 0.0, 1.0
     pub scale: [f32; 3],    // Default: 1.0, 1.0, 1.0
 }
 
+/// What the Pipelines of a Model were created with, So they can be created again when a Shader changes
+pub struct ModelShaders {
+    pub vertex_shader: PathBuf,
+    pub fragment_shader: PathBuf,
+    pub pipeline_layout: vk::PipelineLayout,
+    pub formats: RenderingFormats,
+    pub transparent: TransparentPipelines,
+}
+
+/// How the Pipelines of the `RenderQueue::Transparent` are created
+#[derive(Clone, Default)]
+pub enum TransparentPipelines {
+    /// Alpha blended over the other queues, They have to be drawn back to front. Same Shaders and formats as the other queues
+    #[default]
+    Sorted,
+    /// Weighted Blended Order Independent Transparency, The Fragment Shader writes accumulation and revealage into `formats`
+    WeightedBlended {
+        fragment_shader: PathBuf,
+        formats: RenderingFormats,
+    },
+}
+
 /// Often we must create new Pipelines for Materials/Meshes
 pub struct ModelPipeline {
     pub pipeline: vk::Pipeline,
+    pub info: MaterialPipelineInfo,
     pub materials: Vec<ModelMaterial>,
 }
 
@@ -32,13 +63,95 @@ pub struct ModelMaterial {
     // So every App is Specfic and you will need to create your own DescriptorSet's out of this
     // We only binding them
     pub meshes: Vec<Mesh3D>,
+    /// Used instead of `meshes` when the Model was loaded into a `GeometryPool`
+    pub pooled_meshes: Vec<PooledMesh>,
+    /// Around all Meshes in Model space, e.g. to sort Transparent ones
+    pub bounds: Bounds,
+}
+
+impl ModelMaterial {
+    /// Draws every Mesh with a range of the bound instances, Pipeline and Material have to be bound before
+    pub fn draw(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        instances: Range<u32>,
+    ) {
+        self.meshes.iter().for_each(|mesh| {
+            mesh.bind(device, command_buffer);
+            mesh.draw_instances(device, command_buffer, instances.clone());
+        });
+        self.pooled_meshes.iter().for_each(|mesh| unsafe {
+            device.cmd_draw_indexed(
+                command_buffer,
+                mesh.range.index_count,
+                instances.len() as u32,
+                mesh.range.first_index,
+                mesh.range.vertex_offset,
+                instances.start,
+            )
+        });
+    }
 }
 
+/// A Mesh inside a `GeometryPool`, The Pool owns the actual buffers
+pub struct PooledMesh {
+    pub range: MeshRange,
+    pub bounds: Bounds,
+}
+
+/// How `Model3D::draw` tells the Shader which Material a Mesh uses
+#[derive(Clone, Copy)]
+pub enum MaterialBinding {
+    /// Nothing is bound, e.g. for Depth only passes
+    None,
+    /// Binds the descriptor set of every Material at set 0
+    DescriptorSets,
+    /// The Bindless set is already bound, Only the Material id is pushed as an `uint` at this push constant offset
+    Bindless { material_id_offset: u32 },
+}
+
+/// How Normals are generated for Meshes which have none
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum GeneratedNormals {
+    /// One Normal per Triangle like glTF asks for, The Mesh gets its own Vertices for every Triangle
+    #[default]
+    Flat,
+    /// Averaged over all Triangles sharing a Vertex, Weighted by their area
+    Smooth,
+}
+
+/// A glTF metallic-roughness Material, Textures which are missing in the Model are replaced by neutral ones
 pub struct Material {
+    /// sRGB, Multiplied with `base_color`
     pub diffuse_texture: VulkanImage,
-    pub descriptor_set: Option<Vec<vk::DescriptorSet>>,
+    /// Tangent space normals
+    pub normal_texture: VulkanImage,
+    /// Roughness in green, Metallic in blue
+    pub metallic_roughness_texture: VulkanImage,
+    /// Ambient occlusion in red
+    pub occlusion_texture: VulkanImage,
+    /// sRGB, Multiplied with `emissive_factor`
+    pub emissive_texture: VulkanImage,
+    pub descriptor_set: Option<vk::DescriptorSet>,
+    /// Index into the Material buffer when Bindless Descriptors are used
+    pub bindless_id: Option<u32>,
     pub base_color: [f32; 4],
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    pub occlusion_strength: f32,
+    pub emissive_factor: [f32; 3],
     pub alpha_mode: AlphaMode,
     pub double_sided: bool,
     pub alpha_cut: f32,
 }
+
+impl Material {
+    pub fn destroy(&mut self, device: &ash::Device) {
+        self.diffuse_texture.destroy(device);
+        self.normal_texture.destroy(device);
+        self.metallic_roughness_texture.destroy(device);
+        self.occlusion_texture.destroy(device);
+        self.emissive_texture.destroy(device);
+    }
+}
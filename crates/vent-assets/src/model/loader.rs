@@ -1,65 +1,40 @@
 use std::{collections::HashMap, ffi::CStr, fs::File, path::Path};
 
-use ash::{
-    util::read_spv,
-    vk::{self, PipelineShaderStageCreateInfo},
-};
+use ash::{util::read_spv, vk};
 use vent_rendering::{
-    image::VulkanImage, instance::VulkanInstance, mesh::Mesh3D, vertex::Vertex3D, MaterialPipelineInfo, DEFAULT_TEXTURE_FILTER
+    geometry::{Bounds, GeometryPool},
+    image::VulkanImage,
+    instance::VulkanInstance,
+    mesh::Mesh3D,
+    pipeline::{PipelineError, RenderingFormats},
+    reflection::{PipelineReflection, ShaderReflection},
+    vertex::{InstanceData, Vertex3D},
+    MaterialPipelineInfo, RenderQueue, DEFAULT_TEXTURE_FILTER,
+};
+
+use crate::{
+    GeneratedNormals, Material, Model3D, ModelPipeline, ModelShaders, PooledMesh,
+    TransparentPipelines,
 };
 
-use crate::{Material, Model3D, ModelPipeline};
+use super::tangent_space;
 
 pub(crate) struct ModelLoader {}
 
 impl ModelLoader {
+    #[allow(clippy::too_many_arguments)]
     pub async fn load(
         instance: &mut VulkanInstance,
         vertex_shader: &Path,
         fragment_shader: &Path,
         pipeline_layout: vk::PipelineLayout,
+        formats: &RenderingFormats,
         model: modelz::Model3D,
+        geometry: Option<&mut GeometryPool>,
+        normals: GeneratedNormals,
     ) -> crate::Model3D {
         // let mut matrix = None;
 
-        // Do not load for every node, So we load it here
-        let vertex_code =
-            read_spv(&mut File::open(vertex_shader).expect("Failed to open Vertex File")).unwrap();
-        let vertex_module_info = vk::ShaderModuleCreateInfo::default().code(&vertex_code);
-        let fragment_code =
-            read_spv(&mut File::open(fragment_shader).expect("Failed to open Fragment File"))
-                .unwrap();
-        let fragment_module_info = vk::ShaderModuleCreateInfo::default().code(&fragment_code);
-
-        let vertex_module = unsafe {
-            instance
-                .device
-                .create_shader_module(&vertex_module_info, None)
-        }
-        .unwrap();
-        let fragment_module = unsafe {
-            instance
-                .device
-                .create_shader_module(&fragment_module_info, None)
-        }
-        .unwrap();
-
-        let shader_entry_name = unsafe { CStr::from_bytes_with_nul_unchecked(b"main\0") };
-        let shader_stage_create_info = [
-            vk::PipelineShaderStageCreateInfo {
-                module: vertex_module,
-                p_name: shader_entry_name.as_ptr(),
-                stage: vk::ShaderStageFlags::VERTEX,
-                ..Default::default()
-            },
-            vk::PipelineShaderStageCreateInfo {
-                module: fragment_module,
-                p_name: shader_entry_name.as_ptr(),
-                stage: vk::ShaderStageFlags::FRAGMENT,
-                ..Default::default()
-            },
-        ];
-
         let mut materials = Vec::new();
         for mat in model.materials {
             materials.push(Self::load_material(instance, mat));
@@ -69,195 +44,375 @@ impl ModelLoader {
         Self::load_meshes(
             instance,
             model.meshes,
-            &shader_stage_create_info,
-            pipeline_layout,
             &materials,
             &mut pipelines,
+            geometry,
+            normals,
         );
 
-        unsafe {
-            instance.device.destroy_shader_module(vertex_module, None);
-            instance.device.destroy_shader_module(fragment_module, None);
+        // Drawing in order of the Pipelines then also draws the queues in order
+        pipelines.sort_by_key(|pipeline| pipeline.info.queue);
+
+        let shaders = ModelShaders {
+            vertex_shader: vertex_shader.to_owned(),
+            fragment_shader: fragment_shader.to_owned(),
+            pipeline_layout,
+            formats: formats.clone(),
+            transparent: TransparentPipelines::Sorted,
+        };
+        let infos: Vec<MaterialPipelineInfo> = pipelines.iter().map(|p| p.info).collect();
+        let created = Self::create_pipelines(instance, &shaders, &infos)
+            .expect("Failed to create Model Pipelines");
+        for (pipeline, created) in pipelines.iter_mut().zip(created) {
+            pipeline.pipeline = created;
         }
 
         // let matrix = matrix.unwrap_or_default();
 
-        let descriptor_pool = Self::create_descriptor_pool(
-            materials.len() as u32,
-            instance.swapchain_images.len() as u32,
-            &instance.device,
-        );
-
         Model3D {
-            descriptor_pool,
             materials,
             pipelines,
+            shaders,
             position: [0.0, 0.0, 0.0], // TODO: matrix.0
             rotation: [0.0, 0.0, 0.0, 1.0],
             scale: [1.0, 1.0, 1.0],
         }
     }
 
-    fn create_descriptor_pool(
-        material_count: u32,
-        swapchain_count: u32,
-        device: &ash::Device,
-    ) -> vk::DescriptorPool {
-        let pool_sizes = [
-            vk::DescriptorPoolSize {
-                ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-                descriptor_count: material_count * swapchain_count,
-            },
-            // Material UBO
-            vk::DescriptorPoolSize {
-                ty: vk::DescriptorType::UNIFORM_BUFFER,
-                descriptor_count: material_count * swapchain_count,
-            },
-        ];
-
-        let create_info = vk::DescriptorPoolCreateInfo::default()
-            .pool_sizes(&pool_sizes)
-            .max_sets(material_count * swapchain_count);
-
-        log::debug!(
-            "Creating Description Pool, Size {}",
-            material_count * swapchain_count
-        );
-
-        unsafe { device.create_descriptor_pool(&create_info, None) }.unwrap()
-    }
-
+    /// Groups the Meshes by the Pipeline they need, The Pipelines itself are created later all at once
     fn load_meshes(
         instance: &mut VulkanInstance,
         meshes: Vec<modelz::Mesh>,
-        shader_stage_create_info: &[PipelineShaderStageCreateInfo],
-        pipeline_layout: vk::PipelineLayout,
         loaded_materials: &[Material],
         pipelines: &mut Vec<ModelPipeline>,
+        mut geometry: Option<&mut GeometryPool>,
+        normals: GeneratedNormals,
     ) {
-        let surface_resolution = instance.surface_resolution;
-
-        let binding = [Vertex3D::binding_description()];
-        let attrib = Vertex3D::input_descriptions();
-        let vertex_input_state_info = vk::PipelineVertexInputStateCreateInfo::default()
-            .vertex_attribute_descriptions(&attrib)
-            .vertex_binding_descriptions(&binding);
-
-        let viewports = [vk::Viewport {
-            x: 0.0,
-            y: 0.0,
-            width: surface_resolution.width as f32,
-            height: surface_resolution.height as f32,
-            min_depth: 0.0,
-            max_depth: 1.0,
-        }];
-        let scissors = [surface_resolution.into()];
-        let viewport_state_info = vk::PipelineViewportStateCreateInfo::default()
-            .scissors(&scissors)
-            .viewports(&viewports);
-
         let mut cached_pipeline: HashMap<MaterialPipelineInfo, usize> = HashMap::new(); // We just need to store the pipelines vec index
 
         for mesh in meshes {
             log::debug!("      Loading Mesh {:?}", &mesh.name);
 
             let mut all_meshes = vec![];
+            let mut pooled_meshes = vec![];
+            let bounds;
 
             let material_index = mesh.material_index.unwrap(); // TODO
             let material = &loaded_materials[material_index];
             {
-                let loaded_mesh = Mesh3D::new(
-                    instance,
-                    &Self::convert_vertices(&mesh.vertices),
-                    Self::convert_indices(mesh.indices.unwrap()),
-                    mesh.name.as_deref(),
-                );
-                all_meshes.push(loaded_mesh);
+                let mut vertices = Self::convert_vertices(&mesh.vertices);
+                let mut indices = Self::convert_indices(mesh.indices.unwrap());
+                // glTF has them either for all Vertices or for none
+                if mesh.vertices.iter().any(|vertex| vertex.normal.is_none()) {
+                    (vertices, indices) =
+                        tangent_space::generate_normals(vertices, indices, normals);
+                }
+                // Without texture coordinates there is nothing to map, The Tangents stay zero
+                if mesh.vertices.iter().any(|vertex| vertex.tangent.is_none())
+                    && mesh
+                        .vertices
+                        .iter()
+                        .all(|vertex| vertex.tex_coord.is_some())
+                    && !tangent_space::generate_tangents(&mut vertices, &mut indices)
+                {
+                    log::warn!("      Failed to generate Tangents for {:?}", &mesh.name);
+                }
+                bounds = Bounds::from_positions(vertices.iter().map(|v| &v.position));
+                if let Some(geometry) = geometry.as_deref_mut() {
+                    let range = geometry
+                        .add(instance, &vertices, &indices, mesh.name.as_deref())
+                        .expect("Geometry Pool is too small for the Model");
+                    pooled_meshes.push(PooledMesh { range, bounds });
+                } else {
+                    let loaded_mesh =
+                        Mesh3D::new(instance, &vertices, indices, mesh.name.as_deref());
+                    all_meshes.push(loaded_mesh);
+                }
             }
             let pipeline_info = MaterialPipelineInfo {
                 mode: vk::PrimitiveTopology::TRIANGLE_LIST, // TODO
                 alpha_cut: Some(ordered_float::OrderedFloat(material.alpha_cut)),
                 double_sided: material.double_sided,
+                queue: match material.alpha_mode {
+                    modelz::AlphaMode::Opaque => RenderQueue::Opaque,
+                    modelz::AlphaMode::Mask => RenderQueue::Mask,
+                    modelz::AlphaMode::Blend => RenderQueue::Transparent,
+                },
             };
 
             let model_material = crate::ModelMaterial {
                 material_index,
                 meshes: all_meshes,
+                pooled_meshes,
+                bounds,
             };
 
             if let Some(pipeline_index) = cached_pipeline.get(&pipeline_info) {
                 pipelines[*pipeline_index].materials.push(model_material);
             } else {
-                let vertex_input_assembly_state_info = vk::PipelineInputAssemblyStateCreateInfo {
-                    topology: pipeline_info.mode,
+                cached_pipeline.insert(pipeline_info, pipelines.len());
+
+                pipelines.push(ModelPipeline {
+                    pipeline: vk::Pipeline::null(),
+                    info: pipeline_info,
+                    materials: vec![model_material], // TODO
+                });
+            }
+        }
+    }
+
+    /// Creates one Pipeline for every Info, Also used to reload the Pipelines when a Shader changed
+    pub(crate) fn create_pipelines(
+        instance: &VulkanInstance,
+        shaders: &ModelShaders,
+        infos: &[MaterialPipelineInfo],
+    ) -> Result<Vec<vk::Pipeline>, PipelineError> {
+        // Do not load for every pipeline, So we load it here
+        let vertex_code = read_spv(&mut File::open(&shaders.vertex_shader)?)?;
+        let fragment_code = read_spv(&mut File::open(&shaders.fragment_shader)?)?;
+        let oit = match &shaders.transparent {
+            TransparentPipelines::Sorted => None,
+            TransparentPipelines::WeightedBlended {
+                fragment_shader,
+                formats,
+            } => Some((read_spv(&mut File::open(fragment_shader)?)?, formats)),
+        };
+
+        let reflection = PipelineReflection::new(&[ShaderReflection::new(&vertex_code)?])?;
+
+        let mut binding = vec![Vertex3D::binding_description()];
+        let mut attrib = Vertex3D::input_descriptions().to_vec();
+        // The indirect Shader reads its transforms from a storage buffer, Only add the instance binding when it is used
+        if reflection
+            .inputs
+            .iter()
+            .any(|input| input.location >= InstanceData::FIRST_LOCATION)
+        {
+            binding.push(InstanceData::binding_description());
+            attrib.extend(InstanceData::input_descriptions());
+        }
+        reflection.validate_vertex_input(&attrib)?;
+
+        let create_module = |code: &[u32]| {
+            let module_info = vk::ShaderModuleCreateInfo::default().code(code);
+            unsafe { instance.device.create_shader_module(&module_info, None) }.unwrap()
+        };
+        let vertex_module = create_module(&vertex_code);
+        let fragment_module = create_module(&fragment_code);
+        let oit_module = oit.as_ref().map(|(code, _)| create_module(code));
+
+        let shader_entry_name = unsafe { CStr::from_bytes_with_nul_unchecked(b"main\0") };
+        let shader_stages = |fragment_module: vk::ShaderModule| {
+            [
+                vk::PipelineShaderStageCreateInfo {
+                    module: vertex_module,
+                    p_name: shader_entry_name.as_ptr(),
+                    stage: vk::ShaderStageFlags::VERTEX,
                     ..Default::default()
-                };
-                let rasterization_info = vk::PipelineRasterizationStateCreateInfo {
-                    front_face: vk::FrontFace::COUNTER_CLOCKWISE,
-                    line_width: 1.0,
-                    polygon_mode: vk::PolygonMode::FILL,
-                    cull_mode: if pipeline_info.double_sided {
-                        vk::CullModeFlags::NONE
-                    } else {
-                        vk::CullModeFlags::BACK
-                    },
+                },
+                vk::PipelineShaderStageCreateInfo {
+                    module: fragment_module,
+                    p_name: shader_entry_name.as_ptr(),
+                    stage: vk::ShaderStageFlags::FRAGMENT,
                     ..Default::default()
-                };
+                },
+            ]
+        };
+        let shader_stage_create_info = shader_stages(fragment_module);
+        let oit_stage_create_info = oit_module.map(shader_stages);
 
-                {
-                    let multisample_state_info = vk::PipelineMultisampleStateCreateInfo {
-                        rasterization_samples: vk::SampleCountFlags::TYPE_1,
-                        ..Default::default()
-                    };
-
-                    let depth_state_info = vk::PipelineDepthStencilStateCreateInfo::default()
-                        .depth_test_enable(true)
-                        .depth_write_enable(true)
-                        .depth_compare_op(vk::CompareOp::LESS)
-                        .max_depth_bounds(1.0);
-                    let color_blend_attachment_states = [vk::PipelineColorBlendAttachmentState {
-                        color_write_mask: vk::ColorComponentFlags::RGBA,
-                        ..Default::default()
-                    }];
-                    let color_blend_state = vk::PipelineColorBlendStateCreateInfo::default()
-                        .logic_op(vk::LogicOp::COPY)
-                        .attachments(&color_blend_attachment_states);
-
-                    let dynamic_state = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR]; // TODO
-                    let dynamic_state_info = vk::PipelineDynamicStateCreateInfo::default()
-                        .dynamic_states(&dynamic_state);
-
-                    let graphic_pipeline_info = vk::GraphicsPipelineCreateInfo::default()
-                        .stages(shader_stage_create_info)
+        let surface_resolution = instance.surface_resolution;
+
+        let vertex_input_state_info = vk::PipelineVertexInputStateCreateInfo::default()
+            .vertex_attribute_descriptions(&attrib)
+            .vertex_binding_descriptions(&binding);
+
+        let viewports = [vk::Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: surface_resolution.width as f32,
+            height: surface_resolution.height as f32,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        }];
+        let scissors = [surface_resolution.into()];
+        let viewport_state_info = vk::PipelineViewportStateCreateInfo::default()
+            .scissors(&scissors)
+            .viewports(&viewports);
+
+        let multisample_state_info = vk::PipelineMultisampleStateCreateInfo {
+            rasterization_samples: shaders.formats.samples,
+            ..Default::default()
+        };
+        let oit_multisample_state_info =
+            oit.as_ref()
+                .map(|(_, formats)| vk::PipelineMultisampleStateCreateInfo {
+                    rasterization_samples: formats.samples,
+                    ..Default::default()
+                });
+
+        let depth_state_info = vk::PipelineDepthStencilStateCreateInfo::default()
+            .depth_test_enable(true)
+            .depth_write_enable(true)
+            .depth_compare_op(vk::CompareOp::LESS)
+            .max_depth_bounds(1.0);
+        // Transparent surfaces must not hide what is behind them
+        let transparent_depth_state_info = depth_state_info.depth_write_enable(false);
+
+        let color_blend_attachment_states = [vk::PipelineColorBlendAttachmentState {
+            color_write_mask: vk::ColorComponentFlags::RGBA,
+            ..Default::default()
+        }];
+        let color_blend_state = vk::PipelineColorBlendStateCreateInfo::default()
+            .logic_op(vk::LogicOp::COPY)
+            .attachments(&color_blend_attachment_states);
+        let alpha_blend_attachment_states = [vk::PipelineColorBlendAttachmentState {
+            blend_enable: vk::TRUE,
+            src_color_blend_factor: vk::BlendFactor::SRC_ALPHA,
+            dst_color_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+            color_blend_op: vk::BlendOp::ADD,
+            src_alpha_blend_factor: vk::BlendFactor::ONE,
+            dst_alpha_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+            alpha_blend_op: vk::BlendOp::ADD,
+            color_write_mask: vk::ColorComponentFlags::RGBA,
+        }];
+        let alpha_blend_state = vk::PipelineColorBlendStateCreateInfo::default()
+            .logic_op(vk::LogicOp::COPY)
+            .attachments(&alpha_blend_attachment_states);
+        // Accumulation is summed up, Revealage is multiplied with (1 - alpha) of every surface
+        let oit_blend_attachment_states = [
+            vk::PipelineColorBlendAttachmentState {
+                blend_enable: vk::TRUE,
+                src_color_blend_factor: vk::BlendFactor::ONE,
+                dst_color_blend_factor: vk::BlendFactor::ONE,
+                color_blend_op: vk::BlendOp::ADD,
+                src_alpha_blend_factor: vk::BlendFactor::ONE,
+                dst_alpha_blend_factor: vk::BlendFactor::ONE,
+                alpha_blend_op: vk::BlendOp::ADD,
+                color_write_mask: vk::ColorComponentFlags::RGBA,
+            },
+            vk::PipelineColorBlendAttachmentState {
+                blend_enable: vk::TRUE,
+                src_color_blend_factor: vk::BlendFactor::ZERO,
+                dst_color_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_COLOR,
+                color_blend_op: vk::BlendOp::ADD,
+                src_alpha_blend_factor: vk::BlendFactor::ZERO,
+                dst_alpha_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+                alpha_blend_op: vk::BlendOp::ADD,
+                color_write_mask: vk::ColorComponentFlags::R,
+            },
+        ];
+        let oit_blend_state = vk::PipelineColorBlendStateCreateInfo::default()
+            .logic_op(vk::LogicOp::COPY)
+            .attachments(&oit_blend_attachment_states);
+
+        let dynamic_state = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR]; // TODO
+        let dynamic_state_info =
+            vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_state);
+
+        // The create infos point into these, So they have to live until all pipelines are created
+        let input_assembly_states: Vec<_> = infos
+            .iter()
+            .map(|info| vk::PipelineInputAssemblyStateCreateInfo {
+                topology: info.mode,
+                ..Default::default()
+            })
+            .collect();
+        let rasterization_states: Vec<_> = infos
+            .iter()
+            .map(|info| vk::PipelineRasterizationStateCreateInfo {
+                front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+                line_width: 1.0,
+                polygon_mode: vk::PolygonMode::FILL,
+                cull_mode: if info.double_sided {
+                    vk::CullModeFlags::NONE
+                } else {
+                    vk::CullModeFlags::BACK
+                },
+                ..Default::default()
+            })
+            .collect();
+        let mut rendering_infos: Vec<_> = infos
+            .iter()
+            .map(|info| match &oit {
+                Some((_, formats)) if info.queue == RenderQueue::Transparent => {
+                    formats.rendering_info()
+                }
+                _ => shaders.formats.rendering_info(),
+            })
+            .collect();
+
+        let graphic_pipeline_infos: Vec<_> = rendering_infos
+            .iter_mut()
+            .zip(infos)
+            .zip(
+                input_assembly_states
+                    .iter()
+                    .zip(rasterization_states.iter()),
+            )
+            .map(
+                |((rendering_info, info), (input_assembly, rasterization))| {
+                    let transparent = info.queue == RenderQueue::Transparent;
+                    let (stages, multisample, color_blend) =
+                        match (&oit_stage_create_info, &oit_multisample_state_info) {
+                            (Some(stages), Some(multisample)) if transparent => {
+                                (stages, multisample, &oit_blend_state)
+                            }
+                            _ if transparent => (
+                                &shader_stage_create_info,
+                                &multisample_state_info,
+                                &alpha_blend_state,
+                            ),
+                            _ => (
+                                &shader_stage_create_info,
+                                &multisample_state_info,
+                                &color_blend_state,
+                            ),
+                        };
+                    vk::GraphicsPipelineCreateInfo::default()
+                        .push_next(rendering_info)
+                        .stages(stages)
                         .vertex_input_state(&vertex_input_state_info)
-                        .input_assembly_state(&vertex_input_assembly_state_info)
+                        .input_assembly_state(input_assembly)
                         .viewport_state(&viewport_state_info)
-                        .rasterization_state(&rasterization_info)
-                        .multisample_state(&multisample_state_info)
-                        .depth_stencil_state(&depth_state_info)
-                        .color_blend_state(&color_blend_state)
+                        .rasterization_state(rasterization)
+                        .multisample_state(multisample)
+                        .depth_stencil_state(if transparent {
+                            &transparent_depth_state_info
+                        } else {
+                            &depth_state_info
+                        })
+                        .color_blend_state(color_blend)
                         .dynamic_state(&dynamic_state_info)
-                        .layout(pipeline_layout)
-                        .render_pass(instance.render_pass);
-
-                    let graphics_pipelines = unsafe {
-                        instance.device.create_graphics_pipelines(
-                            vk::PipelineCache::null(),
-                            &[graphic_pipeline_info],
-                            None,
-                        )
-                    }
-                    .expect("Unable to create graphics pipeline");
-
-                    cached_pipeline.insert(pipeline_info, pipelines.len());
-
-                    pipelines.push(ModelPipeline {
-                        pipeline: graphics_pipelines[0],
-                        materials: vec![model_material], // TODO
-                    });
-                }
+                        .layout(shaders.pipeline_layout)
+                },
+            )
+            .collect();
+
+        let graphics_pipelines = unsafe {
+            instance.device.create_graphics_pipelines(
+                instance.pipeline_cache,
+                &graphic_pipeline_infos,
+                None,
+            )
+        };
+
+        unsafe {
+            instance.device.destroy_shader_module(vertex_module, None);
+            instance.device.destroy_shader_module(fragment_module, None);
+            if let Some(oit_module) = oit_module {
+                instance.device.destroy_shader_module(oit_module, None);
             }
         }
+
+        graphics_pipelines.map_err(|(pipelines, err)| {
+            // Some may have been created anyway
+            pipelines
+                .into_iter()
+                .filter(|p| *p != vk::Pipeline::null())
+                .for_each(|p| unsafe { instance.device.destroy_pipeline(p, None) });
+            PipelineError::Vulkan(err)
+        })
     }
 
     /**
@@ -265,33 +420,92 @@ impl ModelLoader {
      *  RAM -> VRAM
      */
     fn load_material(instance: &mut VulkanInstance, data: modelz::Material) -> Material {
-        let diffuse_texture = if let Some(diffuse_texture) = data.diffuse_texture {
-            VulkanImage::from_image(
+        let name = data.name.as_deref(); // TODO: use texture name not material name
+
+        // Colors are stored in sRGB, Everything else is linear data
+        let diffuse_texture = Self::load_texture(
+            instance,
+            data.diffuse_texture,
+            vk::Format::R8G8B8A8_SRGB,
+            [255, 255, 255, 255],
+            name,
+        );
+        // Points straight up in tangent space
+        let normal_texture = Self::load_texture(
+            instance,
+            data.normal_texture,
+            vk::Format::R8G8B8A8_UNORM,
+            [128, 128, 255, 255],
+            name,
+        );
+        let metallic_roughness_texture = Self::load_texture(
+            instance,
+            data.metallic_roughness_texture,
+            vk::Format::R8G8B8A8_UNORM,
+            [255, 255, 255, 255],
+            name,
+        );
+        let occlusion_texture = Self::load_texture(
+            instance,
+            data.occlusion_texture,
+            vk::Format::R8G8B8A8_UNORM,
+            [255, 255, 255, 255],
+            name,
+        );
+        let emissive_texture = Self::load_texture(
+            instance,
+            data.emissive_texture,
+            vk::Format::R8G8B8A8_SRGB,
+            [255, 255, 255, 255],
+            name,
+        );
+
+        Material {
+            diffuse_texture,
+            normal_texture,
+            metallic_roughness_texture,
+            occlusion_texture,
+            emissive_texture,
+            descriptor_set: None,
+            bindless_id: None,
+            alpha_mode: data.alpha_mode,
+            alpha_cut: data.alpha_cutoff.unwrap_or(0.5),
+            double_sided: data.double_sided,
+            base_color: data.base_color.unwrap_or([1.0, 1.0, 1.0, 1.0]),
+            metallic_factor: data.metallic_factor,
+            roughness_factor: data.roughness_factor,
+            occlusion_strength: data.occlusion_strength,
+            emissive_factor: data.emissive_factor,
+        }
+    }
+
+    /// Missing Textures get a small one filled with `default`, So the Shader can always sample all of them
+    fn load_texture(
+        instance: &VulkanInstance,
+        texture: Option<modelz::Texture>,
+        format: vk::Format,
+        default: [u8; 4],
+        name: Option<&str>,
+    ) -> VulkanImage {
+        if let Some(texture) = texture {
+            VulkanImage::from_image_with_format(
                 instance,
-                diffuse_texture.image,
+                texture.image,
+                format,
                 true,
-                Some(Self::convert_sampler(diffuse_texture.sampler)),
-                data.name.as_deref(), // TODO: use texture name not material name
+                Some(Self::convert_sampler(texture.sampler)),
+                name,
             )
         } else {
             VulkanImage::from_color(
                 instance,
-                [255, 255, 255, 255],
+                default,
                 vk::Extent2D {
-                    width: 128,
-                    height: 128,
+                    width: 1,
+                    height: 1,
                 },
-                data.name.as_deref(), // TODO: use texture name not material name
+                name,
             )
-        };
-
-        Material {
-            diffuse_texture,
-            descriptor_set: None,
-            alpha_mode: data.alpha_mode,
-            alpha_cut: data.alpha_cutoff.unwrap_or(0.5),
-            double_sided: data.double_sided,
-            base_color: data.base_color.unwrap_or([1.0, 1.0, 1.0, 1.0]),
         }
     }
 
@@ -359,8 +573,10 @@ impl ModelLoader {
             .iter()
             .map(|vertex| Vertex3D {
                 position: vertex.position,
-                tex_coord: vertex.tex_coord.unwrap_or_default(), // TODO
-                normal: vertex.normal.unwrap_or_default(),       // TODO
+                tex_coord: vertex.tex_coord.unwrap_or_default(),
+                // Generated later when missing
+                normal: vertex.normal.unwrap_or_default(),
+                tangent: vertex.tangent.unwrap_or_default(),
             })
             .collect()
     }
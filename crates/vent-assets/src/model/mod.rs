@@ -2,22 +2,34 @@ use std::path::Path;
 
 use ash::vk;
 use loader::ModelLoader;
-use vent_rendering::instance::VulkanInstance;
+use vent_rendering::{
+    geometry::GeometryPool,
+    instance::VulkanInstance,
+    pipeline::{PipelineError, RenderingFormats},
+    RenderQueue,
+};
 use vent_sdk::utils::stopwatch::Stopwatch;
 
-use crate::Model3D;
+use crate::{GeneratedNormals, MaterialBinding, Model3D, ModelMaterial, TransparentPipelines};
 
 mod loader;
 mod optimizer;
+mod tangent_space;
 
 impl Model3D {
+    /// Loads the Model, With a `GeometryPool` all Meshes are put into it instead of getting their own buffers.
+    /// Meshes without Normals get `normals`, Tangents are generated with MikkTSpace when missing
     #[inline]
+    #[allow(clippy::too_many_arguments)]
     pub async fn load<P: AsRef<Path>>(
         instance: &mut VulkanInstance,
         vertex_shader: P,
         fragment_shader: P,
         pipeline_layout: vk::PipelineLayout,
+        formats: &RenderingFormats,
         path: P,
+        geometry: Option<&mut GeometryPool>,
+        normals: GeneratedNormals,
     ) -> Self {
         let sw = Stopwatch::new_and_start();
         let model = modelz::Model3D::load(path.as_ref()).expect("Failed to Load 3D Model");
@@ -26,7 +38,10 @@ impl Model3D {
             vertex_shader.as_ref(),
             fragment_shader.as_ref(),
             pipeline_layout,
+            formats,
             model,
+            geometry,
+            normals,
         )
         .await;
         log::info!(
@@ -39,7 +54,65 @@ impl Model3D {
         model
     }
 
+    /// Returns true when the Pipelines of this Model were created from this SPIR-V file
+    pub fn uses_shader(&self, spv_file: &Path) -> bool {
+        let transparent = match &self.shaders.transparent {
+            TransparentPipelines::Sorted => false,
+            TransparentPipelines::WeightedBlended {
+                fragment_shader, ..
+            } => fragment_shader == spv_file,
+        };
+        self.shaders.vertex_shader == spv_file
+            || self.shaders.fragment_shader == spv_file
+            || transparent
+    }
+
+    /// Creates all Pipelines again from the Shader files on disk, When anything fails the old Pipelines stay in use.
+    /// The Pipelines must not be in use by the GPU
+    pub fn reload_pipelines(&mut self, instance: &VulkanInstance) -> Result<(), PipelineError> {
+        let infos: Vec<_> = self.pipelines.iter().map(|p| p.info).collect();
+        let created = ModelLoader::create_pipelines(instance, &self.shaders, &infos)?;
+        for (pipeline, created) in self.pipelines.iter_mut().zip(created) {
+            unsafe { instance.device.destroy_pipeline(pipeline.pipeline, None) };
+            pipeline.pipeline = created;
+        }
+        Ok(())
+    }
+
+    /// Creates all Pipelines again for another MSAA sample count, On failure the old ones stay in use.
+    /// The Pipelines must not be in use by the GPU
+    pub fn set_samples(
+        &mut self,
+        instance: &VulkanInstance,
+        samples: vk::SampleCountFlags,
+    ) -> Result<(), PipelineError> {
+        let old_samples = self.shaders.formats.samples;
+        self.shaders.formats.samples = samples;
+        let result = self.reload_pipelines(instance);
+        if result.is_err() {
+            self.shaders.formats.samples = old_samples;
+        }
+        result
+    }
+
+    /// Creates all Pipelines again with another kind of transparency, On failure the old ones stay in use.
+    /// The Pipelines must not be in use by the GPU
+    pub fn set_transparent_pipelines(
+        &mut self,
+        instance: &VulkanInstance,
+        transparent: TransparentPipelines,
+    ) -> Result<(), PipelineError> {
+        let old_transparent = std::mem::replace(&mut self.shaders.transparent, transparent);
+        let result = self.reload_pipelines(instance);
+        if result.is_err() {
+            self.shaders.transparent = old_transparent;
+        }
+        result
+    }
+
     /// So your ideal render loop would be
+    ///
+    /// Pooled Meshes are drawn from the `GeometryPool`, Which has to be bound before
 
     /// For each pipeline
     ///  Set pipeline
@@ -52,47 +125,113 @@ impl Model3D {
         device: &ash::Device,
         pipeline_layout: vk::PipelineLayout,
         command_buffer: vk::CommandBuffer,
-        buffer_index: usize,
-        with_descriptor_set: bool,
+        material_binding: MaterialBinding,
     ) {
-        self.pipelines.iter().for_each(|pipeline| {
-            unsafe {
-                device.cmd_bind_pipeline(
-                    command_buffer,
-                    vk::PipelineBindPoint::GRAPHICS,
-                    pipeline.pipeline,
-                )
-            }
-            pipeline.materials.iter().for_each(|material| {
-                if with_descriptor_set {
-                    let material = &self.materials[material.material_index];
-                    if let Some(ds) = &material.descriptor_set {
-                        unsafe {
-                            device.cmd_bind_descriptor_sets(
-                                command_buffer,
-                                vk::PipelineBindPoint::GRAPHICS,
-                                pipeline_layout,
-                                0,
-                                &ds[buffer_index..=buffer_index],
-                                &[],
-                            )
-                        }
-                    }
+        self.draw_instanced(
+            device,
+            pipeline_layout,
+            command_buffer,
+            material_binding,
+            1,
+            &RenderQueue::ALL,
+        );
+    }
+
+    /// Draws every Mesh of the `queues` `instance_count` times, The instance data has to be bound before (See `vent_rendering::mesh::bind_instances`).
+    /// Transparent Meshes are not sorted here
+    pub fn draw_instanced(
+        &self,
+        device: &ash::Device,
+        pipeline_layout: vk::PipelineLayout,
+        command_buffer: vk::CommandBuffer,
+        material_binding: MaterialBinding,
+        instance_count: u32,
+        queues: &[RenderQueue],
+    ) {
+        self.pipelines
+            .iter()
+            .filter(|pipeline| queues.contains(&pipeline.info.queue))
+            .for_each(|pipeline| {
+                unsafe {
+                    device.cmd_bind_pipeline(
+                        command_buffer,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        pipeline.pipeline,
+                    )
                 }
-                material.meshes.iter().for_each(|mesh| {
-                    // rpass.push_debug_group("Bind Mesh");
-                    mesh.bind(device, command_buffer);
-                    // rpass.pop_debug_group();
-                    // rpass.insert_debug_marker("Draw!");
-                    mesh.draw(device, command_buffer);
+                pipeline.materials.iter().for_each(|material| {
+                    self.bind_material(
+                        device,
+                        pipeline_layout,
+                        command_buffer,
+                        material_binding,
+                        material,
+                    );
+                    material.draw(device, command_buffer, 0..instance_count);
                 });
-            });
-        })
+            })
+    }
+
+    /// Tells the Shader which Material `material` uses, The Pipeline is not bound
+    pub fn bind_material(
+        &self,
+        device: &ash::Device,
+        pipeline_layout: vk::PipelineLayout,
+        command_buffer: vk::CommandBuffer,
+        material_binding: MaterialBinding,
+        material: &ModelMaterial,
+    ) {
+        let model_material = &self.materials[material.material_index];
+        match material_binding {
+            MaterialBinding::None => (),
+            MaterialBinding::DescriptorSets => {
+                if let Some(ds) = model_material.descriptor_set {
+                    unsafe {
+                        device.cmd_bind_descriptor_sets(
+                            command_buffer,
+                            vk::PipelineBindPoint::GRAPHICS,
+                            pipeline_layout,
+                            0,
+                            &[ds],
+                            &[],
+                        )
+                    }
+                }
+            }
+            MaterialBinding::Bindless { material_id_offset } => {
+                let material_id = model_material
+                    .bindless_id
+                    .expect("Material was not added to the Bindless Descriptors");
+                unsafe {
+                    device.cmd_push_constants(
+                        command_buffer,
+                        pipeline_layout,
+                        vk::ShaderStageFlags::VERTEX,
+                        material_id_offset,
+                        &material_id.to_ne_bytes(),
+                    )
+                }
+            }
+        }
+    }
+
+    /// Draws only the geometry of every Mesh, No Pipeline or Material gets bound.
+    /// Useful for depth only passes like Shadows, Which bring their own Pipeline
+    pub fn draw_meshes(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        instance_count: u32,
+    ) {
+        self.pipelines
+            .iter()
+            .flat_map(|pipeline| pipeline.materials.iter())
+            .for_each(|material| material.draw(device, command_buffer, 0..instance_count));
     }
 
     pub fn destroy(&mut self, device: &ash::Device) {
         self.materials.drain(..).for_each(|mut material| {
-            material.diffuse_texture.destroy(device);
+            material.destroy(device);
         });
         self.pipelines.drain(..).for_each(|mut pipeline| {
             unsafe { device.destroy_pipeline(pipeline.pipeline, None) };
@@ -102,7 +241,6 @@ impl Model3D {
                 });
             });
         });
-        // We are getting an Validation error when we try to free an descriptor set, They will all automatily freed when the Descriptor pool is destroyed
-        unsafe { device.destroy_descriptor_pool(self.descriptor_pool, None) };
+        // Descriptor sets of the Materials belong to the descriptor allocator of the Instance, They are freed with it
     }
 }
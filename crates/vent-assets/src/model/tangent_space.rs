@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+
+use vent_rendering::{vertex::Vertex3D, Indices};
+
+use crate::GeneratedNormals;
+
+/// Fills in the Normals of a Mesh which has none.
+///
+/// Flat Normals can not be shared between Triangles, So the Mesh is unindexed and the new indices are returned
+pub(crate) fn generate_normals(
+    vertices: Vec<Vertex3D>,
+    indices: Indices,
+    normals: GeneratedNormals,
+) -> (Vec<Vertex3D>, Indices) {
+    let triangles = indices.to_u32();
+    match normals {
+        GeneratedNormals::Flat => {
+            let mut flat = Vec::with_capacity(triangles.len());
+            for triangle in triangles.chunks_exact(3) {
+                let mut corners = [0, 1, 2].map(|i| vertices[triangle[i] as usize]);
+                let normal = normalize(face_normal(corners.map(|corner| corner.position)));
+                corners.iter_mut().for_each(|corner| corner.normal = normal);
+                flat.extend(corners);
+            }
+            let indices = Indices::U32((0..flat.len() as u32).collect());
+            (flat, indices)
+        }
+        GeneratedNormals::Smooth => {
+            let mut vertices = vertices;
+            let mut sums = vec![[0.0; 3]; vertices.len()];
+            for triangle in triangles.chunks_exact(3) {
+                // Not normalized, So bigger Triangles count more
+                let normal =
+                    face_normal([0, 1, 2].map(|i| vertices[triangle[i] as usize].position));
+                for &index in triangle {
+                    let sum = &mut sums[index as usize];
+                    *sum = [sum[0] + normal[0], sum[1] + normal[1], sum[2] + normal[2]];
+                }
+            }
+            for (vertex, sum) in vertices.iter_mut().zip(sums) {
+                vertex.normal = normalize(sum);
+            }
+            (vertices, indices)
+        }
+    }
+}
+
+/// Fills in the Tangents with MikkTSpace, The same ones Blender and most bakers use, So Normal Maps look like they were authored.
+/// Needs Normals and texture coordinates, Returns false when MikkTSpace failed.
+///
+/// Vertices which get different Tangents from their Triangles (e.g. on mirrored UV seams) are split, So the indices may change
+pub(crate) fn generate_tangents(vertices: &mut Vec<Vertex3D>, indices: &mut Indices) -> bool {
+    let mut triangles = indices.to_u32();
+    let mut corner_tangents = vec![[0.0; 4]; triangles.len()];
+    if !mikktspace::generate_tangents(&mut MikkTSpaceMesh {
+        vertices,
+        indices: &triangles,
+        tangents: &mut corner_tangents,
+    }) {
+        return false;
+    }
+
+    // The first Triangle keeps the Vertex, Every other Tangent gets its own copy
+    let mut assigned = vec![None; vertices.len()];
+    let mut splits: HashMap<(u32, [u32; 4]), u32> = HashMap::new();
+    for (index, tangent) in triangles.iter_mut().zip(corner_tangents) {
+        let vertex = *index as usize;
+        match assigned[vertex] {
+            None => {
+                assigned[vertex] = Some(tangent);
+                vertices[vertex].tangent = tangent;
+            }
+            Some(existing) if existing == tangent => {}
+            Some(_) => {
+                *index = *splits
+                    .entry((*index, tangent.map(f32::to_bits)))
+                    .or_insert_with(|| {
+                        let mut split = vertices[vertex];
+                        split.tangent = tangent;
+                        vertices.push(split);
+                        (vertices.len() - 1) as u32
+                    });
+            }
+        }
+    }
+    if !splits.is_empty() {
+        *indices = Indices::U32(triangles);
+    }
+    true
+}
+
+/// Indexed Triangles as MikkTSpace wants to see them, The Tangents are collected per Triangle corner
+struct MikkTSpaceMesh<'a> {
+    vertices: &'a [Vertex3D],
+    indices: &'a [u32],
+    tangents: &'a mut [[f32; 4]],
+}
+
+impl MikkTSpaceMesh<'_> {
+    fn vertex(&self, face: usize, vert: usize) -> &Vertex3D {
+        &self.vertices[self.indices[face * 3 + vert] as usize]
+    }
+}
+
+impl mikktspace::Geometry for MikkTSpaceMesh<'_> {
+    fn num_faces(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    fn num_vertices_of_face(&self, _face: usize) -> usize {
+        3
+    }
+
+    fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.vertex(face, vert).position
+    }
+
+    fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.vertex(face, vert).normal
+    }
+
+    fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+        self.vertex(face, vert).tex_coord
+    }
+
+    fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+        self.tangents[face * 3 + vert] = tangent;
+    }
+}
+
+/// Cross product of two edges, Its length is twice the area of the Triangle
+fn face_normal([a, b, c]: [[f32; 3]; 3]) -> [f32; 3] {
+    let ab = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+    let ac = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+    [
+        ab[1] * ac[2] - ab[2] * ac[1],
+        ab[2] * ac[0] - ab[0] * ac[2],
+        ab[0] * ac[1] - ab[1] * ac[0],
+    ]
+}
+
+/// Degenerated Triangles have no direction, They just point up
+fn normalize(v: [f32; 3]) -> [f32; 3] {
+    let length = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    if length <= f32::EPSILON {
+        return [0.0, 1.0, 0.0];
+    }
+    v.map(|c| c / length)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vertex(position: [f32; 3], tex_coord: [f32; 2]) -> Vertex3D {
+        Vertex3D {
+            position,
+            tex_coord,
+            normal: [0.0; 3],
+            tangent: [0.0; 4],
+        }
+    }
+
+    /// Two Triangles folded along the x axis, One lies flat and one stands up
+    fn folded() -> (Vec<Vertex3D>, Indices) {
+        let vertices = vec![
+            vertex([0.0, 0.0, 0.0], [0.0, 0.0]),
+            vertex([1.0, 0.0, 0.0], [1.0, 0.0]),
+            vertex([0.0, 0.0, -1.0], [0.0, 1.0]),
+            vertex([0.0, 1.0, 0.0], [0.0, 1.0]),
+        ];
+        (vertices, Indices::U16(vec![0, 1, 2, 0, 1, 3]))
+    }
+
+    fn assert_near(a: [f32; 3], b: [f32; 3]) {
+        assert!(
+            a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-5),
+            "{a:?} != {b:?}"
+        );
+    }
+
+    #[test]
+    fn face_normal_follows_winding() {
+        let triangle = [[0.0, 0.0, 0.0], [2.0, 0.0, 0.0], [0.0, 2.0, 0.0]];
+        // Twice the area
+        assert_eq!(face_normal(triangle), [0.0, 0.0, 4.0]);
+        let [a, b, c] = triangle;
+        assert_eq!(face_normal([a, c, b]), [0.0, 0.0, -4.0]);
+    }
+
+    #[test]
+    fn normalize_degenerated() {
+        assert_near(normalize([3.0, 0.0, 4.0]), [0.6, 0.0, 0.8]);
+        assert_eq!(normalize([0.0; 3]), [0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn flat_normals_unindex() {
+        let (vertices, indices) = folded();
+        let (vertices, indices) = generate_normals(vertices, indices, GeneratedNormals::Flat);
+
+        assert_eq!(vertices.len(), 6);
+        assert_eq!(indices.to_u32(), (0..6).collect::<Vec<_>>());
+        vertices[..3]
+            .iter()
+            .for_each(|v| assert_near(v.normal, [0.0, 1.0, 0.0]));
+        vertices[3..]
+            .iter()
+            .for_each(|v| assert_near(v.normal, [0.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn smooth_normals_average_shared_vertices() {
+        let (vertices, indices) = folded();
+        let (vertices, indices) = generate_normals(vertices, indices, GeneratedNormals::Smooth);
+
+        assert_eq!(vertices.len(), 4);
+        assert_eq!(indices.to_u32(), [0, 1, 2, 0, 1, 3]);
+        let diagonal = std::f32::consts::FRAC_1_SQRT_2;
+        assert_near(vertices[0].normal, [0.0, diagonal, diagonal]);
+        assert_near(vertices[1].normal, [0.0, diagonal, diagonal]);
+        assert_near(vertices[2].normal, [0.0, 1.0, 0.0]);
+        assert_near(vertices[3].normal, [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn mirrored_uvs_split_vertices() {
+        // Two Quads sharing the edge at x = 1, The right one has its texture mirrored
+        let mut vertices = vec![
+            vertex([0.0, 0.0, 0.0], [0.0, 0.0]),
+            vertex([1.0, 0.0, 0.0], [1.0, 0.0]),
+            vertex([1.0, 1.0, 0.0], [1.0, 1.0]),
+            vertex([0.0, 1.0, 0.0], [0.0, 1.0]),
+            vertex([2.0, 0.0, 0.0], [0.0, 0.0]),
+            vertex([2.0, 1.0, 0.0], [0.0, 1.0]),
+        ];
+        vertices.iter_mut().for_each(|v| v.normal = [0.0, 0.0, 1.0]);
+        let mut indices = Indices::U16(vec![0, 1, 2, 0, 2, 3, 1, 4, 5, 1, 5, 2]);
+
+        assert!(generate_tangents(&mut vertices, &mut indices));
+
+        // Both Vertices on the seam got a copy
+        assert_eq!(vertices.len(), 8);
+        let indices = indices.to_u32();
+        let sign = |index: &u32| vertices[*index as usize].tangent[0].signum();
+        assert!(indices[..6].iter().all(|i| sign(i) == 1.0));
+        assert!(indices[6..].iter().all(|i| sign(i) == -1.0));
+    }
+}
@@ -20,14 +20,7 @@ impl MemoryAllocator {
     ) -> vk::DeviceMemory {
         let memory_req = unsafe { device.get_buffer_memory_requirements(buffer) };
 
-        let memory_info = vk::MemoryAllocateInfo::default()
-            .allocation_size(memory_req.size)
-            .memory_type_index(
-                self.find_memorytype_index(memory_req, flags)
-                    .expect("Failed to find Memory Index"),
-            );
-
-        let memory = unsafe { device.allocate_memory(&memory_info, None) }.unwrap();
+        let memory = self.allocate(device, memory_req, flags);
         unsafe {
             device
                 .bind_buffer_memory(buffer, memory, 0)
@@ -45,6 +38,22 @@ impl MemoryAllocator {
     ) -> vk::DeviceMemory {
         let memory_req = unsafe { device.get_image_memory_requirements(image) };
 
+        let memory = self.allocate(device, memory_req, flags);
+        unsafe {
+            device
+                .bind_image_memory(image, memory, 0)
+                .expect("Failed to bind Buffer memory");
+        }
+        memory
+    }
+
+    /// Allocates memory without binding it, Useful when multiple resources should share the same memory
+    pub fn allocate(
+        &self,
+        device: &ash::Device,
+        memory_req: vk::MemoryRequirements,
+        flags: vk::MemoryPropertyFlags,
+    ) -> vk::DeviceMemory {
         let memory_info = vk::MemoryAllocateInfo::default()
             .allocation_size(memory_req.size)
             .memory_type_index(
@@ -52,13 +61,7 @@ impl MemoryAllocator {
                     .expect("Failed to find Memory Index"),
             );
 
-        let memory = unsafe { device.allocate_memory(&memory_info, None) }.unwrap();
-        unsafe {
-            device
-                .bind_image_memory(image, memory, 0)
-                .expect("Failed to bind Buffer memory");
-        }
-        memory
+        unsafe { device.allocate_memory(&memory_info, None) }.unwrap()
     }
 
     fn find_memorytype_index(
@@ -0,0 +1,237 @@
+use std::{mem::size_of, ptr};
+
+use ash::vk;
+
+use crate::{
+    buffer::VulkanBuffer,
+    instance::VulkanInstance,
+    reflection::{PipelineReflection, ReflectionError},
+};
+
+/// Upper limit of the texture array, Devices with a lower limit get less
+pub const MAX_BINDLESS_TEXTURES: u32 = 16384;
+pub const MAX_BINDLESS_MATERIALS: u32 = 16384;
+
+pub const TEXTURE_BINDING: u32 = 0;
+pub const MATERIAL_BINDING: u32 = 1;
+
+/// One Descriptor Set with all Textures and all Materials, Used when the Device supports descriptor indexing.
+///
+/// Shaders access it with
+/// ```glsl
+/// layout (set = 0, binding = 0) uniform sampler2D textures[];
+/// layout (set = 0, binding = 1) readonly buffer Materials { Material materials[]; };
+/// ```
+/// and index it with an material id, So the set gets bound once instead of once per Material.
+/// Textures can be added while rendering, Materials are written into an host visible buffer
+pub struct BindlessDescriptors {
+    pub layout: vk::DescriptorSetLayout,
+    pub set: vk::DescriptorSet,
+    pool: vk::DescriptorPool,
+    texture_capacity: u32,
+    texture_count: u32,
+
+    material_buffer: VulkanBuffer,
+    material_memory: *mut u8,
+    material_size: vk::DeviceSize,
+    material_count: u32,
+}
+
+impl BindlessDescriptors {
+    /// `material_size` is the size of a single Material in the Shader, Must be a multiple of 16 bytes
+    pub fn new(instance: &VulkanInstance, material_size: vk::DeviceSize) -> Self {
+        assert!(
+            instance.capabilities.descriptor_indexing,
+            "Bindless Descriptors need descriptor indexing"
+        );
+        debug_assert!(material_size.is_multiple_of(16));
+        let device = &instance.device;
+        let texture_capacity =
+            MAX_BINDLESS_TEXTURES.min(instance.capabilities.max_bindless_textures);
+
+        let bindings = [
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(TEXTURE_BINDING)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(texture_capacity)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(MATERIAL_BINDING)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+        ];
+        // Not every Texture slot is filled, And new ones get written while older Frames still use the set
+        let binding_flags = [
+            vk::DescriptorBindingFlags::PARTIALLY_BOUND
+                | vk::DescriptorBindingFlags::UPDATE_AFTER_BIND,
+            vk::DescriptorBindingFlags::empty(),
+        ];
+        // Owned by the cache
+        let layout = instance.descriptor_layout_cache.get_with_flags(
+            device,
+            &bindings,
+            &binding_flags,
+            vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL,
+        );
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: texture_capacity,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::STORAGE_BUFFER,
+                descriptor_count: 1,
+            },
+        ];
+        let pool_info = vk::DescriptorPoolCreateInfo::default()
+            .flags(vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND)
+            .pool_sizes(&pool_sizes)
+            .max_sets(1);
+        let pool = unsafe { device.create_descriptor_pool(&pool_info, None) }.unwrap();
+
+        let layouts = [layout];
+        let allocate_info = vk::DescriptorSetAllocateInfo::default()
+            .descriptor_pool(pool)
+            .set_layouts(&layouts);
+        let set = unsafe { device.allocate_descriptor_sets(&allocate_info) }.unwrap()[0];
+
+        let buffer_size = material_size * MAX_BINDLESS_MATERIALS as vk::DeviceSize;
+        let material_buffer = VulkanBuffer::new(
+            instance,
+            buffer_size,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            Some("Bindless Materials"),
+        );
+        // Stays mapped, Materials are written directly
+        let material_memory = material_buffer.map(device, buffer_size) as *mut u8;
+
+        let buffer_info = [vk::DescriptorBufferInfo::default()
+            .buffer(*material_buffer)
+            .range(vk::WHOLE_SIZE)];
+        let write = vk::WriteDescriptorSet::default()
+            .dst_set(set)
+            .dst_binding(MATERIAL_BINDING)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .buffer_info(&buffer_info);
+        unsafe { device.update_descriptor_sets(&[write], &[]) };
+
+        log::debug!(
+            "Using Bindless Descriptors, {} Textures, {} Materials",
+            texture_capacity,
+            MAX_BINDLESS_MATERIALS
+        );
+
+        Self {
+            layout,
+            set,
+            pool,
+            texture_capacity,
+            texture_count: 0,
+            material_buffer,
+            material_memory,
+            material_size,
+            material_count: 0,
+        }
+    }
+
+    /// Checks that the Shaders use the set like we create it, Runtime sized arrays are reflected with a count of 0
+    pub fn validate(
+        &self,
+        reflection: &PipelineReflection,
+        set: u32,
+    ) -> Result<(), ReflectionError> {
+        let expected = [
+            (TEXTURE_BINDING, vk::DescriptorType::COMBINED_IMAGE_SAMPLER),
+            (MATERIAL_BINDING, vk::DescriptorType::STORAGE_BUFFER),
+        ];
+        for binding in reflection.bindings.iter().filter(|b| b.set == set) {
+            if !expected.contains(&(binding.binding, binding.descriptor_type)) {
+                return Err(ReflectionError::BindingMismatch {
+                    set,
+                    binding: binding.binding,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Puts the Texture into the array, Returns its index
+    pub fn add_texture(
+        &mut self,
+        device: &ash::Device,
+        image_view: vk::ImageView,
+        sampler: vk::Sampler,
+    ) -> u32 {
+        assert!(
+            self.texture_count < self.texture_capacity,
+            "Too many Bindless Textures, The limit is {}",
+            self.texture_capacity
+        );
+        let index = self.texture_count;
+        let image_info = [vk::DescriptorImageInfo::default()
+            .image_view(image_view)
+            .sampler(sampler)
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)];
+        let write = vk::WriteDescriptorSet::default()
+            .dst_set(self.set)
+            .dst_binding(TEXTURE_BINDING)
+            .dst_array_element(index)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(&image_info);
+        unsafe { device.update_descriptor_sets(&[write], &[]) };
+        self.texture_count += 1;
+        index
+    }
+
+    /// Writes the Material into the buffer, Returns the id the Shader uses to find it
+    pub fn add_material<T: Copy>(&mut self, material: &T) -> u32 {
+        assert!(size_of::<T>() as vk::DeviceSize <= self.material_size);
+        assert!(
+            self.material_count < MAX_BINDLESS_MATERIALS,
+            "Too many Bindless Materials, The limit is {}",
+            MAX_BINDLESS_MATERIALS
+        );
+        let id = self.material_count;
+        unsafe {
+            ptr::copy_nonoverlapping(
+                material as *const T as *const u8,
+                self.material_memory
+                    .add((id as vk::DeviceSize * self.material_size) as usize),
+                size_of::<T>(),
+            );
+        }
+        self.material_count += 1;
+        id
+    }
+
+    /// Binds the set, Which then stays bound for all draws using this pipeline layout
+    pub fn bind(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        pipeline_layout: vk::PipelineLayout,
+        set: u32,
+    ) {
+        unsafe {
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                pipeline_layout,
+                set,
+                &[self.set],
+                &[],
+            )
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        self.material_buffer.unmap(device);
+        self.material_buffer.destroy(device);
+        unsafe {
+            device.destroy_descriptor_pool(self.pool, None);
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use ash::{prelude::VkResult, vk};
+
+use crate::{buffer::VulkanBuffer, instance::VulkanInstance};
+
+/// A copy of a submitted Frame on its way back to the CPU
+pub(crate) struct PendingCapture {
+    buffer: VulkanBuffer,
+    // The fence of the Frame which recorded the copy
+    fence: vk::Fence,
+    format: vk::Format,
+    extent: vk::Extent2D,
+}
+
+impl VulkanInstance {
+    /// Captures the next submitted Swapchain (or offscreen) image, Get it with `take_capture` once it was submitted.
+    ///
+    /// Fails when the Swapchain images can not be copied from or their format is not supported
+    pub fn request_capture(&mut self) -> VkResult<()> {
+        if let Some(surface_loader) = &self.surface_loader {
+            let capabilities = unsafe {
+                surface_loader
+                    .get_physical_device_surface_capabilities(self.physical_device, self.surface)?
+            };
+            // The swapchain was created without transfer source usage
+            if !capabilities
+                .supported_usage_flags
+                .contains(vk::ImageUsageFlags::TRANSFER_SRC)
+            {
+                return Err(vk::Result::ERROR_FEATURE_NOT_PRESENT);
+            }
+        }
+        Self::capture_bytes_per_pixel(self.surface_format.format)?;
+        self.capture_requested = true;
+        Ok(())
+    }
+
+    /// Waits until the captured Frame is done and copies it back to the CPU, `None` if no capture was submitted.
+    ///
+    /// Has to be called after `submit` and before the next `next_image`, Which would reuse the fence.
+    /// The pixels are returned as they are stored, for sRGB formats these are already the encoded values which end up on the screen.
+    /// BGRA formats get swizzled into RGBA
+    pub fn take_capture(&mut self) -> Option<VkResult<image::RgbaImage>> {
+        let PendingCapture {
+            mut buffer,
+            fence,
+            format,
+            extent,
+        } = self.pending_capture.take()?;
+
+        let result = unsafe { self.device.wait_for_fences(&[fence], true, u64::MAX) };
+        if let Err(err) = result {
+            buffer.destroy(&self.device);
+            return Some(Err(err));
+        }
+
+        let bytes_per_pixel =
+            Self::capture_bytes_per_pixel(format).expect("Checked when requesting the capture");
+        let size = (extent.width * extent.height * bytes_per_pixel) as vk::DeviceSize;
+        let memory = buffer.map(&self.device, size);
+        let pixels =
+            unsafe { std::slice::from_raw_parts(memory as *const u8, size as usize) }.to_vec();
+        buffer.unmap(&self.device);
+        buffer.destroy(&self.device);
+
+        let pixels = Self::convert_to_rgba8(format, pixels);
+        Some(Ok(image::RgbaImage::from_raw(
+            extent.width,
+            extent.height,
+            pixels,
+        )
+        .expect("Capture buffer has the wrong size")))
+    }
+
+    /// Records the copy of `image_index` into the capture command buffer of the current Frame.
+    /// Submitted right after the Frame's own command buffer, So the copy happens before presenting
+    pub(crate) fn record_capture(&mut self, image_index: u32) -> vk::CommandBuffer {
+        if let Some(mut old) = self.pending_capture.take() {
+            // The old capture was never taken, Rare enough to just wait for the GPU
+            unsafe { self.device.device_wait_idle().unwrap() };
+            old.buffer.destroy(&self.device);
+        }
+
+        let format = self.surface_format.format;
+        let bytes_per_pixel =
+            Self::capture_bytes_per_pixel(format).expect("Checked when requesting the capture");
+        let image = self.swapchain_images[image_index as usize];
+        let extent = self.surface_resolution;
+        let size = (extent.width * extent.height * bytes_per_pixel) as vk::DeviceSize;
+
+        let buffer = VulkanBuffer::new(
+            self,
+            size,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            Some("Frame Capture"),
+        );
+
+        let frame = self.current_frame();
+        let command_buffer = frame.capture_command_buffer;
+        let fence = frame.in_flight_fence;
+        let begin_info = vk::CommandBufferBeginInfo::default()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        unsafe {
+            self.device
+                .begin_command_buffer(command_buffer, &begin_info)
+                .unwrap()
+        };
+
+        let present_layout = Self::color_final_layout(self.is_headless());
+        let subresource_range = vk::ImageSubresourceRange::default()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .level_count(1)
+            .layer_count(1);
+
+        let to_transfer = vk::ImageMemoryBarrier2::default()
+            .old_layout(present_layout)
+            .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+            .src_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+            .src_access_mask(vk::AccessFlags2::MEMORY_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags2::TRANSFER)
+            .dst_access_mask(vk::AccessFlags2::TRANSFER_READ)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(image)
+            .subresource_range(subresource_range);
+
+        let binding = [to_transfer];
+        let dep_info = vk::DependencyInfo::default().image_memory_barriers(&binding);
+        unsafe { self.device.cmd_pipeline_barrier2(command_buffer, &dep_info) };
+
+        let region = vk::BufferImageCopy2::default()
+            .image_subresource(
+                vk::ImageSubresourceLayers::default()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .layer_count(1),
+            )
+            .image_extent(extent.into());
+        let binding = [region];
+        let copy_info = vk::CopyImageToBufferInfo2::default()
+            .src_image(image)
+            .src_image_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+            .dst_buffer(*buffer)
+            .regions(&binding);
+        unsafe {
+            self.device
+                .cmd_copy_image_to_buffer2(command_buffer, &copy_info)
+        };
+
+        // Give the image back in the layout it was before, So presenting still works
+        let to_present = vk::ImageMemoryBarrier2::default()
+            .old_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+            .new_layout(present_layout)
+            .src_stage_mask(vk::PipelineStageFlags2::TRANSFER)
+            .src_access_mask(vk::AccessFlags2::TRANSFER_READ)
+            .dst_stage_mask(vk::PipelineStageFlags2::BOTTOM_OF_PIPE)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(image)
+            .subresource_range(subresource_range);
+        let binding = [to_present];
+        let dep_info = vk::DependencyInfo::default().image_memory_barriers(&binding);
+        unsafe {
+            self.device.cmd_pipeline_barrier2(command_buffer, &dep_info);
+            self.device.end_command_buffer(command_buffer).unwrap();
+        }
+
+        self.pending_capture = Some(PendingCapture {
+            buffer,
+            fence,
+            format,
+            extent,
+        });
+        command_buffer
+    }
+
+    pub(crate) fn destroy_pending_capture(&mut self) {
+        if let Some(mut capture) = self.pending_capture.take() {
+            capture.buffer.destroy(&self.device);
+        }
+    }
+
+    const fn capture_bytes_per_pixel(format: vk::Format) -> VkResult<u32> {
+        match format {
+            vk::Format::R8G8B8A8_UNORM
+            | vk::Format::R8G8B8A8_SRGB
+            | vk::Format::B8G8R8A8_UNORM
+            | vk::Format::B8G8R8A8_SRGB
+            | vk::Format::A2B10G10R10_UNORM_PACK32 => Ok(4),
+            _ => Err(vk::Result::ERROR_FORMAT_NOT_SUPPORTED),
+        }
+    }
+
+    fn convert_to_rgba8(format: vk::Format, mut pixels: Vec<u8>) -> Vec<u8> {
+        match format {
+            vk::Format::B8G8R8A8_UNORM | vk::Format::B8G8R8A8_SRGB => pixels
+                .chunks_exact_mut(4)
+                .for_each(|pixel| pixel.swap(0, 2)),
+            vk::Format::A2B10G10R10_UNORM_PACK32 => {
+                pixels.chunks_exact_mut(4).for_each(|pixel| {
+                    let packed = u32::from_le_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]);
+                    // Just keep the 8 most significant bits of every 10 bit channel
+                    pixel[0] = ((packed >> 2) & 0xFF) as u8;
+                    pixel[1] = ((packed >> 12) & 0xFF) as u8;
+                    pixel[2] = ((packed >> 22) & 0xFF) as u8;
+                })
+            }
+            _ => {}
+        }
+        // We present with an opaque composite alpha, So whatever ended up in the alpha channel is not what you see
+        pixels.chunks_exact_mut(4).for_each(|pixel| pixel[3] = 255);
+        pixels
+    }
+}
@@ -0,0 +1,350 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+};
+
+use ash::{prelude::VkResult, vk};
+
+/// How many descriptors of a type a pool has per set, e.g. 2.0 means 2 descriptors for every set the pool can hold
+#[derive(Clone, Copy)]
+pub struct PoolSizeRatio {
+    pub descriptor_type: vk::DescriptorType,
+    pub ratio: f32,
+}
+
+/// Sets the first pool can hold, Every new pool is 1.5 times bigger than the last one
+const INITIAL_SETS_PER_POOL: u32 = 64;
+const MAX_SETS_PER_POOL: u32 = 4096;
+
+/// Allocates Descriptor Sets from pools which are created when they are needed.
+///
+/// Instead of sizing a pool for every subsystem by hand a new pool is created once the current one runs out.
+/// Sets can not be freed one by one, Instead `reset` frees all sets of all pools at once.
+/// The `VulkanInstance` owns one for sets that live as long as their owner, Every Frame has one which is reset when the Frame begins
+pub struct DescriptorAllocator {
+    ratios: Vec<PoolSizeRatio>,
+    ready_pools: RefCell<Vec<vk::DescriptorPool>>,
+    full_pools: RefCell<Vec<vk::DescriptorPool>>,
+    sets_per_pool: Cell<u32>,
+}
+
+impl DescriptorAllocator {
+    pub fn new(ratios: &[PoolSizeRatio]) -> Self {
+        Self {
+            ratios: ratios.to_vec(),
+            ready_pools: RefCell::new(Vec::new()),
+            full_pools: RefCell::new(Vec::new()),
+            sets_per_pool: Cell::new(INITIAL_SETS_PER_POOL),
+        }
+    }
+
+    /// Ratios which fit the usual Image + Uniform Buffer Materials, Storage Buffers and standalone Samplers
+    pub fn default_ratios() -> [PoolSizeRatio; 6] {
+        [
+            PoolSizeRatio {
+                descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                ratio: 4.0,
+            },
+            PoolSizeRatio {
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                ratio: 2.0,
+            },
+            PoolSizeRatio {
+                descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+                ratio: 2.0,
+            },
+            PoolSizeRatio {
+                descriptor_type: vk::DescriptorType::STORAGE_IMAGE,
+                ratio: 1.0,
+            },
+            PoolSizeRatio {
+                descriptor_type: vk::DescriptorType::SAMPLED_IMAGE,
+                ratio: 1.0,
+            },
+            PoolSizeRatio {
+                descriptor_type: vk::DescriptorType::SAMPLER,
+                ratio: 1.0,
+            },
+        ]
+    }
+
+    /// Fails when even a fresh pool can not hold the set, e.g. when the layout uses a descriptor type missing in the ratios
+    pub fn allocate(
+        &self,
+        device: &ash::Device,
+        layout: vk::DescriptorSetLayout,
+    ) -> VkResult<vk::DescriptorSet> {
+        let mut pool = self.get_pool(device);
+        let layouts = [layout];
+        let mut info = vk::DescriptorSetAllocateInfo::default()
+            .descriptor_pool(pool)
+            .set_layouts(&layouts);
+
+        let result = match unsafe { device.allocate_descriptor_sets(&info) } {
+            Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY | vk::Result::ERROR_FRAGMENTED_POOL) => {
+                // The pool is full, So we try again with a fresh one
+                self.full_pools.borrow_mut().push(pool);
+                pool = self.get_pool(device);
+                info = info.descriptor_pool(pool);
+                unsafe { device.allocate_descriptor_sets(&info) }
+            }
+            result => result,
+        };
+        self.ready_pools.borrow_mut().push(pool);
+        result.map(|sets| sets[0])
+    }
+
+    /// Frees all sets which were allocated, They must not be in use by the GPU anymore
+    pub fn reset(&self, device: &ash::Device) {
+        let mut ready_pools = self.ready_pools.borrow_mut();
+        ready_pools.append(&mut self.full_pools.borrow_mut());
+        for &pool in ready_pools.iter() {
+            unsafe {
+                device
+                    .reset_descriptor_pool(pool, vk::DescriptorPoolResetFlags::empty())
+                    .unwrap()
+            };
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        for pool in self
+            .ready_pools
+            .get_mut()
+            .drain(..)
+            .chain(self.full_pools.get_mut().drain(..))
+        {
+            unsafe { device.destroy_descriptor_pool(pool, None) };
+        }
+    }
+
+    fn get_pool(&self, device: &ash::Device) -> vk::DescriptorPool {
+        if let Some(pool) = self.ready_pools.borrow_mut().pop() {
+            return pool;
+        }
+        let sets = self.sets_per_pool.get();
+        self.sets_per_pool
+            .set((sets + sets / 2).min(MAX_SETS_PER_POOL));
+        Self::create_pool(device, sets, &self.ratios)
+    }
+
+    fn create_pool(
+        device: &ash::Device,
+        set_count: u32,
+        ratios: &[PoolSizeRatio],
+    ) -> vk::DescriptorPool {
+        let pool_sizes: Vec<vk::DescriptorPoolSize> = ratios
+            .iter()
+            .map(|ratio| vk::DescriptorPoolSize {
+                ty: ratio.descriptor_type,
+                descriptor_count: ((ratio.ratio * set_count as f32) as u32).max(1),
+            })
+            .collect();
+        log::debug!("Creating Descriptor Pool, Size {}", set_count);
+
+        let create_info = vk::DescriptorPoolCreateInfo::default()
+            .pool_sizes(&pool_sizes)
+            .max_sets(set_count);
+        unsafe { device.create_descriptor_pool(&create_info, None) }.unwrap()
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct LayoutBindingKey {
+    binding: u32,
+    descriptor_type: vk::DescriptorType,
+    count: u32,
+    stages: vk::ShaderStageFlags,
+    flags: vk::DescriptorBindingFlags,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct LayoutKey {
+    flags: vk::DescriptorSetLayoutCreateFlags,
+    bindings: Vec<LayoutBindingKey>,
+}
+
+/// Creates every Descriptor Set Layout only once, Pipelines with the same bindings share their layouts.
+///
+/// The layouts are owned by the cache and destroyed with the `VulkanInstance`, Do not destroy them yourself
+#[derive(Default)]
+pub struct DescriptorLayoutCache {
+    layouts: RefCell<HashMap<LayoutKey, vk::DescriptorSetLayout>>,
+}
+
+impl DescriptorLayoutCache {
+    pub fn get(
+        &self,
+        device: &ash::Device,
+        bindings: &[vk::DescriptorSetLayoutBinding],
+    ) -> vk::DescriptorSetLayout {
+        self.get_with_flags(
+            device,
+            bindings,
+            &[],
+            vk::DescriptorSetLayoutCreateFlags::empty(),
+        )
+    }
+
+    /// Like `get`, With flags for every binding (empty for none) and for the layout, e.g. for update after bind
+    pub fn get_with_flags(
+        &self,
+        device: &ash::Device,
+        bindings: &[vk::DescriptorSetLayoutBinding],
+        binding_flags: &[vk::DescriptorBindingFlags],
+        flags: vk::DescriptorSetLayoutCreateFlags,
+    ) -> vk::DescriptorSetLayout {
+        debug_assert!(binding_flags.is_empty() || binding_flags.len() == bindings.len());
+        let mut key = LayoutKey {
+            flags,
+            bindings: bindings
+                .iter()
+                .enumerate()
+                .map(|(i, b)| LayoutBindingKey {
+                    binding: b.binding,
+                    descriptor_type: b.descriptor_type,
+                    count: b.descriptor_count,
+                    stages: b.stage_flags,
+                    flags: binding_flags.get(i).copied().unwrap_or_default(),
+                })
+                .collect(),
+        };
+        // The order of the bindings does not matter for Vulkan
+        key.bindings.sort_by_key(|b| b.binding);
+
+        *self.layouts.borrow_mut().entry(key).or_insert_with(|| {
+            let mut binding_flags_info = vk::DescriptorSetLayoutBindingFlagsCreateInfo::default()
+                .binding_flags(binding_flags);
+            let mut info = vk::DescriptorSetLayoutCreateInfo::default()
+                .flags(flags)
+                .bindings(bindings);
+            if !binding_flags.is_empty() {
+                info = info.push_next(&mut binding_flags_info);
+            }
+            unsafe { device.create_descriptor_set_layout(&info, None) }.unwrap()
+        })
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        for (_, layout) in self.layouts.get_mut().drain() {
+            unsafe { device.destroy_descriptor_set_layout(layout, None) };
+        }
+    }
+}
+
+enum WriteInfo {
+    Image(usize),
+    Buffer(usize),
+}
+
+/// Collects Descriptor writes and updates a set with them at once
+///
+/// ```ignore
+/// DescriptorWriter::new()
+///     .image(0, texture.image_view, texture.sampler, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+///     .buffer(1, *material_buffer, 0, size, vk::DescriptorType::UNIFORM_BUFFER)
+///     .update(&instance.device, descriptor_set);
+/// ```
+#[derive(Default)]
+pub struct DescriptorWriter {
+    image_infos: Vec<vk::DescriptorImageInfo>,
+    buffer_infos: Vec<vk::DescriptorBufferInfo>,
+    writes: Vec<(u32, vk::DescriptorType, WriteInfo)>,
+}
+
+impl DescriptorWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Writes a `COMBINED_IMAGE_SAMPLER`
+    pub fn image(
+        self,
+        binding: u32,
+        image_view: vk::ImageView,
+        sampler: vk::Sampler,
+        layout: vk::ImageLayout,
+    ) -> Self {
+        self.image_of_type(
+            binding,
+            image_view,
+            sampler,
+            layout,
+            vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+        )
+    }
+
+    /// Writes an `SAMPLED_IMAGE` or `STORAGE_IMAGE`, Or any other image type
+    pub fn image_of_type(
+        mut self,
+        binding: u32,
+        image_view: vk::ImageView,
+        sampler: vk::Sampler,
+        layout: vk::ImageLayout,
+        descriptor_type: vk::DescriptorType,
+    ) -> Self {
+        self.image_infos.push(
+            vk::DescriptorImageInfo::default()
+                .image_view(image_view)
+                .sampler(sampler)
+                .image_layout(layout),
+        );
+        self.writes.push((
+            binding,
+            descriptor_type,
+            WriteInfo::Image(self.image_infos.len() - 1),
+        ));
+        self
+    }
+
+    pub fn sampler(self, binding: u32, sampler: vk::Sampler) -> Self {
+        self.image_of_type(
+            binding,
+            vk::ImageView::null(),
+            sampler,
+            vk::ImageLayout::UNDEFINED,
+            vk::DescriptorType::SAMPLER,
+        )
+    }
+
+    pub fn buffer(
+        mut self,
+        binding: u32,
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        range: vk::DeviceSize,
+        descriptor_type: vk::DescriptorType,
+    ) -> Self {
+        self.buffer_infos.push(
+            vk::DescriptorBufferInfo::default()
+                .buffer(buffer)
+                .offset(offset)
+                .range(range),
+        );
+        self.writes.push((
+            binding,
+            descriptor_type,
+            WriteInfo::Buffer(self.buffer_infos.len() - 1),
+        ));
+        self
+    }
+
+    pub fn update(&self, device: &ash::Device, set: vk::DescriptorSet) {
+        // The infos are only referenced now, So pushing more of them before could not move them
+        let writes: Vec<vk::WriteDescriptorSet> = self
+            .writes
+            .iter()
+            .map(|(binding, descriptor_type, info)| {
+                let write = vk::WriteDescriptorSet::default()
+                    .dst_set(set)
+                    .dst_binding(*binding)
+                    .descriptor_type(*descriptor_type);
+                match info {
+                    WriteInfo::Image(i) => write.image_info(&self.image_infos[*i..=*i]),
+                    WriteInfo::Buffer(i) => write.buffer_info(&self.buffer_infos[*i..=*i]),
+                }
+            })
+            .collect();
+        unsafe { device.update_descriptor_sets(&writes, &[]) };
+    }
+}
@@ -0,0 +1,199 @@
+use std::{cell::Cell, mem::size_of_val, ptr};
+
+use ash::vk;
+
+use crate::{allocator::MemoryAllocator, descriptor::DescriptorAllocator};
+
+/// Size of the Upload Arena of every Frame, Enough for Text, Uniforms and small dynamic Buffers
+pub const UPLOAD_ARENA_SIZE: vk::DeviceSize = 4 * 1024 * 1024;
+
+/// Everything that is needed to record and submit one Frame.
+///
+/// There are `MAX_FRAMES_IN_FLIGHT` of these, Once the fence is signaled the GPU is done with the Frame and all of it can be reused.
+/// This is independent of the Swapchain image, Which one we render into is only known after acquiring
+pub struct FrameContext {
+    pub command_pool: vk::CommandPool,
+    pub command_buffer: vk::CommandBuffer,
+    /// Records the copy of the Frame when a capture was requested, Submitted right after `command_buffer`
+    pub capture_command_buffer: vk::CommandBuffer,
+    pub image_available_semaphore: vk::Semaphore,
+    pub render_finished_semaphore: vk::Semaphore,
+    pub in_flight_fence: vk::Fence,
+    pub upload_arena: UploadArena,
+    /// For Descriptor Sets which are only used in this Frame
+    pub descriptor_allocator: DescriptorAllocator,
+}
+
+impl FrameContext {
+    pub(crate) fn new(
+        device: &ash::Device,
+        allocator: &MemoryAllocator,
+        limits: &vk::PhysicalDeviceLimits,
+        queue_family_index: u32,
+    ) -> Self {
+        let create_info = vk::CommandPoolCreateInfo::default()
+            .queue_family_index(queue_family_index)
+            .flags(vk::CommandPoolCreateFlags::TRANSIENT);
+        let command_pool = unsafe { device.create_command_pool(&create_info, None) }.unwrap();
+
+        let allocate_info = vk::CommandBufferAllocateInfo::default()
+            .command_pool(command_pool)
+            .level(vk::CommandBufferLevel::PRIMARY)
+            .command_buffer_count(2);
+        let command_buffers = unsafe { device.allocate_command_buffers(&allocate_info) }.unwrap();
+
+        let semaphore_info = vk::SemaphoreCreateInfo::default();
+        // Signaled, So waiting on the first use does not block forever
+        let fence_info = vk::FenceCreateInfo::default().flags(vk::FenceCreateFlags::SIGNALED);
+        unsafe {
+            Self {
+                command_pool,
+                command_buffer: command_buffers[0],
+                capture_command_buffer: command_buffers[1],
+                image_available_semaphore: device.create_semaphore(&semaphore_info, None).unwrap(),
+                render_finished_semaphore: device.create_semaphore(&semaphore_info, None).unwrap(),
+                in_flight_fence: device.create_fence(&fence_info, None).unwrap(),
+                upload_arena: UploadArena::new(device, allocator, limits, UPLOAD_ARENA_SIZE),
+                descriptor_allocator: DescriptorAllocator::new(
+                    &DescriptorAllocator::default_ratios(),
+                ),
+            }
+        }
+    }
+
+    /// Waits until the GPU has finished this Frame and resets everything, So it can be recorded again
+    pub(crate) fn begin(&self, device: &ash::Device) {
+        unsafe {
+            device
+                .wait_for_fences(&[self.in_flight_fence], true, u64::MAX)
+                .unwrap();
+            device.reset_fences(&[self.in_flight_fence]).unwrap();
+            device
+                .reset_command_pool(self.command_pool, vk::CommandPoolResetFlags::empty())
+                .unwrap();
+        }
+        self.upload_arena.reset();
+        self.descriptor_allocator.reset(device);
+    }
+
+    pub(crate) fn destroy(&mut self, device: &ash::Device) {
+        unsafe {
+            device.destroy_fence(self.in_flight_fence, None);
+            device.destroy_semaphore(self.render_finished_semaphore, None);
+            device.destroy_semaphore(self.image_available_semaphore, None);
+            device.destroy_command_pool(self.command_pool, None);
+        }
+        self.upload_arena.destroy(device);
+        self.descriptor_allocator.destroy(device);
+    }
+}
+
+/// Where some data ended up in the Upload Arena
+#[derive(Clone, Copy)]
+pub struct UploadAllocation {
+    pub buffer: vk::Buffer,
+    pub offset: vk::DeviceSize,
+    pub size: vk::DeviceSize,
+}
+
+/// A persistently mapped Buffer for data which is only needed for a single Frame.
+///
+/// Allocating just bumps an offset, Everything gets freed at once when the Frame begins again.
+/// It can be used as Vertex, Index, Uniform or Storage buffer
+pub struct UploadArena {
+    buffer: vk::Buffer,
+    memory: vk::DeviceMemory,
+    mapped: *mut u8,
+    size: vk::DeviceSize,
+    // Every allocation is aligned to this, So it is valid as an Uniform or Storage buffer offset
+    alignment: vk::DeviceSize,
+    offset: Cell<vk::DeviceSize>,
+}
+
+impl UploadArena {
+    fn new(
+        device: &ash::Device,
+        allocator: &MemoryAllocator,
+        limits: &vk::PhysicalDeviceLimits,
+        size: vk::DeviceSize,
+    ) -> Self {
+        let buffer_info = vk::BufferCreateInfo::default()
+            .size(size)
+            .usage(
+                vk::BufferUsageFlags::VERTEX_BUFFER
+                    | vk::BufferUsageFlags::INDEX_BUFFER
+                    | vk::BufferUsageFlags::UNIFORM_BUFFER
+                    | vk::BufferUsageFlags::STORAGE_BUFFER,
+            )
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let buffer = unsafe { device.create_buffer(&buffer_info, None) }.unwrap();
+        let memory = allocator.allocate_buffer(
+            device,
+            buffer,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        );
+        let mapped = unsafe {
+            device
+                .map_memory(memory, 0, size, vk::MemoryMapFlags::empty())
+                .unwrap()
+        } as *mut u8;
+
+        let alignment = limits
+            .min_uniform_buffer_offset_alignment
+            .max(limits.min_storage_buffer_offset_alignment)
+            .max(16);
+
+        Self {
+            buffer,
+            memory,
+            mapped,
+            size,
+            alignment,
+            offset: Cell::new(0),
+        }
+    }
+
+    /// Copies the data into the Arena, Returns None when the Arena of this Frame is full
+    pub fn upload<T: Copy>(&self, data: &[T]) -> Option<UploadAllocation> {
+        let size = size_of_val(data) as vk::DeviceSize;
+        let offset = self.offset.get().next_multiple_of(self.alignment);
+        if offset + size > self.size {
+            log::warn!(
+                "Upload Arena is full, {} of {} bytes used",
+                self.offset.get(),
+                self.size
+            );
+            return None;
+        }
+        unsafe {
+            ptr::copy_nonoverlapping(
+                data.as_ptr() as *const u8,
+                self.mapped.add(offset as usize),
+                size as usize,
+            );
+        }
+        self.offset.set(offset + size);
+        Some(UploadAllocation {
+            buffer: self.buffer,
+            offset,
+            size,
+        })
+    }
+
+    /// How many bytes are used in the current Frame
+    pub fn used(&self) -> vk::DeviceSize {
+        self.offset.get()
+    }
+
+    fn reset(&self) {
+        self.offset.set(0);
+    }
+
+    fn destroy(&mut self, device: &ash::Device) {
+        unsafe {
+            device.unmap_memory(self.memory);
+            device.destroy_buffer(self.buffer, None);
+            device.free_memory(self.memory, None);
+        }
+    }
+}
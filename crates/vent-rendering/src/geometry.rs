@@ -0,0 +1,219 @@
+use std::mem::size_of;
+
+use ash::vk;
+
+use crate::{
+    begin_single_time_command, buffer::VulkanBuffer, end_single_time_command,
+    instance::VulkanInstance, Indices,
+};
+
+/// Where a Mesh lives inside the shared buffers of a `GeometryPool`, Exactly what `cmd_draw_indexed` needs
+#[derive(Clone, Copy, Debug)]
+pub struct MeshRange {
+    pub first_index: u32,
+    pub index_count: u32,
+    pub vertex_offset: i32,
+}
+
+/// Axis aligned Bounding Box in Model space
+#[derive(Clone, Copy, Debug)]
+pub struct Bounds {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+impl Bounds {
+    pub fn from_positions<'a>(positions: impl IntoIterator<Item = &'a [f32; 3]>) -> Self {
+        let mut bounds = Self {
+            min: [f32::MAX; 3],
+            max: [f32::MIN; 3],
+        };
+        for position in positions {
+            for ((min, max), value) in bounds.min.iter_mut().zip(&mut bounds.max).zip(position) {
+                *min = min.min(*value);
+                *max = max.max(*value);
+            }
+        }
+        bounds
+    }
+
+    pub fn center(&self) -> [f32; 3] {
+        [
+            (self.min[0] + self.max[0]) * 0.5,
+            (self.min[1] + self.max[1]) * 0.5,
+            (self.min[2] + self.max[2]) * 0.5,
+        ]
+    }
+}
+
+/// One big Vertex and Index buffer shared by many Meshes.
+///
+/// All Meshes in the Pool can be drawn after binding it once, Which is needed to draw them all with
+/// a single `cmd_draw_indexed_indirect_count`. Indices are always stored as `u32`, Vertices of all Meshes must have the same type
+pub struct GeometryPool {
+    pub vertex_buffer: VulkanBuffer,
+    pub index_buffer: VulkanBuffer,
+    vertex_stride: vk::DeviceSize,
+    vertex_capacity: u32,
+    index_capacity: u32,
+    vertex_count: u32,
+    index_count: u32,
+}
+
+impl GeometryPool {
+    pub fn new(
+        instance: &VulkanInstance,
+        vertex_stride: vk::DeviceSize,
+        vertex_capacity: u32,
+        index_capacity: u32,
+    ) -> Self {
+        let vertex_buffer = VulkanBuffer::new(
+            instance,
+            vertex_stride * vertex_capacity as vk::DeviceSize,
+            vk::BufferUsageFlags::VERTEX_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            Some("Geometry Pool Vertices"),
+        );
+        let index_buffer = VulkanBuffer::new(
+            instance,
+            size_of::<u32>() as vk::DeviceSize * index_capacity as vk::DeviceSize,
+            vk::BufferUsageFlags::INDEX_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            Some("Geometry Pool Indices"),
+        );
+        Self {
+            vertex_buffer,
+            index_buffer,
+            vertex_stride,
+            vertex_capacity,
+            index_capacity,
+            vertex_count: 0,
+            index_count: 0,
+        }
+    }
+
+    /// Uploads the Mesh into the Pool, Returns None when the Pool is full
+    pub fn add<V: Copy>(
+        &mut self,
+        instance: &VulkanInstance,
+        vertices: &[V],
+        indices: &Indices,
+        name: Option<&str>,
+    ) -> Option<MeshRange> {
+        assert_eq!(size_of::<V>() as vk::DeviceSize, self.vertex_stride);
+        let indices = indices.to_u32();
+        if self.vertex_count as usize + vertices.len() > self.vertex_capacity as usize
+            || self.index_count as usize + indices.len() > self.index_capacity as usize
+        {
+            log::warn!(
+                "Geometry Pool is full, {} of {} Vertices and {} of {} Indices used",
+                self.vertex_count,
+                self.vertex_capacity,
+                self.index_count,
+                self.index_capacity
+            );
+            return None;
+        }
+
+        let vertex_size = std::mem::size_of_val(vertices) as vk::DeviceSize;
+        let index_size = std::mem::size_of_val(indices.as_slice()) as vk::DeviceSize;
+
+        let mut staging_buf = VulkanBuffer::new(
+            instance,
+            vertex_size + index_size,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            name,
+        );
+        let memory = staging_buf.map(&instance.device, vertex_size + index_size);
+        unsafe {
+            staging_buf.upload_data(memory, vertices, vertex_size);
+            staging_buf.upload_data(
+                memory.wrapping_add(vertex_size as usize),
+                &indices,
+                index_size,
+            );
+        }
+        staging_buf.unmap(&instance.device);
+
+        let command_buffer =
+            begin_single_time_command(&instance.device, instance.global_command_pool);
+        unsafe {
+            let vertex_copy = vk::BufferCopy::default()
+                .size(vertex_size)
+                .dst_offset(self.vertex_count as vk::DeviceSize * self.vertex_stride);
+            instance.device.cmd_copy_buffer(
+                command_buffer,
+                *staging_buf,
+                *self.vertex_buffer,
+                &[vertex_copy],
+            );
+            let index_copy = vk::BufferCopy::default()
+                .size(index_size)
+                .src_offset(vertex_size)
+                .dst_offset(
+                    self.index_count as vk::DeviceSize * size_of::<u32>() as vk::DeviceSize,
+                );
+            instance.device.cmd_copy_buffer(
+                command_buffer,
+                *staging_buf,
+                *self.index_buffer,
+                &[index_copy],
+            );
+        }
+        end_single_time_command(
+            &instance.device,
+            instance.global_command_pool,
+            instance.graphics_queue,
+            command_buffer,
+        );
+        staging_buf.destroy(&instance.device);
+
+        let range = MeshRange {
+            first_index: self.index_count,
+            index_count: indices.len() as u32,
+            vertex_offset: self.vertex_count as i32,
+        };
+        self.vertex_count += vertices.len() as u32;
+        self.index_count += indices.len() as u32;
+        Some(range)
+    }
+
+    pub fn bind(&self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            device.cmd_bind_vertex_buffers2(
+                command_buffer,
+                0,
+                &[*self.vertex_buffer],
+                &[0],
+                None,
+                None,
+            );
+            device.cmd_bind_index_buffer(
+                command_buffer,
+                *self.index_buffer,
+                0,
+                vk::IndexType::UINT32,
+            );
+        }
+    }
+
+    /// Draws a single Mesh of the Pool, The Pool has to be bound
+    pub fn draw(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, range: &MeshRange) {
+        unsafe {
+            device.cmd_draw_indexed(
+                command_buffer,
+                range.index_count,
+                1,
+                range.first_index,
+                range.vertex_offset,
+                0,
+            )
+        };
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        self.vertex_buffer.destroy(device);
+        self.index_buffer.destroy(device);
+    }
+}
@@ -1,37 +1,17 @@
+use std::mem::size_of;
+
 use ash::vk::{self, Extent2D};
 
 use crate::{
-    allocator::MemoryAllocator, begin_single_time_command, buffer::VulkanBuffer, debug,
-    end_single_time_command, instance::VulkanInstance,
+    begin_single_time_command,
+    buffer::VulkanBuffer,
+    debug, end_single_time_command,
+    instance::VulkanInstance,
+    ktx2::{Ktx2Error, Ktx2Texture},
 };
 
 // TODO: Implement Compression/Decompression (e.g KTX)
 
-pub struct DepthImage {
-    pub image: vk::Image,
-    pub image_view: vk::ImageView,
-    pub memory: vk::DeviceMemory,
-}
-
-impl DepthImage {
-    pub fn destroy(&mut self, device: &ash::Device) {
-        unsafe {
-            device.destroy_image_view(self.image_view, None);
-            device.destroy_image(self.image, None);
-            device.free_memory(self.memory, None);
-        }
-    }
-}
-
-pub struct SkyBoxImages {
-    pub right: String,
-    pub left: String,
-    pub top: String,
-    pub bottom: String,
-    pub front: String,
-    pub back: String,
-}
-
 pub struct VulkanImage {
     pub image: vk::Image,
     pub image_view: vk::ImageView,
@@ -64,6 +44,7 @@ impl VulkanImage {
             format,
             image_size,
             1,
+            vk::SampleCountFlags::TYPE_1,
             vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
         );
         if instance.validation {
@@ -116,6 +97,25 @@ impl VulkanImage {
         mipmaps: bool,
         sampler_info: Option<vk::SamplerCreateInfo>,
         name: Option<&str>,
+    ) -> Self {
+        Self::from_image_with_format(
+            instance,
+            image,
+            vk::Format::R8G8B8A8_UNORM,
+            mipmaps,
+            sampler_info,
+            name,
+        )
+    }
+
+    /// Like `from_image`, `format` must be an 8 bit RGBA format. Use `R8G8B8A8_SRGB` for colors, So they are sampled in linear space
+    pub fn from_image_with_format(
+        instance: &VulkanInstance,
+        image: image::DynamicImage,
+        format: vk::Format,
+        mipmaps: bool,
+        sampler_info: Option<vk::SamplerCreateInfo>,
+        name: Option<&str>,
     ) -> Self {
         let image_size = Extent2D {
             width: image.width(),
@@ -150,13 +150,12 @@ impl VulkanImage {
             1
         };
 
-        let format = vk::Format::R8G8B8A8_UNORM;
-
         let image = Self::create_image(
             &instance.device,
             format,
             image_size,
             mip_level,
+            vk::SampleCountFlags::TYPE_1,
             vk::ImageUsageFlags::TRANSFER_DST
                 | vk::ImageUsageFlags::TRANSFER_SRC
                 | vk::ImageUsageFlags::SAMPLED,
@@ -310,52 +309,464 @@ impl VulkanImage {
         }
     }
 
-    pub fn new_depth(
-        device: &ash::Device,
-        allocator: &MemoryAllocator,
-        format: vk::Format,
+    pub fn from_color(
+        instance: &VulkanInstance,
+        color: [u8; 4],
         size: Extent2D,
-    ) -> DepthImage {
-        let image = Self::create_image(
+        name: Option<&str>,
+    ) -> Self {
+        let color_img = image::RgbaImage::from_pixel(size.width, size.height, image::Rgba(color));
+        Self::from_image(
+            instance,
+            image::DynamicImage::ImageRgba8(color_img),
+            false,
+            None,
+            name,
+        )
+    }
+
+    /// A 3D Texture without mipmaps, e.g. a color grading LUT. `format` must have 4 bytes per texel
+    pub fn new_3d(
+        instance: &VulkanInstance,
+        data: &[u8],
+        size: vk::Extent3D,
+        format: vk::Format,
+        sampler_info: Option<vk::SamplerCreateInfo>,
+        name: Option<&str>,
+    ) -> Self {
+        let device = &instance.device;
+        let image_data_size = (size.width * size.height * size.depth * 4) as vk::DeviceSize;
+
+        let mut staging_buffer = VulkanBuffer::new_init(
+            instance,
+            image_data_size,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            data,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            Some(&format!("Staging of {}", name.unwrap_or("Unknown"))),
+        );
+
+        let create_info = vk::ImageCreateInfo::default()
+            .image_type(vk::ImageType::TYPE_3D)
+            .format(format)
+            .extent(size)
+            .mip_levels(1)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let image = unsafe { device.create_image(&create_info, None) }.unwrap();
+        if instance.validation {
+            if let Some(name) = name {
+                debug::set_object_name(&instance.debug_utils_device, image, name)
+            }
+        }
+        let memory = VulkanBuffer::new_image(device, &instance.memory_allocator, image);
+
+        let subresource_range = vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            level_count: 1,
+            layer_count: 1,
+            ..Default::default()
+        };
+        let command_buffer = begin_single_time_command(device, instance.global_command_pool);
+        let to_transfer = [vk::ImageMemoryBarrier2::default()
+            .src_stage_mask(vk::PipelineStageFlags2::TOP_OF_PIPE)
+            .dst_stage_mask(vk::PipelineStageFlags2::TRANSFER)
+            .dst_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+            .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+            .image(image)
+            .subresource_range(subresource_range)];
+        let region = [vk::BufferImageCopy2::default()
+            .image_subresource(
+                vk::ImageSubresourceLayers::default()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .layer_count(1),
+            )
+            .image_extent(size)];
+        let copy_info = vk::CopyBufferToImageInfo2::default()
+            .src_buffer(staging_buffer.buffer)
+            .dst_image(image)
+            .dst_image_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+            .regions(&region);
+        let to_shader = [vk::ImageMemoryBarrier2::default()
+            .src_stage_mask(vk::PipelineStageFlags2::TRANSFER)
+            .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags2::FRAGMENT_SHADER)
+            .dst_access_mask(vk::AccessFlags2::SHADER_READ)
+            .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+            .new_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .image(image)
+            .subresource_range(subresource_range)];
+        unsafe {
+            device.cmd_pipeline_barrier2(
+                command_buffer,
+                &vk::DependencyInfo::default().image_memory_barriers(&to_transfer),
+            );
+            device.cmd_copy_buffer_to_image2(command_buffer, &copy_info);
+            device.cmd_pipeline_barrier2(
+                command_buffer,
+                &vk::DependencyInfo::default().image_memory_barriers(&to_shader),
+            );
+        }
+        end_single_time_command(
+            device,
+            instance.global_command_pool,
+            instance.graphics_queue,
+            command_buffer,
+        );
+        staging_buffer.destroy(device);
+
+        let image_view = Self::create_image_view(
+            image,
             device,
             format,
-            size,
             1,
-            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            1,
+            vk::ImageAspectFlags::COLOR,
+            vk::ImageViewType::TYPE_3D,
+        );
+        let sampler_info = sampler_info.unwrap_or_default();
+        let sampler = unsafe { device.create_sampler(&sampler_info, None).unwrap() };
+
+        Self {
+            image,
+            image_view,
+            sampler,
+            memory,
+        }
+    }
+
+    /// Loads an HDR image, e.g. an equirectangular environment. Stored as 32 bit floats, So nothing gets clamped
+    pub fn from_hdr(
+        instance: &VulkanInstance,
+        image: image::DynamicImage,
+        name: Option<&str>,
+    ) -> Self {
+        let format = vk::Format::R32G32B32A32_SFLOAT;
+        let image_size = Extent2D {
+            width: image.width(),
+            height: image.height(),
+        };
+        let image_data = image.into_rgba32f().into_raw();
+        let mut staging_buffer = VulkanBuffer::new_init(
+            instance,
+            (image_data.len() * size_of::<f32>()) as vk::DeviceSize,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            bytemuck::cast_slice::<f32, u8>(&image_data),
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            Some(&format!("Staging of {}", name.unwrap_or("Unknown"))),
         );
 
-        let memory = VulkanBuffer::new_image(device, allocator, image);
+        let image = Self::create_image(
+            &instance.device,
+            format,
+            image_size,
+            1,
+            vk::SampleCountFlags::TYPE_1,
+            vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
+        );
+        if instance.validation {
+            if let Some(name) = name {
+                debug::set_object_name(&instance.debug_utils_device, image, name)
+            }
+        }
+        let memory = VulkanBuffer::new_image(&instance.device, &instance.memory_allocator, image);
+        Self::copy_buffer_to_image(
+            instance,
+            image,
+            &staging_buffer,
+            instance.global_command_pool,
+            image_size,
+            1,
+            0,
+            1,
+            true,
+        );
+        staging_buffer.destroy(&instance.device);
         let image_view = Self::create_image_view(
             image,
-            device,
+            &instance.device,
             format,
             1,
             1,
-            vk::ImageAspectFlags::DEPTH,
+            vk::ImageAspectFlags::COLOR,
             vk::ImageViewType::TYPE_2D,
         );
 
-        DepthImage {
+        // Wraps around horizontally, But not over the poles
+        let sampler_info = vk::SamplerCreateInfo::default()
+            .address_mode_u(vk::SamplerAddressMode::REPEAT)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR);
+        let sampler = unsafe { instance.device.create_sampler(&sampler_info, None).unwrap() };
+
+        Self {
             image,
             image_view,
+            sampler,
             memory,
         }
     }
 
-    pub fn from_color(
+    /// Uploads a KTX2 texture with all of its mips, Cubemaps get a cube view
+    pub fn from_ktx2(
         instance: &VulkanInstance,
-        color: [u8; 4],
+        texture: &Ktx2Texture,
+        name: Option<&str>,
+    ) -> Result<Self, Ktx2Error> {
+        let device = &instance.device;
+        let properties = unsafe {
+            instance
+                .instance
+                .get_physical_device_format_properties(instance.physical_device, texture.format)
+        };
+        if !properties
+            .optimal_tiling_features
+            .contains(vk::FormatFeatureFlags::SAMPLED_IMAGE)
+        {
+            return Err(Ktx2Error::UnsupportedFormat(texture.format));
+        }
+
+        let mip_levels = texture.levels.len() as u32;
+        let (image, view_type) = if texture.is_cubemap() {
+            (
+                Self::create_cubemap_image(
+                    device,
+                    texture.format,
+                    texture.extent,
+                    mip_levels,
+                    vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
+                ),
+                vk::ImageViewType::CUBE,
+            )
+        } else {
+            (
+                Self::create_image(
+                    device,
+                    texture.format,
+                    texture.extent,
+                    mip_levels,
+                    vk::SampleCountFlags::TYPE_1,
+                    vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
+                ),
+                vk::ImageViewType::TYPE_2D,
+            )
+        };
+        if instance.validation {
+            if let Some(name) = name {
+                debug::set_object_name(&instance.debug_utils_device, image, name)
+            }
+        }
+        let memory = VulkanBuffer::new_image(device, &instance.memory_allocator, image);
+
+        // All levels go into one staging buffer, Every level is one copy of all faces
+        let data = texture.levels.concat();
+        let mut staging_buffer = VulkanBuffer::new_init(
+            instance,
+            data.len() as vk::DeviceSize,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            &data,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            Some(&format!("Staging of {}", name.unwrap_or("Unknown"))),
+        );
+        let mut offset = 0;
+        let regions: Vec<_> = texture
+            .levels
+            .iter()
+            .enumerate()
+            .map(|(level, level_data)| {
+                let region = vk::BufferImageCopy2::default()
+                    .buffer_offset(offset)
+                    .image_subresource(
+                        vk::ImageSubresourceLayers::default()
+                            .aspect_mask(vk::ImageAspectFlags::COLOR)
+                            .mip_level(level as u32)
+                            .layer_count(texture.face_count),
+                    )
+                    .image_extent(vk::Extent3D {
+                        width: (texture.extent.width >> level).max(1),
+                        height: (texture.extent.height >> level).max(1),
+                        depth: 1,
+                    });
+                offset += level_data.len() as vk::DeviceSize;
+                region
+            })
+            .collect();
+
+        let subresource_range = vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            level_count: mip_levels,
+            layer_count: texture.face_count,
+            ..Default::default()
+        };
+        let command_buffer = begin_single_time_command(device, instance.global_command_pool);
+        let to_transfer = [vk::ImageMemoryBarrier2::default()
+            .src_stage_mask(vk::PipelineStageFlags2::TOP_OF_PIPE)
+            .dst_stage_mask(vk::PipelineStageFlags2::TRANSFER)
+            .dst_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+            .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+            .image(image)
+            .subresource_range(subresource_range)];
+        let copy_info = vk::CopyBufferToImageInfo2::default()
+            .src_buffer(staging_buffer.buffer)
+            .dst_image(image)
+            .dst_image_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+            .regions(&regions);
+        let to_shader = [vk::ImageMemoryBarrier2::default()
+            .src_stage_mask(vk::PipelineStageFlags2::TRANSFER)
+            .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+            .dst_stage_mask(
+                vk::PipelineStageFlags2::FRAGMENT_SHADER | vk::PipelineStageFlags2::COMPUTE_SHADER,
+            )
+            .dst_access_mask(vk::AccessFlags2::SHADER_READ)
+            .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+            .new_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .image(image)
+            .subresource_range(subresource_range)];
+        unsafe {
+            device.cmd_pipeline_barrier2(
+                command_buffer,
+                &vk::DependencyInfo::default().image_memory_barriers(&to_transfer),
+            );
+            device.cmd_copy_buffer_to_image2(command_buffer, &copy_info);
+            device.cmd_pipeline_barrier2(
+                command_buffer,
+                &vk::DependencyInfo::default().image_memory_barriers(&to_shader),
+            );
+        }
+        end_single_time_command(
+            device,
+            instance.global_command_pool,
+            instance.graphics_queue,
+            command_buffer,
+        );
+        staging_buffer.destroy(device);
+
+        let image_view = Self::create_image_view(
+            image,
+            device,
+            texture.format,
+            mip_levels,
+            texture.face_count,
+            vk::ImageAspectFlags::COLOR,
+            view_type,
+        );
+        let sampler_info = vk::SamplerCreateInfo::default()
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
+            .max_lod(mip_levels as f32);
+        let sampler = unsafe { device.create_sampler(&sampler_info, None).unwrap() };
+
+        Ok(Self {
+            image,
+            image_view,
+            sampler,
+            memory,
+        })
+    }
+
+    /// An empty image which compute shaders write into, e.g. the cubemaps of Image Based Lighting.
+    /// The view covers all mips and faces, The layout is UNDEFINED until the first barrier
+    pub fn new_storage(
+        instance: &VulkanInstance,
+        format: vk::Format,
         size: Extent2D,
+        mip_levels: u32,
+        cubemap: bool,
         name: Option<&str>,
     ) -> Self {
-        let color_img = image::RgbaImage::from_pixel(size.width, size.height, image::Rgba(color));
-        Self::from_image(
-            instance,
-            image::DynamicImage::ImageRgba8(color_img),
-            false,
-            None,
-            name,
-        )
+        let usage = vk::ImageUsageFlags::STORAGE
+            | vk::ImageUsageFlags::SAMPLED
+            | vk::ImageUsageFlags::TRANSFER_SRC
+            | vk::ImageUsageFlags::TRANSFER_DST;
+        let (image, layer_count, view_type) = if cubemap {
+            (
+                Self::create_cubemap_image(&instance.device, format, size, mip_levels, usage),
+                6,
+                vk::ImageViewType::CUBE,
+            )
+        } else {
+            (
+                Self::create_image(
+                    &instance.device,
+                    format,
+                    size,
+                    mip_levels,
+                    vk::SampleCountFlags::TYPE_1,
+                    usage,
+                ),
+                1,
+                vk::ImageViewType::TYPE_2D,
+            )
+        };
+        if instance.validation {
+            if let Some(name) = name {
+                debug::set_object_name(&instance.debug_utils_device, image, name)
+            }
+        }
+        let memory = VulkanBuffer::new_image(&instance.device, &instance.memory_allocator, image);
+        let image_view = Self::create_image_view(
+            image,
+            &instance.device,
+            format,
+            mip_levels,
+            layer_count,
+            vk::ImageAspectFlags::COLOR,
+            view_type,
+        );
+
+        let sampler_info = vk::SamplerCreateInfo::default()
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
+            .max_lod(mip_levels as f32);
+        let sampler = unsafe { instance.device.create_sampler(&sampler_info, None).unwrap() };
+
+        Self {
+            image,
+            image_view,
+            sampler,
+            memory,
+        }
+    }
+
+    /// A view of a single mip level for storage writes, The faces of a cubemap become a 2D array.
+    /// Has to be destroyed by the caller
+    pub fn create_mip_view(
+        &self,
+        device: &ash::Device,
+        format: vk::Format,
+        mip_level: u32,
+        layer_count: u32,
+    ) -> vk::ImageView {
+        let view_type = if layer_count > 1 {
+            vk::ImageViewType::TYPE_2D_ARRAY
+        } else {
+            vk::ImageViewType::TYPE_2D
+        };
+        let image_view_info = vk::ImageViewCreateInfo::default()
+            .subresource_range(
+                vk::ImageSubresourceRange::default()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .base_mip_level(mip_level)
+                    .level_count(1)
+                    .layer_count(layer_count),
+            )
+            .image(self.image)
+            .format(format)
+            .view_type(view_type);
+
+        unsafe { device.create_image_view(&image_view_info, None) }.unwrap()
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -588,7 +999,7 @@ impl VulkanImage {
         );
     }
 
-    fn create_image_view(
+    pub(crate) fn create_image_view(
         image: vk::Image,
         device: &ash::Device,
         format: vk::Format,
@@ -611,11 +1022,12 @@ impl VulkanImage {
         unsafe { device.create_image_view(&image_view_info, None) }.unwrap()
     }
 
-    fn create_image(
+    pub(crate) fn create_image(
         device: &ash::Device,
         format: vk::Format,
         size: Extent2D,
         mip_level: u32,
+        samples: vk::SampleCountFlags,
         usage: vk::ImageUsageFlags,
     ) -> vk::Image {
         let create_info = vk::ImageCreateInfo::default()
@@ -624,7 +1036,7 @@ impl VulkanImage {
             .extent(size.into())
             .mip_levels(mip_level)
             .array_layers(1)
-            .samples(vk::SampleCountFlags::TYPE_1)
+            .samples(samples)
             .tiling(vk::ImageTiling::OPTIMAL)
             .usage(usage)
             .sharing_mode(vk::SharingMode::EXCLUSIVE);
@@ -5,7 +5,7 @@ use ash::vk::{Extent2D, PushConstantRange, SwapchainKHR};
 use ash::{khr, vk, Entry};
 use raw_window_handle::{HasDisplayHandle, HasWindowHandle};
 
-use std::{default::Default, ffi::CStr};
+use std::{default::Default, ffi::CStr, os::raw::c_char, path::PathBuf};
 
 #[cfg(any(target_os = "macos", target_os = "ios"))]
 use ash::vk::{
@@ -13,53 +13,143 @@ use ash::vk::{
 };
 
 use crate::allocator::MemoryAllocator;
+use crate::capture::PendingCapture;
 use crate::debug::{self, check_validation_layer_support, setup_debug_messenger};
-use crate::image::{DepthImage, VulkanImage};
+use crate::descriptor::{DescriptorAllocator, DescriptorLayoutCache};
+use crate::frame::FrameContext;
+use crate::image::VulkanImage;
 use crate::surface;
 
 pub const MAX_FRAMES_IN_FLIGHT: u8 = 2;
 
+/// Optional Device features we use when they are there, Everything else has a fallback
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DeviceCapabilities {
+    /// Runtime sized, partially bound `sampler2D[]` arrays which can be updated after binding
+    pub descriptor_indexing: bool,
+    /// How many sampled images an update after bind set can hold, 0 without descriptor indexing
+    pub max_bindless_textures: u32,
+    /// `cmd_draw_indexed_indirect_count` with more than one draw, Needed for GPU driven rendering
+    pub draw_indirect_count: bool,
+    /// Sample counts both color and depth attachments support
+    pub msaa_sample_counts: vk::SampleCountFlags,
+}
+
+impl DeviceCapabilities {
+    pub fn query(instance: &ash::Instance, pdevice: vk::PhysicalDevice) -> Self {
+        let mut features_1_2 = vk::PhysicalDeviceVulkan12Features::default();
+        let mut features = vk::PhysicalDeviceFeatures2::default().push_next(&mut features_1_2);
+        unsafe { instance.get_physical_device_features2(pdevice, &mut features) };
+        let multi_draw_indirect = features.features.multi_draw_indirect == vk::TRUE;
+
+        let mut properties_1_2 = vk::PhysicalDeviceVulkan12Properties::default();
+        let mut properties =
+            vk::PhysicalDeviceProperties2::default().push_next(&mut properties_1_2);
+        unsafe { instance.get_physical_device_properties2(pdevice, &mut properties) };
+
+        let descriptor_indexing = features_1_2.descriptor_indexing == vk::TRUE
+            && features_1_2.runtime_descriptor_array == vk::TRUE
+            && features_1_2.shader_sampled_image_array_non_uniform_indexing == vk::TRUE
+            && features_1_2.descriptor_binding_partially_bound == vk::TRUE
+            && features_1_2.descriptor_binding_sampled_image_update_after_bind == vk::TRUE;
+        let draw_indirect_count =
+            features_1_2.draw_indirect_count == vk::TRUE && multi_draw_indirect;
+        let limits = &properties.properties.limits;
+        let msaa_sample_counts =
+            limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts;
+
+        Self {
+            descriptor_indexing,
+            max_bindless_textures: if descriptor_indexing {
+                properties_1_2
+                    .max_per_stage_descriptor_update_after_bind_sampled_images
+                    .min(properties_1_2.max_descriptor_set_update_after_bind_sampled_images)
+            } else {
+                0
+            },
+            draw_indirect_count,
+            msaa_sample_counts,
+        }
+    }
+
+    /// The highest sample count up to the requested one which color and depth attachments both support
+    pub fn clamp_msaa_samples(&self, samples: vk::SampleCountFlags) -> vk::SampleCountFlags {
+        [
+            vk::SampleCountFlags::TYPE_8,
+            vk::SampleCountFlags::TYPE_4,
+            vk::SampleCountFlags::TYPE_2,
+        ]
+        .into_iter()
+        .find(|count| {
+            count.as_raw() <= samples.as_raw() && self.msaa_sample_counts.contains(*count)
+        })
+        .unwrap_or(vk::SampleCountFlags::TYPE_1)
+    }
+}
+
+/// The images we render into, Either from an Swapchain or our own offscreen images when headless
+struct RenderTargets {
+    surface_loader: Option<khr::surface::Instance>,
+    surface: vk::SurfaceKHR,
+    surface_format: vk::SurfaceFormatKHR,
+    surface_resolution: vk::Extent2D,
+    swapchain_loader: Option<khr::swapchain::Device>,
+    swapchain: vk::SwapchainKHR,
+    images: Vec<vk::Image>,
+    image_views: Vec<vk::ImageView>,
+    offscreen_memory: Vec<vk::DeviceMemory>,
+}
+
 pub struct VulkanInstance {
     // Important: Do not drop ash::Entry, As it drops all functions pointers
     pub entry: ash::Entry,
     pub instance: ash::Instance,
     pub physical_device: vk::PhysicalDevice,
     pub device_features: vk::PhysicalDeviceFeatures,
+    pub capabilities: DeviceCapabilities,
     pub device: ash::Device,
 
-    pub surface_loader: khr::surface::Instance,
+    // Both are None when running headless
+    pub surface_loader: Option<khr::surface::Instance>,
     pub surface: vk::SurfaceKHR,
     pub surface_format: vk::SurfaceFormatKHR,
     pub surface_resolution: vk::Extent2D,
 
-    pub swapchain_loader: khr::swapchain::Device,
+    pub swapchain_loader: Option<khr::swapchain::Device>,
     pub swapchain: vk::SwapchainKHR,
 
+    // When headless these are our own offscreen images, not the ones of a swapchain
     pub swapchain_images: Vec<vk::Image>,
     pub swapchain_image_views: Vec<vk::ImageView>,
-    pub frame_buffers: Vec<vk::Framebuffer>,
+    offscreen_memory: Vec<vk::DeviceMemory>,
     pub depth_format: vk::Format,
-    pub depth_image: DepthImage,
+    /// Samples of the scene color and depth, Pipelines created with `RenderingFormats::hdr` use it.
+    /// Changed by the Renderer, Which also creates its Pipelines again
+    pub msaa_samples: vk::SampleCountFlags,
     pub graphics_queue: vk::Queue,
     pub present_queue: vk::Queue,
 
-    pub render_pass: vk::RenderPass,
     pub global_command_pool: vk::CommandPool,
-    pub command_pools: Vec<vk::CommandPool>,
-    pub command_buffers: Vec<vk::CommandBuffer>,
 
-    pub image_available_semaphores: Vec<vk::Semaphore>,
-    pub render_finished_semaphores: Vec<vk::Semaphore>,
-
-    pub in_flight_fences: Vec<vk::Fence>,
-    pub images_in_flight: Vec<vk::Fence>,
+    /// One for every Frame in flight, Not for every Swapchain image
+    pub frames: Vec<FrameContext>,
 
     pub memory_allocator: MemoryAllocator,
+    /// For Descriptor Sets which live as long as their owner, e.g. Materials
+    pub descriptor_allocator: DescriptorAllocator,
+    pub descriptor_layout_cache: DescriptorLayoutCache,
     pub vulkan_version: u32,
 
+    /// Should be used for every Pipeline, Gets saved to disk when the Instance is dropped
+    pub pipeline_cache: vk::PipelineCache,
+    pub(crate) pipeline_cache_path: PathBuf,
+
     pub vsync: bool,
 
-    frame: usize,
+    frame_index: usize,
+    // Set by `request_capture`, The next `submit` records the copy
+    pub(crate) capture_requested: bool,
+    pub(crate) pending_capture: Option<PendingCapture>,
 
     // debugging
     pub validation: bool,
@@ -77,83 +167,22 @@ impl VulkanInstance {
     ) -> Self {
         let entry = unsafe { Entry::load().expect("Failed to load Vulkan Library") };
 
-        let engine_version = vk::make_api_version(
-            0,
-            env!("CARGO_PKG_VERSION_MAJOR").parse().unwrap(),
-            env!("CARGO_PKG_VERSION_MINOR").parse().unwrap(),
-            env!("CARGO_PKG_VERSION_PATCH").parse().unwrap(),
-        );
-        let vulkan_version = match unsafe { entry.try_enumerate_instance_version().unwrap() } {
-            Some(version) => version,
-            None => {
-                log::warn!("Failed to get vulkan instance version, Probably using Vulkan 1.0");
-                vk::API_VERSION_1_0
-            }
-        };
-
-        // We now set validation at runtime, This will be usefull later, We should make this an startup flag so release builds for example could be debugged
-        let validation = cfg!(debug_assertions);
-
-        let app_info = unsafe {
-            vk::ApplicationInfo::default()
-                .application_name(CStr::from_bytes_with_nul_unchecked(
-                    application_name.as_bytes(),
-                ))
-                .application_version(application_version)
-                .engine_name(CStr::from_bytes_with_nul_unchecked(b"Vent-Engine\0"))
-                .engine_version(engine_version)
-                .api_version(vk::API_VERSION_1_3)
-        };
-
         let display_handle = window.display_handle().expect("No Display Handle");
         let window_handle = window.window_handle().expect("No Window Handle");
 
-        let mut extension_names = surface::enumerate_required_extensions(display_handle.as_raw())
-            .expect("Unsupported Surface Extension")
-            .to_vec();
-        if validation {
-            extension_names.push(validation_features::NAME.as_ptr());
-            extension_names.push(debug_utils::NAME.as_ptr());
-        }
+        let surface_extensions = surface::enumerate_required_extensions(display_handle.as_raw())
+            .expect("Unsupported Surface Extension");
 
-        #[cfg(any(target_os = "macos", target_os = "ios"))]
-        {
-            extension_names.push(KhrPortabilityEnumerationFn::name().as_ptr());
-            // Enabling this extension is a requirement when using `VK_KHR_portability_subset`
-            extension_names.push(KhrGetPhysicalDeviceProperties2Fn::name().as_ptr());
-        }
-
-        let create_flags = if cfg!(any(target_os = "macos", target_os = "ios")) {
-            vk::InstanceCreateFlags::ENUMERATE_PORTABILITY_KHR
-        } else {
-            vk::InstanceCreateFlags::default()
-        };
-
-        let (layer_names_ptrs, mut validation_features) = if validation {
-            check_validation_layer_support(&entry);
-            (
-                debug::get_layer_names_and_pointers(),
-                debug::get_validation_features(),
-            )
-        } else {
-            (
-                (Vec::new(), Vec::new()),
-                vk::ValidationFeaturesEXT::default(),
-            )
-        };
-
-        let create_info = vk::InstanceCreateInfo::default()
-            .application_info(&app_info)
-            .enabled_extension_names(&extension_names)
-            .enabled_layer_names(&layer_names_ptrs.1)
-            .flags(create_flags)
-            .push_next(&mut validation_features);
+        // We now set validation at runtime, This will be usefull later, We should make this an startup flag so release builds for example could be debugged
+        let validation = cfg!(debug_assertions);
 
-        let instance = unsafe {
-            entry
-                .create_instance(&create_info, None)
-                .expect("Failed Create Vulkan Instance")
-        };
+        let (instance, vulkan_version) = Self::create_instance(
+            &entry,
+            application_name,
+            application_version,
+            surface_extensions,
+            validation,
+        );
 
         let surface = unsafe {
             surface::create_surface(&entry, &instance, display_handle, window_handle, None)
@@ -162,16 +191,7 @@ impl VulkanInstance {
         let surface_loader = khr::surface::Instance::new(&entry, &instance);
 
         let (pdevice, graphics_queue_family_index, present_queue_family_index) =
-            Self::create_physical_device(&instance, &surface_loader, surface);
-
-        let info = unsafe { instance.get_physical_device_properties(pdevice) };
-        unsafe {
-            log::debug!(
-                "Vulkan {}, Selected graphics device (`{}`)",
-                info.api_version,
-                CStr::from_ptr(info.device_name.as_ptr()).to_string_lossy()
-            );
-        }
+            Self::create_physical_device(&instance, Some((&surface_loader, surface)));
 
         let surface_format =
             unsafe { surface_loader.get_physical_device_surface_formats(pdevice, surface) }
@@ -184,19 +204,9 @@ impl VulkanInstance {
             pdevice,
             graphics_queue_family_index,
             device_features,
+            false,
         );
 
-        let (debug_utils, debug_utils_device, debug_messenger) = if validation {
-            let (utils, device_utils, messenger) =
-                setup_debug_messenger(&entry, &instance, &device);
-            (Some(utils), Some(device_utils), Some(messenger))
-        } else {
-            (None, None, None)
-        };
-
-        let graphics_queue = unsafe { device.get_device_queue(graphics_queue_family_index, 0) };
-        let present_queue = unsafe { device.get_device_queue(present_queue_family_index, 0) };
-
         let swapchain_loader = khr::swapchain::Device::new(&instance, &device);
 
         let (swapchain, surface_resolution) = Self::create_swapchain(
@@ -213,43 +223,230 @@ impl VulkanInstance {
         let (swapchain_image_views, swapchain_images) =
             Self::create_image_views(&device, &swapchain_loader, swapchain, surface_format);
 
-        let depth_format = Self::get_depth_format(&instance, pdevice);
-
-        let render_pass = Self::create_render_pass(&device, surface_format, depth_format);
-
         let memory_allocator = MemoryAllocator::new(unsafe {
             instance.get_physical_device_memory_properties(pdevice)
         });
 
-        let depth_image =
-            VulkanImage::new_depth(&device, &memory_allocator, depth_format, surface_resolution);
-        let frame_buffers = Self::create_frame_buffers(
-            &swapchain_image_views,
-            render_pass,
-            &device,
-            depth_image.image_view,
-            surface_resolution,
+        Self::finish(
+            application_name,
+            entry,
+            instance,
+            pdevice,
+            device_features,
+            device,
+            memory_allocator,
+            (graphics_queue_family_index, present_queue_family_index),
+            RenderTargets {
+                surface_loader: Some(surface_loader),
+                surface,
+                surface_format,
+                surface_resolution,
+                swapchain_loader: Some(swapchain_loader),
+                swapchain,
+                images: swapchain_images,
+                image_views: swapchain_image_views,
+                offscreen_memory: Vec::new(),
+            },
+            vulkan_version,
+            vsync,
+            validation,
+        )
+    }
+
+    /// Whether `new_headless` can succeed here, It needs a Vulkan device (and the validation layers in debug builds).
+    /// Tests rendering headless are skipped otherwise
+    #[doc(hidden)]
+    pub fn headless_available() -> bool {
+        let Ok(entry) = (unsafe { Entry::load() }) else {
+            return false;
+        };
+        if cfg!(debug_assertions) {
+            let layers = unsafe { entry.enumerate_instance_layer_properties() }.unwrap_or_default();
+            let validation = layers
+                .iter()
+                .any(|layer| layer.layer_name_as_c_str() == Ok(c"VK_LAYER_KHRONOS_validation"));
+            if !validation {
+                return false;
+            }
+        }
+        let app_info = vk::ApplicationInfo::default().api_version(vk::API_VERSION_1_3);
+        let create_info = vk::InstanceCreateInfo::default().application_info(&app_info);
+        let Ok(instance) = (unsafe { entry.create_instance(&create_info, None) }) else {
+            return false;
+        };
+        let devices = unsafe { instance.enumerate_physical_devices() }.unwrap_or_default();
+        unsafe { instance.destroy_instance(None) };
+        !devices.is_empty()
+    }
+
+    /// Creates an Instance without any Window, Surface or Swapchain.
+    ///
+    /// Rendering goes into offscreen color images of the given size, They are handed out by `next_image` just like swapchain images,
+    /// `submit` only submits the work without presenting it. This allows rendering on machines without a Display or GPU (e.g. CI using lavapipe)
+    pub fn new_headless(
+        application_name: &String,
+        application_version: u32,
+        size: (u32, u32),
+    ) -> Self {
+        let entry = unsafe { Entry::load().expect("Failed to load Vulkan Library") };
+
+        let validation = cfg!(debug_assertions);
+
+        let (instance, vulkan_version) = Self::create_instance(
+            &entry,
+            application_name,
+            application_version,
+            &[],
+            validation,
         );
-        let (global_command_pool, command_pools) = Self::create_command_pools(
-            &device,
+
+        let (pdevice, graphics_queue_family_index, present_queue_family_index) =
+            Self::create_physical_device(&instance, None);
+
+        let format = Self::get_supported_format(
+            &instance,
+            pdevice,
+            &[
+                vk::Format::R8G8B8A8_SRGB,
+                vk::Format::B8G8R8A8_SRGB,
+                vk::Format::R8G8B8A8_UNORM,
+            ],
+            vk::ImageTiling::OPTIMAL,
+            vk::FormatFeatureFlags::COLOR_ATTACHMENT | vk::FormatFeatureFlags::TRANSFER_SRC,
+        )
+        .expect("No Offscreen Color Format found");
+        let surface_format = vk::SurfaceFormatKHR {
+            format,
+            color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+        };
+        let surface_resolution = vk::Extent2D {
+            width: size.0,
+            height: size.1,
+        };
+
+        let device_features = unsafe { instance.get_physical_device_features(pdevice) };
+
+        let device = Self::create_device(
+            &instance,
+            pdevice,
             graphics_queue_family_index,
-            swapchain_images.len(),
+            device_features,
+            true,
         );
-        let command_buffers =
-            Self::allocate_command_buffers(&device, &command_pools, swapchain_images.len());
 
-        let (
-            image_available_semaphores,
-            render_finished_semaphores,
-            in_flight_fences,
-            images_in_flight,
-        ) = Self::create_sync_objects(&device, &swapchain_images);
+        let memory_allocator = MemoryAllocator::new(unsafe {
+            instance.get_physical_device_memory_properties(pdevice)
+        });
+
+        let (offscreen_images, offscreen_image_views, offscreen_memory) =
+            Self::create_offscreen_images(
+                &device,
+                &memory_allocator,
+                surface_format.format,
+                surface_resolution,
+            );
+
+        Self::finish(
+            application_name,
+            entry,
+            instance,
+            pdevice,
+            device_features,
+            device,
+            memory_allocator,
+            (graphics_queue_family_index, present_queue_family_index),
+            RenderTargets {
+                surface_loader: None,
+                surface: vk::SurfaceKHR::null(),
+                surface_format,
+                surface_resolution,
+                swapchain_loader: None,
+                swapchain: vk::SwapchainKHR::null(),
+                images: offscreen_images,
+                image_views: offscreen_image_views,
+                offscreen_memory,
+            },
+            vulkan_version,
+            false,
+            validation,
+        )
+    }
+
+    /// Everything which is shared between an Windowed and an Headless Instance
+    #[allow(clippy::too_many_arguments)]
+    fn finish(
+        application_name: &str,
+        entry: ash::Entry,
+        instance: ash::Instance,
+        pdevice: vk::PhysicalDevice,
+        device_features: vk::PhysicalDeviceFeatures,
+        device: ash::Device,
+        memory_allocator: MemoryAllocator,
+        queue_family_indices: (u32, u32),
+        targets: RenderTargets,
+        vulkan_version: u32,
+        vsync: bool,
+        validation: bool,
+    ) -> Self {
+        let (graphics_queue_family_index, present_queue_family_index) = queue_family_indices;
+        let RenderTargets {
+            surface_loader,
+            surface,
+            surface_format,
+            surface_resolution,
+            swapchain_loader,
+            swapchain,
+            images: swapchain_images,
+            image_views: swapchain_image_views,
+            offscreen_memory,
+        } = targets;
+
+        let info = unsafe { instance.get_physical_device_properties(pdevice) };
+        let capabilities = DeviceCapabilities::query(&instance, pdevice);
+        log::debug!("Device capabilities: {:?}", capabilities);
+        unsafe {
+            log::debug!(
+                "Vulkan {}, Selected graphics device (`{}`)",
+                info.api_version,
+                CStr::from_ptr(info.device_name.as_ptr()).to_string_lossy()
+            );
+        }
+
+        let (pipeline_cache, pipeline_cache_path) = Self::load_pipeline_cache(&device, &info, application_name);
+
+        let (debug_utils, debug_utils_device, debug_messenger) = if validation {
+            let (utils, device_utils, messenger) =
+                setup_debug_messenger(&entry, &instance, &device);
+            (Some(utils), Some(device_utils), Some(messenger))
+        } else {
+            (None, None, None)
+        };
+
+        let graphics_queue = unsafe { device.get_device_queue(graphics_queue_family_index, 0) };
+        let present_queue = unsafe { device.get_device_queue(present_queue_family_index, 0) };
+
+        let depth_format = Self::get_depth_format(&instance, pdevice);
+
+        let global_command_pool =
+            Self::create_global_command_pool(&device, graphics_queue_family_index);
+
+        let frames = (0..MAX_FRAMES_IN_FLIGHT)
+            .map(|_| {
+                FrameContext::new(
+                    &device,
+                    &memory_allocator,
+                    &info.limits,
+                    graphics_queue_family_index,
+                )
+            })
+            .collect();
 
         Self {
             entry,
             instance,
             physical_device: pdevice,
             device_features,
+            capabilities,
             device,
             surface_loader,
             surface,
@@ -259,23 +456,23 @@ impl VulkanInstance {
             swapchain,
             swapchain_images,
             swapchain_image_views,
-            frame_buffers,
+            offscreen_memory,
             depth_format,
-            depth_image,
+            msaa_samples: vk::SampleCountFlags::TYPE_1,
             graphics_queue,
             present_queue,
-            render_pass,
             global_command_pool,
-            command_pools,
-            command_buffers,
-            image_available_semaphores,
-            render_finished_semaphores,
-            in_flight_fences,
-            images_in_flight,
+            frames,
             memory_allocator,
+            descriptor_allocator: DescriptorAllocator::new(&DescriptorAllocator::default_ratios()),
+            descriptor_layout_cache: DescriptorLayoutCache::default(),
             vulkan_version,
+            pipeline_cache,
+            pipeline_cache_path,
             vsync,
-            frame: 0,
+            frame_index: 0,
+            capture_requested: false,
+            pending_capture: None,
             validation,
             debug_utils,
             debug_utils_device,
@@ -283,24 +480,140 @@ impl VulkanInstance {
         }
     }
 
+    fn create_instance(
+        entry: &Entry,
+        application_name: &String,
+        application_version: u32,
+        surface_extensions: &[*const c_char],
+        validation: bool,
+    ) -> (ash::Instance, u32) {
+        let engine_version = vk::make_api_version(
+            0,
+            env!("CARGO_PKG_VERSION_MAJOR").parse().unwrap(),
+            env!("CARGO_PKG_VERSION_MINOR").parse().unwrap(),
+            env!("CARGO_PKG_VERSION_PATCH").parse().unwrap(),
+        );
+        let vulkan_version = match unsafe { entry.try_enumerate_instance_version().unwrap() } {
+            Some(version) => version,
+            None => {
+                log::warn!("Failed to get vulkan instance version, Probably using Vulkan 1.0");
+                vk::API_VERSION_1_0
+            }
+        };
+
+        let app_info = unsafe {
+            vk::ApplicationInfo::default()
+                .application_name(CStr::from_bytes_with_nul_unchecked(
+                    application_name.as_bytes(),
+                ))
+                .application_version(application_version)
+                .engine_name(CStr::from_bytes_with_nul_unchecked(b"Vent-Engine\0"))
+                .engine_version(engine_version)
+                .api_version(vk::API_VERSION_1_3)
+        };
+
+        let mut extension_names = surface_extensions.to_vec();
+        if validation {
+            extension_names.push(validation_features::NAME.as_ptr());
+            extension_names.push(debug_utils::NAME.as_ptr());
+        }
+
+        #[cfg(any(target_os = "macos", target_os = "ios"))]
+        {
+            extension_names.push(KhrPortabilityEnumerationFn::name().as_ptr());
+            // Enabling this extension is a requirement when using `VK_KHR_portability_subset`
+            extension_names.push(KhrGetPhysicalDeviceProperties2Fn::name().as_ptr());
+        }
+
+        let create_flags = if cfg!(any(target_os = "macos", target_os = "ios")) {
+            vk::InstanceCreateFlags::ENUMERATE_PORTABILITY_KHR
+        } else {
+            vk::InstanceCreateFlags::default()
+        };
+
+        let (layer_names_ptrs, mut validation_features) = if validation {
+            check_validation_layer_support(entry);
+            (
+                debug::get_layer_names_and_pointers(),
+                debug::get_validation_features(),
+            )
+        } else {
+            (
+                (Vec::new(), Vec::new()),
+                vk::ValidationFeaturesEXT::default(),
+            )
+        };
+
+        let create_info = vk::InstanceCreateInfo::default()
+            .application_info(&app_info)
+            .enabled_extension_names(&extension_names)
+            .enabled_layer_names(&layer_names_ptrs.1)
+            .flags(create_flags)
+            .push_next(&mut validation_features);
+
+        let instance = unsafe {
+            entry
+                .create_instance(&create_info, None)
+                .expect("Failed Create Vulkan Instance")
+        };
+        (instance, vulkan_version)
+    }
+
+    /// Returns true when there is no Surface/Swapchain and we render into offscreen images
+    #[inline]
+    pub fn is_headless(&self) -> bool {
+        self.swapchain_loader.is_none()
+    }
+
+    /// The layout the color images have to be in once a frame is rendered
+    #[inline]
+    pub const fn color_final_layout(headless: bool) -> vk::ImageLayout {
+        if headless {
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL
+        } else {
+            vk::ImageLayout::PRESENT_SRC_KHR
+        }
+    }
+
+    /// Index of the Frame in flight which is currently recorded, Goes from 0 to `MAX_FRAMES_IN_FLIGHT - 1`.
+    /// Use this for per frame resources, The Swapchain image index can be in any order
+    #[inline]
+    pub fn frame_index(&self) -> usize {
+        self.frame_index
+    }
+
+    #[inline]
+    pub fn current_frame(&self) -> &FrameContext {
+        &self.frames[self.frame_index]
+    }
+
+    // Waits until the current frame can be reused,
     // returns the next image's index and whether the swapchain is suboptimal for the surface.
     pub fn next_image(&self) -> VkResult<(u32, bool)> {
-        let in_flight_fence = self.in_flight_fences[self.frame];
-
+        let frame = self.current_frame();
+        let Some(swapchain_loader) = &self.swapchain_loader else {
+            frame.begin(&self.device);
+            // Headless, We have one offscreen image per frame in flight
+            return Ok((self.frame_index as u32, false));
+        };
         unsafe {
             self.device
-                .wait_for_fences(&[in_flight_fence], true, u64::MAX)
+                .wait_for_fences(&[frame.in_flight_fence], true, u64::MAX)
                 .unwrap();
-            self.device.reset_fences(&[in_flight_fence]).unwrap();
         }
-        unsafe {
-            self.swapchain_loader.acquire_next_image(
+        let result = unsafe {
+            swapchain_loader.acquire_next_image(
                 self.swapchain,
                 u64::MAX,
-                self.image_available_semaphores[self.frame],
+                frame.image_available_semaphore,
                 vk::Fence::null(),
             )
+        };
+        // Only reset when we are going to submit, Otherwise the next wait on the fence would never return
+        if result.is_ok() {
+            frame.begin(&self.device);
         }
+        result
     }
 
     pub fn recreate_swap_chain(&mut self, new_size: Option<(u32, u32)>) {
@@ -311,44 +624,50 @@ impl VulkanInstance {
         unsafe {
             self.device.device_wait_idle().unwrap();
 
-            let (swapchain, surface_resolution) = Self::create_swapchain(
-                &self.swapchain_loader,
-                self.surface_format,
-                &self.surface_loader,
-                self.physical_device,
-                self.vsync,
-                self.surface,
-                new_size,
-                Some(self.swapchain),
-            );
-            // We reuse the old Swapchain and then deleting it
-            self.clean_swapchain();
-
-            self.swapchain = swapchain;
-            self.surface_resolution = surface_resolution;
-
-            (self.swapchain_image_views, self.swapchain_images) = Self::create_image_views(
-                &self.device,
-                &self.swapchain_loader,
-                self.swapchain,
-                self.surface_format,
-            );
-
-            self.depth_image.destroy(&self.device);
-            self.depth_image = VulkanImage::new_depth(
-                &self.device,
-                &self.memory_allocator,
-                self.depth_format,
-                surface_resolution,
-            );
-
-            self.frame_buffers = Self::create_frame_buffers(
-                &self.swapchain_image_views,
-                self.render_pass,
-                &self.device,
-                self.depth_image.image_view,
-                self.surface_resolution,
-            );
+            match (&self.swapchain_loader, &self.surface_loader) {
+                (Some(swapchain_loader), Some(surface_loader)) => {
+                    let (swapchain, surface_resolution) = Self::create_swapchain(
+                        swapchain_loader,
+                        self.surface_format,
+                        surface_loader,
+                        self.physical_device,
+                        self.vsync,
+                        self.surface,
+                        new_size,
+                        Some(self.swapchain),
+                    );
+                    // We reuse the old Swapchain and then deleting it
+                    self.clean_swapchain();
+
+                    self.swapchain = swapchain;
+                    self.surface_resolution = surface_resolution;
+
+                    (self.swapchain_image_views, self.swapchain_images) = Self::create_image_views(
+                        &self.device,
+                        self.swapchain_loader.as_ref().unwrap(),
+                        self.swapchain,
+                        self.surface_format,
+                    );
+                }
+                _ => {
+                    self.clean_swapchain();
+
+                    self.surface_resolution = vk::Extent2D {
+                        width: new_size.0,
+                        height: new_size.1,
+                    };
+                    (
+                        self.swapchain_images,
+                        self.swapchain_image_views,
+                        self.offscreen_memory,
+                    ) = Self::create_offscreen_images(
+                        &self.device,
+                        &self.memory_allocator,
+                        self.surface_format.format,
+                        self.surface_resolution,
+                    );
+                }
+            }
         }
     }
 
@@ -356,59 +675,121 @@ impl VulkanInstance {
      * Returns if should resize
      */
     pub fn submit(&mut self, image_index: u32) -> VkResult<bool> {
-        let in_flight_fence = self.in_flight_fences[self.frame];
+        let capture_command_buffer = if self.capture_requested {
+            self.capture_requested = false;
+            Some(self.record_capture(image_index))
+        } else {
+            None
+        };
+        let frame = &self.frames[self.frame_index];
 
         let wait_semaphores = vk::SemaphoreSubmitInfo::default()
-            .semaphore(self.image_available_semaphores[self.frame])
+            .semaphore(frame.image_available_semaphore)
             .stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT);
 
-        let command_buffers = vk::CommandBufferSubmitInfo::default()
-            .command_buffer(self.command_buffers[image_index as usize]);
         let signal_semaphores = vk::SemaphoreSubmitInfo::default()
-            .semaphore(self.render_finished_semaphores[self.frame])
+            .semaphore(frame.render_finished_semaphore)
             .stage_mask(vk::PipelineStageFlags2::ALL_GRAPHICS);
 
-        let signal_infos = [signal_semaphores];
-        let command_infos = [command_buffers];
-        let wait_infos = [wait_semaphores];
+        let headless = self.is_headless();
+        // When headless nothing gets acquired or presented, So there is nothing to wait for or to signal
+        let signal_infos = if headless {
+            &[][..]
+        } else {
+            &[signal_semaphores][..]
+        };
+        let wait_infos = if headless {
+            &[][..]
+        } else {
+            &[wait_semaphores][..]
+        };
+        let command_infos: Vec<_> = std::iter::once(frame.command_buffer)
+            .chain(capture_command_buffer)
+            .map(|command_buffer| {
+                vk::CommandBufferSubmitInfo::default().command_buffer(command_buffer)
+            })
+            .collect();
         let submit_info = vk::SubmitInfo2::default()
-            .wait_semaphore_infos(&wait_infos)
+            .wait_semaphore_infos(wait_infos)
             .command_buffer_infos(&command_infos)
-            .signal_semaphore_infos(&signal_infos);
+            .signal_semaphore_infos(signal_infos);
 
         unsafe {
             self.device
-                .queue_submit2(self.graphics_queue, &[submit_info], in_flight_fence)
+                .queue_submit2(self.graphics_queue, &[submit_info], frame.in_flight_fence)
                 .unwrap();
         }
 
         let swapchains = &[self.swapchain];
         let image_indices = &[image_index];
-        let binding = [self.render_finished_semaphores[self.frame]];
+        let binding = [frame.render_finished_semaphore];
         let present_info = vk::PresentInfoKHR::default()
             .wait_semaphores(&binding)
             .swapchains(swapchains)
             .image_indices(image_indices);
 
-        self.frame = (self.frame + 1) % MAX_FRAMES_IN_FLIGHT as usize;
+        self.frame_index = (self.frame_index + 1) % MAX_FRAMES_IN_FLIGHT as usize;
 
-        unsafe {
-            self.swapchain_loader
-                .queue_present(self.present_queue, &present_info)
+        match &self.swapchain_loader {
+            Some(swapchain_loader) => unsafe {
+                swapchain_loader.queue_present(self.present_queue, &present_info)
+            },
+            None => Ok(false),
         }
     }
 
     unsafe fn clean_swapchain(&mut self) {
-        self.frame_buffers
-            .drain(..)
-            .for_each(|f| self.device.destroy_framebuffer(f, None));
-
         self.swapchain_image_views
             .drain(..)
             .for_each(|v| self.device.destroy_image_view(v, None));
 
-        self.swapchain_loader
-            .destroy_swapchain(self.swapchain, None);
+        match &self.swapchain_loader {
+            Some(swapchain_loader) => swapchain_loader.destroy_swapchain(self.swapchain, None),
+            None => {
+                // We own the offscreen images, Swapchain images are owned by the Swapchain
+                self.swapchain_images
+                    .drain(..)
+                    .for_each(|i| self.device.destroy_image(i, None));
+                self.offscreen_memory
+                    .drain(..)
+                    .for_each(|m| self.device.free_memory(m, None));
+            }
+        }
+    }
+
+    fn create_offscreen_images(
+        device: &ash::Device,
+        allocator: &MemoryAllocator,
+        format: vk::Format,
+        size: Extent2D,
+    ) -> (Vec<vk::Image>, Vec<vk::ImageView>, Vec<vk::DeviceMemory>) {
+        let (mut images, mut image_views, mut memory) = (Vec::new(), Vec::new(), Vec::new());
+        for _ in 0..MAX_FRAMES_IN_FLIGHT {
+            let image = VulkanImage::create_image(
+                device,
+                format,
+                size,
+                1,
+                vk::SampleCountFlags::TYPE_1,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC,
+            );
+            memory.push(allocator.allocate_image(
+                device,
+                image,
+                vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            ));
+            image_views.push(VulkanImage::create_image_view(
+                image,
+                device,
+                format,
+                1,
+                1,
+                vk::ImageAspectFlags::COLOR,
+                vk::ImageViewType::TYPE_2D,
+            ));
+            images.push(image);
+        }
+        (images, image_views, memory)
     }
 
     fn get_depth_format(instance: &ash::Instance, pdevice: vk::PhysicalDevice) -> vk::Format {
@@ -452,37 +833,10 @@ impl VulkanInstance {
             .copied()
     }
 
-    fn create_frame_buffers(
-        swapchain_image_views: &[vk::ImageView],
-        render_pass: vk::RenderPass,
-        device: &ash::Device,
-        depth_image_view: vk::ImageView,
-        surface_resolution: Extent2D,
-    ) -> Vec<vk::Framebuffer> {
-        swapchain_image_views
-            .iter()
-            .map(|&present_image_view| {
-                let framebuffer_attachments = [present_image_view, depth_image_view];
-                let frame_buffer_create_info = vk::FramebufferCreateInfo::default()
-                    .render_pass(render_pass)
-                    .attachments(&framebuffer_attachments)
-                    .width(surface_resolution.width)
-                    .height(surface_resolution.height)
-                    .layers(1);
-
-                unsafe {
-                    device
-                        .create_framebuffer(&frame_buffer_create_info, None)
-                        .unwrap()
-                }
-            })
-            .collect::<Vec<vk::Framebuffer>>()
-    }
-
+    /// When no surface is given (headless) the graphics queue is also used as present queue
     fn create_physical_device(
         instance: &ash::Instance,
-        surface_loader: &khr::surface::Instance,
-        surface: vk::SurfaceKHR,
+        surface: Option<(&khr::surface::Instance, vk::SurfaceKHR)>,
     ) -> (vk::PhysicalDevice, u32, u32) {
         let pdevices = unsafe {
             instance
@@ -498,6 +852,13 @@ impl VulkanInstance {
                 .position(|p| p.queue_flags.contains(vk::QueueFlags::GRAPHICS))
                 .map(|i| i as u32);
 
+            let Some((surface_loader, surface)) = surface else {
+                if let Some(graphics) = graphics {
+                    return (pdevice, graphics, graphics);
+                }
+                continue;
+            };
+
             let mut present = None;
             for (index, _properties) in properties.iter().enumerate() {
                 if unsafe {
@@ -526,19 +887,38 @@ impl VulkanInstance {
         pdevice: vk::PhysicalDevice,
         queue_family_index: u32,
         available_features: vk::PhysicalDeviceFeatures,
+        headless: bool,
     ) -> ash::Device {
-        let device_extension_names_raw = [
-            swapchain::NAME.as_ptr(),
+        let mut device_extension_names_raw = vec![
             #[cfg(any(target_os = "macos", target_os = "ios"))]
             portability_subset::NAME.as_ptr(),
         ];
+        if !headless {
+            device_extension_names_raw.push(swapchain::NAME.as_ptr());
+        }
 
         let mut features_1_3 = vk::PhysicalDeviceVulkan13Features::default()
             .synchronization2(true)
+            .dynamic_rendering(true)
             .maintenance4(true);
 
+        let capabilities = DeviceCapabilities::query(instance, pdevice);
+        let mut features_1_2 = vk::PhysicalDeviceVulkan12Features::default();
+        if capabilities.descriptor_indexing {
+            features_1_2 = features_1_2
+                .descriptor_indexing(true)
+                .runtime_descriptor_array(true)
+                .shader_sampled_image_array_non_uniform_indexing(true)
+                .descriptor_binding_partially_bound(true)
+                .descriptor_binding_sampled_image_update_after_bind(true);
+        }
+        if capabilities.draw_indirect_count {
+            features_1_2 = features_1_2.draw_indirect_count(true);
+        }
+
         let features = vk::PhysicalDeviceFeatures {
             sampler_anisotropy: available_features.sampler_anisotropy,
+            multi_draw_indirect: capabilities.draw_indirect_count.into(),
             ..Default::default()
         };
         //        features.shader_clip_distance = available_features.shader_clip_distance;
@@ -553,7 +933,8 @@ impl VulkanInstance {
             .queue_create_infos(std::slice::from_ref(&queue_info))
             .enabled_extension_names(&device_extension_names_raw)
             .enabled_features(&features)
-            .push_next(&mut features_1_3);
+            .push_next(&mut features_1_3)
+            .push_next(&mut features_1_2);
 
         unsafe { instance.create_device(pdevice, &device_create_info, None) }.unwrap()
     }
@@ -619,13 +1000,17 @@ impl VulkanInstance {
             log::warn!("Swapchain: wanted mode is not supported, Using FIFO");
         }
 
+        // Transfer source is needed to capture frames
+        let image_usage = vk::ImageUsageFlags::COLOR_ATTACHMENT
+            | (surface_capabilities.supported_usage_flags & vk::ImageUsageFlags::TRANSFER_SRC);
+
         let swapchain_create_info = vk::SwapchainCreateInfoKHR::default()
             .surface(surface)
             .min_image_count(desired_image_count)
             .image_color_space(surface_format.color_space)
             .image_format(surface_format.format)
             .image_extent(surface_resolution)
-            .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
+            .image_usage(image_usage)
             .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
             .pre_transform(pre_transform)
             .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
@@ -640,76 +1025,15 @@ impl VulkanInstance {
         )
     }
 
-    fn create_command_pools(
+    /// Used for one time commands like uploads, Frames have their own pools
+    fn create_global_command_pool(
         device: &ash::Device,
         queue_family_index: u32,
-        count: usize,
-    ) -> (vk::CommandPool, Vec<vk::CommandPool>) {
+    ) -> vk::CommandPool {
         let create_info = vk::CommandPoolCreateInfo::default()
             .queue_family_index(queue_family_index)
             .flags(vk::CommandPoolCreateFlags::TRANSIENT);
-        let global = unsafe { device.create_command_pool(&create_info, None) }.unwrap();
-
-        let mut pools: Vec<_> = Vec::with_capacity(count);
-        for _ in 0..count {
-            let create_info = vk::CommandPoolCreateInfo::default()
-                .queue_family_index(queue_family_index)
-                .flags(vk::CommandPoolCreateFlags::TRANSIENT);
-            pools.push(unsafe { device.create_command_pool(&create_info, None) }.unwrap());
-        }
-        (global, pools)
-    }
-
-    fn allocate_command_buffers(
-        device: &ash::Device,
-        command_pools: &[vk::CommandPool],
-        count: usize,
-    ) -> Vec<vk::CommandBuffer> {
-        let mut buffers = Vec::with_capacity(count);
-        for pool in command_pools.iter().take(count) {
-            let allocate_info = vk::CommandBufferAllocateInfo::default()
-                .command_pool(*pool)
-                .level(vk::CommandBufferLevel::PRIMARY)
-                .command_buffer_count(1);
-
-            buffers.push(unsafe { device.allocate_command_buffers(&allocate_info) }.unwrap()[0]);
-        }
-        buffers
-    }
-
-    fn create_sync_objects(
-        device: &ash::Device,
-        swapchain_images: &[vk::Image],
-    ) -> (
-        Vec<vk::Semaphore>,
-        Vec<vk::Semaphore>,
-        Vec<vk::Fence>,
-        Vec<vk::Fence>,
-    ) {
-        let (mut image_available_semaphores, mut render_finished_semaphores, mut in_flight_fences) =
-            (Vec::new(), Vec::new(), Vec::new());
-
-        let create_info = vk::SemaphoreCreateInfo::default();
-
-        let fence_info = vk::FenceCreateInfo::default().flags(vk::FenceCreateFlags::SIGNALED);
-
-        for _ in 0..MAX_FRAMES_IN_FLIGHT {
-            image_available_semaphores
-                .push(unsafe { device.create_semaphore(&create_info, None) }.unwrap());
-            render_finished_semaphores
-                .push(unsafe { device.create_semaphore(&create_info, None) }.unwrap());
-
-            in_flight_fences.push(unsafe { device.create_fence(&fence_info, None) }.unwrap());
-        }
-
-        let images_in_flight = swapchain_images.iter().map(|_| vk::Fence::null()).collect();
-
-        (
-            image_available_semaphores,
-            render_finished_semaphores,
-            in_flight_fences,
-            images_in_flight,
-        )
+        unsafe { device.create_command_pool(&create_info, None) }.unwrap()
     }
 
     fn create_image_views(
@@ -747,20 +1071,6 @@ impl VulkanInstance {
         )
     }
 
-    pub fn allocate_descriptor_sets(
-        device: &ash::Device,
-        descriptor_pool: vk::DescriptorPool,
-        descriptor_set_layout: vk::DescriptorSetLayout,
-        size: usize,
-    ) -> Vec<vk::DescriptorSet> {
-        let layouts = (0..size).map(|_| descriptor_set_layout).collect::<Vec<_>>();
-        let info = vk::DescriptorSetAllocateInfo::default()
-            .descriptor_pool(descriptor_pool)
-            .set_layouts(&layouts);
-
-        unsafe { device.allocate_descriptor_sets(&info) }.unwrap()
-    }
-
     pub fn create_pipeline_layout(
         &self,
         push_constant_ranges: &[PushConstantRange],
@@ -772,79 +1082,6 @@ impl VulkanInstance {
 
         unsafe { self.device.create_pipeline_layout(&create_info, None) }.unwrap()
     }
-
-    fn create_render_pass(
-        device: &ash::Device,
-        surface_format: vk::SurfaceFormatKHR,
-        depth_format: vk::Format,
-    ) -> vk::RenderPass {
-        let renderpass_attachments = [
-            vk::AttachmentDescription2 {
-                format: surface_format.format,
-                samples: vk::SampleCountFlags::TYPE_1,
-                load_op: vk::AttachmentLoadOp::CLEAR,
-                store_op: vk::AttachmentStoreOp::STORE,
-                stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
-                stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
-                initial_layout: vk::ImageLayout::UNDEFINED,
-                final_layout: vk::ImageLayout::PRESENT_SRC_KHR,
-                ..Default::default()
-            },
-            vk::AttachmentDescription2 {
-                format: depth_format,
-                samples: vk::SampleCountFlags::TYPE_1,
-                load_op: vk::AttachmentLoadOp::CLEAR,
-                store_op: vk::AttachmentStoreOp::DONT_CARE,
-                stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
-                stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
-                initial_layout: vk::ImageLayout::UNDEFINED,
-                final_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
-                ..Default::default()
-            },
-        ];
-        let color_attachment_refs = [vk::AttachmentReference2 {
-            attachment: 0,
-            layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
-            ..Default::default()
-        }];
-        let depth_attachment_ref = vk::AttachmentReference2::default()
-            .attachment(1)
-            .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
-
-        let dependencies = [
-            vk::SubpassDependency2 {
-                src_subpass: vk::SUBPASS_EXTERNAL,
-                src_stage_mask: vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
-                    | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
-                dst_stage_mask: vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
-                    | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
-                src_access_mask: vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
-                dst_access_mask: vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
-                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
-                ..Default::default()
-            },
-            vk::SubpassDependency2 {
-                src_subpass: vk::SUBPASS_EXTERNAL,
-                src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-                dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-                dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_READ
-                    | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
-                ..Default::default()
-            },
-        ];
-
-        let subpass = vk::SubpassDescription2::default()
-            .color_attachments(&color_attachment_refs)
-            .depth_stencil_attachment(&depth_attachment_ref)
-            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS);
-
-        let create_info = vk::RenderPassCreateInfo2::default()
-            .attachments(&renderpass_attachments)
-            .subpasses(std::slice::from_ref(&subpass))
-            .dependencies(&dependencies);
-
-        unsafe { device.create_render_pass2(&create_info, None) }.unwrap()
-    }
 }
 
 impl Drop for VulkanInstance {
@@ -852,32 +1089,28 @@ impl Drop for VulkanInstance {
         unsafe {
             self.device.device_wait_idle().unwrap();
 
+            self.destroy_pending_capture();
             self.clean_swapchain();
 
-            self.in_flight_fences
+            self.frames
                 .drain(..)
-                .for_each(|f| self.device.destroy_fence(f, None));
-            self.render_finished_semaphores
-                .drain(..)
-                .for_each(|s| self.device.destroy_semaphore(s, None));
-            self.image_available_semaphores
-                .drain(..)
-                .for_each(|s| self.device.destroy_semaphore(s, None));
-
-            self.device.destroy_render_pass(self.render_pass, None);
+                .for_each(|mut frame| frame.destroy(&self.device));
+            self.device
+                .destroy_command_pool(self.global_command_pool, None);
 
-            self.depth_image.destroy(&self.device);
+            self.descriptor_allocator.destroy(&self.device);
+            self.descriptor_layout_cache.destroy(&self.device);
 
-            self.command_pools
-                .iter()
-                .for_each(|p| self.device.destroy_command_pool(*p, None));
+            self.save_pipeline_cache();
             self.device
-                .destroy_command_pool(self.global_command_pool, None);
+                .destroy_pipeline_cache(self.pipeline_cache, None);
 
             // DEVICE DESTRUCTION
             self.device.destroy_device(None);
 
-            self.surface_loader.destroy_surface(self.surface, None);
+            if let Some(surface_loader) = &self.surface_loader {
+                surface_loader.destroy_surface(self.surface, None);
+            }
 
             if let Some(debug_messenger) = self.debug_messenger {
                 if let Some(debug_utils) = &self.debug_utils {
@@ -890,3 +1123,34 @@ impl Drop for VulkanInstance {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const X1: vk::SampleCountFlags = vk::SampleCountFlags::TYPE_1;
+    const X2: vk::SampleCountFlags = vk::SampleCountFlags::TYPE_2;
+    const X4: vk::SampleCountFlags = vk::SampleCountFlags::TYPE_4;
+    const X8: vk::SampleCountFlags = vk::SampleCountFlags::TYPE_8;
+
+    #[test]
+    fn clamps_msaa_to_counts_of_color_and_depth() {
+        let color = X1 | X2 | X4 | X8;
+        let depth = X1 | X2 | X8;
+        let capabilities = DeviceCapabilities {
+            msaa_sample_counts: color & depth,
+            ..Default::default()
+        };
+
+        assert_eq!(capabilities.clamp_msaa_samples(X8), X8);
+        // Only color supports 4 samples
+        assert_eq!(capabilities.clamp_msaa_samples(X4), X2);
+        assert_eq!(capabilities.clamp_msaa_samples(X2), X2);
+        assert_eq!(capabilities.clamp_msaa_samples(X1), X1);
+        assert_eq!(
+            capabilities.clamp_msaa_samples(vk::SampleCountFlags::TYPE_16),
+            X8
+        );
+        assert_eq!(DeviceCapabilities::default().clamp_msaa_samples(X4), X1);
+    }
+}
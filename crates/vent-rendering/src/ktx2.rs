@@ -0,0 +1,213 @@
+use std::{fmt, path::Path};
+
+use ash::vk;
+
+const IDENTIFIER: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+/// Header and index, The level index follows right after
+const HEADER_SIZE: usize = 80;
+const LEVEL_INDEX_SIZE: usize = 24;
+
+#[derive(Debug)]
+pub enum Ktx2Error {
+    Io(std::io::Error),
+    /// Not a KTX2 file or cut off
+    Invalid,
+    /// Basis Universal or zstd, We only load data which can be copied as is
+    Supercompressed(u32),
+    /// Array and 3D textures
+    Unsupported,
+    /// The GPU can not sample this format, e.g. BC on mobile
+    UnsupportedFormat(vk::Format),
+}
+
+impl fmt::Display for Ktx2Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Ktx2Error::Io(err) => write!(f, "Failed to read KTX2 file: {err}"),
+            Ktx2Error::Invalid => write!(f, "Not a valid KTX2 file"),
+            Ktx2Error::Supercompressed(scheme) => {
+                write!(f, "Supercompression scheme {scheme} is not supported")
+            }
+            Ktx2Error::Unsupported => write!(f, "Only 2D textures and cubemaps are supported"),
+            Ktx2Error::UnsupportedFormat(format) => {
+                write!(f, "Format {format:?} can not be sampled on this GPU")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Ktx2Error {}
+
+impl From<std::io::Error> for Ktx2Error {
+    fn from(err: std::io::Error) -> Self {
+        Ktx2Error::Io(err)
+    }
+}
+
+/// A KTX2 texture as it is stored in the file, The data of every level can be copied into a `vk::Image` as is.
+///
+/// Only 2D textures and cubemaps without supercompression, Block compressed formats are fine
+pub struct Ktx2Texture {
+    pub format: vk::Format,
+    pub extent: vk::Extent2D,
+    /// 6 for cubemaps, 1 otherwise
+    pub face_count: u32,
+    /// Largest level first, Every level contains all faces one after another
+    pub levels: Vec<Vec<u8>>,
+}
+
+impl Ktx2Texture {
+    pub fn load(path: &Path) -> Result<Self, Ktx2Error> {
+        Self::parse(&std::fs::read(path)?)
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Self, Ktx2Error> {
+        if bytes.len() < HEADER_SIZE || bytes[..12] != IDENTIFIER {
+            return Err(Ktx2Error::Invalid);
+        }
+        let read_u32 =
+            |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        let read_u64 =
+            |offset: usize| u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap());
+
+        let format = vk::Format::from_raw(read_u32(12) as i32);
+        let extent = vk::Extent2D {
+            width: read_u32(20),
+            height: read_u32(24),
+        };
+        let depth = read_u32(28);
+        let layer_count = read_u32(32);
+        let face_count = read_u32(36);
+        // 0 means the mips should be generated, We just take the base level
+        let level_count = read_u32(40).max(1) as usize;
+        let supercompression = read_u32(44);
+
+        if supercompression != 0 {
+            return Err(Ktx2Error::Supercompressed(supercompression));
+        }
+        // Basis Universal, It would have to be transcoded first
+        if format == vk::Format::UNDEFINED {
+            return Err(Ktx2Error::UnsupportedFormat(format));
+        }
+        if depth > 1 || layer_count > 1 || !matches!(face_count, 1 | 6) {
+            return Err(Ktx2Error::Unsupported);
+        }
+        let index_end = level_count
+            .checked_mul(LEVEL_INDEX_SIZE)
+            .and_then(|size| size.checked_add(HEADER_SIZE));
+        if index_end.is_none_or(|end| bytes.len() < end) {
+            return Err(Ktx2Error::Invalid);
+        }
+
+        let levels = (0..level_count)
+            .map(|level| {
+                let index = HEADER_SIZE + level * LEVEL_INDEX_SIZE;
+                // Taken from the file, So they may point anywhere
+                let offset = usize::try_from(read_u64(index)).ok();
+                let length = usize::try_from(read_u64(index + 8)).ok();
+                offset
+                    .zip(length)
+                    .and_then(|(offset, length)| Some(offset..offset.checked_add(length)?))
+                    .and_then(|range| bytes.get(range))
+                    .map(<[u8]>::to_vec)
+                    .ok_or(Ktx2Error::Invalid)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            format,
+            extent,
+            face_count,
+            levels,
+        })
+    }
+
+    pub fn is_cubemap(&self) -> bool {
+        self.face_count == 6
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 1x1 RGBA8 cubemap with a single level, Every face has its own color
+    fn cubemap() -> Vec<u8> {
+        let mut bytes = IDENTIFIER.to_vec();
+        let header = [
+            vk::Format::R8G8B8A8_UNORM.as_raw() as u32,
+            1, // Type size
+            1, // Width
+            1, // Height
+            0, // Depth
+            0, // Layers
+            6, // Faces
+            1, // Levels
+            0, // Supercompression
+        ];
+        header
+            .iter()
+            .for_each(|value| bytes.extend(value.to_le_bytes()));
+        // Data format descriptor, Key/value data and supercompression global data are empty
+        bytes.resize(HEADER_SIZE, 0);
+
+        let data_offset = (HEADER_SIZE + LEVEL_INDEX_SIZE) as u64;
+        let faces: Vec<u8> = (0..6).flat_map(|face| [face, face, face, 255]).collect();
+        bytes.extend(data_offset.to_le_bytes());
+        bytes.extend((faces.len() as u64).to_le_bytes());
+        bytes.extend((faces.len() as u64).to_le_bytes());
+        bytes.extend(faces);
+        bytes
+    }
+
+    /// Writes the offset and length of the first level
+    fn set_level(bytes: &mut [u8], offset: u64, length: u64) {
+        bytes[HEADER_SIZE..HEADER_SIZE + 8].copy_from_slice(&offset.to_le_bytes());
+        bytes[HEADER_SIZE + 8..HEADER_SIZE + 16].copy_from_slice(&length.to_le_bytes());
+    }
+
+    fn is_invalid(bytes: &[u8]) -> bool {
+        matches!(Ktx2Texture::parse(bytes), Err(Ktx2Error::Invalid))
+    }
+
+    #[test]
+    fn parses_cubemap() {
+        let texture = Ktx2Texture::parse(&cubemap()).unwrap();
+        assert_eq!(texture.format, vk::Format::R8G8B8A8_UNORM);
+        assert_eq!((texture.extent.width, texture.extent.height), (1, 1));
+        assert!(texture.is_cubemap());
+        assert_eq!(texture.levels.len(), 1);
+        assert_eq!(texture.levels[0].len(), 6 * 4);
+        assert_eq!(texture.levels[0][20..], [5, 5, 5, 255]);
+    }
+
+    #[test]
+    fn rejects_truncated_header() {
+        let bytes = cubemap();
+        assert!(is_invalid(&bytes[..HEADER_SIZE - 1]));
+        // The level index is cut off
+        assert!(is_invalid(&bytes[..HEADER_SIZE + 8]));
+    }
+
+    #[test]
+    fn rejects_bad_identifier() {
+        let mut bytes = cubemap();
+        bytes[1] = b'k';
+        assert!(is_invalid(&bytes));
+    }
+
+    #[test]
+    fn rejects_levels_out_of_range() {
+        let mut bytes = cubemap();
+        let end = bytes.len() as u64;
+        set_level(&mut bytes, end, 1);
+        assert!(is_invalid(&bytes));
+        // Would overflow when added up
+        set_level(&mut bytes, u64::MAX - 2, 16);
+        assert!(is_invalid(&bytes));
+        set_level(&mut bytes, 0, u64::MAX);
+        assert!(is_invalid(&bytes));
+    }
+}
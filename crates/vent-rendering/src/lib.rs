@@ -8,12 +8,21 @@ use bytemuck::cast_slice;
 use ordered_float::OrderedFloat;
 
 pub mod allocator;
+pub mod bindless;
 pub mod buffer;
+mod capture;
 mod debug;
+pub mod descriptor;
+pub mod frame;
+pub mod geometry;
 pub mod image;
 pub mod instance;
+pub mod ktx2;
 pub mod mesh;
 pub mod pipeline;
+mod pipeline_cache;
+pub mod reflection;
+pub mod render_graph;
 mod surface;
 pub mod vertex;
 
@@ -23,11 +32,30 @@ pub const DEFAULT_MIPMAP_MODE: vk::SamplerMipmapMode = vk::SamplerMipmapMode::LI
 const DEFAULT_FENCE_TIMEOUT: u64 = 100000000000;
 
 // Used for caching
-#[derive(PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub struct MaterialPipelineInfo {
     pub mode: vk::PrimitiveTopology,
     pub alpha_cut: Option<OrderedFloat<f32>>, // Default 0.5
     pub double_sided: bool,
+    pub queue: RenderQueue,
+}
+
+/// When a Material is drawn, The queues are drawn in this order
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum RenderQueue {
+    Opaque,
+    /// Alpha tested, After the Opaque ones so they fill the depth buffer first
+    Mask,
+    /// Alpha blended without depth write, Last as they need everything behind them
+    Transparent,
+}
+
+impl RenderQueue {
+    pub const ALL: [RenderQueue; 3] = [
+        RenderQueue::Opaque,
+        RenderQueue::Mask,
+        RenderQueue::Transparent,
+    ];
 }
 
 pub enum Indices {
@@ -57,6 +85,15 @@ impl Indices {
         }
     }
 
+    /// Copies the indices, Widened to `u32`
+    pub fn to_u32(&self) -> Vec<u32> {
+        match self {
+            Indices::U8(indices) => indices.iter().map(|&i| i as u32).collect(),
+            Indices::U16(indices) => indices.iter().map(|&i| i as u32).collect(),
+            Indices::U32(indices) => indices.clone(),
+        }
+    }
+
     pub fn get_slice(&self) -> &[u8] {
         match self {
             Indices::U8(indices) => cast_slice(&indices[..]),
@@ -1,8 +1,10 @@
+use std::ops::Range;
+
 use ash::vk;
 
 use crate::{
-    begin_single_time_command, buffer::VulkanBuffer, end_single_time_command,
-    instance::VulkanInstance, Indices,
+    begin_single_time_command, buffer::VulkanBuffer, end_single_time_command, frame::UploadArena,
+    instance::VulkanInstance, vertex::InstanceData, Indices,
 };
 
 /// This is a simple mesh that consists of vertices and indices. It is useful when you need to hard-code 3D data into your application.
@@ -122,7 +124,36 @@ impl Mesh3D {
     }
 
     pub fn draw(&self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
-        unsafe { device.cmd_draw_indexed(command_buffer, self.index_count, 1, 0, 0, 0) };
+        self.draw_instanced(device, command_buffer, 1);
+    }
+
+    /// Instance data has to be bound before, See `bind_instances`
+    pub fn draw_instanced(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        instance_count: u32,
+    ) {
+        self.draw_instances(device, command_buffer, 0..instance_count);
+    }
+
+    /// Draws only a range of the bound instances, e.g. a single one out of many sorted instances
+    pub fn draw_instances(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        instances: Range<u32>,
+    ) {
+        unsafe {
+            device.cmd_draw_indexed(
+                command_buffer,
+                self.index_count,
+                instances.len() as u32,
+                0,
+                0,
+                instances.start,
+            )
+        };
     }
 
     pub fn destroy(&mut self, device: &ash::Device) {
@@ -130,3 +161,79 @@ impl Mesh3D {
         self.index_buf.destroy(device);
     }
 }
+
+/// A Mesh drawn many times with a single draw call, Every instance has its own transform.
+///
+/// The instances are uploaded into the Upload Arena of the Frame, So they can be changed every Frame.
+/// `M` can be anything drawn per instance (e.g. a whole Model), Only `Mesh3D` can be drawn by itself
+pub struct InstancedMesh<M = Mesh3D> {
+    pub mesh: M,
+    pub instances: Vec<InstanceData>,
+}
+
+impl<M> InstancedMesh<M> {
+    pub fn new(mesh: M) -> Self {
+        Self {
+            mesh,
+            instances: Vec::new(),
+        }
+    }
+
+    #[inline]
+    pub fn instance_count(&self) -> u32 {
+        self.instances.len() as u32
+    }
+
+    /// Binds the instances at `InstanceData::BINDING`, Returns false when there are none or the Arena is full
+    pub fn bind_instances(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        arena: &UploadArena,
+    ) -> bool {
+        !self.instances.is_empty() && bind_instances(device, command_buffer, arena, &self.instances)
+    }
+}
+
+impl InstancedMesh {
+    pub fn draw(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        arena: &UploadArena,
+    ) {
+        if !self.bind_instances(device, command_buffer, arena) {
+            return;
+        }
+        self.mesh.bind(device, command_buffer);
+        self.mesh
+            .draw_instanced(device, command_buffer, self.instance_count());
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        self.mesh.destroy(device);
+    }
+}
+
+/// Uploads the instances into the Arena and binds them at `InstanceData::BINDING`, Returns false when the Arena is full
+pub fn bind_instances(
+    device: &ash::Device,
+    command_buffer: vk::CommandBuffer,
+    arena: &UploadArena,
+    instances: &[InstanceData],
+) -> bool {
+    let Some(allocation) = arena.upload(instances) else {
+        return false;
+    };
+    unsafe {
+        device.cmd_bind_vertex_buffers2(
+            command_buffer,
+            InstanceData::BINDING,
+            &[allocation.buffer],
+            &[allocation.offset],
+            None,
+            None,
+        );
+    }
+    true
+}
@@ -1,11 +1,136 @@
-use std::{ffi::CStr, fs::File, path::Path};
+use std::{
+    ffi::CStr,
+    fmt,
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use ash::{util::read_spv, vk};
 
-use ash::{
-    util::read_spv,
-    vk::{self, PushConstantRange},
+use crate::{
+    instance::VulkanInstance,
+    reflection::{PipelineReflection, ReflectionError, ShaderReflection},
 };
 
-use crate::instance::VulkanInstance;
+/// Format of the scene color, Everything before tonemapping is rendered in HDR
+pub const HDR_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+/// Weighted Blended Order Independent Transparency, Premultiplied color and alpha weighted by depth
+pub const OIT_ACCUMULATION_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+/// Weighted Blended Order Independent Transparency, How much of the background is still visible
+pub const OIT_REVEALAGE_FORMAT: vk::Format = vk::Format::R16_SFLOAT;
+
+/// The attachment formats a pipeline renders into, Have to match the attachments of the pass using the pipeline
+#[derive(Clone, Debug)]
+pub struct RenderingFormats {
+    pub color: Vec<vk::Format>,
+    /// `vk::Format::UNDEFINED` when rendering without depth
+    pub depth: vk::Format,
+    /// Of all attachments, More than one when rendering with MSAA
+    pub samples: vk::SampleCountFlags,
+}
+
+impl RenderingFormats {
+    /// Renders into the Swapchain (or offscreen) image with depth
+    pub fn swapchain(instance: &VulkanInstance) -> Self {
+        Self {
+            color: vec![instance.surface_format.format],
+            depth: instance.depth_format,
+            samples: vk::SampleCountFlags::TYPE_1,
+        }
+    }
+
+    /// Renders into the HDR scene color with depth, Multisampled with `VulkanInstance::msaa_samples`
+    pub fn hdr(instance: &VulkanInstance) -> Self {
+        Self {
+            color: vec![HDR_FORMAT],
+            depth: instance.depth_format,
+            samples: instance.msaa_samples,
+        }
+    }
+
+    /// Renders into an HDR image only, e.g. for post-processing
+    pub fn hdr_color() -> Self {
+        Self {
+            color: vec![HDR_FORMAT],
+            depth: vk::Format::UNDEFINED,
+            samples: vk::SampleCountFlags::TYPE_1,
+        }
+    }
+
+    /// Renders into the Weighted Blended OIT accumulation and revealage, With the resolved depth to test against
+    pub fn weighted_blended(instance: &VulkanInstance) -> Self {
+        Self {
+            color: vec![OIT_ACCUMULATION_FORMAT, OIT_REVEALAGE_FORMAT],
+            depth: instance.depth_format,
+            samples: vk::SampleCountFlags::TYPE_1,
+        }
+    }
+
+    /// Renders into the Swapchain (or offscreen) image only
+    pub fn swapchain_color(instance: &VulkanInstance) -> Self {
+        Self {
+            color: vec![instance.surface_format.format],
+            depth: vk::Format::UNDEFINED,
+            samples: vk::SampleCountFlags::TYPE_1,
+        }
+    }
+
+    pub fn rendering_info(&self) -> vk::PipelineRenderingCreateInfo<'_> {
+        vk::PipelineRenderingCreateInfo::default()
+            .color_attachment_formats(&self.color)
+            .depth_attachment_format(self.depth)
+    }
+}
+
+#[derive(Debug)]
+pub enum PipelineError {
+    Io(std::io::Error),
+    Reflection(ReflectionError),
+    /// The Shaders need other Descriptor Sets or Push Constants than the existing Layout provides
+    LayoutChanged,
+    Vulkan(vk::Result),
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PipelineError::Io(err) => write!(f, "Failed to read shader: {err}"),
+            PipelineError::Reflection(err) => write!(f, "{err}"),
+            PipelineError::LayoutChanged => write!(
+                f,
+                "Shader bindings or push constants changed, This needs a restart"
+            ),
+            PipelineError::Vulkan(err) => write!(f, "Failed to create pipeline: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for PipelineError {}
+
+impl From<std::io::Error> for PipelineError {
+    fn from(err: std::io::Error) -> Self {
+        PipelineError::Io(err)
+    }
+}
+
+impl From<ReflectionError> for PipelineError {
+    fn from(err: ReflectionError) -> Self {
+        PipelineError::Reflection(err)
+    }
+}
+
+/// Everything needed to create the pipeline again, e.g. when a shader changed
+struct SimplePipelineDesc {
+    vertex_file: PathBuf,
+    fragment_file: PathBuf,
+    binding_desc: Vec<vk::VertexInputBindingDescription>,
+    attrib_desc: Vec<vk::VertexInputAttributeDescription>,
+    surface_resolution: vk::Extent2D,
+    formats: RenderingFormats,
+    /// None writes the color as is
+    blend: Option<vk::PipelineColorBlendAttachmentState>,
+}
 
 pub struct VulkanPipeline {
     pub pipeline: vk::Pipeline,
@@ -14,6 +139,9 @@ pub struct VulkanPipeline {
 
     pub vertex_module: vk::ShaderModule,
     pub fragment_module: vk::ShaderModule,
+
+    pub reflection: PipelineReflection,
+    desc: SimplePipelineDesc,
 }
 
 impl VulkanPipeline {
@@ -25,7 +153,9 @@ impl VulkanPipeline {
     /// Front Face: CC,
     /// Polygon Mode: Fill
     ///
-    #[allow(clippy::too_many_arguments)]
+    /// The Descriptor Set and Pipeline Layout are created from the Shaders, An error is returned when
+    /// the Vertex Shader inputs do not match `attrib_desc`
+    ///
     pub fn create_simple_pipeline(
         instance: &VulkanInstance,
         vertex_file: &Path,
@@ -33,24 +163,148 @@ impl VulkanPipeline {
         binding_desc: &[vk::VertexInputBindingDescription],
         attrib_desc: &[vk::VertexInputAttributeDescription],
         surface_resolution: vk::Extent2D,
-        push_constant_ranges: &[PushConstantRange],
-        desc_layout_bindings: &[vk::DescriptorSetLayoutBinding],
-    ) -> Self {
-        let info = vk::DescriptorSetLayoutCreateInfo::default().bindings(desc_layout_bindings);
+        formats: &RenderingFormats,
+    ) -> Result<Self, PipelineError> {
+        let desc = SimplePipelineDesc {
+            vertex_file: vertex_file.to_owned(),
+            fragment_file: fragment_file.to_owned(),
+            binding_desc: binding_desc.to_vec(),
+            attrib_desc: attrib_desc.to_vec(),
+            surface_resolution,
+            formats: formats.clone(),
+            blend: None,
+        };
+        let (vertex_code, fragment_code, reflection) = Self::load_shaders(&desc)?;
+        if let Some(binding) = reflection.bindings.iter().find(|b| b.set != 0) {
+            return Err(ReflectionError::UnsupportedSet(binding.set).into());
+        }
 
-        let descriptor_set_layout =
-            unsafe { instance.device.create_descriptor_set_layout(&info, None) }.unwrap();
+        let descriptor_set_layout = reflection.create_descriptor_set_layout(instance, 0);
 
-        let pipeline_layout =
-            instance.create_pipeline_layout(push_constant_ranges, &[descriptor_set_layout]);
+        let pipeline_layout = instance
+            .create_pipeline_layout(&reflection.push_constant_ranges, &[descriptor_set_layout]);
 
-        let vertex_code =
-            read_spv(&mut File::open(vertex_file).expect("Failed to open Vertex File")).unwrap();
-        let vertex_module_info = vk::ShaderModuleCreateInfo::default().code(&vertex_code);
-        let fragment_code =
-            read_spv(&mut File::open(fragment_file).expect("Failed to open Fragment File"))
-                .unwrap();
-        let fragment_module_info = vk::ShaderModuleCreateInfo::default().code(&fragment_code);
+        let (pipeline, vertex_module, fragment_module) = match Self::create_pipeline(
+            instance,
+            &desc,
+            &vertex_code,
+            &fragment_code,
+            pipeline_layout,
+        ) {
+            Ok(created) => created,
+            Err(err) => {
+                unsafe {
+                    instance
+                        .device
+                        .destroy_pipeline_layout(pipeline_layout, None);
+                }
+                return Err(err);
+            }
+        };
+
+        Ok(Self {
+            pipeline,
+            descriptor_set_layout,
+            pipeline_layout,
+            vertex_module,
+            fragment_module,
+            reflection,
+            desc,
+        })
+    }
+
+    /// Returns true when the Pipeline was created from this SPIR-V file
+    pub fn uses_shader(&self, spv_file: &Path) -> bool {
+        self.desc.vertex_file == spv_file || self.desc.fragment_file == spv_file
+    }
+
+    /// Creates the Pipeline again from the Shader files on disk.
+    ///
+    /// The Layouts are kept, So existing Descriptor Sets stay valid. When anything fails the old Pipeline stays in use.
+    /// The Pipeline must not be in use by the GPU
+    pub fn reload(&mut self, instance: &VulkanInstance) -> Result<(), PipelineError> {
+        let (vertex_code, fragment_code, reflection) = Self::load_shaders(&self.desc)?;
+        if !reflection.is_layout_compatible(&self.reflection) {
+            return Err(PipelineError::LayoutChanged);
+        }
+        let (pipeline, vertex_module, fragment_module) = Self::create_pipeline(
+            instance,
+            &self.desc,
+            &vertex_code,
+            &fragment_code,
+            self.pipeline_layout,
+        )?;
+        unsafe {
+            instance.device.destroy_pipeline(self.pipeline, None);
+            instance
+                .device
+                .destroy_shader_module(self.vertex_module, None);
+            instance
+                .device
+                .destroy_shader_module(self.fragment_module, None);
+        }
+        self.pipeline = pipeline;
+        self.vertex_module = vertex_module;
+        self.fragment_module = fragment_module;
+        self.reflection = reflection;
+        Ok(())
+    }
+
+    /// Creates the Pipeline again for attachments with another sample count, e.g. when MSAA got changed.
+    /// The Pipeline must not be in use by the GPU
+    pub fn set_samples(
+        &mut self,
+        instance: &VulkanInstance,
+        samples: vk::SampleCountFlags,
+    ) -> Result<(), PipelineError> {
+        let old_samples = self.desc.formats.samples;
+        self.desc.formats.samples = samples;
+        let result = self.reload(instance);
+        if result.is_err() {
+            self.desc.formats.samples = old_samples;
+        }
+        result
+    }
+
+    /// Creates the Pipeline again blending into its color attachment, e.g. to composite something over the scene.
+    /// The Pipeline must not be in use by the GPU
+    pub fn set_blend(
+        &mut self,
+        instance: &VulkanInstance,
+        blend: Option<vk::PipelineColorBlendAttachmentState>,
+    ) -> Result<(), PipelineError> {
+        let old_blend = self.desc.blend;
+        self.desc.blend = blend;
+        let result = self.reload(instance);
+        if result.is_err() {
+            self.desc.blend = old_blend;
+        }
+        result
+    }
+
+    fn load_shaders(
+        desc: &SimplePipelineDesc,
+    ) -> Result<(Vec<u32>, Vec<u32>, PipelineReflection), PipelineError> {
+        let vertex_code = read_spv(&mut File::open(&desc.vertex_file)?)?;
+        let fragment_code = read_spv(&mut File::open(&desc.fragment_file)?)?;
+
+        let reflection = PipelineReflection::new(&[
+            ShaderReflection::new(&vertex_code)?,
+            ShaderReflection::new(&fragment_code)?,
+        ])?;
+        reflection.validate_vertex_input(&desc.attrib_desc)?;
+        Ok((vertex_code, fragment_code, reflection))
+    }
+
+    fn create_pipeline(
+        instance: &VulkanInstance,
+        desc: &SimplePipelineDesc,
+        vertex_code: &[u32],
+        fragment_code: &[u32],
+        pipeline_layout: vk::PipelineLayout,
+    ) -> Result<(vk::Pipeline, vk::ShaderModule, vk::ShaderModule), PipelineError> {
+        let vertex_module_info = vk::ShaderModuleCreateInfo::default().code(vertex_code);
+        let fragment_module_info = vk::ShaderModuleCreateInfo::default().code(fragment_code);
 
         let vertex_module = unsafe {
             instance
@@ -82,8 +336,8 @@ impl VulkanPipeline {
         ];
 
         let vertex_input_state_info = vk::PipelineVertexInputStateCreateInfo::default()
-            .vertex_attribute_descriptions(attrib_desc)
-            .vertex_binding_descriptions(binding_desc);
+            .vertex_attribute_descriptions(&desc.attrib_desc)
+            .vertex_binding_descriptions(&desc.binding_desc);
         let vertex_input_assembly_state_info = vk::PipelineInputAssemblyStateCreateInfo {
             topology: vk::PrimitiveTopology::TRIANGLE_LIST,
             ..Default::default()
@@ -91,12 +345,12 @@ impl VulkanPipeline {
         let viewports = [vk::Viewport {
             x: 0.0,
             y: 0.0,
-            width: surface_resolution.width as f32,
-            height: surface_resolution.height as f32,
+            width: desc.surface_resolution.width as f32,
+            height: desc.surface_resolution.height as f32,
             min_depth: 0.0,
             max_depth: 1.0,
         }];
-        let scissors = [surface_resolution.into()];
+        let scissors = [desc.surface_resolution.into()];
         let viewport_state_info = vk::PipelineViewportStateCreateInfo::default()
             .scissors(&scissors)
             .viewports(&viewports);
@@ -109,7 +363,7 @@ impl VulkanPipeline {
             ..Default::default()
         };
         let multisample_state_info = vk::PipelineMultisampleStateCreateInfo {
-            rasterization_samples: vk::SampleCountFlags::TYPE_1,
+            rasterization_samples: desc.formats.samples,
             ..Default::default()
         };
 
@@ -118,10 +372,11 @@ impl VulkanPipeline {
         // .depth_write_enable(true)
         // .depth_compare_op(vk::CompareOp::LESS)
         // .max_depth_bounds(1.0);
-        let color_blend_attachment_states = [vk::PipelineColorBlendAttachmentState {
-            color_write_mask: vk::ColorComponentFlags::RGBA,
-            ..Default::default()
-        }];
+        let color_blend_attachment_states =
+            [desc.blend.unwrap_or(vk::PipelineColorBlendAttachmentState {
+                color_write_mask: vk::ColorComponentFlags::RGBA,
+                ..Default::default()
+            })];
         let color_blend_state = vk::PipelineColorBlendStateCreateInfo::default()
             .logic_op(vk::LogicOp::COPY)
             .attachments(&color_blend_attachment_states);
@@ -130,7 +385,10 @@ impl VulkanPipeline {
         let dynamic_state_info =
             vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_state);
 
+        let mut rendering_info = desc.formats.rendering_info();
+
         let graphic_pipeline_info = vk::GraphicsPipelineCreateInfo::default()
+            .push_next(&mut rendering_info)
             .stages(&shader_stage_create_info)
             .vertex_input_state(&vertex_input_state_info)
             .input_assembly_state(&vertex_input_assembly_state_info)
@@ -140,30 +398,30 @@ impl VulkanPipeline {
             .depth_stencil_state(&depth_state_info)
             .color_blend_state(&color_blend_state)
             .dynamic_state(&dynamic_state_info)
-            .layout(pipeline_layout)
-            .render_pass(instance.render_pass);
+            .layout(pipeline_layout);
 
         let graphics_pipelines = unsafe {
             instance.device.create_graphics_pipelines(
-                vk::PipelineCache::null(),
+                instance.pipeline_cache,
                 &[graphic_pipeline_info],
                 None,
             )
-        }
-        .expect("Unable to create graphics pipeline");
-
-        Self {
-            pipeline: graphics_pipelines[0],
-            descriptor_set_layout,
-            pipeline_layout,
-            vertex_module,
-            fragment_module,
+        };
+        match graphics_pipelines {
+            Ok(pipelines) => Ok((pipelines[0], vertex_module, fragment_module)),
+            Err((_, err)) => {
+                unsafe {
+                    instance.device.destroy_shader_module(vertex_module, None);
+                    instance.device.destroy_shader_module(fragment_module, None);
+                }
+                Err(PipelineError::Vulkan(err))
+            }
         }
     }
 
     pub fn destroy(&mut self, device: &ash::Device) {
         unsafe {
-            device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+            // The descriptor set layout belongs to the layout cache
             device.destroy_pipeline_layout(self.pipeline_layout, None);
             device.destroy_shader_module(self.vertex_module, None);
             device.destroy_shader_module(self.fragment_module, None);
@@ -172,27 +430,325 @@ impl VulkanPipeline {
     }
 }
 
-#[allow(dead_code)]
-fn conv_shader_stage(model: spirv::ExecutionModel) -> vk::ShaderStageFlags {
-    match model {
-        spirv::ExecutionModel::Vertex => vk::ShaderStageFlags::VERTEX,
-        spirv::ExecutionModel::TessellationControl => vk::ShaderStageFlags::TESSELLATION_CONTROL,
-        spirv::ExecutionModel::TessellationEvaluation => {
-            vk::ShaderStageFlags::TESSELLATION_EVALUATION
-        }
-        spirv::ExecutionModel::Geometry => vk::ShaderStageFlags::GEOMETRY,
-        spirv::ExecutionModel::Fragment => vk::ShaderStageFlags::FRAGMENT,
-        spirv::ExecutionModel::GLCompute => vk::ShaderStageFlags::COMPUTE,
-        spirv::ExecutionModel::Kernel => todo!(),
-        spirv::ExecutionModel::TaskNV => vk::ShaderStageFlags::TASK_NV,
-        spirv::ExecutionModel::MeshNV => vk::ShaderStageFlags::MESH_NV,
-        spirv::ExecutionModel::RayGenerationNV => vk::ShaderStageFlags::RAYGEN_NV,
-        spirv::ExecutionModel::IntersectionNV => vk::ShaderStageFlags::INTERSECTION_NV,
-        spirv::ExecutionModel::AnyHitNV => vk::ShaderStageFlags::ANY_HIT_NV,
-        spirv::ExecutionModel::ClosestHitNV => vk::ShaderStageFlags::CLOSEST_HIT_NV,
-        spirv::ExecutionModel::MissNV => vk::ShaderStageFlags::MISS_NV,
-        spirv::ExecutionModel::CallableNV => vk::ShaderStageFlags::CALLABLE_NV,
-        spirv::ExecutionModel::TaskEXT => vk::ShaderStageFlags::TASK_EXT,
-        spirv::ExecutionModel::MeshEXT => vk::ShaderStageFlags::MESH_EXT,
+/// A Pipeline with a single Compute Shader, The Layouts are created from the Shader like for `VulkanPipeline`
+pub struct ComputePipeline {
+    pub pipeline: vk::Pipeline,
+    pub pipeline_layout: vk::PipelineLayout,
+    /// One for every set the Shader uses, Owned by the layout cache
+    pub descriptor_set_layouts: Vec<vk::DescriptorSetLayout>,
+    pub reflection: PipelineReflection,
+    file: PathBuf,
+}
+
+impl ComputePipeline {
+    pub fn new(instance: &VulkanInstance, file: &Path) -> Result<Self, PipelineError> {
+        let code = read_spv(&mut File::open(file)?)?;
+        let reflection = PipelineReflection::new(&[ShaderReflection::new(&code)?])?;
+
+        let descriptor_set_layouts: Vec<_> = (0..reflection.set_count())
+            .map(|set| reflection.create_descriptor_set_layout(instance, set))
+            .collect();
+        let pipeline_layout = instance
+            .create_pipeline_layout(&reflection.push_constant_ranges, &descriptor_set_layouts);
+
+        let pipeline = match Self::create_pipeline(instance, &code, pipeline_layout) {
+            Ok(pipeline) => pipeline,
+            Err(err) => {
+                unsafe {
+                    instance
+                        .device
+                        .destroy_pipeline_layout(pipeline_layout, None);
+                }
+                return Err(err);
+            }
+        };
+
+        Ok(Self {
+            pipeline,
+            pipeline_layout,
+            descriptor_set_layouts,
+            reflection,
+            file: file.to_owned(),
+        })
+    }
+
+    /// Returns true when the Pipeline was created from this SPIR-V file
+    pub fn uses_shader(&self, spv_file: &Path) -> bool {
+        self.file == spv_file
+    }
+
+    /// Creates the Pipeline again from the Shader file on disk, See `VulkanPipeline::reload`
+    pub fn reload(&mut self, instance: &VulkanInstance) -> Result<(), PipelineError> {
+        let code = read_spv(&mut File::open(&self.file)?)?;
+        let reflection = PipelineReflection::new(&[ShaderReflection::new(&code)?])?;
+        if !reflection.is_layout_compatible(&self.reflection) {
+            return Err(PipelineError::LayoutChanged);
+        }
+        let pipeline = Self::create_pipeline(instance, &code, self.pipeline_layout)?;
+        unsafe { instance.device.destroy_pipeline(self.pipeline, None) };
+        self.pipeline = pipeline;
+        self.reflection = reflection;
+        Ok(())
+    }
+
+    /// Binds the Pipeline and dispatches enough groups to cover `invocations` with groups of `group_size`
+    pub fn dispatch(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        invocations: u32,
+        group_size: u32,
+    ) {
+        unsafe {
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline,
+            );
+            device.cmd_dispatch(command_buffer, invocations.div_ceil(group_size), 1, 1);
+        }
+    }
+
+    /// Like `dispatch` for work over an image, e.g. one invocation per pixel
+    pub fn dispatch_2d(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        invocations: vk::Extent2D,
+        group_size: (u32, u32),
+    ) {
+        unsafe {
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline,
+            );
+            device.cmd_dispatch(
+                command_buffer,
+                invocations.width.div_ceil(group_size.0),
+                invocations.height.div_ceil(group_size.1),
+                1,
+            );
+        }
+    }
+
+    fn create_pipeline(
+        instance: &VulkanInstance,
+        code: &[u32],
+        pipeline_layout: vk::PipelineLayout,
+    ) -> Result<vk::Pipeline, PipelineError> {
+        let module_info = vk::ShaderModuleCreateInfo::default().code(code);
+        let module = unsafe { instance.device.create_shader_module(&module_info, None) }.unwrap();
+
+        let stage = vk::PipelineShaderStageCreateInfo::default()
+            .module(module)
+            .name(c"main")
+            .stage(vk::ShaderStageFlags::COMPUTE);
+        let pipeline_info = vk::ComputePipelineCreateInfo::default()
+            .stage(stage)
+            .layout(pipeline_layout);
+
+        let pipelines = unsafe {
+            instance.device.create_compute_pipelines(
+                instance.pipeline_cache,
+                &[pipeline_info],
+                None,
+            )
+        };
+        // The module is not needed anymore once the Pipeline exists
+        unsafe { instance.device.destroy_shader_module(module, None) };
+        match pipelines {
+            Ok(pipelines) => Ok(pipelines[0]),
+            Err((_, err)) => Err(PipelineError::Vulkan(err)),
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+    }
+}
+
+/// Everything needed to create the depth Pipeline again
+struct DepthPipelineDesc {
+    vertex_file: PathBuf,
+    binding_desc: Vec<vk::VertexInputBindingDescription>,
+    attrib_desc: Vec<vk::VertexInputAttributeDescription>,
+    depth_format: vk::Format,
+    /// Constant and slope factor
+    depth_bias: (f32, f32),
+}
+
+/// A Pipeline with only a Vertex Shader which writes depth, e.g. for Shadow Maps.
+///
+/// Depth: Enabled (LESS_OR_EQUAL) with a constant Depth Bias,
+/// Cull: Disabled, So single sided geometry still casts Shadows,
+/// Viewport & Scissor: Dynamic
+pub struct DepthPipeline {
+    pub pipeline: vk::Pipeline,
+    pub pipeline_layout: vk::PipelineLayout,
+    pub reflection: PipelineReflection,
+    desc: DepthPipelineDesc,
+}
+
+impl DepthPipeline {
+    /// The Pipeline Layout only has the Push Constants of the Shader, Descriptor Sets are not supported
+    pub fn new(
+        instance: &VulkanInstance,
+        vertex_file: &Path,
+        binding_desc: &[vk::VertexInputBindingDescription],
+        attrib_desc: &[vk::VertexInputAttributeDescription],
+        depth_format: vk::Format,
+        depth_bias: (f32, f32),
+    ) -> Result<Self, PipelineError> {
+        let desc = DepthPipelineDesc {
+            vertex_file: vertex_file.to_owned(),
+            binding_desc: binding_desc.to_vec(),
+            attrib_desc: attrib_desc.to_vec(),
+            depth_format,
+            depth_bias,
+        };
+        let (code, reflection) = Self::load_shader(&desc)?;
+        if let Some(binding) = reflection.bindings.first() {
+            return Err(ReflectionError::UnsupportedSet(binding.set).into());
+        }
+        let pipeline_layout =
+            instance.create_pipeline_layout(&reflection.push_constant_ranges, &[]);
+
+        let pipeline = match Self::create_pipeline(instance, &desc, &code, pipeline_layout) {
+            Ok(pipeline) => pipeline,
+            Err(err) => {
+                unsafe {
+                    instance
+                        .device
+                        .destroy_pipeline_layout(pipeline_layout, None);
+                }
+                return Err(err);
+            }
+        };
+
+        Ok(Self {
+            pipeline,
+            pipeline_layout,
+            reflection,
+            desc,
+        })
+    }
+
+    /// Returns true when the Pipeline was created from this SPIR-V file
+    pub fn uses_shader(&self, spv_file: &Path) -> bool {
+        self.desc.vertex_file == spv_file
+    }
+
+    /// Creates the Pipeline again from the Shader file on disk, See `VulkanPipeline::reload`
+    pub fn reload(&mut self, instance: &VulkanInstance) -> Result<(), PipelineError> {
+        let (code, reflection) = Self::load_shader(&self.desc)?;
+        if !reflection.is_layout_compatible(&self.reflection) {
+            return Err(PipelineError::LayoutChanged);
+        }
+        let pipeline = Self::create_pipeline(instance, &self.desc, &code, self.pipeline_layout)?;
+        unsafe { instance.device.destroy_pipeline(self.pipeline, None) };
+        self.pipeline = pipeline;
+        self.reflection = reflection;
+        Ok(())
+    }
+
+    fn load_shader(
+        desc: &DepthPipelineDesc,
+    ) -> Result<(Vec<u32>, PipelineReflection), PipelineError> {
+        let code = read_spv(&mut File::open(&desc.vertex_file)?)?;
+        let reflection = PipelineReflection::new(&[ShaderReflection::new(&code)?])?;
+        reflection.validate_vertex_input(&desc.attrib_desc)?;
+        Ok((code, reflection))
+    }
+
+    fn create_pipeline(
+        instance: &VulkanInstance,
+        desc: &DepthPipelineDesc,
+        code: &[u32],
+        pipeline_layout: vk::PipelineLayout,
+    ) -> Result<vk::Pipeline, PipelineError> {
+        let module_info = vk::ShaderModuleCreateInfo::default().code(code);
+        let module = unsafe { instance.device.create_shader_module(&module_info, None) }.unwrap();
+
+        let stages = [vk::PipelineShaderStageCreateInfo::default()
+            .module(module)
+            .name(c"main")
+            .stage(vk::ShaderStageFlags::VERTEX)];
+
+        let vertex_input_state_info = vk::PipelineVertexInputStateCreateInfo::default()
+            .vertex_attribute_descriptions(&desc.attrib_desc)
+            .vertex_binding_descriptions(&desc.binding_desc);
+        let vertex_input_assembly_state_info = vk::PipelineInputAssemblyStateCreateInfo::default()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+        // Set with every draw
+        let viewport_state_info = vk::PipelineViewportStateCreateInfo::default()
+            .viewport_count(1)
+            .scissor_count(1);
+
+        let (constant_factor, slope_factor) = desc.depth_bias;
+        let rasterization_info = vk::PipelineRasterizationStateCreateInfo::default()
+            .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+            .line_width(1.0)
+            .polygon_mode(vk::PolygonMode::FILL)
+            .cull_mode(vk::CullModeFlags::NONE)
+            .depth_bias_enable(true)
+            .depth_bias_constant_factor(constant_factor)
+            .depth_bias_slope_factor(slope_factor);
+        let multisample_state_info = vk::PipelineMultisampleStateCreateInfo::default()
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+        let depth_state_info = vk::PipelineDepthStencilStateCreateInfo::default()
+            .depth_test_enable(true)
+            .depth_write_enable(true)
+            .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL)
+            .max_depth_bounds(1.0);
+        let color_blend_state = vk::PipelineColorBlendStateCreateInfo::default();
+
+        let dynamic_state = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        let dynamic_state_info =
+            vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_state);
+
+        let formats = RenderingFormats {
+            color: Vec::new(),
+            depth: desc.depth_format,
+            samples: vk::SampleCountFlags::TYPE_1,
+        };
+        let mut rendering_info = formats.rendering_info();
+
+        let pipeline_info = vk::GraphicsPipelineCreateInfo::default()
+            .push_next(&mut rendering_info)
+            .stages(&stages)
+            .vertex_input_state(&vertex_input_state_info)
+            .input_assembly_state(&vertex_input_assembly_state_info)
+            .viewport_state(&viewport_state_info)
+            .rasterization_state(&rasterization_info)
+            .multisample_state(&multisample_state_info)
+            .depth_stencil_state(&depth_state_info)
+            .color_blend_state(&color_blend_state)
+            .dynamic_state(&dynamic_state_info)
+            .layout(pipeline_layout);
+
+        let pipelines = unsafe {
+            instance.device.create_graphics_pipelines(
+                instance.pipeline_cache,
+                &[pipeline_info],
+                None,
+            )
+        };
+        // The module is not needed anymore once the Pipeline exists
+        unsafe { instance.device.destroy_shader_module(module, None) };
+        match pipelines {
+            Ok(pipelines) => Ok(pipelines[0]),
+            Err((_, err)) => Err(PipelineError::Vulkan(err)),
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+        }
     }
 }
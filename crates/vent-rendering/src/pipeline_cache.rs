@@ -0,0 +1,266 @@
+use std::{fs, path::PathBuf};
+
+use ash::vk;
+
+use crate::instance::VulkanInstance;
+
+/// Inside the user cache directory, Or next to the executable when there is none
+const CACHE_DIR: &str = "cache";
+
+/// We put our own small header in front of the data Vulkan gives us, So files which were cut off or got damaged are noticed
+const FILE_MAGIC: &[u8; 8] = b"VENTPCAC";
+const FILE_HEADER_SIZE: usize = FILE_MAGIC.len() + std::mem::size_of::<u64>();
+
+/// Size of the header every Vulkan pipeline cache starts with (`VkPipelineCacheHeaderVersionOne`)
+const VK_HEADER_SIZE: usize = 32;
+
+impl VulkanInstance {
+    /// Creates the Pipeline Cache, Filled with the data of the last run on this device if there is any.
+    ///
+    /// Returns the cache and the file it gets saved into
+    pub(crate) fn load_pipeline_cache(
+        device: &ash::Device,
+        properties: &vk::PhysicalDeviceProperties,
+        application_name: &str,
+    ) -> (vk::PipelineCache, PathBuf) {
+        let path = cache_dir(application_name).join(format!(
+            "pipeline_cache_{:x}_{:x}.bin",
+            properties.vendor_id, properties.device_id
+        ));
+
+        let data = match fs::read(&path) {
+            Ok(file) => {
+                if let Some(data) = Self::read_pipeline_cache(&file, properties) {
+                    log::debug!("Loaded Pipeline Cache from {}", path.display());
+                    data.to_vec()
+                } else {
+                    // An old Driver or an broken file, Drivers are not required to handle garbage so we never give it to them
+                    log::warn!(
+                        "Pipeline Cache {} is invalid or from an other Device, Ignoring it",
+                        path.display()
+                    );
+                    Vec::new()
+                }
+            }
+            Err(_) => Vec::new(),
+        };
+
+        let create_info = vk::PipelineCacheCreateInfo::default().initial_data(&data);
+        let cache = match unsafe { device.create_pipeline_cache(&create_info, None) } {
+            Ok(cache) => cache,
+            Err(err) => {
+                log::warn!("Failed to create Pipeline Cache with initial data: {err}");
+                let create_info = vk::PipelineCacheCreateInfo::default();
+                unsafe { device.create_pipeline_cache(&create_info, None) }
+                    .expect("Failed to create Pipeline Cache")
+            }
+        };
+        (cache, path)
+    }
+
+    /// Writes the Pipeline Cache to disk, So the next start does not have to compile all pipelines again
+    pub fn save_pipeline_cache(&self) {
+        let data = match unsafe { self.device.get_pipeline_cache_data(self.pipeline_cache) } {
+            Ok(data) => data,
+            Err(err) => {
+                log::warn!("Failed to get Pipeline Cache data: {err}");
+                return;
+            }
+        };
+        if let Some(parent) = self.pipeline_cache_path.parent() {
+            if let Err(err) = fs::create_dir_all(parent) {
+                log::warn!("Failed to create {}: {err}", parent.display());
+                return;
+            }
+        }
+        // Write into an temp file first, So a crash while saving does not leave an half written cache behind
+        let temp_path = self.pipeline_cache_path.with_extension("tmp");
+        let mut file = Vec::with_capacity(FILE_HEADER_SIZE + data.len());
+        file.extend_from_slice(FILE_MAGIC);
+        file.extend_from_slice(&hash(&data).to_le_bytes());
+        file.extend_from_slice(&data);
+        let result = fs::write(&temp_path, &file)
+            .and_then(|_| fs::rename(&temp_path, &self.pipeline_cache_path));
+        match result {
+            Ok(_) => log::debug!(
+                "Saved Pipeline Cache ({} bytes) to {}",
+                data.len(),
+                self.pipeline_cache_path.display()
+            ),
+            Err(err) => log::warn!("Failed to save Pipeline Cache: {err}"),
+        }
+    }
+
+    /// Returns the Vulkan data of an cache file when it is intact and was created by the current Device
+    fn read_pipeline_cache<'a>(
+        file: &'a [u8],
+        properties: &vk::PhysicalDeviceProperties,
+    ) -> Option<&'a [u8]> {
+        if file.len() < FILE_HEADER_SIZE || &file[..FILE_MAGIC.len()] != FILE_MAGIC {
+            return None;
+        }
+        let stored_hash =
+            u64::from_le_bytes(file[FILE_MAGIC.len()..FILE_HEADER_SIZE].try_into().unwrap());
+        let data = &file[FILE_HEADER_SIZE..];
+        if hash(data) != stored_hash || !Self::is_vulkan_header_valid(data, properties) {
+            return None;
+        }
+        Some(data)
+    }
+
+    /// Checks the header Vulkan puts in front of every Pipeline Cache against the current Device
+    fn is_vulkan_header_valid(data: &[u8], properties: &vk::PhysicalDeviceProperties) -> bool {
+        if data.len() < VK_HEADER_SIZE {
+            return false;
+        }
+        let read_u32 = |offset: usize| {
+            u32::from_ne_bytes([
+                data[offset],
+                data[offset + 1],
+                data[offset + 2],
+                data[offset + 3],
+            ])
+        };
+        let header_size = read_u32(0) as usize;
+        let header_version = read_u32(4);
+        let vendor_id = read_u32(8);
+        let device_id = read_u32(12);
+        let uuid = &data[16..VK_HEADER_SIZE];
+
+        header_size >= VK_HEADER_SIZE
+            && header_size <= data.len()
+            && header_version == vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32
+            && vendor_id == properties.vendor_id
+            && device_id == properties.device_id
+            && uuid == properties.pipeline_cache_uuid
+    }
+}
+
+/// Where the Pipeline Caches of the application go, The same no matter which directory it was started from
+fn cache_dir(application_name: &str) -> PathBuf {
+    dirs::cache_dir()
+        .map(|dir| dir.join(application_name).join(CACHE_DIR))
+        .or_else(|| {
+            let exe = std::env::current_exe().ok()?;
+            Some(exe.parent()?.join(CACHE_DIR))
+        })
+        .unwrap_or_else(|| PathBuf::from(CACHE_DIR))
+}
+
+/// FNV-1a, Good enough to notice damaged files
+fn hash(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn properties() -> vk::PhysicalDeviceProperties {
+        vk::PhysicalDeviceProperties {
+            vendor_id: 0x10de,
+            device_id: 0x2484,
+            pipeline_cache_uuid: [7; vk::UUID_SIZE],
+            ..Default::default()
+        }
+    }
+
+    /// What a driver would hand out, A `VkPipelineCacheHeaderVersionOne` and some data
+    fn vulkan_data(properties: &vk::PhysicalDeviceProperties) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend((VK_HEADER_SIZE as u32).to_ne_bytes());
+        data.extend((vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32).to_ne_bytes());
+        data.extend(properties.vendor_id.to_ne_bytes());
+        data.extend(properties.device_id.to_ne_bytes());
+        data.extend(properties.pipeline_cache_uuid);
+        data.extend([1, 2, 3, 4]);
+        data
+    }
+
+    /// The file `save_pipeline_cache` writes
+    fn cache_file(data: &[u8]) -> Vec<u8> {
+        let mut file = FILE_MAGIC.to_vec();
+        file.extend(hash(data).to_le_bytes());
+        file.extend(data);
+        file
+    }
+
+    fn read(data: &[u8]) -> bool {
+        VulkanInstance::read_pipeline_cache(&cache_file(data), &properties()).is_some()
+    }
+
+    #[test]
+    fn reads_valid_cache() {
+        let data = vulkan_data(&properties());
+        let file = cache_file(&data);
+        assert_eq!(
+            VulkanInstance::read_pipeline_cache(&file, &properties()),
+            Some(data.as_slice())
+        );
+    }
+
+    #[test]
+    fn rejects_short_data() {
+        let file = cache_file(&vulkan_data(&properties()));
+        assert!(
+            VulkanInstance::read_pipeline_cache(&file[..FILE_HEADER_SIZE - 1], &properties())
+                .is_none()
+        );
+        // Our header is fine, But the one of Vulkan is cut off
+        assert!(!read(&vulkan_data(&properties())[..VK_HEADER_SIZE - 1]));
+    }
+
+    #[test]
+    fn rejects_wrong_header() {
+        let mut data = vulkan_data(&properties());
+        data[..4].copy_from_slice(&16u32.to_ne_bytes());
+        assert!(!read(&data));
+        // Longer than the data
+        let too_long = data.len() as u32 + 1;
+        data[..4].copy_from_slice(&too_long.to_ne_bytes());
+        assert!(!read(&data));
+
+        let mut data = vulkan_data(&properties());
+        data[4..8].copy_from_slice(&2u32.to_ne_bytes());
+        assert!(!read(&data));
+    }
+
+    #[test]
+    fn rejects_other_device() {
+        let other_vendor = vk::PhysicalDeviceProperties {
+            vendor_id: 0x1002,
+            ..properties()
+        };
+        assert!(!read(&vulkan_data(&other_vendor)));
+        let other_device = vk::PhysicalDeviceProperties {
+            device_id: 0x2204,
+            ..properties()
+        };
+        assert!(!read(&vulkan_data(&other_device)));
+        // e.g. after a driver update
+        let other_uuid = vk::PhysicalDeviceProperties {
+            pipeline_cache_uuid: [8; vk::UUID_SIZE],
+            ..properties()
+        };
+        assert!(!read(&vulkan_data(&other_uuid)));
+    }
+
+    #[test]
+    fn rejects_damaged_file() {
+        let mut file = cache_file(&vulkan_data(&properties()));
+        let last = file.len() - 1;
+        file[last] ^= 0xff;
+        assert!(VulkanInstance::read_pipeline_cache(&file, &properties()).is_none());
+
+        let mut file = cache_file(&vulkan_data(&properties()));
+        file[0] = b'X';
+        assert!(VulkanInstance::read_pipeline_cache(&file, &properties()).is_none());
+    }
+
+    #[test]
+    fn cache_dir_does_not_depend_on_working_directory() {
+        assert!(cache_dir("Test").is_absolute());
+    }
+}
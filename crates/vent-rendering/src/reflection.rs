@@ -0,0 +1,872 @@
+use std::{collections::HashMap, fmt, fs::File, path::Path};
+
+use ash::{util::read_spv, vk};
+use spirv::{Decoration, Dim, ExecutionModel, Op, StorageClass};
+
+use crate::instance::VulkanInstance;
+
+/// A descriptor used by a shader, Bindings of the same set and binding from multiple stages get merged
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DescriptorBinding {
+    pub set: u32,
+    pub binding: u32,
+    pub descriptor_type: vk::DescriptorType,
+    /// 0 for runtime sized arrays
+    pub count: u32,
+    pub stages: vk::ShaderStageFlags,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NumericType {
+    Float,
+    SInt,
+    UInt,
+}
+
+/// An `in` variable of a Vertex Shader
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VertexInput {
+    pub location: u32,
+    pub numeric_type: NumericType,
+    pub components: u32,
+}
+
+#[derive(Debug)]
+pub enum ReflectionError {
+    Io(std::io::Error),
+    InvalidSpirv(&'static str),
+    /// Two stages use the same set and binding with different descriptor types
+    BindingMismatch {
+        set: u32,
+        binding: u32,
+    },
+    /// The shader reads a location no attribute provides
+    MissingAttribute {
+        location: u32,
+    },
+    /// The attribute format does not fit the shader input
+    FormatMismatch {
+        location: u32,
+        format: vk::Format,
+        input: VertexInput,
+    },
+    /// `VulkanPipeline` only creates a single descriptor set layout
+    UnsupportedSet(u32),
+    /// OpenCL kernels can not be used with Vulkan
+    UnsupportedExecutionModel(ExecutionModel),
+}
+
+impl fmt::Display for ReflectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReflectionError::Io(err) => write!(f, "Failed to read shader: {err}"),
+            ReflectionError::InvalidSpirv(reason) => write!(f, "Invalid SPIR-V: {reason}"),
+            ReflectionError::BindingMismatch { set, binding } => write!(
+                f,
+                "Set {set} Binding {binding} is used with different descriptor types"
+            ),
+            ReflectionError::MissingAttribute { location } => write!(
+                f,
+                "Vertex shader input at location {location} has no matching attribute"
+            ),
+            ReflectionError::FormatMismatch {
+                location,
+                format,
+                input,
+            } => write!(
+                f,
+                "Attribute at location {location} has format {format:?}, But the shader expects {} {:?} component(s)",
+                input.components, input.numeric_type
+            ),
+            ReflectionError::UnsupportedSet(set) => {
+                write!(f, "Descriptor set {set} is used, Only set 0 is supported")
+            }
+            ReflectionError::UnsupportedExecutionModel(model) => {
+                write!(f, "Execution model {model:?} is not supported by Vulkan")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReflectionError {}
+
+impl From<std::io::Error> for ReflectionError {
+    fn from(err: std::io::Error) -> Self {
+        ReflectionError::Io(err)
+    }
+}
+
+/// Everything we read out of a single compiled SPIR-V module
+#[derive(Clone, Debug, Default)]
+pub struct ShaderReflection {
+    pub stage: vk::ShaderStageFlags,
+    pub bindings: Vec<DescriptorBinding>,
+    /// Size of the push constant block, 0 when the shader has none
+    pub push_constant_size: u32,
+    /// Only filled for Vertex Shaders
+    pub inputs: Vec<VertexInput>,
+}
+
+enum Type {
+    Scalar(NumericType, u32),
+    Vector { component: u32, count: u32 },
+    Matrix { column: u32, count: u32 },
+    Image { dim: Dim, sampled: u32 },
+    Sampler,
+    SampledImage,
+    Array { element: u32, length: u32 },
+    RuntimeArray { element: u32 },
+    Struct { members: Vec<u32> },
+    Pointer { pointee: u32 },
+    AccelerationStructure,
+}
+
+#[derive(Default)]
+struct Decorations {
+    set: Option<u32>,
+    binding: Option<u32>,
+    location: Option<u32>,
+    builtin: bool,
+    buffer_block: bool,
+    array_stride: Option<u32>,
+}
+
+impl ShaderReflection {
+    pub fn from_file(path: &Path) -> Result<Self, ReflectionError> {
+        let code = read_spv(&mut File::open(path)?)?;
+        Self::new(&code)
+    }
+
+    pub fn new(code: &[u32]) -> Result<Self, ReflectionError> {
+        if code.len() < 5 || code[0] != spirv::MAGIC_NUMBER {
+            return Err(ReflectionError::InvalidSpirv("Missing header"));
+        }
+
+        let mut stage = None;
+        let mut types: HashMap<u32, Type> = HashMap::new();
+        let mut constants: HashMap<u32, u32> = HashMap::new();
+        let mut decorations: HashMap<u32, Decorations> = HashMap::new();
+        let mut member_offsets: HashMap<(u32, u32), u32> = HashMap::new();
+        // (id, pointer type, storage class)
+        let mut variables = Vec::new();
+
+        let mut words = &code[5..];
+        while !words.is_empty() {
+            let count = (words[0] >> 16) as usize;
+            if count == 0 || count > words.len() {
+                return Err(ReflectionError::InvalidSpirv("Broken instruction"));
+            }
+            let (instruction, rest) = words.split_at(count);
+            words = rest;
+            let Some(op) = Op::from_u32(instruction[0] & 0xFFFF) else {
+                continue;
+            };
+            let operands = &instruction[1..];
+            let operand = |index: usize| {
+                operands
+                    .get(index)
+                    .copied()
+                    .ok_or(ReflectionError::InvalidSpirv("Missing operand"))
+            };
+            match op {
+                Op::EntryPoint if stage.is_none() => {
+                    let model = ExecutionModel::from_u32(operand(0)?)
+                        .ok_or(ReflectionError::InvalidSpirv("Unknown execution model"))?;
+                    stage = Some(conv_shader_stage(model)?);
+                }
+                Op::Decorate => {
+                    let entry = decorations.entry(operand(0)?).or_default();
+                    match Decoration::from_u32(operand(1)?) {
+                        Some(Decoration::DescriptorSet) => entry.set = Some(operand(2)?),
+                        Some(Decoration::Binding) => entry.binding = Some(operand(2)?),
+                        Some(Decoration::Location) => entry.location = Some(operand(2)?),
+                        Some(Decoration::ArrayStride) => entry.array_stride = Some(operand(2)?),
+                        Some(Decoration::BuiltIn) => entry.builtin = true,
+                        Some(Decoration::BufferBlock) => entry.buffer_block = true,
+                        _ => {}
+                    }
+                }
+                Op::MemberDecorate => {
+                    match Decoration::from_u32(operand(2)?) {
+                        Some(Decoration::Offset) => {
+                            member_offsets.insert((operand(0)?, operand(1)?), operand(3)?);
+                        }
+                        // gl_PerVertex and friends
+                        Some(Decoration::BuiltIn) => {
+                            decorations.entry(operand(0)?).or_default().builtin = true
+                        }
+                        _ => {}
+                    }
+                }
+                Op::TypeFloat => {
+                    types.insert(operand(0)?, Type::Scalar(NumericType::Float, operand(1)?));
+                }
+                Op::TypeInt => {
+                    let numeric_type = if operand(2)? == 1 {
+                        NumericType::SInt
+                    } else {
+                        NumericType::UInt
+                    };
+                    types.insert(operand(0)?, Type::Scalar(numeric_type, operand(1)?));
+                }
+                Op::TypeVector => {
+                    types.insert(
+                        operand(0)?,
+                        Type::Vector {
+                            component: operand(1)?,
+                            count: operand(2)?,
+                        },
+                    );
+                }
+                Op::TypeMatrix => {
+                    types.insert(
+                        operand(0)?,
+                        Type::Matrix {
+                            column: operand(1)?,
+                            count: operand(2)?,
+                        },
+                    );
+                }
+                Op::TypeImage => {
+                    let dim = Dim::from_u32(operand(2)?)
+                        .ok_or(ReflectionError::InvalidSpirv("Unknown image dimension"))?;
+                    types.insert(
+                        operand(0)?,
+                        Type::Image {
+                            dim,
+                            sampled: operand(6)?,
+                        },
+                    );
+                }
+                Op::TypeSampler => {
+                    types.insert(operand(0)?, Type::Sampler);
+                }
+                Op::TypeSampledImage => {
+                    types.insert(operand(0)?, Type::SampledImage);
+                }
+                Op::TypeArray => {
+                    types.insert(
+                        operand(0)?,
+                        Type::Array {
+                            element: operand(1)?,
+                            length: operand(2)?,
+                        },
+                    );
+                }
+                Op::TypeRuntimeArray => {
+                    types.insert(
+                        operand(0)?,
+                        Type::RuntimeArray {
+                            element: operand(1)?,
+                        },
+                    );
+                }
+                Op::TypeStruct => {
+                    types.insert(
+                        operand(0)?,
+                        Type::Struct {
+                            members: operands[1..].to_vec(),
+                        },
+                    );
+                }
+                Op::TypePointer => {
+                    types.insert(
+                        operand(0)?,
+                        Type::Pointer {
+                            pointee: operand(2)?,
+                        },
+                    );
+                }
+                Op::TypeAccelerationStructureKHR => {
+                    types.insert(operand(0)?, Type::AccelerationStructure);
+                }
+                Op::Constant => {
+                    constants.insert(operand(1)?, operand(2)?);
+                }
+                Op::Variable => {
+                    let storage = StorageClass::from_u32(operand(2)?)
+                        .ok_or(ReflectionError::InvalidSpirv("Unknown storage class"))?;
+                    variables.push((operand(1)?, operand(0)?, storage));
+                }
+                _ => {}
+            }
+        }
+
+        let stage = stage.ok_or(ReflectionError::InvalidSpirv("No entry point"))?;
+        let module = Module {
+            types,
+            constants,
+            decorations,
+            member_offsets,
+        };
+
+        let mut reflection = ShaderReflection {
+            stage,
+            ..Default::default()
+        };
+        for (id, pointer, storage) in variables {
+            let Some(Type::Pointer { pointee }) = module.types.get(&pointer) else {
+                return Err(ReflectionError::InvalidSpirv("Variable is not a pointer"));
+            };
+            let pointee = *pointee;
+            let decoration = module.decorations.get(&id);
+            match storage {
+                StorageClass::UniformConstant
+                | StorageClass::Uniform
+                | StorageClass::StorageBuffer => {
+                    let Some(decoration) = decoration else {
+                        continue;
+                    };
+                    let (Some(set), Some(binding)) = (decoration.set, decoration.binding) else {
+                        continue;
+                    };
+                    let (descriptor_type, count) = module.descriptor_type(pointee, storage)?;
+                    reflection.bindings.push(DescriptorBinding {
+                        set,
+                        binding,
+                        descriptor_type,
+                        count,
+                        stages: stage,
+                    });
+                }
+                StorageClass::PushConstant => {
+                    reflection.push_constant_size = module.size_of(pointee);
+                }
+                StorageClass::Input if stage == vk::ShaderStageFlags::VERTEX => {
+                    let Some(location) = decoration.and_then(|d| d.location) else {
+                        continue;
+                    };
+                    if decoration.is_some_and(|d| d.builtin) {
+                        continue;
+                    }
+                    // A matrix takes one location for every column
+                    let (column, columns) = match module.types.get(&pointee) {
+                        Some(Type::Matrix { column, count }) => (*column, *count),
+                        _ => (pointee, 1),
+                    };
+                    let (numeric_type, components) = module.vertex_input_type(column)?;
+                    for i in 0..columns {
+                        reflection.inputs.push(VertexInput {
+                            location: location + i,
+                            numeric_type,
+                            components,
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+        reflection.bindings.sort_by_key(|b| (b.set, b.binding));
+        reflection.inputs.sort_by_key(|i| i.location);
+        Ok(reflection)
+    }
+}
+
+struct Module {
+    types: HashMap<u32, Type>,
+    constants: HashMap<u32, u32>,
+    decorations: HashMap<u32, Decorations>,
+    member_offsets: HashMap<(u32, u32), u32>,
+}
+
+impl Module {
+    fn descriptor_type(
+        &self,
+        ty: u32,
+        storage: StorageClass,
+    ) -> Result<(vk::DescriptorType, u32), ReflectionError> {
+        let decoration = self.decorations.get(&ty);
+        let descriptor_type =
+            match self.types.get(&ty) {
+                Some(Type::Array { element, length }) => {
+                    let (descriptor_type, _) = self.descriptor_type(*element, storage)?;
+                    let length = self.constants.get(length).copied().ok_or(
+                        ReflectionError::InvalidSpirv("Array length is not a constant"),
+                    )?;
+                    return Ok((descriptor_type, length));
+                }
+                Some(Type::RuntimeArray { element }) => {
+                    let (descriptor_type, _) = self.descriptor_type(*element, storage)?;
+                    return Ok((descriptor_type, 0));
+                }
+                Some(Type::SampledImage) => vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                Some(Type::Sampler) => vk::DescriptorType::SAMPLER,
+                Some(Type::Image { dim, sampled }) => match (dim, sampled) {
+                    (Dim::DimBuffer, 2) => vk::DescriptorType::STORAGE_TEXEL_BUFFER,
+                    (Dim::DimBuffer, _) => vk::DescriptorType::UNIFORM_TEXEL_BUFFER,
+                    (Dim::DimSubpassData, _) => vk::DescriptorType::INPUT_ATTACHMENT,
+                    (_, 2) => vk::DescriptorType::STORAGE_IMAGE,
+                    _ => vk::DescriptorType::SAMPLED_IMAGE,
+                },
+                Some(Type::AccelerationStructure) => vk::DescriptorType::ACCELERATION_STRUCTURE_KHR,
+                Some(Type::Struct { .. }) => match storage {
+                    StorageClass::StorageBuffer => vk::DescriptorType::STORAGE_BUFFER,
+                    // Older GLSL versions declare storage buffers as `Uniform` with `BufferBlock`
+                    _ if decoration.is_some_and(|d| d.buffer_block) => {
+                        vk::DescriptorType::STORAGE_BUFFER
+                    }
+                    _ => vk::DescriptorType::UNIFORM_BUFFER,
+                },
+                _ => return Err(ReflectionError::InvalidSpirv("Unknown descriptor type")),
+            };
+        Ok((descriptor_type, 1))
+    }
+
+    fn vertex_input_type(&self, ty: u32) -> Result<(NumericType, u32), ReflectionError> {
+        match self.types.get(&ty) {
+            Some(Type::Scalar(numeric_type, _)) => Ok((*numeric_type, 1)),
+            Some(Type::Vector { component, count }) => {
+                let (numeric_type, _) = self.vertex_input_type(*component)?;
+                Ok((numeric_type, *count))
+            }
+            _ => Err(ReflectionError::InvalidSpirv(
+                "Unsupported vertex input type",
+            )),
+        }
+    }
+
+    /// Size in bytes as laid out in a Block, Uses the Offset/ArrayStride decorations where they exist
+    fn size_of(&self, ty: u32) -> u32 {
+        match self.types.get(&ty) {
+            Some(Type::Scalar(_, width)) => width / 8,
+            Some(Type::Vector { component, count }) => self.size_of(*component) * count,
+            Some(Type::Matrix { column, count }) => {
+                // Columns are aligned like vec4 when they have 3 components
+                let column_size = match self.types.get(column) {
+                    Some(Type::Vector {
+                        component,
+                        count: 3,
+                    }) => self.size_of(*component) * 4,
+                    _ => self.size_of(*column),
+                };
+                column_size * count
+            }
+            Some(Type::Array { element, length }) => {
+                let length = self.constants.get(length).copied().unwrap_or(1);
+                let stride = self
+                    .decorations
+                    .get(&ty)
+                    .and_then(|d| d.array_stride)
+                    .unwrap_or_else(|| self.size_of(*element));
+                stride * length
+            }
+            Some(Type::Struct { members }) => members
+                .iter()
+                .enumerate()
+                .map(|(index, member)| {
+                    let offset = self
+                        .member_offsets
+                        .get(&(ty, index as u32))
+                        .copied()
+                        .unwrap_or(0);
+                    offset + self.size_of(*member)
+                })
+                .max()
+                .unwrap_or(0),
+            _ => 0,
+        }
+    }
+}
+
+/// All stages of a Pipeline merged together
+#[derive(Clone, Debug, Default)]
+pub struct PipelineReflection {
+    pub bindings: Vec<DescriptorBinding>,
+    pub push_constant_ranges: Vec<vk::PushConstantRange>,
+    /// Inputs of the Vertex Shader
+    pub inputs: Vec<VertexInput>,
+}
+
+impl PipelineReflection {
+    pub fn from_files(files: &[&Path]) -> Result<Self, ReflectionError> {
+        let shaders = files
+            .iter()
+            .map(|file| ShaderReflection::from_file(file))
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::new(&shaders)
+    }
+
+    pub fn new(shaders: &[ShaderReflection]) -> Result<Self, ReflectionError> {
+        let mut bindings: Vec<DescriptorBinding> = Vec::new();
+        let mut push_constant_size = 0;
+        let mut push_constant_stages = vk::ShaderStageFlags::empty();
+        let mut inputs = Vec::new();
+
+        for shader in shaders {
+            for binding in shader.bindings.iter() {
+                match bindings
+                    .iter_mut()
+                    .find(|b| b.set == binding.set && b.binding == binding.binding)
+                {
+                    Some(existing) => {
+                        if existing.descriptor_type != binding.descriptor_type
+                            || existing.count != binding.count
+                        {
+                            return Err(ReflectionError::BindingMismatch {
+                                set: binding.set,
+                                binding: binding.binding,
+                            });
+                        }
+                        existing.stages |= binding.stages;
+                    }
+                    None => bindings.push(*binding),
+                }
+            }
+            if shader.push_constant_size > 0 {
+                push_constant_size = push_constant_size.max(shader.push_constant_size);
+                push_constant_stages |= shader.stage;
+            }
+            if shader.stage == vk::ShaderStageFlags::VERTEX {
+                inputs.clone_from(&shader.inputs);
+            }
+        }
+        bindings.sort_by_key(|b| (b.set, b.binding));
+
+        // A single range for all stages, Every stage can then push the whole block
+        let push_constant_ranges = if push_constant_size > 0 {
+            vec![vk::PushConstantRange::default()
+                .stage_flags(push_constant_stages)
+                .size(push_constant_size)]
+        } else {
+            Vec::new()
+        };
+
+        Ok(Self {
+            bindings,
+            push_constant_ranges,
+            inputs,
+        })
+    }
+
+    /// Returns true when both need the same Descriptor Set and Pipeline Layouts
+    pub fn is_layout_compatible(&self, other: &PipelineReflection) -> bool {
+        self.bindings == other.bindings
+            && self.push_constant_ranges.len() == other.push_constant_ranges.len()
+            && self
+                .push_constant_ranges
+                .iter()
+                .zip(other.push_constant_ranges.iter())
+                .all(|(a, b)| {
+                    a.stage_flags == b.stage_flags && a.offset == b.offset && a.size == b.size
+                })
+    }
+
+    /// The highest set used + 1
+    pub fn set_count(&self) -> u32 {
+        self.bindings.iter().map(|b| b.set + 1).max().unwrap_or(0)
+    }
+
+    pub fn set_layout_bindings(&self, set: u32) -> Vec<vk::DescriptorSetLayoutBinding<'static>> {
+        self.bindings
+            .iter()
+            .filter(|b| b.set == set)
+            .map(|b| {
+                vk::DescriptorSetLayoutBinding::default()
+                    .binding(b.binding)
+                    .descriptor_type(b.descriptor_type)
+                    .descriptor_count(b.count)
+                    .stage_flags(b.stages)
+            })
+            .collect()
+    }
+
+    /// The layout comes from the layout cache of the Instance, So it must not be destroyed
+    pub fn create_descriptor_set_layout(
+        &self,
+        instance: &VulkanInstance,
+        set: u32,
+    ) -> vk::DescriptorSetLayout {
+        let bindings = self.set_layout_bindings(set);
+        instance
+            .descriptor_layout_cache
+            .get(&instance.device, &bindings)
+    }
+
+    /// Checks that every input of the Vertex Shader gets fed by an attribute of a fitting format.
+    ///
+    /// Formats with more components than the shader reads are also treated as an error, As they usually mean
+    /// the format does not match the Rust field (e.g. an `[f32; 3]` described as `R32G32B32A32_SFLOAT`)
+    pub fn validate_vertex_input(
+        &self,
+        attributes: &[vk::VertexInputAttributeDescription],
+    ) -> Result<(), ReflectionError> {
+        for input in self.inputs.iter() {
+            let Some(attribute) = attributes.iter().find(|a| a.location == input.location) else {
+                return Err(ReflectionError::MissingAttribute {
+                    location: input.location,
+                });
+            };
+            let Some((numeric_type, components)) = format_info(attribute.format) else {
+                // We don't know this format, Leave it to the validation layers
+                continue;
+            };
+            if numeric_type != input.numeric_type || components > input.components {
+                return Err(ReflectionError::FormatMismatch {
+                    location: input.location,
+                    format: attribute.format,
+                    input: *input,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How the shader sees an vertex attribute of this format
+fn format_info(format: vk::Format) -> Option<(NumericType, u32)> {
+    let info = match format {
+        vk::Format::R32_SFLOAT | vk::Format::R16_SFLOAT | vk::Format::R8_UNORM => {
+            (NumericType::Float, 1)
+        }
+        vk::Format::R32G32_SFLOAT | vk::Format::R16G16_SFLOAT | vk::Format::R8G8_UNORM => {
+            (NumericType::Float, 2)
+        }
+        vk::Format::R32G32B32_SFLOAT => (NumericType::Float, 3),
+        vk::Format::R32G32B32A32_SFLOAT
+        | vk::Format::R16G16B16A16_SFLOAT
+        | vk::Format::R8G8B8A8_UNORM
+        | vk::Format::R8G8B8A8_SNORM
+        | vk::Format::B8G8R8A8_UNORM
+        | vk::Format::A2B10G10R10_UNORM_PACK32 => (NumericType::Float, 4),
+        vk::Format::R32_SINT => (NumericType::SInt, 1),
+        vk::Format::R32G32_SINT => (NumericType::SInt, 2),
+        vk::Format::R32G32B32_SINT => (NumericType::SInt, 3),
+        vk::Format::R32G32B32A32_SINT | vk::Format::R8G8B8A8_SINT => (NumericType::SInt, 4),
+        vk::Format::R32_UINT => (NumericType::UInt, 1),
+        vk::Format::R32G32_UINT => (NumericType::UInt, 2),
+        vk::Format::R32G32B32_UINT => (NumericType::UInt, 3),
+        vk::Format::R32G32B32A32_UINT
+        | vk::Format::R8G8B8A8_UINT
+        | vk::Format::R16G16B16A16_UINT => (NumericType::UInt, 4),
+        _ => return None,
+    };
+    Some(info)
+}
+
+pub fn conv_shader_stage(model: ExecutionModel) -> Result<vk::ShaderStageFlags, ReflectionError> {
+    let stage = match model {
+        ExecutionModel::Vertex => vk::ShaderStageFlags::VERTEX,
+        ExecutionModel::TessellationControl => vk::ShaderStageFlags::TESSELLATION_CONTROL,
+        ExecutionModel::TessellationEvaluation => vk::ShaderStageFlags::TESSELLATION_EVALUATION,
+        ExecutionModel::Geometry => vk::ShaderStageFlags::GEOMETRY,
+        ExecutionModel::Fragment => vk::ShaderStageFlags::FRAGMENT,
+        ExecutionModel::GLCompute => vk::ShaderStageFlags::COMPUTE,
+        ExecutionModel::Kernel => return Err(ReflectionError::UnsupportedExecutionModel(model)),
+        ExecutionModel::TaskNV => vk::ShaderStageFlags::TASK_NV,
+        ExecutionModel::MeshNV => vk::ShaderStageFlags::MESH_NV,
+        ExecutionModel::RayGenerationNV => vk::ShaderStageFlags::RAYGEN_NV,
+        ExecutionModel::IntersectionNV => vk::ShaderStageFlags::INTERSECTION_NV,
+        ExecutionModel::AnyHitNV => vk::ShaderStageFlags::ANY_HIT_NV,
+        ExecutionModel::ClosestHitNV => vk::ShaderStageFlags::CLOSEST_HIT_NV,
+        ExecutionModel::MissNV => vk::ShaderStageFlags::MISS_NV,
+        ExecutionModel::CallableNV => vk::ShaderStageFlags::CALLABLE_NV,
+        ExecutionModel::TaskEXT => vk::ShaderStageFlags::TASK_EXT,
+        ExecutionModel::MeshEXT => vk::ShaderStageFlags::MESH_EXT,
+    };
+    Ok(stage)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binding(
+        binding: u32,
+        descriptor_type: vk::DescriptorType,
+        stages: vk::ShaderStageFlags,
+    ) -> DescriptorBinding {
+        DescriptorBinding {
+            set: 0,
+            binding,
+            descriptor_type,
+            count: 1,
+            stages,
+        }
+    }
+
+    fn vertex_shader() -> ShaderReflection {
+        ShaderReflection {
+            stage: vk::ShaderStageFlags::VERTEX,
+            bindings: vec![binding(
+                0,
+                vk::DescriptorType::UNIFORM_BUFFER,
+                vk::ShaderStageFlags::VERTEX,
+            )],
+            push_constant_size: 64,
+            inputs: vec![
+                VertexInput {
+                    location: 0,
+                    numeric_type: NumericType::Float,
+                    components: 3,
+                },
+                VertexInput {
+                    location: 1,
+                    numeric_type: NumericType::Float,
+                    components: 2,
+                },
+            ],
+        }
+    }
+
+    fn fragment_shader() -> ShaderReflection {
+        ShaderReflection {
+            stage: vk::ShaderStageFlags::FRAGMENT,
+            bindings: vec![
+                binding(
+                    1,
+                    vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                    vk::ShaderStageFlags::FRAGMENT,
+                ),
+                binding(
+                    0,
+                    vk::DescriptorType::UNIFORM_BUFFER,
+                    vk::ShaderStageFlags::FRAGMENT,
+                ),
+            ],
+            push_constant_size: 80,
+            inputs: Vec::new(),
+        }
+    }
+
+    fn attribute(location: u32, format: vk::Format) -> vk::VertexInputAttributeDescription {
+        vk::VertexInputAttributeDescription {
+            location,
+            binding: 0,
+            format,
+            offset: 0,
+        }
+    }
+
+    #[test]
+    fn merges_stages() {
+        let pipeline = PipelineReflection::new(&[vertex_shader(), fragment_shader()]).unwrap();
+
+        assert_eq!(
+            pipeline.bindings,
+            vec![
+                binding(
+                    0,
+                    vk::DescriptorType::UNIFORM_BUFFER,
+                    vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                ),
+                binding(
+                    1,
+                    vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                    vk::ShaderStageFlags::FRAGMENT,
+                ),
+            ]
+        );
+        assert_eq!(pipeline.set_count(), 1);
+
+        // One range covering the biggest block
+        assert_eq!(pipeline.push_constant_ranges.len(), 1);
+        let range = pipeline.push_constant_ranges[0];
+        assert_eq!(range.offset, 0);
+        assert_eq!(range.size, 80);
+        assert_eq!(
+            range.stage_flags,
+            vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT
+        );
+        assert_eq!(pipeline.inputs, vertex_shader().inputs);
+    }
+
+    #[test]
+    fn push_constants_only_in_using_stages() {
+        let mut fragment = fragment_shader();
+        fragment.push_constant_size = 0;
+        let pipeline = PipelineReflection::new(&[vertex_shader(), fragment]).unwrap();
+        assert_eq!(pipeline.push_constant_ranges.len(), 1);
+        assert_eq!(pipeline.push_constant_ranges[0].size, 64);
+        assert_eq!(
+            pipeline.push_constant_ranges[0].stage_flags,
+            vk::ShaderStageFlags::VERTEX
+        );
+
+        let mut vertex = vertex_shader();
+        vertex.push_constant_size = 0;
+        let pipeline = PipelineReflection::new(&[vertex]).unwrap();
+        assert!(pipeline.push_constant_ranges.is_empty());
+    }
+
+    #[test]
+    fn binding_mismatch() {
+        let mut fragment = fragment_shader();
+        fragment.bindings[1].descriptor_type = vk::DescriptorType::STORAGE_BUFFER;
+        let result = PipelineReflection::new(&[vertex_shader(), fragment]);
+        assert!(matches!(
+            result,
+            Err(ReflectionError::BindingMismatch { set: 0, binding: 0 })
+        ));
+    }
+
+    #[test]
+    fn validates_vertex_input() {
+        let pipeline = PipelineReflection::new(&[vertex_shader()]).unwrap();
+
+        let valid = [
+            attribute(0, vk::Format::R32G32B32_SFLOAT),
+            attribute(1, vk::Format::R32G32_SFLOAT),
+            // Attributes the shader does not read are fine
+            attribute(2, vk::Format::R32G32B32A32_SFLOAT),
+        ];
+        assert!(pipeline.validate_vertex_input(&valid).is_ok());
+
+        let missing = [attribute(0, vk::Format::R32G32B32_SFLOAT)];
+        assert!(matches!(
+            pipeline.validate_vertex_input(&missing),
+            Err(ReflectionError::MissingAttribute { location: 1 })
+        ));
+
+        let wrong_type = [
+            attribute(0, vk::Format::R32G32B32_SINT),
+            attribute(1, vk::Format::R32G32_SFLOAT),
+        ];
+        assert!(matches!(
+            pipeline.validate_vertex_input(&wrong_type),
+            Err(ReflectionError::FormatMismatch { location: 0, .. })
+        ));
+
+        let too_many_components = [
+            attribute(0, vk::Format::R32G32B32_SFLOAT),
+            attribute(1, vk::Format::R32G32B32A32_SFLOAT),
+        ];
+        assert!(matches!(
+            pipeline.validate_vertex_input(&too_many_components),
+            Err(ReflectionError::FormatMismatch { location: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_kernels() {
+        assert!(matches!(
+            conv_shader_stage(ExecutionModel::Kernel),
+            Err(ReflectionError::UnsupportedExecutionModel(
+                ExecutionModel::Kernel
+            ))
+        ));
+        assert_eq!(
+            conv_shader_stage(ExecutionModel::GLCompute).unwrap(),
+            vk::ShaderStageFlags::COMPUTE
+        );
+
+        // Header followed by `OpEntryPoint Kernel %1 "main"`
+        let code = [
+            spirv::MAGIC_NUMBER,
+            0x0001_0000,
+            0,
+            2,
+            0,
+            (5 << 16) | Op::EntryPoint as u32,
+            ExecutionModel::Kernel as u32,
+            1,
+            u32::from_le_bytes(*b"main"),
+            0,
+        ];
+        assert!(matches!(
+            ShaderReflection::new(&code),
+            Err(ReflectionError::UnsupportedExecutionModel(
+                ExecutionModel::Kernel
+            ))
+        ));
+    }
+}
@@ -0,0 +1,1454 @@
+use std::ffi::CString;
+
+use ash::vk;
+
+use crate::{
+    buffer::VulkanBuffer, debug, frame::FrameContext, image::VulkanImage, instance::VulkanInstance,
+};
+
+pub use resource::*;
+
+mod resource;
+
+/// A Frame described as a list of passes, Every pass declares which images and buffers it reads and writes.
+///
+/// From these declarations the Graph:
+/// - culls passes whose results are never used,
+/// - creates transient images and lets images which are never alive at the same time share memory,
+/// - records all barriers and layout transitions in between the passes.
+///
+/// Passes run in the order they were added. The actual commands are recorded by the callback given to `execute`
+pub struct RenderGraph {
+    images: Vec<GraphImage>,
+    buffers: Vec<GraphBuffer>,
+    passes: Vec<GraphPass>,
+    compiled: Option<CompiledGraph>,
+}
+
+struct GraphImage {
+    name: String,
+    source: ImageSource,
+}
+
+struct GraphBuffer {
+    name: String,
+    source: BufferSource,
+}
+
+struct ImageUse {
+    image: ImageHandle,
+    access: ImageAccess,
+    load: AttachmentLoad,
+}
+
+struct BufferUse {
+    buffer: BufferHandle,
+    access: BufferAccess,
+}
+
+impl ImageUse {
+    /// Attachments which get loaded also depend on what was written before
+    fn reads(&self, pass_type: PassType) -> bool {
+        !self.access.state(pass_type).write || matches!(self.load, AttachmentLoad::Load)
+    }
+}
+
+struct GraphPass {
+    name: CString,
+    pass_type: PassType,
+    images: Vec<ImageUse>,
+    buffers: Vec<BufferUse>,
+    side_effects: bool,
+}
+
+pub struct PassBuilder<'a> {
+    graph: &'a mut RenderGraph,
+    pass: GraphPass,
+}
+
+impl<'a> PassBuilder<'a> {
+    pub fn color_attachment(mut self, image: ImageHandle, load: AttachmentLoad) -> Self {
+        self.pass.images.push(ImageUse {
+            image,
+            access: ImageAccess::ColorAttachment,
+            load,
+        });
+        self
+    }
+
+    pub fn depth_attachment(mut self, image: ImageHandle, load: AttachmentLoad) -> Self {
+        self.pass.images.push(ImageUse {
+            image,
+            access: ImageAccess::DepthAttachment,
+            load,
+        });
+        self
+    }
+
+    /// Resolves the multisampled color attachment added before this into `image`, In the same order
+    pub fn color_resolve(mut self, image: ImageHandle) -> Self {
+        self.pass.images.push(ImageUse {
+            image,
+            access: ImageAccess::ColorResolve,
+            load: AttachmentLoad::DontCare,
+        });
+        self
+    }
+
+    /// Resolves the multisampled depth attachment into `image`
+    pub fn depth_resolve(mut self, image: ImageHandle) -> Self {
+        self.pass.images.push(ImageUse {
+            image,
+            access: ImageAccess::DepthResolve,
+            load: AttachmentLoad::DontCare,
+        });
+        self
+    }
+
+    /// Uses the image as read only depth attachment
+    pub fn depth_read(mut self, image: ImageHandle) -> Self {
+        self.pass.images.push(ImageUse {
+            image,
+            access: ImageAccess::DepthRead,
+            load: AttachmentLoad::Load,
+        });
+        self
+    }
+
+    /// Any other use of an image, Attachments used this way keep their contents
+    pub fn image(mut self, image: ImageHandle, access: ImageAccess) -> Self {
+        self.pass.images.push(ImageUse {
+            image,
+            access,
+            load: AttachmentLoad::Load,
+        });
+        self
+    }
+
+    pub fn buffer(mut self, buffer: BufferHandle, access: BufferAccess) -> Self {
+        self.pass.buffers.push(BufferUse { buffer, access });
+        self
+    }
+
+    /// Never cull this pass, e.g. when it writes something the graph does not know about
+    pub fn side_effects(mut self) -> Self {
+        self.pass.side_effects = true;
+        self
+    }
+
+    pub fn build(self) -> PassHandle {
+        let graph = self.graph;
+        graph.passes.push(self.pass);
+        graph.invalidate();
+        PassHandle(graph.passes.len() - 1)
+    }
+}
+
+/// Given to the callback of `RenderGraph::execute` for every pass which was not culled
+pub struct PassContext<'a> {
+    pub command_buffer: vk::CommandBuffer,
+    /// The acquired Swapchain image, Only meaningful for the Swapchain itself
+    pub image_index: u32,
+    /// The Frame in flight, Use this to pick per frame resources
+    pub frame_index: usize,
+    /// Size of the attachments, For passes without attachments the size of the Swapchain
+    pub extent: vk::Extent2D,
+    graph: &'a RenderGraph,
+    instance: &'a VulkanInstance,
+}
+
+impl<'a> PassContext<'a> {
+    pub fn image(&self, image: ImageHandle) -> vk::Image {
+        self.graph
+            .resolve_image(self.instance, image, self.image_index)
+            .0
+    }
+
+    pub fn image_view(&self, image: ImageHandle) -> vk::ImageView {
+        self.graph
+            .resolve_image(self.instance, image, self.image_index)
+            .1
+    }
+
+    pub fn buffer(&self, buffer: BufferHandle) -> vk::Buffer {
+        self.graph.resolve_buffer(buffer)
+    }
+
+    /// The Frame which is recorded, e.g. to upload data for this Frame only
+    pub fn frame(&self) -> &FrameContext {
+        &self.instance.frames[self.frame_index]
+    }
+}
+
+struct TransientImage {
+    image: vk::Image,
+    image_view: vk::ImageView,
+    extent: vk::Extent2D,
+}
+
+struct ImageBarrier {
+    image: ImageHandle,
+    old_layout: vk::ImageLayout,
+    new_layout: vk::ImageLayout,
+    src_stage: vk::PipelineStageFlags2,
+    src_access: vk::AccessFlags2,
+    dst_stage: vk::PipelineStageFlags2,
+    dst_access: vk::AccessFlags2,
+}
+
+struct BufferBarrier {
+    buffer: BufferHandle,
+    src_stage: vk::PipelineStageFlags2,
+    src_access: vk::AccessFlags2,
+    dst_stage: vk::PipelineStageFlags2,
+    dst_access: vk::AccessFlags2,
+}
+
+struct CompiledAttachment {
+    image: ImageHandle,
+    layout: vk::ImageLayout,
+    load_op: vk::AttachmentLoadOp,
+    store_op: vk::AttachmentStoreOp,
+    clear_value: vk::ClearValue,
+    /// The single sampled image, Its layout and how the samples get combined
+    resolve: Option<(ImageHandle, vk::ImageLayout, vk::ResolveModeFlags)>,
+}
+
+struct CompiledPass {
+    pass: usize,
+    image_barriers: Vec<ImageBarrier>,
+    buffer_barriers: Vec<BufferBarrier>,
+    // Only used by Graphics passes
+    color_attachments: Vec<CompiledAttachment>,
+    depth_attachment: Option<CompiledAttachment>,
+    extent: vk::Extent2D,
+}
+
+struct CompiledGraph {
+    extent: vk::Extent2D,
+    images: Vec<Option<TransientImage>>,
+    buffers: Vec<Option<VulkanBuffer>>,
+    memory: Vec<vk::DeviceMemory>,
+    passes: Vec<CompiledPass>,
+    final_image_barriers: Vec<ImageBarrier>,
+}
+
+/// The last synchronized state of a resource while walking through the passes
+#[derive(Clone, Copy)]
+struct TrackedState {
+    layout: vk::ImageLayout,
+    write_stage: vk::PipelineStageFlags2,
+    write_access: vk::AccessFlags2,
+    // Reads since the last write, These are already synchronized against it
+    read_stage: vk::PipelineStageFlags2,
+    read_access: vk::AccessFlags2,
+}
+
+impl TrackedState {
+    const fn new(
+        layout: vk::ImageLayout,
+        write_stage: vk::PipelineStageFlags2,
+        write_access: vk::AccessFlags2,
+    ) -> Self {
+        Self {
+            layout,
+            write_stage,
+            write_access,
+            read_stage: vk::PipelineStageFlags2::NONE,
+            read_access: vk::AccessFlags2::NONE,
+        }
+    }
+
+    /// Returns `(src_stage, src_access, old_layout)` if a barrier is needed before the access
+    fn transition(
+        &mut self,
+        access: AccessState,
+    ) -> Option<(vk::PipelineStageFlags2, vk::AccessFlags2, vk::ImageLayout)> {
+        let old_layout = self.layout;
+        if !access.write && access.layout == self.layout {
+            // Read after read, Only needs to wait for the last write
+            if self.read_stage.contains(access.stage) && self.read_access.contains(access.access) {
+                return None;
+            }
+            self.read_stage |= access.stage;
+            self.read_access |= access.access;
+            if self.write_stage.is_empty() {
+                return None;
+            }
+            return Some((self.write_stage, self.write_access, old_layout));
+        }
+        // Writes and layout transitions also have to wait for all reads
+        let src_stage = self.write_stage | self.read_stage;
+        let src_access = self.write_access;
+        *self = if access.write {
+            Self::new(access.layout, access.stage, access.access)
+        } else {
+            // The layout transition itself is a write, So later readers wait on this stage
+            Self {
+                layout: access.layout,
+                write_stage: access.stage,
+                write_access: vk::AccessFlags2::NONE,
+                read_stage: access.stage,
+                read_access: access.access,
+            }
+        };
+        Some((src_stage, src_access, old_layout))
+    }
+}
+
+/// When and how every resource is used by the passes which are not culled
+struct ResourceUsage {
+    first_use: Vec<usize>,
+    last_use: Vec<usize>,
+    image_usage: Vec<vk::ImageUsageFlags>,
+    buffer_usage: Vec<vk::BufferUsageFlags>,
+}
+
+/// Images alive at different times of the frame can share memory
+struct MemoryBlock {
+    requirements: vk::MemoryRequirements,
+    last_use: usize,
+    images: Vec<usize>,
+}
+
+impl Default for RenderGraph {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RenderGraph {
+    pub fn new() -> Self {
+        Self {
+            images: Vec::new(),
+            buffers: Vec::new(),
+            passes: Vec::new(),
+            compiled: None,
+        }
+    }
+
+    /// The image we present, Or the offscreen image when running headless
+    pub fn import_backbuffer(&mut self) -> ImageHandle {
+        self.add_image("Backbuffer", ImageSource::Backbuffer)
+    }
+
+    pub fn import_image(&mut self, name: &str, image: ImportedImage) -> ImageHandle {
+        self.add_image(name, ImageSource::Imported(image))
+    }
+
+    pub fn create_image(&mut self, name: &str, desc: ImageDesc) -> ImageHandle {
+        self.add_image(name, ImageSource::Transient(desc))
+    }
+
+    pub fn import_buffer(&mut self, name: &str, buffer: vk::Buffer) -> BufferHandle {
+        self.add_buffer(name, BufferSource::Imported(buffer))
+    }
+
+    /// Creates a device local buffer, Its usage flags are collected from the passes using it
+    pub fn create_buffer(&mut self, name: &str, size: vk::DeviceSize) -> BufferHandle {
+        self.add_buffer(name, BufferSource::Transient(size))
+    }
+
+    pub fn add_pass(&mut self, name: &str, pass_type: PassType) -> PassBuilder<'_> {
+        PassBuilder {
+            graph: self,
+            pass: GraphPass {
+                name: CString::new(name).expect("Pass name contains a null byte"),
+                pass_type,
+                images: Vec::new(),
+                buffers: Vec::new(),
+                side_effects: false,
+            },
+        }
+    }
+
+    fn add_image(&mut self, name: &str, source: ImageSource) -> ImageHandle {
+        self.images.push(GraphImage {
+            name: name.to_owned(),
+            source,
+        });
+        self.invalidate();
+        ImageHandle(self.images.len() - 1)
+    }
+
+    fn add_buffer(&mut self, name: &str, source: BufferSource) -> BufferHandle {
+        self.buffers.push(GraphBuffer {
+            name: name.to_owned(),
+            source,
+        });
+        self.invalidate();
+        BufferHandle(self.buffers.len() - 1)
+    }
+
+    /// Forces the Graph to be compiled again before the next execution
+    pub fn invalidate(&mut self) {
+        if let Some(compiled) = &mut self.compiled {
+            // We can not destroy it here without a device, So just mark it as outdated
+            compiled.extent = vk::Extent2D::default();
+        }
+    }
+
+    fn needs_compile(&self, instance: &VulkanInstance) -> bool {
+        match &self.compiled {
+            Some(compiled) => compiled.extent != instance.surface_resolution,
+            None => true,
+        }
+    }
+
+    /// Returns which passes are still needed, A pass is needed when something outside of the graph depends on it
+    fn cull(&self) -> Vec<bool> {
+        #[derive(Clone, Copy)]
+        enum Resource {
+            Image(usize),
+            Buffer(usize),
+        }
+
+        let mut pass_refs: Vec<usize> = self
+            .passes
+            .iter()
+            .map(|pass| {
+                let image_writes = pass
+                    .images
+                    .iter()
+                    .filter(|u| u.access.state(pass.pass_type).write)
+                    .count();
+                let buffer_writes = pass
+                    .buffers
+                    .iter()
+                    .filter(|u| u.access.state(pass.pass_type).write)
+                    .count();
+                image_writes + buffer_writes
+            })
+            .collect();
+        let mut image_refs = vec![0; self.images.len()];
+        let mut buffer_refs = vec![0; self.buffers.len()];
+        for pass in self.passes.iter() {
+            for image_use in pass.images.iter().filter(|u| u.reads(pass.pass_type)) {
+                image_refs[image_use.image.0] += 1;
+            }
+            for buffer_use in pass
+                .buffers
+                .iter()
+                .filter(|u| !u.access.state(pass.pass_type).write)
+            {
+                buffer_refs[buffer_use.buffer.0] += 1;
+            }
+        }
+
+        let mut alive = vec![true; self.passes.len()];
+        let mut unused = Vec::new();
+
+        let cull_pass = |index: usize,
+                         alive: &mut Vec<bool>,
+                         unused: &mut Vec<Resource>,
+                         image_refs: &mut Vec<usize>,
+                         buffer_refs: &mut Vec<usize>| {
+            alive[index] = false;
+            let pass = &self.passes[index];
+            for image_use in pass.images.iter().filter(|u| u.reads(pass.pass_type)) {
+                let image = image_use.image.0;
+                image_refs[image] -= 1;
+                if image_refs[image] == 0 && self.images[image].source.is_transient() {
+                    unused.push(Resource::Image(image));
+                }
+            }
+            for buffer_use in pass
+                .buffers
+                .iter()
+                .filter(|u| !u.access.state(pass.pass_type).write)
+            {
+                let buffer = buffer_use.buffer.0;
+                buffer_refs[buffer] -= 1;
+                if buffer_refs[buffer] == 0 && self.buffers[buffer].source.is_transient() {
+                    unused.push(Resource::Buffer(buffer));
+                }
+            }
+        };
+
+        // Passes which write nothing at all
+        for (index, pass) in self.passes.iter().enumerate() {
+            if pass_refs[index] == 0 && !pass.side_effects {
+                cull_pass(
+                    index,
+                    &mut alive,
+                    &mut unused,
+                    &mut image_refs,
+                    &mut buffer_refs,
+                );
+            }
+        }
+        // Imported resources are used outside of the graph, So only transient ones can be unused
+        for (index, image) in self.images.iter().enumerate() {
+            if image_refs[index] == 0 && image.source.is_transient() {
+                unused.push(Resource::Image(index));
+            }
+        }
+        for (index, buffer) in self.buffers.iter().enumerate() {
+            if buffer_refs[index] == 0 && buffer.source.is_transient() {
+                unused.push(Resource::Buffer(index));
+            }
+        }
+
+        while let Some(resource) = unused.pop() {
+            for index in 0..self.passes.len() {
+                if !alive[index] {
+                    continue;
+                }
+                let pass = &self.passes[index];
+                let writes = match resource {
+                    Resource::Image(image) => pass
+                        .images
+                        .iter()
+                        .filter(|u| u.image.0 == image && u.access.state(pass.pass_type).write)
+                        .count(),
+                    Resource::Buffer(buffer) => pass
+                        .buffers
+                        .iter()
+                        .filter(|u| u.buffer.0 == buffer && u.access.state(pass.pass_type).write)
+                        .count(),
+                };
+                if writes == 0 {
+                    continue;
+                }
+                pass_refs[index] -= writes;
+                if pass_refs[index] == 0 && !pass.side_effects {
+                    log::debug!("Render Graph: Culling pass {:?}", pass.name);
+                    cull_pass(
+                        index,
+                        &mut alive,
+                        &mut unused,
+                        &mut image_refs,
+                        &mut buffer_refs,
+                    );
+                }
+            }
+        }
+        alive
+    }
+
+    /// Culls unused passes, Creates all transient resources and plans the barriers.
+    /// Called by `execute` when the graph changed or the Swapchain got recreated
+    pub fn compile(&mut self, instance: &VulkanInstance) {
+        if let Some(compiled) = self.compiled.take() {
+            unsafe { instance.device.device_wait_idle().unwrap() };
+            Self::destroy_compiled(compiled, &instance.device);
+        }
+        let device = &instance.device;
+        let swapchain_extent = instance.surface_resolution;
+
+        let alive = self.cull();
+        let order: Vec<usize> = (0..self.passes.len()).filter(|&i| alive[i]).collect();
+
+        let ResourceUsage {
+            first_use,
+            last_use,
+            image_usage,
+            buffer_usage,
+        } = self.resource_usage(&order);
+
+        let mut images: Vec<Option<TransientImage>> = self.images.iter().map(|_| None).collect();
+        let mut requirements = vec![vk::MemoryRequirements::default(); self.images.len()];
+        for (index, graph_image) in self.images.iter().enumerate() {
+            let ImageSource::Transient(desc) = graph_image.source else {
+                continue;
+            };
+            if first_use[index] == usize::MAX {
+                continue;
+            }
+            let extent = desc.size.resolve(swapchain_extent);
+            let image = VulkanImage::create_image(
+                device,
+                desc.format,
+                extent,
+                1,
+                desc.samples,
+                image_usage[index],
+            );
+            debug::set_object_name(&instance.debug_utils_device, image, &graph_image.name);
+            requirements[index] = unsafe { device.get_image_memory_requirements(image) };
+            images[index] = Some(TransientImage {
+                image,
+                image_view: vk::ImageView::null(),
+                extent,
+            });
+        }
+
+        let transient: Vec<usize> = (0..self.images.len())
+            .filter(|&i| images[i].is_some())
+            .collect();
+        let blocks = Self::alias_memory(&transient, &first_use, &last_use, &requirements);
+        let mut memory = Vec::with_capacity(blocks.len());
+        for block in blocks.iter() {
+            let block_memory = instance.memory_allocator.allocate(
+                device,
+                block.requirements,
+                vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            );
+            for &index in block.images.iter() {
+                let ImageSource::Transient(desc) = self.images[index].source else {
+                    unreachable!()
+                };
+                let transient = images[index].as_mut().unwrap();
+                unsafe { device.bind_image_memory(transient.image, block_memory, 0) }
+                    .expect("Failed to bind Image memory");
+                let aspect = format_aspect(desc.format);
+                // Views of depth stencil formats only see the depth, The same as we do for `DepthImage`
+                let view_aspect = if aspect.contains(vk::ImageAspectFlags::DEPTH) {
+                    vk::ImageAspectFlags::DEPTH
+                } else {
+                    aspect
+                };
+                transient.image_view = VulkanImage::create_image_view(
+                    transient.image,
+                    device,
+                    desc.format,
+                    1,
+                    1,
+                    view_aspect,
+                    vk::ImageViewType::TYPE_2D,
+                );
+            }
+            memory.push(block_memory);
+        }
+        if blocks.len() < transient.len() {
+            log::debug!(
+                "Render Graph: {} transient images share {} allocations",
+                transient.len(),
+                blocks.len()
+            );
+        }
+
+        let buffers = self
+            .buffers
+            .iter()
+            .enumerate()
+            .map(|(index, graph_buffer)| match graph_buffer.source {
+                BufferSource::Transient(size) if !buffer_usage[index].is_empty() => {
+                    Some(VulkanBuffer::new(
+                        instance,
+                        size,
+                        buffer_usage[index],
+                        vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                        Some(&graph_buffer.name),
+                    ))
+                }
+                _ => None,
+            })
+            .collect();
+
+        // Walk through the frame once to know in which state every transient image ends,
+        // The next frame (or the next image in the same memory) has to wait for that
+        let headless = instance.is_headless();
+        let (_, _, end_states, _) = self.plan_barriers(&order, headless, |_| {
+            TrackedState::new(
+                vk::ImageLayout::UNDEFINED,
+                vk::PipelineStageFlags2::NONE,
+                vk::AccessFlags2::NONE,
+            )
+        });
+        let initial_transient_state = |image: usize| {
+            let block = blocks.iter().find(|b| b.images.contains(&image)).unwrap();
+            let position = block.images.iter().position(|&i| i == image).unwrap();
+            let previous = if position == 0 {
+                *block.images.last().unwrap()
+            } else {
+                block.images[position - 1]
+            };
+            let end = end_states[previous];
+            TrackedState::new(
+                vk::ImageLayout::UNDEFINED,
+                end.write_stage | end.read_stage,
+                end.write_access,
+            )
+        };
+        let (mut image_barriers, mut buffer_barriers, _, final_image_barriers) = self
+            .plan_barriers(&order, headless, |image| {
+                if images[image].is_some() {
+                    initial_transient_state(image)
+                } else {
+                    // Transient images which are never used
+                    TrackedState::new(
+                        vk::ImageLayout::UNDEFINED,
+                        vk::PipelineStageFlags2::NONE,
+                        vk::AccessFlags2::NONE,
+                    )
+                }
+            });
+
+        let mut compiled = CompiledGraph {
+            extent: swapchain_extent,
+            images,
+            buffers,
+            memory,
+            passes: Vec::with_capacity(order.len()),
+            final_image_barriers,
+        };
+
+        for (position, &index) in order.iter().enumerate() {
+            let pass = &self.passes[index];
+            let mut compiled_pass = CompiledPass {
+                pass: index,
+                image_barriers: std::mem::take(&mut image_barriers[position]),
+                buffer_barriers: std::mem::take(&mut buffer_barriers[position]),
+                color_attachments: Vec::new(),
+                depth_attachment: None,
+                extent: swapchain_extent,
+            };
+            if pass.pass_type == PassType::Graphics {
+                self.collect_attachments(
+                    instance,
+                    &compiled,
+                    &order[position + 1..],
+                    pass,
+                    &mut compiled_pass,
+                );
+            }
+            compiled.passes.push(compiled_pass);
+        }
+        self.compiled = Some(compiled);
+    }
+
+    /// Lifetimes and usage of every resource, `order` are the passes which survived culling
+    fn resource_usage(&self, order: &[usize]) -> ResourceUsage {
+        let mut usage = ResourceUsage {
+            first_use: vec![usize::MAX; self.images.len()],
+            last_use: vec![0; self.images.len()],
+            image_usage: vec![vk::ImageUsageFlags::empty(); self.images.len()],
+            buffer_usage: vec![vk::BufferUsageFlags::empty(); self.buffers.len()],
+        };
+        for (position, &index) in order.iter().enumerate() {
+            let pass = &self.passes[index];
+            for image_use in pass.images.iter() {
+                let image = image_use.image.0;
+                usage.first_use[image] = usage.first_use[image].min(position);
+                usage.last_use[image] = usage.last_use[image].max(position);
+                usage.image_usage[image] |= image_use.access.usage_flags();
+            }
+            for buffer_use in pass.buffers.iter() {
+                usage.buffer_usage[buffer_use.buffer.0] |= buffer_use.access.usage_flags();
+            }
+        }
+        usage
+    }
+
+    /// Greedy aliasing, An image can move into a block once everything in it is dead
+    fn alias_memory(
+        transient: &[usize],
+        first_use: &[usize],
+        last_use: &[usize],
+        requirements: &[vk::MemoryRequirements],
+    ) -> Vec<MemoryBlock> {
+        let mut transient_order = transient.to_vec();
+        transient_order.sort_by_key(|&i| first_use[i]);
+        let mut blocks: Vec<MemoryBlock> = Vec::new();
+        for &index in transient_order.iter() {
+            let req = requirements[index];
+            let block = blocks.iter_mut().find(|block| {
+                block.last_use < first_use[index]
+                    && block.requirements.memory_type_bits & req.memory_type_bits != 0
+            });
+            match block {
+                Some(block) => {
+                    block.requirements.size = block.requirements.size.max(req.size);
+                    block.requirements.alignment = block.requirements.alignment.max(req.alignment);
+                    block.requirements.memory_type_bits &= req.memory_type_bits;
+                    block.last_use = last_use[index];
+                    block.images.push(index);
+                }
+                None => blocks.push(MemoryBlock {
+                    requirements: req,
+                    last_use: last_use[index],
+                    images: vec![index],
+                }),
+            }
+        }
+        blocks
+    }
+
+    /// Returns the barriers before every pass, The state every image ends in and the barriers after the last pass
+    #[allow(clippy::type_complexity)]
+    fn plan_barriers(
+        &self,
+        order: &[usize],
+        headless: bool,
+        transient_state: impl Fn(usize) -> TrackedState,
+    ) -> (
+        Vec<Vec<ImageBarrier>>,
+        Vec<Vec<BufferBarrier>>,
+        Vec<TrackedState>,
+        Vec<ImageBarrier>,
+    ) {
+        let mut image_states: Vec<TrackedState> = self
+            .images
+            .iter()
+            .enumerate()
+            .map(|(index, image)| match &image.source {
+                ImageSource::Transient(_) => transient_state(index),
+                // Has to chain with the acquire semaphore, Which waits on this stage
+                ImageSource::Backbuffer => TrackedState::new(
+                    vk::ImageLayout::UNDEFINED,
+                    vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+                    vk::AccessFlags2::NONE,
+                ),
+                ImageSource::Imported(imported) => TrackedState::new(
+                    imported.layout,
+                    vk::PipelineStageFlags2::ALL_COMMANDS,
+                    vk::AccessFlags2::MEMORY_WRITE,
+                ),
+            })
+            .collect();
+        let mut buffer_states: Vec<TrackedState> = self
+            .buffers
+            .iter()
+            .map(|_| {
+                TrackedState::new(
+                    vk::ImageLayout::UNDEFINED,
+                    vk::PipelineStageFlags2::ALL_COMMANDS,
+                    vk::AccessFlags2::MEMORY_WRITE,
+                )
+            })
+            .collect();
+
+        let mut image_barriers = Vec::with_capacity(order.len());
+        let mut buffer_barriers = Vec::with_capacity(order.len());
+        for &index in order {
+            let pass = &self.passes[index];
+            let mut pass_image_barriers = Vec::new();
+            for image_use in pass.images.iter() {
+                let access = image_use.access.state(pass.pass_type);
+                if let Some((src_stage, src_access, old_layout)) =
+                    image_states[image_use.image.0].transition(access)
+                {
+                    pass_image_barriers.push(ImageBarrier {
+                        image: image_use.image,
+                        old_layout,
+                        new_layout: access.layout,
+                        src_stage,
+                        src_access,
+                        dst_stage: access.stage,
+                        dst_access: access.access,
+                    });
+                }
+            }
+            let mut pass_buffer_barriers = Vec::new();
+            for buffer_use in pass.buffers.iter() {
+                let access = buffer_use.access.state(pass.pass_type);
+                if let Some((src_stage, src_access, _)) =
+                    buffer_states[buffer_use.buffer.0].transition(access)
+                {
+                    pass_buffer_barriers.push(BufferBarrier {
+                        buffer: buffer_use.buffer,
+                        src_stage,
+                        src_access,
+                        dst_stage: access.stage,
+                        dst_access: access.access,
+                    });
+                }
+            }
+            image_barriers.push(pass_image_barriers);
+            buffer_barriers.push(pass_buffer_barriers);
+        }
+
+        // Hand imported images back the way we got them
+        let mut final_barriers = Vec::new();
+        for (index, image) in self.images.iter().enumerate() {
+            let (layout, dst_stage, dst_access) = match &image.source {
+                ImageSource::Transient(_) => continue,
+                ImageSource::Backbuffer => (
+                    VulkanInstance::color_final_layout(headless),
+                    vk::PipelineStageFlags2::BOTTOM_OF_PIPE,
+                    vk::AccessFlags2::NONE,
+                ),
+                ImageSource::Imported(imported) => (
+                    imported.layout,
+                    vk::PipelineStageFlags2::ALL_COMMANDS,
+                    vk::AccessFlags2::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE,
+                ),
+            };
+            let state = image_states[index];
+            if state.layout == layout {
+                continue;
+            }
+            final_barriers.push(ImageBarrier {
+                image: ImageHandle(index),
+                old_layout: state.layout,
+                new_layout: layout,
+                src_stage: state.write_stage | state.read_stage,
+                src_access: state.write_access,
+                dst_stage,
+                dst_access,
+            });
+        }
+        (
+            image_barriers,
+            buffer_barriers,
+            image_states,
+            final_barriers,
+        )
+    }
+
+    /// Collects what `cmd_begin_rendering` needs for a Graphics pass, All layout transitions are done by our barriers
+    fn collect_attachments(
+        &self,
+        instance: &VulkanInstance,
+        compiled: &CompiledGraph,
+        later_passes: &[usize],
+        pass: &GraphPass,
+        compiled_pass: &mut CompiledPass,
+    ) {
+        let mut color_resolves = pass
+            .images
+            .iter()
+            .filter(|u| u.access == ImageAccess::ColorResolve);
+        let mut depth_resolve = pass
+            .images
+            .iter()
+            .find(|u| u.access == ImageAccess::DepthResolve);
+        for attachment in pass.images.iter() {
+            let (depth, resolve) = match attachment.access {
+                ImageAccess::ColorAttachment => (
+                    false,
+                    color_resolves
+                        .next()
+                        .map(|u| (u, vk::ResolveModeFlags::AVERAGE)),
+                ),
+                ImageAccess::DepthAttachment | ImageAccess::DepthRead => (
+                    true,
+                    depth_resolve
+                        .take()
+                        .map(|u| (u, vk::ResolveModeFlags::SAMPLE_ZERO)),
+                ),
+                _ => continue,
+            };
+            compiled_pass.extent = self.image_extent(instance, compiled, attachment.image);
+            let (load_op, clear_value) = match attachment.load {
+                AttachmentLoad::Clear(value) => (vk::AttachmentLoadOp::CLEAR, value),
+                AttachmentLoad::Load => (vk::AttachmentLoadOp::LOAD, vk::ClearValue::default()),
+                AttachmentLoad::DontCare => {
+                    (vk::AttachmentLoadOp::DONT_CARE, vk::ClearValue::default())
+                }
+            };
+            // Nobody cares about a transient image after its last read
+            let read_later = later_passes.iter().any(|&index| {
+                let later = &self.passes[index];
+                later
+                    .images
+                    .iter()
+                    .any(|u| u.image == attachment.image && u.reads(later.pass_type))
+            });
+            let store_op = if self.images[attachment.image.0].source.is_transient() && !read_later {
+                vk::AttachmentStoreOp::DONT_CARE
+            } else {
+                vk::AttachmentStoreOp::STORE
+            };
+            let compiled_attachment = CompiledAttachment {
+                image: attachment.image,
+                layout: attachment.access.state(pass.pass_type).layout,
+                load_op,
+                store_op,
+                clear_value,
+                resolve: resolve
+                    .map(|(u, mode)| (u.image, u.access.state(pass.pass_type).layout, mode)),
+            };
+            if depth {
+                compiled_pass.depth_attachment = Some(compiled_attachment);
+            } else {
+                compiled_pass.color_attachments.push(compiled_attachment);
+            }
+        }
+    }
+
+    fn image_extent(
+        &self,
+        instance: &VulkanInstance,
+        compiled: &CompiledGraph,
+        image: ImageHandle,
+    ) -> vk::Extent2D {
+        match &self.images[image.0].source {
+            ImageSource::Transient(_) => compiled.images[image.0].as_ref().unwrap().extent,
+            ImageSource::Backbuffer => instance.surface_resolution,
+            ImageSource::Imported(imported) => imported.extent,
+        }
+    }
+
+    fn resolve_image(
+        &self,
+        instance: &VulkanInstance,
+        image: ImageHandle,
+        image_index: u32,
+    ) -> (vk::Image, vk::ImageView) {
+        let compiled = self
+            .compiled
+            .as_ref()
+            .expect("Render Graph is not compiled");
+        match &self.images[image.0].source {
+            ImageSource::Transient(_) => {
+                let transient = compiled.images[image.0]
+                    .as_ref()
+                    .expect("Image is not used by any pass");
+                (transient.image, transient.image_view)
+            }
+            ImageSource::Backbuffer => (
+                instance.swapchain_images[image_index as usize],
+                instance.swapchain_image_views[image_index as usize],
+            ),
+            ImageSource::Imported(imported) => (imported.image, imported.image_view),
+        }
+    }
+
+    fn resolve_buffer(&self, buffer: BufferHandle) -> vk::Buffer {
+        match &self.buffers[buffer.0].source {
+            BufferSource::Transient(_) => {
+                let compiled = self
+                    .compiled
+                    .as_ref()
+                    .expect("Render Graph is not compiled");
+                **compiled.buffers[buffer.0]
+                    .as_ref()
+                    .expect("Buffer is not used by any pass")
+            }
+            BufferSource::Imported(buffer) => *buffer,
+        }
+    }
+
+    fn cmd_barriers(
+        &self,
+        instance: &VulkanInstance,
+        command_buffer: vk::CommandBuffer,
+        image_index: u32,
+        image_barriers: &[ImageBarrier],
+        buffer_barriers: &[BufferBarrier],
+    ) {
+        if image_barriers.is_empty() && buffer_barriers.is_empty() {
+            return;
+        }
+        let image_barriers: Vec<vk::ImageMemoryBarrier2> = image_barriers
+            .iter()
+            .map(|barrier| {
+                let format = match &self.images[barrier.image.0].source {
+                    ImageSource::Transient(desc) => desc.format,
+                    ImageSource::Backbuffer => instance.surface_format.format,
+                    ImageSource::Imported(imported) => imported.format,
+                };
+                vk::ImageMemoryBarrier2::default()
+                    .old_layout(barrier.old_layout)
+                    .new_layout(barrier.new_layout)
+                    .src_stage_mask(barrier.src_stage)
+                    .src_access_mask(barrier.src_access)
+                    .dst_stage_mask(barrier.dst_stage)
+                    .dst_access_mask(barrier.dst_access)
+                    .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .image(self.resolve_image(instance, barrier.image, image_index).0)
+                    .subresource_range(
+                        vk::ImageSubresourceRange::default()
+                            .aspect_mask(format_aspect(format))
+                            .level_count(vk::REMAINING_MIP_LEVELS)
+                            .layer_count(vk::REMAINING_ARRAY_LAYERS),
+                    )
+            })
+            .collect();
+        let buffer_barriers: Vec<vk::BufferMemoryBarrier2> = buffer_barriers
+            .iter()
+            .map(|barrier| {
+                vk::BufferMemoryBarrier2::default()
+                    .src_stage_mask(barrier.src_stage)
+                    .src_access_mask(barrier.src_access)
+                    .dst_stage_mask(barrier.dst_stage)
+                    .dst_access_mask(barrier.dst_access)
+                    .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .buffer(self.resolve_buffer(barrier.buffer))
+                    .size(vk::WHOLE_SIZE)
+            })
+            .collect();
+        let dep_info = vk::DependencyInfo::default()
+            .image_memory_barriers(&image_barriers)
+            .buffer_memory_barriers(&buffer_barriers);
+        unsafe {
+            instance
+                .device
+                .cmd_pipeline_barrier2(command_buffer, &dep_info)
+        };
+    }
+
+    /// Records the whole frame into `command_buffer`, `record` gets called for every pass which was not culled
+    pub fn execute(
+        &mut self,
+        instance: &VulkanInstance,
+        command_buffer: vk::CommandBuffer,
+        image_index: u32,
+        mut record: impl FnMut(PassHandle, &PassContext),
+    ) {
+        if self.needs_compile(instance) {
+            self.compile(instance);
+        }
+        let compiled = self.compiled.as_ref().unwrap();
+        let device = &instance.device;
+
+        for compiled_pass in compiled.passes.iter() {
+            let pass = &self.passes[compiled_pass.pass];
+            if let Some(debug_utils) = &instance.debug_utils_device {
+                let label = vk::DebugUtilsLabelEXT::default().label_name(&pass.name);
+                unsafe { debug_utils.cmd_begin_debug_utils_label(command_buffer, &label) };
+            }
+
+            self.cmd_barriers(
+                instance,
+                command_buffer,
+                image_index,
+                &compiled_pass.image_barriers,
+                &compiled_pass.buffer_barriers,
+            );
+
+            let rendering = !compiled_pass.color_attachments.is_empty()
+                || compiled_pass.depth_attachment.is_some();
+            if rendering {
+                let attachment_info = |attachment: &CompiledAttachment| {
+                    let info = vk::RenderingAttachmentInfo::default()
+                        .image_view(
+                            self.resolve_image(instance, attachment.image, image_index)
+                                .1,
+                        )
+                        .image_layout(attachment.layout)
+                        .load_op(attachment.load_op)
+                        .store_op(attachment.store_op)
+                        .clear_value(attachment.clear_value);
+                    match attachment.resolve {
+                        Some((image, layout, mode)) => info
+                            .resolve_mode(mode)
+                            .resolve_image_view(self.resolve_image(instance, image, image_index).1)
+                            .resolve_image_layout(layout),
+                        None => info,
+                    }
+                };
+                let color_attachments: Vec<vk::RenderingAttachmentInfo> = compiled_pass
+                    .color_attachments
+                    .iter()
+                    .map(attachment_info)
+                    .collect();
+                let depth_attachment = compiled_pass.depth_attachment.as_ref().map(attachment_info);
+
+                let render_area = vk::Rect2D::default().extent(compiled_pass.extent);
+                let mut info = vk::RenderingInfo::default()
+                    .render_area(render_area)
+                    .layer_count(1)
+                    .color_attachments(&color_attachments);
+                if let Some(depth_attachment) = &depth_attachment {
+                    info = info.depth_attachment(depth_attachment);
+                }
+                let viewport = vk::Viewport::default()
+                    .width(compiled_pass.extent.width as f32)
+                    .height(compiled_pass.extent.height as f32)
+                    .max_depth(1.0);
+                unsafe {
+                    device.cmd_begin_rendering(command_buffer, &info);
+                    device.cmd_set_viewport(command_buffer, 0, &[viewport]);
+                    device.cmd_set_scissor(command_buffer, 0, &[render_area]);
+                }
+            }
+
+            let context = PassContext {
+                command_buffer,
+                image_index,
+                frame_index: instance.frame_index(),
+                extent: compiled_pass.extent,
+                graph: self,
+                instance,
+            };
+            record(PassHandle(compiled_pass.pass), &context);
+
+            if rendering {
+                unsafe { device.cmd_end_rendering(command_buffer) };
+            }
+            if let Some(debug_utils) = &instance.debug_utils_device {
+                unsafe { debug_utils.cmd_end_debug_utils_label(command_buffer) };
+            }
+        }
+
+        self.cmd_barriers(
+            instance,
+            command_buffer,
+            image_index,
+            &compiled.final_image_barriers,
+            &[],
+        );
+    }
+
+    fn destroy_compiled(mut compiled: CompiledGraph, device: &ash::Device) {
+        unsafe {
+            for image in compiled.images.drain(..).flatten() {
+                device.destroy_image_view(image.image_view, None);
+                device.destroy_image(image.image, None);
+            }
+            compiled
+                .memory
+                .drain(..)
+                .for_each(|m| device.free_memory(m, None));
+        }
+        for mut buffer in compiled.buffers.drain(..).flatten() {
+            buffer.destroy(device);
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        if let Some(compiled) = self.compiled.take() {
+            Self::destroy_compiled(compiled, device);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn color_desc() -> ImageDesc {
+        ImageDesc {
+            format: vk::Format::R16G16B16A16_SFLOAT,
+            size: ImageSize::Swapchain,
+            samples: vk::SampleCountFlags::TYPE_1,
+        }
+    }
+
+    fn clear() -> AttachmentLoad {
+        AttachmentLoad::Clear(vk::ClearValue::default())
+    }
+
+    fn untouched(_: usize) -> TrackedState {
+        TrackedState::new(
+            vk::ImageLayout::UNDEFINED,
+            vk::PipelineStageFlags2::NONE,
+            vk::AccessFlags2::NONE,
+        )
+    }
+
+    fn alive_order(graph: &RenderGraph) -> Vec<usize> {
+        let alive = graph.cull();
+        (0..graph.passes.len()).filter(|&i| alive[i]).collect()
+    }
+
+    #[test]
+    fn culls_unused_passes() {
+        let mut graph = RenderGraph::new();
+        let backbuffer = graph.import_backbuffer();
+        let scene = graph.create_image("Scene", color_desc());
+        let unused = graph.create_image("Unused", color_desc());
+        let chained = graph.create_image("Chained", color_desc());
+
+        let scene_pass = graph
+            .add_pass("Scene", PassType::Graphics)
+            .color_attachment(scene, clear())
+            .build();
+        // Nobody reads `unused`, And so nobody needs `chained` either
+        let chained_pass = graph
+            .add_pass("Chained", PassType::Graphics)
+            .color_attachment(chained, clear())
+            .build();
+        let unused_pass = graph
+            .add_pass("Unused", PassType::Graphics)
+            .color_attachment(unused, clear())
+            .image(chained, ImageAccess::Sampled)
+            .build();
+        let side_effects_pass = graph
+            .add_pass("Side Effects", PassType::Compute)
+            .side_effects()
+            .build();
+        let present_pass = graph
+            .add_pass("Present", PassType::Graphics)
+            .color_attachment(backbuffer, clear())
+            .image(scene, ImageAccess::Sampled)
+            .build();
+
+        let alive = graph.cull();
+        assert!(alive[scene_pass.0]);
+        assert!(!alive[chained_pass.0]);
+        assert!(!alive[unused_pass.0]);
+        assert!(alive[side_effects_pass.0]);
+        assert!(alive[present_pass.0]);
+    }
+
+    #[test]
+    fn read_after_write_barriers() {
+        let mut graph = RenderGraph::new();
+        let backbuffer = graph.import_backbuffer();
+        let scene = graph.create_image("Scene", color_desc());
+        let commands = graph.create_buffer("Commands", 64);
+
+        graph
+            .add_pass("Cull", PassType::Compute)
+            .buffer(commands, BufferAccess::StorageWrite)
+            .build();
+        graph
+            .add_pass("Scene", PassType::Graphics)
+            .color_attachment(scene, clear())
+            .buffer(commands, BufferAccess::Indirect)
+            .build();
+        graph
+            .add_pass("Present", PassType::Graphics)
+            .color_attachment(backbuffer, clear())
+            .image(scene, ImageAccess::Sampled)
+            // Reading it again does not need another barrier
+            .image(scene, ImageAccess::Sampled)
+            .build();
+
+        let order = alive_order(&graph);
+        assert_eq!(order, vec![0, 1, 2]);
+        let (image_barriers, buffer_barriers, _, final_barriers) =
+            graph.plan_barriers(&order, false, untouched);
+
+        let indirect = &buffer_barriers[1][0];
+        assert_eq!(indirect.buffer, commands);
+        assert_eq!(indirect.src_stage, vk::PipelineStageFlags2::COMPUTE_SHADER);
+        assert!(indirect
+            .src_access
+            .contains(vk::AccessFlags2::SHADER_STORAGE_WRITE));
+        assert_eq!(indirect.dst_stage, vk::PipelineStageFlags2::DRAW_INDIRECT);
+        assert_eq!(indirect.dst_access, vk::AccessFlags2::INDIRECT_COMMAND_READ);
+
+        let sampled: Vec<_> = image_barriers[2]
+            .iter()
+            .filter(|barrier| barrier.image == scene)
+            .collect();
+        assert_eq!(sampled.len(), 1);
+        let sampled = sampled[0];
+        assert_eq!(
+            sampled.old_layout,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+        );
+        assert_eq!(
+            sampled.new_layout,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+        );
+        assert_eq!(
+            sampled.src_stage,
+            vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT
+        );
+        assert!(sampled
+            .src_access
+            .contains(vk::AccessFlags2::COLOR_ATTACHMENT_WRITE));
+        assert_eq!(sampled.dst_access, vk::AccessFlags2::SHADER_SAMPLED_READ);
+
+        // The backbuffer ends up ready to present
+        assert_eq!(final_barriers.len(), 1);
+        assert_eq!(final_barriers[0].image, backbuffer);
+        assert_eq!(
+            final_barriers[0].new_layout,
+            vk::ImageLayout::PRESENT_SRC_KHR
+        );
+    }
+
+    #[test]
+    fn write_after_read_barriers() {
+        let mut graph = RenderGraph::new();
+        let backbuffer = graph.import_backbuffer();
+        let history = graph.create_image("History", color_desc());
+        let particles = graph.create_buffer("Particles", 64);
+
+        graph
+            .add_pass("Simulate", PassType::Compute)
+            .buffer(particles, BufferAccess::StorageWrite)
+            .image(history, ImageAccess::StorageWrite)
+            .build();
+        graph
+            .add_pass("Draw", PassType::Graphics)
+            .color_attachment(backbuffer, clear())
+            .buffer(particles, BufferAccess::Vertex)
+            .image(history, ImageAccess::Sampled)
+            .build();
+        graph
+            .add_pass("Simulate Again", PassType::Compute)
+            .buffer(particles, BufferAccess::StorageWrite)
+            .image(history, ImageAccess::StorageWrite)
+            .side_effects()
+            .build();
+
+        let order = alive_order(&graph);
+        assert_eq!(order, vec![0, 1, 2]);
+        let (image_barriers, buffer_barriers, _, _) = graph.plan_barriers(&order, false, untouched);
+
+        // The second write has to wait until the vertices were read
+        let buffer = &buffer_barriers[2][0];
+        assert!(buffer
+            .src_stage
+            .contains(vk::PipelineStageFlags2::VERTEX_ATTRIBUTE_INPUT));
+        assert_eq!(buffer.dst_stage, vk::PipelineStageFlags2::COMPUTE_SHADER);
+
+        let image = &image_barriers[2][0];
+        assert_eq!(image.image, history);
+        assert_eq!(image.old_layout, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        assert_eq!(image.new_layout, vk::ImageLayout::GENERAL);
+        assert!(image
+            .src_stage
+            .contains(vk::PipelineStageFlags2::FRAGMENT_SHADER));
+        // Reads never have to be made available
+        assert!(!image
+            .src_access
+            .contains(vk::AccessFlags2::SHADER_SAMPLED_READ));
+    }
+
+    #[test]
+    fn aliases_non_overlapping_images() {
+        let mut graph = RenderGraph::new();
+        let backbuffer = graph.import_backbuffer();
+        let first = graph.create_image("First", color_desc());
+        let middle = graph.create_image("Middle", color_desc());
+        let second = graph.create_image("Second", color_desc());
+        let overlapping = graph.create_image("Overlapping", color_desc());
+
+        graph
+            .add_pass("Write First", PassType::Graphics)
+            .color_attachment(first, clear())
+            .color_attachment(overlapping, clear())
+            .build();
+        graph
+            .add_pass("Write Middle", PassType::Graphics)
+            .color_attachment(middle, clear())
+            .image(first, ImageAccess::Sampled)
+            .build();
+        graph
+            .add_pass("Write Second", PassType::Graphics)
+            .color_attachment(second, clear())
+            .image(middle, ImageAccess::Sampled)
+            .build();
+        graph
+            .add_pass("Present", PassType::Graphics)
+            .color_attachment(backbuffer, clear())
+            .image(second, ImageAccess::Sampled)
+            .image(overlapping, ImageAccess::Sampled)
+            .build();
+
+        let order = alive_order(&graph);
+        let usage = graph.resource_usage(&order);
+        assert_eq!((usage.first_use[first.0], usage.last_use[first.0]), (0, 1));
+        assert_eq!(
+            (usage.first_use[second.0], usage.last_use[second.0]),
+            (2, 3)
+        );
+
+        let requirements = vec![
+            vk::MemoryRequirements {
+                size: 1024,
+                alignment: 256,
+                memory_type_bits: 0b11,
+            };
+            graph.images.len()
+        ];
+        let transient = [first.0, middle.0, second.0, overlapping.0];
+        let blocks =
+            RenderGraph::alias_memory(&transient, &usage.first_use, &usage.last_use, &requirements);
+        // `second` is only written once `first` is dead, Everything else overlaps
+        assert_eq!(blocks.len(), 3);
+        let shared = blocks
+            .iter()
+            .find(|block| block.images.contains(&first.0))
+            .unwrap();
+        assert_eq!(shared.images, vec![first.0, second.0]);
+
+        // Memory which can not hold the image is never shared
+        let mut requirements = requirements;
+        requirements[second.0].memory_type_bits = 0b100;
+        let blocks =
+            RenderGraph::alias_memory(&transient, &usage.first_use, &usage.last_use, &requirements);
+        assert_eq!(blocks.len(), 4);
+    }
+}
@@ -0,0 +1,321 @@
+use ash::vk;
+
+/// Refers to an Image declared in a `RenderGraph`
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ImageHandle(pub(crate) usize);
+
+/// Refers to a Buffer declared in a `RenderGraph`
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct BufferHandle(pub(crate) usize);
+
+/// Refers to a Pass added to a `RenderGraph`
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct PassHandle(pub(crate) usize);
+
+#[derive(Clone, Copy, Debug)]
+pub enum ImageSize {
+    /// Same size as the Swapchain, Follows every resize
+    Swapchain,
+    /// Swapchain size multiplied by the factor, e.g. 0.5 for half resolution
+    Scaled(f32),
+    Fixed(vk::Extent2D),
+}
+
+impl ImageSize {
+    pub fn resolve(self, swapchain: vk::Extent2D) -> vk::Extent2D {
+        match self {
+            ImageSize::Swapchain => swapchain,
+            ImageSize::Scaled(factor) => vk::Extent2D {
+                width: ((swapchain.width as f32 * factor) as u32).max(1),
+                height: ((swapchain.height as f32 * factor) as u32).max(1),
+            },
+            ImageSize::Fixed(extent) => extent,
+        }
+    }
+}
+
+/// Describes an Image which is created and owned by the Graph.
+/// Its contents only live for a single frame, So its memory may be shared with other transient images
+#[derive(Clone, Copy, Debug)]
+pub struct ImageDesc {
+    pub format: vk::Format,
+    pub size: ImageSize,
+    /// Anything above TYPE_1 can only be used as attachment and has to be resolved before sampling
+    pub samples: vk::SampleCountFlags,
+}
+
+/// An Image which lives outside of the Graph, e.g. a Texture which gets updated by a compute pass
+#[derive(Clone, Copy, Debug)]
+pub struct ImportedImage {
+    pub image: vk::Image,
+    pub image_view: vk::ImageView,
+    pub format: vk::Format,
+    pub extent: vk::Extent2D,
+    /// The layout the image is in before the Graph runs, It gets transitioned back into it at the end
+    pub layout: vk::ImageLayout,
+}
+
+pub(crate) enum ImageSource {
+    Transient(ImageDesc),
+    /// The current Swapchain (or offscreen) image
+    Backbuffer,
+    Imported(ImportedImage),
+}
+
+pub(crate) enum BufferSource {
+    Transient(vk::DeviceSize),
+    Imported(vk::Buffer),
+}
+
+impl ImageSource {
+    pub(crate) fn is_transient(&self) -> bool {
+        matches!(self, ImageSource::Transient(_))
+    }
+}
+
+impl BufferSource {
+    pub(crate) fn is_transient(&self) -> bool {
+        matches!(self, BufferSource::Transient(_))
+    }
+}
+
+/// What kind of work a pass records, Decides which pipeline stages are waited on
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PassType {
+    Graphics,
+    Compute,
+    Transfer,
+}
+
+impl PassType {
+    fn shader_stages(self) -> vk::PipelineStageFlags2 {
+        match self {
+            PassType::Graphics => {
+                vk::PipelineStageFlags2::VERTEX_SHADER | vk::PipelineStageFlags2::FRAGMENT_SHADER
+            }
+            PassType::Compute => vk::PipelineStageFlags2::COMPUTE_SHADER,
+            PassType::Transfer => vk::PipelineStageFlags2::ALL_COMMANDS,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum AttachmentLoad {
+    Clear(vk::ClearValue),
+    /// Keeps what previous passes have written
+    Load,
+    DontCare,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ImageAccess {
+    ColorAttachment,
+    DepthAttachment,
+    /// Receives the average of a multisampled color attachment at the end of the pass
+    ColorResolve,
+    /// Receives the first sample of a multisampled depth attachment at the end of the pass
+    DepthResolve,
+    /// Depth testing without writing
+    DepthRead,
+    Sampled,
+    StorageRead,
+    StorageWrite,
+    TransferSrc,
+    TransferDst,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BufferAccess {
+    Uniform,
+    StorageRead,
+    StorageWrite,
+    Vertex,
+    Index,
+    Indirect,
+    TransferSrc,
+    TransferDst,
+}
+
+/// Everything a barrier needs to know about a single access
+#[derive(Clone, Copy)]
+pub(crate) struct AccessState {
+    pub stage: vk::PipelineStageFlags2,
+    pub access: vk::AccessFlags2,
+    pub layout: vk::ImageLayout,
+    pub write: bool,
+}
+
+impl ImageAccess {
+    pub(crate) fn state(self, pass_type: PassType) -> AccessState {
+        let (stage, access, layout, write) = match self {
+            ImageAccess::ColorAttachment => (
+                vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+                vk::AccessFlags2::COLOR_ATTACHMENT_READ | vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                true,
+            ),
+            ImageAccess::DepthAttachment => (
+                vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS,
+                vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ
+                    | vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                true,
+            ),
+            // Resolves run in the color attachment output stage, Even for depth
+            ImageAccess::ColorResolve => (
+                vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+                vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                true,
+            ),
+            ImageAccess::DepthResolve => (
+                vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+                vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+                vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                true,
+            ),
+            ImageAccess::DepthRead => (
+                vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS,
+                vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ,
+                vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+                false,
+            ),
+            ImageAccess::Sampled => (
+                pass_type.shader_stages(),
+                vk::AccessFlags2::SHADER_SAMPLED_READ,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                false,
+            ),
+            ImageAccess::StorageRead => (
+                pass_type.shader_stages(),
+                vk::AccessFlags2::SHADER_STORAGE_READ,
+                vk::ImageLayout::GENERAL,
+                false,
+            ),
+            ImageAccess::StorageWrite => (
+                pass_type.shader_stages(),
+                vk::AccessFlags2::SHADER_STORAGE_READ | vk::AccessFlags2::SHADER_STORAGE_WRITE,
+                vk::ImageLayout::GENERAL,
+                true,
+            ),
+            ImageAccess::TransferSrc => (
+                vk::PipelineStageFlags2::TRANSFER,
+                vk::AccessFlags2::TRANSFER_READ,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                false,
+            ),
+            ImageAccess::TransferDst => (
+                vk::PipelineStageFlags2::TRANSFER,
+                vk::AccessFlags2::TRANSFER_WRITE,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                true,
+            ),
+        };
+        AccessState {
+            stage,
+            access,
+            layout,
+            write,
+        }
+    }
+
+    pub(crate) fn usage_flags(self) -> vk::ImageUsageFlags {
+        match self {
+            ImageAccess::ColorAttachment | ImageAccess::ColorResolve => {
+                vk::ImageUsageFlags::COLOR_ATTACHMENT
+            }
+            ImageAccess::DepthAttachment | ImageAccess::DepthResolve | ImageAccess::DepthRead => {
+                vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT
+            }
+            ImageAccess::Sampled => vk::ImageUsageFlags::SAMPLED,
+            ImageAccess::StorageRead | ImageAccess::StorageWrite => vk::ImageUsageFlags::STORAGE,
+            ImageAccess::TransferSrc => vk::ImageUsageFlags::TRANSFER_SRC,
+            ImageAccess::TransferDst => vk::ImageUsageFlags::TRANSFER_DST,
+        }
+    }
+}
+
+impl BufferAccess {
+    pub(crate) fn state(self, pass_type: PassType) -> AccessState {
+        let (stage, access, write) = match self {
+            BufferAccess::Uniform => (
+                pass_type.shader_stages(),
+                vk::AccessFlags2::UNIFORM_READ,
+                false,
+            ),
+            BufferAccess::StorageRead => (
+                pass_type.shader_stages(),
+                vk::AccessFlags2::SHADER_STORAGE_READ,
+                false,
+            ),
+            BufferAccess::StorageWrite => (
+                pass_type.shader_stages(),
+                vk::AccessFlags2::SHADER_STORAGE_READ | vk::AccessFlags2::SHADER_STORAGE_WRITE,
+                true,
+            ),
+            BufferAccess::Vertex => (
+                vk::PipelineStageFlags2::VERTEX_ATTRIBUTE_INPUT,
+                vk::AccessFlags2::VERTEX_ATTRIBUTE_READ,
+                false,
+            ),
+            BufferAccess::Index => (
+                vk::PipelineStageFlags2::INDEX_INPUT,
+                vk::AccessFlags2::INDEX_READ,
+                false,
+            ),
+            BufferAccess::Indirect => (
+                vk::PipelineStageFlags2::DRAW_INDIRECT,
+                vk::AccessFlags2::INDIRECT_COMMAND_READ,
+                false,
+            ),
+            BufferAccess::TransferSrc => (
+                vk::PipelineStageFlags2::TRANSFER,
+                vk::AccessFlags2::TRANSFER_READ,
+                false,
+            ),
+            BufferAccess::TransferDst => (
+                vk::PipelineStageFlags2::TRANSFER,
+                vk::AccessFlags2::TRANSFER_WRITE,
+                true,
+            ),
+        };
+        AccessState {
+            stage,
+            access,
+            layout: vk::ImageLayout::UNDEFINED,
+            write,
+        }
+    }
+
+    pub(crate) fn usage_flags(self) -> vk::BufferUsageFlags {
+        match self {
+            BufferAccess::Uniform => vk::BufferUsageFlags::UNIFORM_BUFFER,
+            BufferAccess::StorageRead | BufferAccess::StorageWrite => {
+                vk::BufferUsageFlags::STORAGE_BUFFER
+            }
+            BufferAccess::Vertex => vk::BufferUsageFlags::VERTEX_BUFFER,
+            BufferAccess::Index => vk::BufferUsageFlags::INDEX_BUFFER,
+            BufferAccess::Indirect => vk::BufferUsageFlags::INDIRECT_BUFFER,
+            BufferAccess::TransferSrc => vk::BufferUsageFlags::TRANSFER_SRC,
+            BufferAccess::TransferDst => vk::BufferUsageFlags::TRANSFER_DST,
+        }
+    }
+}
+
+/// Returns the aspects a barrier on an image of this format has to cover
+pub(crate) fn format_aspect(format: vk::Format) -> vk::ImageAspectFlags {
+    match format {
+        vk::Format::D16_UNORM | vk::Format::D32_SFLOAT | vk::Format::X8_D24_UNORM_PACK32 => {
+            vk::ImageAspectFlags::DEPTH
+        }
+        vk::Format::D16_UNORM_S8_UINT
+        | vk::Format::D24_UNORM_S8_UINT
+        | vk::Format::D32_SFLOAT_S8_UINT => {
+            vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+        }
+        vk::Format::S8_UINT => vk::ImageAspectFlags::STENCIL,
+        _ => vk::ImageAspectFlags::COLOR,
+    }
+}
@@ -7,6 +7,16 @@ pub struct Vertex3D {
     pub position: [f32; 3],
     pub tex_coord: [f32; 2],
     pub normal: [f32; 3],
+    /// xyz points along +U of the texture, w is the handedness of the bitangent like in glTF.
+    /// Zero when the Mesh has none, The Shader falls back to screen space derivatives then
+    pub tangent: [f32; 4],
+}
+
+/// Per instance data of instanced draws, Bound at `InstanceData::BINDING` next to the vertices
+#[derive(Clone, Copy, PartialEq)]
+pub struct InstanceData {
+    /// Model matrix, Column by column
+    pub transform: [[f32; 4]; 4],
 }
 
 #[derive(Clone, Copy, PartialEq)]
@@ -28,13 +38,13 @@ impl Vertex3D {
             .stride(std::mem::size_of::<Self>() as u32)
             .input_rate(vk::VertexInputRate::VERTEX)
     }
-    pub fn input_descriptions() -> [vk::VertexInputAttributeDescription; 3] {
+    pub fn input_descriptions() -> [vk::VertexInputAttributeDescription; 4] {
         [
             // offset_of macro got stabilized in rust 1.77
             vk::VertexInputAttributeDescription::default()
                 .location(0)
                 .binding(0)
-                .format(vk::Format::R32G32B32A32_SFLOAT)
+                .format(vk::Format::R32G32B32_SFLOAT)
                 .offset(offset_of!(Self, position) as u32),
             vk::VertexInputAttributeDescription::default()
                 .location(1)
@@ -44,12 +54,40 @@ impl Vertex3D {
             vk::VertexInputAttributeDescription::default()
                 .location(2)
                 .binding(0)
-                .format(vk::Format::R32G32B32A32_SFLOAT)
+                .format(vk::Format::R32G32B32_SFLOAT)
                 .offset(offset_of!(Self, normal) as u32),
+            vk::VertexInputAttributeDescription::default()
+                .location(3)
+                .binding(0)
+                .format(vk::Format::R32G32B32A32_SFLOAT)
+                .offset(offset_of!(Self, tangent) as u32),
         ]
     }
 }
 
+impl InstanceData {
+    pub const BINDING: u32 = 1;
+    /// The matrix takes this and the 3 following locations, The ones below are left for `Vertex3D`
+    pub const FIRST_LOCATION: u32 = 4;
+
+    pub fn binding_description() -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription::default()
+            .binding(Self::BINDING)
+            .stride(std::mem::size_of::<Self>() as u32)
+            .input_rate(vk::VertexInputRate::INSTANCE)
+    }
+    pub fn input_descriptions() -> [vk::VertexInputAttributeDescription; 4] {
+        let column_size = std::mem::size_of::<[f32; 4]>() as u32;
+        [0, 1, 2, 3].map(|column| {
+            vk::VertexInputAttributeDescription::default()
+                .location(Self::FIRST_LOCATION + column)
+                .binding(Self::BINDING)
+                .format(vk::Format::R32G32B32A32_SFLOAT)
+                .offset(offset_of!(Self, transform) as u32 + column * column_size)
+        })
+    }
+}
+
 impl VertexPos3D {
     pub fn binding_description() -> vk::VertexInputBindingDescription {
         vk::VertexInputBindingDescription::default()
@@ -63,7 +101,7 @@ impl VertexPos3D {
             vk::VertexInputAttributeDescription::default()
                 .location(0)
                 .binding(0)
-                .format(vk::Format::R32G32B32A32_SFLOAT)
+                .format(vk::Format::R32G32B32_SFLOAT)
                 .offset(offset_of!(Self, position) as u32),
         ]
     }
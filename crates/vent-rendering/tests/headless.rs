@@ -0,0 +1,72 @@
+use ash::vk;
+use vent_rendering::instance::VulkanInstance;
+
+#[test]
+fn headless_frame_capture() {
+    if !VulkanInstance::headless_available() {
+        eprintln!("No Vulkan device, Skipping");
+        return;
+    }
+    let mut instance = VulkanInstance::new_headless(&"Headless Test".to_owned(), 0, (64, 32));
+
+    let (image_index, _) = instance.next_image().unwrap();
+    let device = &instance.device;
+    let command_buffer = instance.current_frame().command_buffer;
+    let image = instance.swapchain_images[image_index as usize];
+    let subresource_range = vk::ImageSubresourceRange::default()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .level_count(1)
+        .layer_count(1);
+
+    unsafe {
+        device
+            .begin_command_buffer(command_buffer, &vk::CommandBufferBeginInfo::default())
+            .unwrap();
+
+        let to_clear = vk::ImageMemoryBarrier2::default()
+            .old_layout(vk::ImageLayout::UNDEFINED)
+            .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+            .dst_stage_mask(vk::PipelineStageFlags2::CLEAR)
+            .dst_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+            .image(image)
+            .subresource_range(subresource_range);
+        device.cmd_pipeline_barrier2(
+            command_buffer,
+            &vk::DependencyInfo::default().image_memory_barriers(&[to_clear]),
+        );
+        device.cmd_clear_color_image(
+            command_buffer,
+            image,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            &vk::ClearColorValue {
+                float32: [1.0, 0.0, 1.0, 1.0],
+            },
+            &[subresource_range],
+        );
+        let to_final = vk::ImageMemoryBarrier2::default()
+            .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+            .new_layout(VulkanInstance::color_final_layout(true))
+            .src_stage_mask(vk::PipelineStageFlags2::CLEAR)
+            .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+            .image(image)
+            .subresource_range(subresource_range);
+        device.cmd_pipeline_barrier2(
+            command_buffer,
+            &vk::DependencyInfo::default().image_memory_barriers(&[to_final]),
+        );
+        device.end_command_buffer(command_buffer).unwrap();
+    }
+
+    instance.request_capture().unwrap();
+    assert!(!instance.submit(image_index).unwrap());
+    let capture = instance
+        .take_capture()
+        .expect("The submitted Frame was not captured")
+        .unwrap();
+
+    assert_eq!(capture.dimensions(), (64, 32));
+    assert!(capture.pixels().all(|pixel| pixel.0 == [255, 0, 255, 255]));
+    // Nothing gets captured without asking
+    assert!(instance.take_capture().is_none());
+}
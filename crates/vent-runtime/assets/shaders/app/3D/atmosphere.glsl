@@ -0,0 +1,103 @@
+// Physically based Atmosphere, See "A Scalable and Production Ready Sky and Atmosphere Rendering Technique" by Sébastien Hillaire.
+// Distances are in kilometers, The planet center is at the origin. Needs PI from ibl_sampling.glsl
+
+// Must match the constants in atmosphere.rs
+const float BOTTOM_RADIUS = 6360.0;
+const float TOP_RADIUS = 6460.0;
+
+const vec3 RAYLEIGH_SCATTERING = vec3(5.802, 13.558, 33.1) * 1e-3;
+const float RAYLEIGH_SCALE_HEIGHT = 8.0;
+const float MIE_SCATTERING = 3.996e-3;
+const float MIE_EXTINCTION = 4.4e-3;
+const float MIE_SCALE_HEIGHT = 1.2;
+const float MIE_G = 0.8;
+// Ozone only absorbs, Its density is a tent around 25 km
+const vec3 OZONE_ABSORPTION = vec3(0.65, 1.881, 0.085) * 1e-3;
+const float OZONE_CENTER = 25.0;
+const float OZONE_WIDTH = 15.0;
+
+struct Medium {
+    vec3 rayleigh;
+    float mie;
+    vec3 extinction;
+};
+
+Medium sample_medium(float height) {
+    float rayleigh_density = exp(-height / RAYLEIGH_SCALE_HEIGHT);
+    float mie_density = exp(-height / MIE_SCALE_HEIGHT);
+    float ozone_density = max(0.0, 1.0 - abs(height - OZONE_CENTER) / OZONE_WIDTH);
+
+    Medium medium;
+    medium.rayleigh = RAYLEIGH_SCATTERING * rayleigh_density;
+    medium.mie = MIE_SCATTERING * mie_density;
+    medium.extinction = medium.rayleigh + MIE_EXTINCTION * mie_density + OZONE_ABSORPTION * ozone_density;
+    return medium;
+}
+
+float rayleigh_phase(float cos_theta) {
+    return 3.0 / (16.0 * PI) * (1.0 + cos_theta * cos_theta);
+}
+
+// Cornette-Shanks, Closer to real Mie scattering than plain Henyey-Greenstein
+float mie_phase(float cos_theta) {
+    float g2 = MIE_G * MIE_G;
+    float k = 3.0 / (8.0 * PI) * (1.0 - g2) / (2.0 + g2);
+    return k * (1.0 + cos_theta * cos_theta) / pow(1.0 + g2 - 2.0 * MIE_G * cos_theta, 1.5);
+}
+
+// Distance to the sphere along the ray, -1 when it is missed. Returns the far hit when the origin is inside
+float ray_sphere(vec3 origin, vec3 direction, float radius) {
+    float b = dot(origin, direction);
+    float c = dot(origin, origin) - radius * radius;
+    float discriminant = b * b - c;
+    if (discriminant < 0.0) {
+        return -1.0;
+    }
+    float root = sqrt(discriminant);
+    float near = -b - root;
+    return near >= 0.0 ? near : -b + root;
+}
+
+// How far a ray travels through the Atmosphere, It stops at the ground
+float atmosphere_distance(vec3 origin, vec3 direction) {
+    float ground = ray_sphere(origin, direction, BOTTOM_RADIUS);
+    float top = ray_sphere(origin, direction, TOP_RADIUS);
+    return ground > 0.0 ? ground : max(top, 0.0);
+}
+
+// Parameterization of the Transmittance LUT from Bruneton, Puts more texels near the horizon
+vec2 transmittance_uv(float radius, float cos_zenith) {
+    float H = sqrt(TOP_RADIUS * TOP_RADIUS - BOTTOM_RADIUS * BOTTOM_RADIUS);
+    float rho = sqrt(max(radius * radius - BOTTOM_RADIUS * BOTTOM_RADIUS, 0.0));
+    float discriminant = radius * radius * (cos_zenith * cos_zenith - 1.0) + TOP_RADIUS * TOP_RADIUS;
+    float d = max(0.0, -radius * cos_zenith + sqrt(max(discriminant, 0.0)));
+    float d_min = TOP_RADIUS - radius;
+    float d_max = rho + H;
+    return vec2((d - d_min) / (d_max - d_min), rho / H);
+}
+
+void transmittance_parameters(vec2 uv, out float radius, out float cos_zenith) {
+    float H = sqrt(TOP_RADIUS * TOP_RADIUS - BOTTOM_RADIUS * BOTTOM_RADIUS);
+    float rho = H * uv.y;
+    radius = sqrt(rho * rho + BOTTOM_RADIUS * BOTTOM_RADIUS);
+    float d_min = TOP_RADIUS - radius;
+    float d_max = rho + H;
+    float d = d_min + uv.x * (d_max - d_min);
+    cos_zenith = d == 0.0 ? 1.0 : (H * H - rho * rho - d * d) / (2.0 * radius * d);
+    cos_zenith = clamp(cos_zenith, -1.0, 1.0);
+}
+
+// x is the cosine of the sun zenith angle, y the height in the Atmosphere
+vec2 multiscattering_uv(float radius, float cos_sun_zenith) {
+    return vec2(cos_sun_zenith * 0.5 + 0.5, (radius - BOTTOM_RADIUS) / (TOP_RADIUS - BOTTOM_RADIUS));
+}
+
+// Sunlight arriving at `position`, Zero when the planet is in the way
+vec3 sun_transmittance(sampler2D transmittance_lut, vec3 position, vec3 sun_direction) {
+    float radius = length(position);
+    vec3 up = position / radius;
+    if (ray_sphere(position, sun_direction, BOTTOM_RADIUS) > 0.0) {
+        return vec3(0.0);
+    }
+    return textureLod(transmittance_lut, transmittance_uv(radius, dot(up, sun_direction)), 0.0).rgb;
+}
@@ -0,0 +1,52 @@
+// Clustered Lights, The view frustum is split into froxels and every one has a list of the Lights touching it.
+// Built by cluster.comp every Frame, So the Fragment Shader only loops over the Lights which can reach it
+
+#ifndef FRAME_SET
+#define FRAME_SET 1
+#endif
+// cluster.comp writes the lists, Everyone else only reads them
+#ifndef CLUSTER_ACCESS
+#define CLUSTER_ACCESS readonly
+#endif
+
+// Must match light_clusters.rs
+#define CLUSTERS_X 16
+#define CLUSTERS_Y 9
+#define CLUSTERS_Z 24
+#define CLUSTER_COUNT (CLUSTERS_X * CLUSTERS_Y * CLUSTERS_Z)
+#define MAX_LIGHTS_PER_CLUSTER 128
+
+layout (std140, set = FRAME_SET, binding = 4) uniform Clusters {
+    mat4 view;
+    vec2 screen_size;
+    float z_near;
+    float z_far;
+    // tan(fovy / 2) * aspect and tan(fovy / 2), Scales a view space depth to the size of the frustum
+    vec2 tan_half_fov;
+} clusters;
+
+struct Cluster {
+    uint light_count;
+    uint light_indices[MAX_LIGHTS_PER_CLUSTER];
+};
+
+layout (std430, set = FRAME_SET, binding = 5) CLUSTER_ACCESS buffer ClusterLights {
+    Cluster cluster_lights[];
+};
+
+// Exponential slices, So the Clusters close to the Camera stay small
+float cluster_slice_depth(uint slice) {
+    return clusters.z_near * pow(clusters.z_far / clusters.z_near, float(slice) / float(CLUSTERS_Z));
+}
+
+// The Cluster a fragment is in, `frag_coord` is gl_FragCoord.xy
+uint cluster_index(vec2 frag_coord, vec3 world_position) {
+    float depth = -(clusters.view * vec4(world_position, 1.0)).z;
+    float slice = log(max(depth, clusters.z_near) / clusters.z_near) / log(clusters.z_far / clusters.z_near);
+    uvec3 cluster = uvec3(
+        uvec2(frag_coord / clusters.screen_size * vec2(CLUSTERS_X, CLUSTERS_Y)),
+        uint(slice * float(CLUSTERS_Z))
+    );
+    cluster = min(cluster, uvec3(CLUSTERS_X - 1, CLUSTERS_Y - 1, CLUSTERS_Z - 1));
+    return cluster.x + cluster.y * CLUSTERS_X + cluster.z * CLUSTERS_X * CLUSTERS_Y;
+}
@@ -0,0 +1,30 @@
+// Image Based Lighting from the environment, Precomputed once by the compute passes in ibl.rs.
+// Needs pbr.glsl
+
+// Must match the bindings in ibl.rs
+layout (set = FRAME_SET, binding = 6) uniform samplerCube irradiance_map;
+// Mip 0 is perfectly smooth, The last mip fully rough
+layout (set = FRAME_SET, binding = 7) uniform samplerCube prefiltered_map;
+// Scale and bias of F0, x is NdotV, y the roughness
+layout (set = FRAME_SET, binding = 8) uniform sampler2D brdf_lut;
+
+// Fresnel averaged over the lobe, Rough surfaces do not get as bright at grazing angles
+vec3 fresnel_schlick_roughness(float n_dot_v, vec3 f0, float roughness) {
+    return f0 + (max(vec3(1.0 - roughness), f0) - f0) * pow(1.0 - n_dot_v, 5.0);
+}
+
+// Ambient light coming from the environment, Split into a diffuse and a specular part
+vec3 ibl_ambient(PbrSurface surface, vec3 N, vec3 V) {
+    float n_dot_v = clamp(abs(dot(N, V)), 1e-4, 1.0);
+    vec3 R = reflect(-V, N);
+
+    vec3 F = fresnel_schlick_roughness(n_dot_v, surface.f0, surface.roughness);
+    vec3 irradiance = texture(irradiance_map, N).rgb;
+    vec3 diffuse = (1.0 - F) * surface.diffuse * irradiance;
+
+    float lod = surface.roughness * float(textureQueryLevels(prefiltered_map) - 1);
+    vec3 prefiltered = textureLod(prefiltered_map, R, lod).rgb;
+    vec2 brdf = texture(brdf_lut, vec2(n_dot_v, surface.roughness)).rg;
+    vec3 specular = prefiltered * (surface.f0 * brdf.x + brdf.y);
+    return diffuse + specular;
+}
@@ -0,0 +1,47 @@
+// Shared by the compute passes precomputing the Image Based Lighting
+
+const float PI = 3.14159265359;
+
+// Must match `IBL_GROUP_SIZE` in ibl.rs
+#define IBL_GROUP_SIZE 8
+
+// Direction through the texel center of a cubemap face, Faces are in Vulkan order (+X, -X, +Y, -Y, +Z, -Z)
+vec3 cube_direction(uvec3 texel, uint size) {
+    vec2 uv = (vec2(texel.xy) + 0.5) / float(size) * 2.0 - 1.0;
+    vec3 direction;
+    switch (texel.z) {
+    case 0: direction = vec3(1.0, -uv.y, -uv.x); break;
+    case 1: direction = vec3(-1.0, -uv.y, uv.x); break;
+    case 2: direction = vec3(uv.x, 1.0, uv.y); break;
+    case 3: direction = vec3(uv.x, -1.0, -uv.y); break;
+    case 4: direction = vec3(uv.x, -uv.y, 1.0); break;
+    default: direction = vec3(-uv.x, -uv.y, -1.0); break;
+    }
+    return normalize(direction);
+}
+
+// Low discrepancy sequence, Covers the hemisphere much more evenly than random numbers
+vec2 hammersley(uint i, uint count) {
+    uint bits = bitfieldReverse(i);
+    return vec2(float(i) / float(count), float(bits) * 2.3283064365386963e-10);
+}
+
+// Half vector around N, Distributed like the GGX lobe of `alpha`
+vec3 importance_sample_ggx(vec2 xi, vec3 N, float alpha) {
+    float a2 = alpha * alpha;
+    float phi = 2.0 * PI * xi.x;
+    float cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a2 - 1.0) * xi.y));
+    float sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    vec3 H = vec3(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+
+    vec3 up = abs(N.z) < 0.999 ? vec3(0.0, 0.0, 1.0) : vec3(1.0, 0.0, 0.0);
+    vec3 tangent = normalize(cross(up, N));
+    vec3 bitangent = cross(N, tangent);
+    return normalize(tangent * H.x + bitangent * H.y + N * H.z);
+}
+
+float distribution_ggx(float n_dot_h, float alpha) {
+    float a2 = alpha * alpha;
+    float d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
@@ -2,8 +2,8 @@
 
 layout(location = 0) in vec3 color;
 
-layout(location = 0) out vec4 clip_position;
+layout(location = 0) out vec4 frag_color;
 
 void main() {
-    clip_position = vec4(color, 1.0);
-}
\ No newline at end of file
+    frag_color = vec4(color, 1.0);
+}
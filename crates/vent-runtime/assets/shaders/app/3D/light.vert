@@ -1,23 +1,18 @@
 #version 450 core
 
-layout(binding = 0) uniform Camera {
-    mat4 projection;
-    mat4 view;
-} camera;
-
-layout(binding = 1) uniform Light {
-    vec3 position;
-    vec3 color;
-} light;
+// Gizmo of a single Light
+layout(push_constant) uniform Gizmo {
+    mat4 proj_view;
+    // xyz: Position, w: Scale
+    vec4 position_scale;
+    vec4 color;
+} gizmo;
 
 layout(location = 0) in vec3 in_position;
 
-layout(location = 0) out vec4 clip_position;
-layout(location = 1) out vec3 color;
-
-const float scale = 1.0;
+layout(location = 0) out vec3 color;
 
 void main() {
-    clip_position = camera.projection * camera.view * vec4(in_position * scale + light.position, 1.0);
-    color = light.color;
-}
\ No newline at end of file
+    gl_Position = gizmo.proj_view * vec4(in_position * gizmo.position_scale.w + gizmo.position_scale.xyz, 1.0);
+    color = gizmo.color.rgb;
+}
@@ -0,0 +1,55 @@
+// Lights of the Frame, Written by the LightRenderer every Frame
+
+#define LIGHT_DIRECTIONAL 0
+#define LIGHT_POINT 1
+#define LIGHT_SPOT 2
+
+struct Light {
+    vec3 position;
+    // 0.0 means infinite
+    float range;
+    // Where the Light shines to, Normalized
+    vec3 direction;
+    uint kind;
+    vec3 color;
+    float intensity;
+    float spot_scale;
+    float spot_offset;
+};
+
+#ifndef FRAME_SET
+#define FRAME_SET 1
+#endif
+
+// The count is the length of the buffer
+layout (std430, set = FRAME_SET, binding = 1) readonly buffer Lights {
+    Light lights[];
+};
+
+// Inverse square falloff which smoothly reaches zero at the range, Like KHR_lights_punctual
+float range_attenuation(float distance, float range) {
+    float falloff = 1.0 / max(distance * distance, 1e-4);
+    if (range <= 0.0) {
+        return falloff;
+    }
+    float ratio = distance / range;
+    float window = clamp(1.0 - ratio * ratio * ratio * ratio, 0.0, 1.0);
+    return falloff * window * window;
+}
+
+// Radiance arriving at `world_position`, `L` is set to the direction towards the Light
+vec3 light_radiance(Light light, vec3 world_position, out vec3 L) {
+    if (light.kind == LIGHT_DIRECTIONAL) {
+        L = -light.direction;
+        return light.color * light.intensity;
+    }
+    vec3 to_light = light.position - world_position;
+    float distance = length(to_light);
+    L = to_light / max(distance, 1e-4);
+    float attenuation = range_attenuation(distance, light.range);
+    if (light.kind == LIGHT_SPOT) {
+        float cone = clamp(dot(light.direction, -L) * light.spot_scale + light.spot_offset, 0.0, 1.0);
+        attenuation *= cone * cone;
+    }
+    return light.color * light.intensity * attenuation;
+}
@@ -0,0 +1,16 @@
+// Everything the GPU needs to cull and draw a single Mesh, Must match `GpuObject` in Rust
+struct Object {
+    mat4 transform;
+    // Model space Bounding Box, w is unused
+    vec4 bounds_min;
+    vec4 bounds_max;
+    uint material_id;
+    uint first_index;
+    uint index_count;
+    int vertex_offset;
+    // Draws of a batch use the same Pipeline, They go into their own range of the command buffer
+    uint batch;
+    uint first_command;
+    // Sorted Transparent draws, Every Object keeps its own command so the order stays
+    uint ordered;
+};
@@ -0,0 +1,18 @@
+#version 450 core
+
+// Resolves the Weighted Blended Transparency onto the scene color, Blended with SRC_ALPHA / ONE_MINUS_SRC_ALPHA
+layout (binding = 0) uniform sampler2D accumulation;
+layout (binding = 1) uniform sampler2D revealage;
+
+layout (location = 0) out vec4 fragColor;
+
+void main() {
+    ivec2 texel = ivec2(gl_FragCoord.xy);
+    float reveal = texelFetch(revealage, texel, 0).r;
+    // Nothing Transparent was drawn here
+    if (reveal >= 1.0) {
+        discard;
+    }
+    vec4 accum = texelFetch(accumulation, texel, 0);
+    fragColor = vec4(accum.rgb / max(accum.a, 1e-5), 1.0 - reveal);
+}
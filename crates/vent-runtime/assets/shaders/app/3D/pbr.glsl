@@ -0,0 +1,88 @@
+// Cook-Torrance BRDF for the glTF metallic-roughness Model, Everything is in linear space
+
+const float PI = 3.14159265359;
+// Dielectrics reflect about 4% at normal incidence
+const vec3 DIELECTRIC_F0 = vec3(0.04);
+
+struct PbrSurface {
+    // Albedo without the metallic part
+    vec3 diffuse;
+    vec3 f0;
+    // Perceptual roughness, Clamped
+    float roughness;
+    // Perceptual roughness squared
+    float alpha;
+};
+
+PbrSurface pbr_surface(vec3 base_color, float metallic, float roughness) {
+    PbrSurface surface;
+    surface.diffuse = base_color * (1.0 - metallic);
+    surface.f0 = mix(DIELECTRIC_F0, base_color, metallic);
+    // Very low roughness makes highlights disappear
+    surface.roughness = clamp(roughness, 0.045, 1.0);
+    surface.alpha = surface.roughness * surface.roughness;
+    return surface;
+}
+
+float distribution_ggx(float n_dot_h, float alpha) {
+    float a2 = alpha * alpha;
+    float d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+// Height correlated Smith, Already contains the 1 / (4 NdotL NdotV) of the BRDF
+float visibility_smith_ggx(float n_dot_v, float n_dot_l, float alpha) {
+    float a2 = alpha * alpha;
+    float ggx_v = n_dot_l * sqrt(n_dot_v * n_dot_v * (1.0 - a2) + a2);
+    float ggx_l = n_dot_v * sqrt(n_dot_l * n_dot_l * (1.0 - a2) + a2);
+    return 0.5 / max(ggx_v + ggx_l, 1e-5);
+}
+
+vec3 fresnel_schlick(float v_dot_h, vec3 f0) {
+    return f0 + (1.0 - f0) * pow(1.0 - v_dot_h, 5.0);
+}
+
+// Outgoing radiance for a single light, `L` points towards the light and `radiance` already contains the attenuation
+vec3 pbr_light(PbrSurface surface, vec3 N, vec3 V, vec3 L, vec3 radiance) {
+    vec3 H = normalize(V + L);
+    float n_dot_l = clamp(dot(N, L), 0.0, 1.0);
+    float n_dot_v = clamp(abs(dot(N, V)), 1e-4, 1.0);
+    float n_dot_h = clamp(dot(N, H), 0.0, 1.0);
+    float v_dot_h = clamp(dot(V, H), 0.0, 1.0);
+
+    vec3 F = fresnel_schlick(v_dot_h, surface.f0);
+    vec3 specular = F * distribution_ggx(n_dot_h, surface.alpha)
+        * visibility_smith_ggx(n_dot_v, n_dot_l, surface.alpha);
+    vec3 diffuse = (1.0 - F) * surface.diffuse / PI;
+    return (diffuse + specular) * radiance * n_dot_l;
+}
+
+// Normal mapping with the vertex tangent, Its w is the handedness of the bitangent like in glTF
+vec3 tangent_space_normal(vec3 N, vec4 tangent, vec3 tangent_normal) {
+    // Interpolation skews them a bit, So T is made orthogonal to N again
+    vec3 T = normalize(tangent.xyz - N * dot(N, tangent.xyz));
+    vec3 B = cross(N, T) * tangent.w;
+    return normalize(mat3(T, B, N) * tangent_normal);
+}
+
+// Normal mapping without vertex tangents, The tangent frame is built from screen space derivatives.
+// Only used for Meshes without texture coordinates or when the Tangents could not be generated
+// See "Followup: Normal Mapping Without Precomputed Tangents" by Christian Schüler
+vec3 perturb_normal(vec3 N, vec3 position, vec2 uv, vec3 tangent_normal) {
+    vec3 dp1 = dFdx(position);
+    vec3 dp2 = dFdy(position);
+    vec2 duv1 = dFdx(uv);
+    vec2 duv2 = dFdy(uv);
+
+    vec3 dp2_perp = cross(dp2, N);
+    vec3 dp1_perp = cross(N, dp1);
+    vec3 T = dp2_perp * duv1.x + dp1_perp * duv2.x;
+    vec3 B = dp2_perp * duv1.y + dp1_perp * duv2.y;
+    float scale = max(dot(T, T), dot(B, B));
+    // No texture coordinates, Nothing to map
+    if (scale <= 0.0) {
+        return N;
+    }
+    float inv_max = inversesqrt(scale);
+    return normalize(mat3(T * inv_max, B * inv_max, N) * tangent_normal);
+}
@@ -1,66 +1,144 @@
 #version 450 core
+//! permutation bindless BINDLESS
+//! permutation oit OIT
+//! permutation bindless_oit BINDLESS OIT
+
+#ifdef BINDLESS
+#extension GL_EXT_nonuniform_qualifier : require
+#endif
+
+#include "pbr.glsl"
+#include "lights.glsl"
+#include "clusters.glsl"
+#include "shadows.glsl"
+#include "ibl.glsl"
+
+#ifdef BINDLESS
+struct Material {
+    vec4 base_color;
+    vec3 emissive_factor;
+    int alpha_mode;
+    float alpha_cutoff;
+    float metallic_factor;
+    float roughness_factor;
+    float occlusion_strength;
+    uint diffuse_texture;
+    uint normal_texture;
+    uint metallic_roughness_texture;
+    uint occlusion_texture;
+    uint emissive_texture;
+};
+
+layout (binding = 0) uniform sampler2D textures[];
+
+layout (std430, binding = 1) readonly buffer Materials {
+    Material materials[];
+};
 
-layout (binding = 0) uniform sampler2D texture_diffuse;
+layout (location = 5) flat in uint material_id;
+
+#define SAMPLE(name) texture(textures[nonuniformEXT(material.name)], tex_coord)
+#else
+layout (binding = 0) uniform sampler2D diffuse_texture;
 
 layout (binding = 1) uniform Material {
     vec4 base_color;
+    vec3 emissive_factor;
     int alpha_mode;
     float alpha_cutoff;
+    float metallic_factor;
+    float roughness_factor;
+    float occlusion_strength;
 } material;
 
+layout (binding = 2) uniform sampler2D normal_texture;
+layout (binding = 3) uniform sampler2D metallic_roughness_texture;
+layout (binding = 4) uniform sampler2D occlusion_texture;
+layout (binding = 5) uniform sampler2D emissive_texture;
 
-// layout (binding = 2) uniform Light {
-//     vec3 position;
-//     vec3 color;
-// } light;
+#define SAMPLE(name) texture(name, tex_coord)
+#endif
 
 layout (location = 0) in vec2 tex_coord;
-layout (location = 1) in vec2 normal;
+layout (location = 1) in vec3 normal;
 layout (location = 2) in vec3 world_position;
-layout (location = 3) in vec3 position;
+layout (location = 3) in vec4 position;
 layout (location = 4) in vec3 view_position;
+layout (location = 6) in vec4 tangent;
 
 
 layout (location = 0) out vec4 fragColor;
-
-// We don't need (or want) much ambient light, so 0.1 is fine
-const float ambient_strength = 0.1;
-
-
+#ifdef OIT
+// Weighted Blended Order Independent Transparency, See "Weighted Blended Order-Independent Transparency" by McGuire and Bavoil
+layout (location = 1) out float revealage;
+#endif
 
 void main() {
-    vec4 texture = texture(texture_diffuse, tex_coord) * material.base_color;
+#ifdef BINDLESS
+    Material material = materials[material_id];
+#endif
+    vec4 base_color = SAMPLE(diffuse_texture) * material.base_color;
 
     if (material.alpha_mode == 2) { // ALPHA MASK
-		if (texture.a < material.alpha_cutoff) {
+		if (base_color.a < material.alpha_cutoff) {
 			discard;
 		}
-	} else if (material.alpha_mode == 3) { // BLEND
-        // todo
+	}
+
+    vec3 geometric_normal = normalize(normal);
+    vec3 tangent_normal = SAMPLE(normal_texture).xyz * 2.0 - 1.0;
+    vec3 N;
+    if (dot(tangent.xyz, tangent.xyz) > 0.0) {
+        N = tangent_space_normal(geometric_normal, tangent, tangent_normal);
+        // Back faces of double sided Materials, The whole tangent frame flips
+        if (!gl_FrontFacing) {
+            geometric_normal = -geometric_normal;
+            N = -N;
+        }
+    } else {
+        if (!gl_FrontFacing) {
+            geometric_normal = -geometric_normal;
+        }
+        N = perturb_normal(geometric_normal, world_position, tex_coord, tangent_normal);
+    }
+    vec3 V = normalize(view_position - world_position);
+
+    // glTF: Roughness is in green, Metallic in blue
+    vec4 metallic_roughness = SAMPLE(metallic_roughness_texture);
+    float metallic = material.metallic_factor * metallic_roughness.b;
+    float roughness = material.roughness_factor * metallic_roughness.g;
+    float occlusion = mix(1.0, SAMPLE(occlusion_texture).r, material.occlusion_strength);
+    vec3 emissive = SAMPLE(emissive_texture).rgb * material.emissive_factor;
+
+    PbrSurface surface = pbr_surface(base_color.rgb, metallic, roughness);
+    vec3 color = vec3(0.0);
+    // Only the Lights which can reach this Cluster
+    uint cluster = cluster_index(gl_FragCoord.xy, world_position);
+    uint light_count = cluster_lights[cluster].light_count;
+    for (uint j = 0; j < light_count; j++) {
+        uint i = cluster_lights[cluster].light_indices[j];
+        vec3 L;
+        vec3 radiance = light_radiance(lights[i], world_position, L);
+        if (int(i) == shadows.light_index) {
+            radiance *= shadow_factor(world_position, geometric_normal, L);
+        }
+        color += pbr_light(surface, N, V, L, radiance);
+    }
+    color += ibl_ambient(surface, N, V) * occlusion;
+    color += emissive;
+    if (shadows.debug_cascades != 0) {
+        color *= debug_cascade_color(world_position);
     }
 
-    // Calculate the ambient color
-    // vec3 ambient_color = light.color * ambient_strength;
-
-    // // Calculate the light direction
-    // vec3 light_dir = normalize(light.position - world_position);
-
-    // // Calculate the view direction
-    // vec3 view_dir = normalize(view_position.xyz - world_position);
-
-    // // Calculate the half vector
-    // vec3 half_dir = normalize(view_dir + light_dir);
-
-    // // Calculate the diffuse color
-    // float diffuse_strength = max(dot(world_position, light_dir), 0.0);
-    // vec3 diffuse_color = light.color * diffuse_strength;
-
-    // // Calculate the specular color
-    // float specular_strength = pow(max(dot(world_position, half_dir), 0.0), 32.0);
-    // vec3 specular_color = specular_strength * light.color;
-
-    // // Calculate the final color
-    // vec3 result = (ambient_color + diffuse_color + specular_color) * texture.xyz;
-
-    fragColor = texture; // vec4(result, 1.0);
-}
\ No newline at end of file
+    // HDR and linear, The tonemapping at the end takes care of the rest
+#ifdef OIT
+    // Closer surfaces count more, The upper clamp is low enough to not overflow the half floats with HDR colors
+    float alpha = base_color.a;
+    float view_depth = distance(view_position, world_position);
+    float weight = alpha * clamp(10.0 / (1e-5 + pow(view_depth / 5.0, 2.0) + pow(view_depth / 200.0, 6.0)), 1e-2, 3e2);
+    fragColor = vec4(color * alpha, alpha) * weight;
+    revealage = alpha;
+#else
+    fragColor = vec4(color, base_color.a);
+#endif
+}
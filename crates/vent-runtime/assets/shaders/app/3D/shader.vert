@@ -1,25 +1,64 @@
 #version 450 core
+//! permutation bindless BINDLESS
+//! permutation indirect BINDLESS INDIRECT
 
 layout(location = 0) in vec3 in_position;
 layout(location = 1) in vec2 in_tex_coord;
 layout(location = 2) in vec3 in_normal;
+// w is the handedness of the bitangent, Zero when the Mesh has no Tangents
+layout(location = 3) in vec4 in_tangent;
+#ifndef INDIRECT
+// Per instance, Every instance of the Model has its own transform
+layout(location = 4) in mat4 instance_transform;
+#endif
 
 layout(push_constant) uniform PushConsts {
     vec3 view_position;
     mat4 proj_view_trans;
+#if defined(BINDLESS) && !defined(INDIRECT)
+    uint material_id;
+#endif
 } camera;
 
+#ifdef INDIRECT
+#include "object.glsl"
+
+// Written by the Culling pass, Every draw has the index of its Object as first instance
+layout (std430, set = 1, binding = 0) readonly buffer Objects {
+    Object objects[];
+};
+#endif
+
 layout(location = 0) out vec2 tex_coord;
 layout(location = 1) out vec3 normal;
 layout(location = 2) out vec3 world_position;
 layout(location = 3) out vec4 position;
 layout(location = 4) out vec3 view_position;
+#ifdef BINDLESS
+layout(location = 5) flat out uint material_id;
+#endif
+layout(location = 6) out vec4 tangent;
 
 void main() {
     tex_coord = in_tex_coord;
-    normal = in_normal;
-    world_position = in_position;
-    position = camera.proj_view_trans * vec4(in_position, 1.0);
+#ifdef INDIRECT
+    Object object = objects[gl_InstanceIndex];
+    vec4 world = object.transform * vec4(in_position, 1.0);
+    normal = mat3(object.transform) * in_normal;
+    tangent = vec4(mat3(object.transform) * in_tangent.xyz, in_tangent.w);
+    world_position = world.xyz;
+    position = camera.proj_view_trans * world;
+    material_id = object.material_id;
+#else
+    vec4 world = instance_transform * vec4(in_position, 1.0);
+    normal = mat3(instance_transform) * in_normal;
+    tangent = vec4(mat3(instance_transform) * in_tangent.xyz, in_tangent.w);
+    world_position = world.xyz;
+    position = camera.proj_view_trans * world;
+#ifdef BINDLESS
+    material_id = camera.material_id;
+#endif
+#endif
     view_position = camera.view_position;
 
     gl_Position = position;
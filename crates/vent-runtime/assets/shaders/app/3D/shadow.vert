@@ -0,0 +1,14 @@
+#version 450 core
+
+layout(location = 0) in vec3 in_position;
+// Per instance, Same as in shader.vert
+layout(location = 4) in mat4 instance_transform;
+
+// One draw per Cascade, Each one with its own Light matrix
+layout(push_constant) uniform PushConsts {
+    mat4 light_view_proj;
+} cascade;
+
+void main() {
+    gl_Position = cascade.light_view_proj * instance_transform * vec4(in_position, 1.0);
+}
@@ -0,0 +1,69 @@
+// Cascaded Shadow Maps of the first directional Light, Written by the ShadowRenderer every Frame
+
+// Must match `CASCADE_COUNT` in shadow_renderer.rs
+#define CASCADE_COUNT 4
+
+// All Cascades are in one 2x2 atlas, Cascade i is in the tile (i % 2, i / 2)
+layout (set = 1, binding = 2) uniform sampler2DShadow shadow_map;
+
+layout (std140, set = 1, binding = 3) uniform Shadows {
+    mat4 cascade_view_proj[CASCADE_COUNT];
+    // Size of a single Texel of every Cascade in world units
+    vec4 texel_sizes;
+    // Index into `lights` of the Light casting the Shadows, -1 when there is none
+    int light_index;
+    uint debug_cascades;
+} shadows;
+
+const vec3 CASCADE_COLORS[CASCADE_COUNT] = vec3[](
+    vec3(1.0, 0.25, 0.25),
+    vec3(0.25, 1.0, 0.25),
+    vec3(0.25, 0.25, 1.0),
+    vec3(1.0, 1.0, 0.25)
+);
+
+// The first Cascade containing the position, Or CASCADE_COUNT when it is outside of all of them
+int select_cascade(vec3 world_position) {
+    for (int i = 0; i < CASCADE_COUNT; i++) {
+        vec4 clip = shadows.cascade_view_proj[i] * vec4(world_position, 1.0);
+        vec3 ndc = clip.xyz / clip.w;
+        // A small border, So the PCF kernel never reads from the neighbour tile
+        if (all(lessThan(abs(ndc.xy), vec2(0.98))) && ndc.z >= 0.0 && ndc.z <= 1.0) {
+            return i;
+        }
+    }
+    return CASCADE_COUNT;
+}
+
+// 1.0 is fully lit, `N` has to be the geometric normal, Normal maps only cause acne here
+float shadow_factor(vec3 world_position, vec3 N, vec3 L) {
+    int cascade = select_cascade(world_position);
+    if (cascade >= CASCADE_COUNT) {
+        return 1.0;
+    }
+    // Normal offset, Pushes the lookup out of the surface by about a texel (more at grazing angles)
+    float texel_size = shadows.texel_sizes[cascade];
+    float n_dot_l = clamp(dot(N, L), 0.0, 1.0);
+    vec3 offset_position = world_position + N * texel_size * (1.5 - n_dot_l);
+
+    vec4 clip = shadows.cascade_view_proj[cascade] * vec4(offset_position, 1.0);
+    vec3 coord = clip.xyz / clip.w;
+    vec2 tile = vec2(cascade % 2, cascade / 2);
+    vec2 uv = (tile + coord.xy * 0.5 + 0.5) * 0.5;
+
+    // 3x3 PCF, Every tap is already filtered 2x2 by the comparison sampler
+    vec2 atlas_texel = 1.0 / vec2(textureSize(shadow_map, 0));
+    float lit = 0.0;
+    for (int x = -1; x <= 1; x++) {
+        for (int y = -1; y <= 1; y++) {
+            lit += texture(shadow_map, vec3(uv + vec2(x, y) * atlas_texel, coord.z));
+        }
+    }
+    return lit / 9.0;
+}
+
+// Tints the color by the Cascade the position is in, White outside of all Cascades
+vec3 debug_cascade_color(vec3 world_position) {
+    int cascade = select_cascade(world_position);
+    return cascade < CASCADE_COUNT ? CASCADE_COLORS[cascade] : vec3(1.0);
+}
@@ -0,0 +1,60 @@
+#version 450 core
+
+// 13 tap downsample from "Next Generation Post Processing in Call of Duty: Advanced Warfare" by Jorge Jimenez
+
+layout (location = 0) in vec2 uv;
+
+// The next larger level, Or the scene color for the first one
+layout (binding = 0) uniform sampler2D source;
+
+layout (push_constant) uniform Downsample {
+    // Only for the first level, Keeps single very bright pixels from flickering
+    uint karis_average;
+} downsample;
+
+layout (location = 0) out vec4 frag_color;
+
+float karis_weight(vec3 color) {
+    float luminance = dot(color, vec3(0.2126, 0.7152, 0.0722));
+    return 1.0 / (1.0 + luminance);
+}
+
+void main() {
+    vec2 texel = 1.0 / vec2(textureSize(source, 0));
+    vec3 a = texture(source, uv + texel * vec2(-2.0, 2.0)).rgb;
+    vec3 b = texture(source, uv + texel * vec2(0.0, 2.0)).rgb;
+    vec3 c = texture(source, uv + texel * vec2(2.0, 2.0)).rgb;
+    vec3 d = texture(source, uv + texel * vec2(-2.0, 0.0)).rgb;
+    vec3 e = texture(source, uv).rgb;
+    vec3 f = texture(source, uv + texel * vec2(2.0, 0.0)).rgb;
+    vec3 g = texture(source, uv + texel * vec2(-2.0, -2.0)).rgb;
+    vec3 h = texture(source, uv + texel * vec2(0.0, -2.0)).rgb;
+    vec3 i = texture(source, uv + texel * vec2(2.0, -2.0)).rgb;
+    vec3 j = texture(source, uv + texel * vec2(-1.0, 1.0)).rgb;
+    vec3 k = texture(source, uv + texel * vec2(1.0, 1.0)).rgb;
+    vec3 l = texture(source, uv + texel * vec2(-1.0, -1.0)).rgb;
+    vec3 m = texture(source, uv + texel * vec2(1.0, -1.0)).rgb;
+
+    // Four overlapping corner boxes and the center box
+    vec3 boxes[5] = vec3[](
+        (a + b + d + e) * 0.25,
+        (b + c + e + f) * 0.25,
+        (d + e + g + h) * 0.25,
+        (e + f + h + i) * 0.25,
+        (j + k + l + m) * 0.25
+    );
+    float box_weights[5] = float[](0.125, 0.125, 0.125, 0.125, 0.5);
+
+    vec3 color = vec3(0.0);
+    float weight_sum = 0.0;
+    for (int box = 0; box < 5; box++) {
+        float weight = box_weights[box];
+        if (downsample.karis_average != 0) {
+            weight *= karis_weight(boxes[box]);
+        }
+        color += boxes[box] * weight;
+        weight_sum += weight;
+    }
+    // NaNs and negative colors would spread over the whole screen
+    frag_color = vec4(max(color / weight_sum, 0.0), 1.0);
+}
@@ -0,0 +1,36 @@
+#version 450 core
+
+// 3x3 tent upsample, Adds the blurred smaller level on top of the current one
+
+layout (location = 0) in vec2 uv;
+
+// The next smaller level, Already upsampled
+layout (binding = 0) uniform sampler2D bloom;
+// The downsampled level of the same size, Or the scene color for the last one
+layout (binding = 1) uniform sampler2D base;
+
+layout (push_constant) uniform Upsample {
+    // In texels of `bloom`
+    float filter_radius;
+    float base_weight;
+    float bloom_weight;
+} upsample;
+
+layout (location = 0) out vec4 frag_color;
+
+void main() {
+    vec2 radius = upsample.filter_radius / vec2(textureSize(bloom, 0));
+    vec3 blurred = texture(bloom, uv).rgb * 4.0;
+    blurred += texture(bloom, uv + radius * vec2(-1.0, 0.0)).rgb * 2.0;
+    blurred += texture(bloom, uv + radius * vec2(1.0, 0.0)).rgb * 2.0;
+    blurred += texture(bloom, uv + radius * vec2(0.0, -1.0)).rgb * 2.0;
+    blurred += texture(bloom, uv + radius * vec2(0.0, 1.0)).rgb * 2.0;
+    blurred += texture(bloom, uv + radius * vec2(-1.0, -1.0)).rgb;
+    blurred += texture(bloom, uv + radius * vec2(1.0, -1.0)).rgb;
+    blurred += texture(bloom, uv + radius * vec2(-1.0, 1.0)).rgb;
+    blurred += texture(bloom, uv + radius * vec2(1.0, 1.0)).rgb;
+    blurred /= 16.0;
+
+    vec3 color = texture(base, uv).rgb * upsample.base_weight + blurred * upsample.bloom_weight;
+    frag_color = vec4(color, 1.0);
+}
@@ -0,0 +1,24 @@
+// sRGB transfer functions, For the Swapchains which do not encode for us
+
+vec3 linear_to_srgb(vec3 color) {
+    vec3 low = color * 12.92;
+    vec3 high = 1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055;
+    return mix(high, low, lessThanEqual(color, vec3(0.0031308)));
+}
+
+vec3 srgb_to_linear(vec3 color) {
+    vec3 low = color / 12.92;
+    vec3 high = pow((color + 0.055) / 1.055, vec3(2.4));
+    return mix(high, low, lessThanEqual(color, vec3(0.04045)));
+}
+
+// The LDR effects work on display encoded colors, Like the LUTs and FXAA expect them.
+// Their images have the format of the Swapchain, With an sRGB format the hardware decodes and encodes for us,
+// So we have to undo it. Otherwise the images already hold the encoded colors
+vec3 to_display(vec3 color, uint encode_srgb) {
+    return encode_srgb != 0 ? color : linear_to_srgb(color);
+}
+
+vec3 from_display(vec3 color, uint encode_srgb) {
+    return encode_srgb != 0 ? color : srgb_to_linear(color);
+}
@@ -0,0 +1,30 @@
+#version 450 core
+
+// Color grading with a 3D LUT, Indexed with the display encoded color
+
+#include "color.glsl"
+
+layout (location = 0) in vec2 uv;
+
+layout (binding = 0) uniform sampler2D source;
+layout (binding = 1) uniform sampler3D lut;
+
+layout (push_constant) uniform ColorGrading {
+    // 0.0 keeps the original colors
+    float strength;
+    uint encode_srgb;
+} grading;
+
+layout (location = 0) out vec4 frag_color;
+
+void main() {
+    vec3 color = clamp(to_display(texture(source, uv).rgb, grading.encode_srgb), 0.0, 1.0);
+
+    // The outermost texels hold 0.0 and 1.0, Not the texel borders
+    float size = float(textureSize(lut, 0).x);
+    vec3 coord = color * ((size - 1.0) / size) + 0.5 / size;
+    vec3 graded = texture(lut, coord).rgb;
+
+    color = mix(color, graded, grading.strength);
+    frag_color = vec4(from_display(color, grading.encode_srgb), 1.0);
+}
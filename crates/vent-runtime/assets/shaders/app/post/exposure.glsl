@@ -0,0 +1,21 @@
+// Automatic Exposure, Written by exposure.comp and read when tonemapping
+
+// Must match exposure.rs
+#define HISTOGRAM_BINS 256
+
+struct Exposure {
+    // Adapted average luminance of the scene
+    float luminance;
+    // What the scene color gets multiplied with
+    float exposure;
+};
+
+// Bin 0 is for black pixels, The rest covers the log2 luminance range evenly
+uint luminance_bin(vec3 color, float min_log_luminance, float inv_log_luminance_range) {
+    float luminance = dot(color, vec3(0.2126, 0.7152, 0.0722));
+    if (luminance < 1e-5) {
+        return 0;
+    }
+    float t = clamp((log2(luminance) - min_log_luminance) * inv_log_luminance_range, 0.0, 1.0);
+    return uint(t * float(HISTOGRAM_BINS - 2) + 1.0);
+}
@@ -0,0 +1,39 @@
+#version 450 core
+
+// Animated film grain, Strongest in the mid tones
+
+#include "color.glsl"
+
+layout (location = 0) in vec2 uv;
+
+layout (binding = 0) uniform sampler2D source;
+
+layout (push_constant) uniform FilmGrain {
+    float intensity;
+    // In pixels
+    float grain_size;
+    // In seconds, Changes the pattern every Frame
+    float time;
+    uint encode_srgb;
+} grain;
+
+layout (location = 0) out vec4 frag_color;
+
+// Hash without sine by Dave Hoskins
+float hash(vec3 p) {
+    p = fract(p * 0.1031);
+    p += dot(p, p.zyx + 31.32);
+    return fract((p.x + p.y) * p.z);
+}
+
+void main() {
+    vec3 color = to_display(texture(source, uv).rgb, grain.encode_srgb);
+
+    vec2 cell = floor(gl_FragCoord.xy / max(grain.grain_size, 1.0));
+    float noise = hash(vec3(cell, floor(grain.time * 60.0))) - 0.5;
+    float luminance = dot(color, vec3(0.2126, 0.7152, 0.0722));
+    float response = 4.0 * luminance * (1.0 - luminance);
+    color = max(color + noise * grain.intensity * response, 0.0);
+
+    frag_color = vec4(from_display(color, grain.encode_srgb), 1.0);
+}
@@ -0,0 +1,62 @@
+#version 450 core
+
+// FXAA by Timothy Lottes, The small variant which only searches along a single direction
+
+#include "color.glsl"
+
+layout (location = 0) in vec2 uv;
+
+layout (binding = 0) uniform sampler2D source;
+
+layout (push_constant) uniform Fxaa {
+    // In pixels, How far along an edge we blur
+    float span_max;
+    // Higher values blur less on low contrast edges
+    float reduce_mul;
+    float reduce_min;
+    uint encode_srgb;
+} fxaa;
+
+layout (location = 0) out vec4 frag_color;
+
+vec3 fetch(vec2 coord) {
+    return to_display(texture(source, coord).rgb, fxaa.encode_srgb);
+}
+
+void main() {
+    const vec3 LUMA = vec3(0.299, 0.587, 0.114);
+    vec2 texel = 1.0 / vec2(textureSize(source, 0));
+
+    vec3 center = fetch(uv);
+    float luma_nw = dot(fetch(uv + vec2(-1.0, -1.0) * texel), LUMA);
+    float luma_ne = dot(fetch(uv + vec2(1.0, -1.0) * texel), LUMA);
+    float luma_sw = dot(fetch(uv + vec2(-1.0, 1.0) * texel), LUMA);
+    float luma_se = dot(fetch(uv + vec2(1.0, 1.0) * texel), LUMA);
+    float luma_m = dot(center, LUMA);
+
+    float luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    float luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    // Perpendicular to the gradient, So along the edge
+    vec2 direction = vec2(
+        -((luma_nw + luma_ne) - (luma_sw + luma_se)),
+        (luma_nw + luma_sw) - (luma_ne + luma_se)
+    );
+    float direction_reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * fxaa.reduce_mul, fxaa.reduce_min);
+    float inverse_direction_min = 1.0 / (min(abs(direction.x), abs(direction.y)) + direction_reduce);
+    direction = clamp(direction * inverse_direction_min, -fxaa.span_max, fxaa.span_max) * texel;
+
+    vec3 color_a = 0.5 * (
+        fetch(uv + direction * (1.0 / 3.0 - 0.5)) +
+        fetch(uv + direction * (2.0 / 3.0 - 0.5))
+    );
+    vec3 color_b = color_a * 0.5 + 0.25 * (
+        fetch(uv + direction * -0.5) +
+        fetch(uv + direction * 0.5)
+    );
+    // The wider blur went over the edge, Stay with the narrow one
+    float luma_b = dot(color_b, LUMA);
+    vec3 color = (luma_b < luma_min || luma_b > luma_max) ? color_a : color_b;
+
+    frag_color = vec4(from_display(color, fxaa.encode_srgb), 1.0);
+}
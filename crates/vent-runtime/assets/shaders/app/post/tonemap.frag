@@ -0,0 +1,95 @@
+#version 450 core
+
+#include "color.glsl"
+#include "exposure.glsl"
+
+// Must match `Tonemapper` in post/mod.rs
+#define TONEMAP_ACES 0
+#define TONEMAP_AGX 1
+#define TONEMAP_REINHARD 2
+
+layout (location = 0) in vec2 uv;
+
+layout (binding = 0) uniform sampler2D scene_color;
+
+layout (std430, binding = 1) readonly buffer ExposureBuffer {
+    Exposure auto_exposure;
+};
+
+layout (push_constant) uniform Tonemap {
+    uint tonemapper;
+    // Use the Exposure of the histogram instead of `exposure`
+    uint automatic;
+    float exposure;
+    // The Swapchain is not sRGB, So we have to encode ourselves
+    uint encode_srgb;
+} tonemap;
+
+layout (location = 0) out vec4 frag_color;
+
+// ACES fit by Stephen Hill, sRGB => AP1 => RRT and ODT => sRGB
+vec3 tonemap_aces(vec3 color) {
+    const mat3 ACES_INPUT = mat3(
+        0.59719, 0.07600, 0.02840,
+        0.35458, 0.90834, 0.13383,
+        0.04823, 0.01566, 0.83777
+    );
+    const mat3 ACES_OUTPUT = mat3(
+        1.60475, -0.10208, -0.00327,
+        -0.53108, 1.10813, -0.07276,
+        -0.07367, -0.00605, 1.07602
+    );
+    vec3 v = ACES_INPUT * color;
+    vec3 a = v * (v + 0.0245786) - 0.000090537;
+    vec3 b = v * (0.983729 * v + 0.4329510) + 0.238081;
+    return clamp(ACES_OUTPUT * (a / b), 0.0, 1.0);
+}
+
+// Minimal AgX by Benjamin Wrensch, With the default look
+vec3 agx_contrast(vec3 x) {
+    vec3 x2 = x * x;
+    vec3 x4 = x2 * x2;
+    return 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232;
+}
+
+vec3 tonemap_agx(vec3 color) {
+    const mat3 AGX_INSET = mat3(
+        0.842479062253094, 0.0423282422610123, 0.0423756549057051,
+        0.0784335999999992, 0.878468636469772, 0.0784336,
+        0.0792237451477643, 0.0791661274605434, 0.879142973793104
+    );
+    const mat3 AGX_OUTSET = mat3(
+        1.19687900512017, -0.0528968517574562, -0.0529716355144438,
+        -0.0980208811401368, 1.15190312990417, -0.0980434501171241,
+        -0.0990297440797205, -0.0989611768448433, 1.15107367264116
+    );
+    const float MIN_EV = -12.47393;
+    const float MAX_EV = 4.026069;
+    vec3 v = AGX_INSET * max(color, 1e-10);
+    v = clamp(log2(v), MIN_EV, MAX_EV);
+    v = agx_contrast((v - MIN_EV) / (MAX_EV - MIN_EV));
+    // The curve ends in display space, Back to linear
+    return pow(clamp(AGX_OUTSET * v, 0.0, 1.0), vec3(2.2));
+}
+
+vec3 tonemap_reinhard(vec3 color) {
+    return color / (1.0 + color);
+}
+
+void main() {
+    float exposure = tonemap.automatic != 0 ? auto_exposure.exposure : tonemap.exposure;
+    vec3 color = texture(scene_color, uv).rgb * exposure;
+
+    if (tonemap.tonemapper == TONEMAP_AGX) {
+        color = tonemap_agx(color);
+    } else if (tonemap.tonemapper == TONEMAP_REINHARD) {
+        color = tonemap_reinhard(color);
+    } else {
+        color = tonemap_aces(color);
+    }
+
+    if (tonemap.encode_srgb != 0) {
+        color = linear_to_srgb(color);
+    }
+    frag_color = vec4(color, 1.0);
+}
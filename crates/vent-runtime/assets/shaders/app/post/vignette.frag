@@ -0,0 +1,32 @@
+#version 450 core
+
+// Darkens the corners of the screen
+
+#include "color.glsl"
+
+layout (location = 0) in vec2 uv;
+
+layout (binding = 0) uniform sampler2D source;
+
+layout (push_constant) uniform Vignette {
+    // How dark the corners get, 1.0 is black
+    float intensity;
+    // Distance from the center where the darkening starts, 1.0 are the corners
+    float radius;
+    // Width of the transition
+    float smoothness;
+    uint encode_srgb;
+} vignette;
+
+layout (location = 0) out vec4 frag_color;
+
+void main() {
+    vec3 color = to_display(texture(source, uv).rgb, vignette.encode_srgb);
+
+    // 0.0 in the center and 1.0 in the corners
+    float distance_to_center = length(uv - 0.5) * sqrt(2.0);
+    float shade = smoothstep(vignette.radius, vignette.radius + vignette.smoothness, distance_to_center);
+    color *= 1.0 - shade * vignette.intensity;
+
+    frag_color = vec4(from_display(color, vignette.encode_srgb), 1.0);
+}
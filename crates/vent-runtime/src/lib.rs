@@ -1,11 +1,18 @@
-use crate::render::Dimension;
+use crate::render::{Dimension, Msaa, SkyboxSource, Transparency};
 
 use project::{RenderSettings, VentApplicationProject};
-use render::{camera::camera_controller3d::CameraController3D, DefaultRuntimeRenderer};
+use render::{
+    camera::camera_controller3d::CameraController3D,
+    post::{ExposureSettings, PostEffectSettings, Tonemapper},
+    DefaultRuntimeRenderer,
+};
 
 use util::{crash::init_panic_hook, input_handler::InputHandler, version::Version};
 use vent_logging::Logger;
-use vent_window::{Window, WindowAttribs, WindowEvent};
+use vent_window::{
+    keyboard::{Key, KeyState},
+    Window, WindowAttribs, WindowEvent,
+};
 
 pub mod project;
 pub mod render;
@@ -27,6 +34,12 @@ impl VentApplication {
             render_settings: RenderSettings {
                 dimension: Dimension::D3,
                 vsync: false,
+                tonemapper: Tonemapper::default(),
+                exposure: ExposureSettings::default(),
+                post_effects: PostEffectSettings::default_stack(),
+                msaa: Msaa::X4,
+                transparency: Transparency::default(),
+                skybox: SkyboxSource::default(),
             },
         };
         let app = VentApplication::new(project);
@@ -48,6 +61,7 @@ impl VentApplication {
 
         let mut controller = CameraController3D::new(5.0, 1.0);
         let mut delta_time = 0.0;
+        let mut msaa = project.render_settings.msaa;
 
         // TODO, Handle scale factor change
         app_window.poll(move |event| {
@@ -59,7 +73,22 @@ impl VentApplication {
             renderer.progress_event(&event);
             match event {
                 WindowEvent::Close => {} // Closes automaticly
-                WindowEvent::Key { key, state } => input_handler.set_key(key, state),
+                WindowEvent::Key { key, state } => {
+                    if key == Key::F12 && state == KeyState::Pressed {
+                        renderer.request_screenshot();
+                    }
+                    if key == Key::F9 && state == KeyState::Pressed {
+                        renderer.toggle_debug_view();
+                    }
+                    if key == Key::F8 && state == KeyState::Pressed {
+                        let next = msaa.next();
+                        match renderer.set_msaa(next) {
+                            Ok(()) => msaa = next,
+                            Err(err) => log::error!("Failed to switch MSAA, {}", err),
+                        }
+                    }
+                    input_handler.set_key(key, state)
+                }
                 WindowEvent::MouseButton { button, state } => {
                     controller.process_mouse_input(&button, &state);
                 }
@@ -2,7 +2,13 @@ use serde::{Deserialize, Serialize};
 use std::fs::{File, OpenOptions};
 use vent_window::WindowAttribs;
 
-use crate::{render::Dimension, util::version::Version};
+use crate::{
+    render::{
+        post::{ExposureSettings, PostEffectSettings, Tonemapper},
+        Dimension, Msaa, SkyboxSource, Transparency,
+    },
+    util::version::Version,
+};
 
 /// Basic Project Information's
 #[derive(Serialize, Deserialize)]
@@ -22,6 +28,19 @@ pub struct RenderSettings {
     // Inital vsync setting, can be changed later
     pub dimension: Dimension,
     pub vsync: bool,
+    #[serde(default)]
+    pub tonemapper: Tonemapper,
+    #[serde(default)]
+    pub exposure: ExposureSettings,
+    /// Runs in this order, Bloom always runs before tonemapping
+    #[serde(default = "PostEffectSettings::default_stack")]
+    pub post_effects: Vec<PostEffectSettings>,
+    #[serde(default)]
+    pub msaa: Msaa,
+    #[serde(default)]
+    pub transparency: Transparency,
+    #[serde(default)]
+    pub skybox: SkyboxSource,
 }
 
 impl VentApplicationProject {
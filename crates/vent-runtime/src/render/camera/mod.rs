@@ -52,6 +52,7 @@ pub struct Camera3D {
     fovy: f32,
     znear: f32,
     zfar: f32,
+    aspect_ratio: f32,
     pub ubo: Camera3DData,
     pub projection: Mat4,
     pub view: Mat4,
@@ -73,6 +74,7 @@ impl Camera for Camera3D {
             fovy: 60.0,
             znear: 0.1,
             zfar: 10000.0,
+            aspect_ratio,
             rotation: Quat::IDENTITY,
             position: Vec3::ZERO,
             ubo: Default::default(),
@@ -95,6 +97,7 @@ impl Camera for Camera3D {
     // znear is changed
     // zfar is changed
     fn recreate_projection(&mut self, aspect_ratio: f32) {
+        self.aspect_ratio = aspect_ratio;
         self.projection =
             Mat4::perspective_rh(self.fovy.to_radians(), aspect_ratio, self.znear, self.zfar);
         // Flip the cameras prospective upside down
@@ -105,6 +108,27 @@ impl Camera for Camera3D {
 impl Camera3D {
     pub fn update_set() {}
 
+    /// Vertical field of view in degrees
+    #[inline]
+    pub const fn fovy(&self) -> f32 {
+        self.fovy
+    }
+
+    #[inline]
+    pub const fn znear(&self) -> f32 {
+        self.znear
+    }
+
+    #[inline]
+    pub const fn zfar(&self) -> f32 {
+        self.zfar
+    }
+
+    #[inline]
+    pub const fn aspect_ratio(&self) -> f32 {
+        self.aspect_ratio
+    }
+
     /// Call when position changed
     pub fn recreate_view(&mut self) {
         let view = Mat4::look_at_rh(self.position, self.position + self.direction, Vec3::Y);
@@ -1,6 +1,10 @@
-use ash::vk;
+use std::path::PathBuf;
 
-use super::{camera::Camera, Renderer};
+use vent_rendering::render_graph::{PassContext, PassHandle, RenderGraph};
+
+use crate::project::RenderSettings;
+
+use super::{camera::Camera, GraphTargets, Renderer};
 
 #[allow(dead_code)]
 pub struct UBO2D {}
@@ -10,6 +14,7 @@ pub struct Renderer2D {}
 impl Renderer for Renderer2D {
     fn init(
         _instance: &mut vent_rendering::instance::VulkanInstance,
+        _settings: &RenderSettings,
         _camera: &mut dyn Camera,
     ) -> Self
     where
@@ -18,6 +23,10 @@ impl Renderer for Renderer2D {
         todo!()
     }
 
+    fn setup_passes(&mut self, _graph: &mut RenderGraph, _targets: &GraphTargets) {
+        todo!()
+    }
+
     fn resize(
         &mut self,
         _instance: &mut vent_rendering::instance::VulkanInstance,
@@ -27,11 +36,27 @@ impl Renderer for Renderer2D {
         todo!()
     }
 
-    fn render(
+    fn reload_shaders(
+        &mut self,
+        _instance: &vent_rendering::instance::VulkanInstance,
+        _changed: &[PathBuf],
+    ) {
+        todo!()
+    }
+
+    fn set_msaa_samples(
+        &mut self,
+        _instance: &vent_rendering::instance::VulkanInstance,
+        _samples: ash::vk::SampleCountFlags,
+    ) -> Result<(), vent_rendering::pipeline::PipelineError> {
+        todo!()
+    }
+
+    fn execute_pass(
         &mut self,
         _instance: &vent_rendering::instance::VulkanInstance,
-        _image_index: u32,
-        _command_buffer: vk::CommandBuffer,
+        _pass: PassHandle,
+        _context: &PassContext,
         _camera: &mut dyn Camera,
     ) {
         todo!()
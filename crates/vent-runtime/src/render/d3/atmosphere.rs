@@ -0,0 +1,487 @@
+use std::path::{Path, PathBuf};
+
+use ash::vk;
+use serde::{Deserialize, Serialize};
+use vent_math::vec::vec3::Vec3;
+use vent_rendering::{
+    any_as_u8_slice,
+    descriptor::{DescriptorAllocator, DescriptorWriter},
+    image::VulkanImage,
+    instance::VulkanInstance,
+    pipeline::{ComputePipeline, HDR_FORMAT},
+};
+
+use super::{
+    ibl::{self, IBL_GROUP_SIZE, SHADER_DIR},
+    light_renderer::LightRenderer,
+};
+
+const TRANSMITTANCE_SIZE: vk::Extent2D = vk::Extent2D {
+    width: 256,
+    height: 64,
+};
+const MULTISCATTERING_SIZE: vk::Extent2D = vk::Extent2D {
+    width: 32,
+    height: 32,
+};
+/// The sky is very smooth, The Image Based Lighting scales it up for its environment
+const SKY_SIZE: u32 = 256;
+
+/// Where the viewer is, The Atmosphere does not move with the Camera. In kilometers
+const VIEW_HEIGHT: f32 = 0.2;
+/// Cosine of how far the sun has to move until the sky is rendered again (about half a degree), Every time also regenerates the Image Based Lighting
+const RENDER_SUN_COS: f32 = 0.99996;
+/// Towards the sun when there is no Directional Light, Noon
+const DEFAULT_SUN: Vec3 = Vec3::Y;
+/// The sun turns around this axis over the day
+const SUN_AXIS: Vec3 = Vec3::Z;
+
+// Must match atmosphere.glsl, Only needed for the sun color on the CPU
+const BOTTOM_RADIUS: f32 = 6360.0;
+const TOP_RADIUS: f32 = 6460.0;
+const RAYLEIGH_SCATTERING: [f32; 3] = [5.802e-3, 13.558e-3, 33.1e-3];
+const RAYLEIGH_SCALE_HEIGHT: f32 = 8.0;
+const MIE_EXTINCTION: f32 = 4.4e-3;
+const MIE_SCALE_HEIGHT: f32 = 1.2;
+const OZONE_ABSORPTION: [f32; 3] = [0.65e-3, 1.881e-3, 0.085e-3];
+const OZONE_CENTER: f32 = 25.0;
+const OZONE_WIDTH: f32 = 15.0;
+const SUN_STEP_COUNT: u32 = 40;
+
+/// A physically based sky instead of a texture, Lit by the sun Directional Light
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct AtmosphereSettings {
+    /// Of the sun outside the Atmosphere, Also the intensity of the sun Directional Light
+    pub sun_illuminance: f32,
+    /// Linear RGB, Sunlight bounced off the ground brightens the sky too
+    pub ground_albedo: [f32; 3],
+    /// Seconds for a full day, With 0 the sun stays where it is
+    pub day_length: f32,
+}
+
+impl AtmosphereSettings {
+    /// How far the sun moves around `SUN_AXIS` in `delta_time` milliseconds, In radians
+    fn sun_angle(&self, delta_time: f32) -> f32 {
+        if self.day_length > 0.0 {
+            std::f32::consts::TAU * (delta_time / 1000.0) / self.day_length
+        } else {
+            0.0
+        }
+    }
+}
+
+impl Default for AtmosphereSettings {
+    fn default() -> Self {
+        Self {
+            sun_illuminance: 3.0,
+            ground_albedo: [0.3, 0.3, 0.3],
+            day_length: 0.0,
+        }
+    }
+}
+
+/// Push Constants of atmosphere_sky.comp
+#[repr(C)]
+struct SkyData {
+    sun_direction: [f32; 3],
+    sun_illuminance: f32,
+    ground_albedo: [f32; 3],
+    view_height: f32,
+}
+
+/// Sky and Atmosphere after Hillaire, Rendered into a cubemap which the Image Based Lighting uses as environment.
+///
+/// The transmittance and multiple scattering LUTs only depend on the planet and are computed once.
+/// The sky itself follows the sun, So the time of day can change without any asset
+pub struct Atmosphere {
+    transmittance_pipeline: ComputePipeline,
+    multiscattering_pipeline: ComputePipeline,
+    sky_pipeline: ComputePipeline,
+
+    transmittance: VulkanImage,
+    multiscattering: VulkanImage,
+    sky: VulkanImage,
+
+    settings: AtmosphereSettings,
+    /// Towards the sun, What the sky was rendered with last
+    sun_direction: Option<Vec3>,
+}
+
+impl Atmosphere {
+    /// Computes the LUTs, The sky stays empty until the first `update`
+    pub fn new(instance: &VulkanInstance, settings: &AtmosphereSettings) -> Self {
+        let create_pipeline = |name: &str| {
+            ComputePipeline::new(instance, &Path::new(SHADER_DIR).join(name))
+                .expect("Failed to create Atmosphere Pipeline")
+        };
+        let atmosphere = Self {
+            transmittance_pipeline: create_pipeline("atmosphere_transmittance.comp.spv"),
+            multiscattering_pipeline: create_pipeline("atmosphere_multiscattering.comp.spv"),
+            sky_pipeline: create_pipeline("atmosphere_sky.comp.spv"),
+            transmittance: VulkanImage::new_storage(
+                instance,
+                HDR_FORMAT,
+                TRANSMITTANCE_SIZE,
+                1,
+                false,
+                Some("Atmosphere Transmittance"),
+            ),
+            multiscattering: VulkanImage::new_storage(
+                instance,
+                HDR_FORMAT,
+                MULTISCATTERING_SIZE,
+                1,
+                false,
+                Some("Atmosphere Multiscattering"),
+            ),
+            sky: VulkanImage::new_storage(
+                instance,
+                HDR_FORMAT,
+                vk::Extent2D {
+                    width: SKY_SIZE,
+                    height: SKY_SIZE,
+                },
+                1,
+                true,
+                Some("Atmosphere Sky"),
+            ),
+            settings: settings.clone(),
+            sun_direction: None,
+        };
+        ibl::submit(instance, |command_buffer, allocator, _| {
+            atmosphere.record_luts(instance, command_buffer, allocator)
+        });
+        atmosphere
+    }
+
+    /// The rendered sky as HDR cubemap
+    pub fn sky(&self) -> &VulkanImage {
+        &self.sky
+    }
+
+    /// Moves the sun along the day and tints the sun Directional Light by the Atmosphere it shines through.
+    /// Renders the sky again when the sun moved far enough, Returns true then and the Image Based Lighting has to be generated again.
+    /// `delta_time` is in milliseconds
+    pub fn update(
+        &mut self,
+        instance: &VulkanInstance,
+        lights: &mut LightRenderer,
+        delta_time: f32,
+    ) -> bool {
+        let sun_direction = match lights.sun_mut() {
+            Some(sun) => {
+                let angle = self.settings.sun_angle(delta_time);
+                sun.direction = rotate(Vec3::from_array(sun.direction), angle).into();
+                let sun_direction = (Vec3::ZERO - Vec3::from_array(sun.direction)).normalize();
+                sun.color = sun_transmittance(sun_direction);
+                sun.intensity = self.settings.sun_illuminance;
+                sun_direction
+            }
+            None => DEFAULT_SUN,
+        };
+        if self
+            .sun_direction
+            .is_some_and(|last| last.dot(sun_direction) > RENDER_SUN_COS)
+        {
+            return false;
+        }
+        ibl::submit(instance, |command_buffer, allocator, views| {
+            self.record_sky(instance, command_buffer, allocator, views, sun_direction)
+        });
+        self.sun_direction = Some(sun_direction);
+        true
+    }
+
+    fn record_luts(
+        &self,
+        instance: &VulkanInstance,
+        command_buffer: vk::CommandBuffer,
+        allocator: &DescriptorAllocator,
+    ) {
+        let device = &instance.device;
+        let storage = |writer: DescriptorWriter, binding: u32, image: &VulkanImage| {
+            writer.image_of_type(
+                binding,
+                image.image_view,
+                vk::Sampler::null(),
+                vk::ImageLayout::GENERAL,
+                vk::DescriptorType::STORAGE_IMAGE,
+            )
+        };
+
+        let pipeline = &self.transmittance_pipeline;
+        let set = allocator
+            .allocate(device, pipeline.descriptor_set_layouts[0])
+            .expect("Failed to allocate Descriptor Set");
+        storage(DescriptorWriter::new(), 0, &self.transmittance).update(device, set);
+        dispatch_lut(
+            device,
+            command_buffer,
+            pipeline,
+            set,
+            &self.transmittance,
+            TRANSMITTANCE_SIZE,
+            &[],
+        );
+
+        // Reads the transmittance, So it has to come after it
+        let pipeline = &self.multiscattering_pipeline;
+        let set = allocator
+            .allocate(device, pipeline.descriptor_set_layouts[0])
+            .expect("Failed to allocate Descriptor Set");
+        let writer = DescriptorWriter::new().image(
+            0,
+            self.transmittance.image_view,
+            self.transmittance.sampler,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        );
+        storage(writer, 1, &self.multiscattering).update(device, set);
+        dispatch_lut(
+            device,
+            command_buffer,
+            pipeline,
+            set,
+            &self.multiscattering,
+            MULTISCATTERING_SIZE,
+            any_as_u8_slice(&self.settings.ground_albedo),
+        );
+    }
+
+    fn record_sky(
+        &self,
+        instance: &VulkanInstance,
+        command_buffer: vk::CommandBuffer,
+        allocator: &DescriptorAllocator,
+        views: &mut Vec<vk::ImageView>,
+        sun_direction: Vec3,
+    ) {
+        let device = &instance.device;
+        let pipeline = &self.sky_pipeline;
+        let image = self.sky.image;
+        ibl::barrier(
+            device,
+            command_buffer,
+            &[ibl::transition(
+                image,
+                0..1,
+                6,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::GENERAL,
+            )],
+        );
+        let view = self.sky.create_mip_view(device, HDR_FORMAT, 0, 6);
+        views.push(view);
+        let set = allocator
+            .allocate(device, pipeline.descriptor_set_layouts[0])
+            .expect("Failed to allocate Descriptor Set");
+        DescriptorWriter::new()
+            .image(
+                0,
+                self.transmittance.image_view,
+                self.transmittance.sampler,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            )
+            .image(
+                1,
+                self.multiscattering.image_view,
+                self.multiscattering.sampler,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            )
+            .image_of_type(
+                2,
+                view,
+                vk::Sampler::null(),
+                vk::ImageLayout::GENERAL,
+                vk::DescriptorType::STORAGE_IMAGE,
+            )
+            .update(device, set);
+        let sky = SkyData {
+            sun_direction: sun_direction.into(),
+            sun_illuminance: self.settings.sun_illuminance,
+            ground_albedo: self.settings.ground_albedo,
+            view_height: VIEW_HEIGHT,
+        };
+        ibl::dispatch_faces(
+            device,
+            command_buffer,
+            pipeline,
+            set,
+            SKY_SIZE,
+            any_as_u8_slice(&sky),
+        );
+        ibl::barrier(
+            device,
+            command_buffer,
+            &[ibl::transition(
+                image,
+                0..1,
+                6,
+                vk::ImageLayout::GENERAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            )],
+        );
+    }
+
+    /// Rebuilds the Pipelines using one of the changed SPIR-V files, The LUTs stay as they are and the sky is rendered again with the next `update`
+    pub fn reload_shaders(&mut self, instance: &VulkanInstance, changed: &[PathBuf]) {
+        let mut reloaded = false;
+        for (name, pipeline) in [
+            ("Atmosphere Transmittance", &mut self.transmittance_pipeline),
+            (
+                "Atmosphere Multiscattering",
+                &mut self.multiscattering_pipeline,
+            ),
+            ("Atmosphere Sky", &mut self.sky_pipeline),
+        ] {
+            if !changed.iter().any(|file| pipeline.uses_shader(file)) {
+                continue;
+            }
+            match pipeline.reload(instance) {
+                Ok(()) => {
+                    log::info!("Reloaded {name} Pipeline");
+                    reloaded = true;
+                }
+                Err(err) => {
+                    log::error!("Failed to reload {name} Pipeline, Keeping the old one: {err}")
+                }
+            }
+        }
+        if reloaded {
+            self.sun_direction = None;
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        self.transmittance_pipeline.destroy(device);
+        self.multiscattering_pipeline.destroy(device);
+        self.sky_pipeline.destroy(device);
+        self.transmittance.destroy(device);
+        self.multiscattering.destroy(device);
+        self.sky.destroy(device);
+    }
+}
+
+/// Writes a whole LUT and makes it readable for the passes after
+fn dispatch_lut(
+    device: &ash::Device,
+    command_buffer: vk::CommandBuffer,
+    pipeline: &ComputePipeline,
+    set: vk::DescriptorSet,
+    image: &VulkanImage,
+    size: vk::Extent2D,
+    push_constants: &[u8],
+) {
+    ibl::barrier(
+        device,
+        command_buffer,
+        &[ibl::transition(
+            image.image,
+            0..1,
+            1,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::GENERAL,
+        )],
+    );
+    unsafe {
+        device.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PipelineBindPoint::COMPUTE,
+            pipeline.pipeline_layout,
+            0,
+            &[set],
+            &[],
+        );
+        if !push_constants.is_empty() {
+            device.cmd_push_constants(
+                command_buffer,
+                pipeline.pipeline_layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                push_constants,
+            );
+        }
+    }
+    pipeline.dispatch_2d(
+        device,
+        command_buffer,
+        size,
+        (IBL_GROUP_SIZE, IBL_GROUP_SIZE),
+    );
+    ibl::barrier(
+        device,
+        command_buffer,
+        &[ibl::transition(
+            image.image,
+            0..1,
+            1,
+            vk::ImageLayout::GENERAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        )],
+    );
+}
+
+/// Rodrigues' rotation around `SUN_AXIS`
+fn rotate(direction: Vec3, angle: f32) -> Vec3 {
+    let (sin, cos) = angle.sin_cos();
+    direction * cos
+        + SUN_AXIS.cross(direction) * sin
+        + SUN_AXIS * SUN_AXIS.dot(direction) * (1.0 - cos)
+}
+
+/// Sunlight arriving at the viewer, The same integral as atmosphere_transmittance.comp. Black below the horizon
+fn sun_transmittance(sun_direction: Vec3) -> [f32; 3] {
+    let origin = Vec3::new(0.0, BOTTOM_RADIUS + VIEW_HEIGHT, 0.0);
+    let b = origin.dot(sun_direction);
+    let c = origin.dot(origin);
+    if b < 0.0 && b * b - c + BOTTOM_RADIUS * BOTTOM_RADIUS >= 0.0 {
+        return [0.0; 3];
+    }
+    let distance = -b + (b * b - c + TOP_RADIUS * TOP_RADIUS).sqrt();
+    let step_size = distance / SUN_STEP_COUNT as f32;
+
+    let mut optical_depth = [0.0; 3];
+    for i in 0..SUN_STEP_COUNT {
+        let position = origin + sun_direction * ((i as f32 + 0.5) * step_size);
+        let height = position.length() - BOTTOM_RADIUS;
+        let rayleigh = (-height / RAYLEIGH_SCALE_HEIGHT).exp();
+        let mie = (-height / MIE_SCALE_HEIGHT).exp();
+        let ozone = (1.0 - (height - OZONE_CENTER).abs() / OZONE_WIDTH).max(0.0);
+        for (channel, depth) in optical_depth.iter_mut().enumerate() {
+            *depth += (RAYLEIGH_SCATTERING[channel] * rayleigh
+                + MIE_EXTINCTION * mie
+                + OZONE_ABSORPTION[channel] * ozone)
+                * step_size;
+        }
+    }
+    optical_depth.map(|depth: f32| (-depth).exp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sun_angle_from_milliseconds() {
+        let settings = AtmosphereSettings {
+            day_length: 60.0,
+            ..Default::default()
+        };
+        // A quarter of a one minute day
+        let angle = settings.sun_angle(15_000.0);
+        assert!((angle - std::f32::consts::FRAC_PI_2).abs() < 1e-5);
+        // About 0.1 degrees in a 16ms Frame
+        assert!((settings.sun_angle(16.0).to_degrees() - 0.096).abs() < 1e-4);
+
+        let direction = Vec3::new(0.0, 1.0, 0.0);
+        let rotated = rotate(direction, angle);
+        assert!(rotated.dot(direction).abs() < 1e-5);
+        assert!((rotated.length() - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn sun_stays_without_day_length() {
+        let settings = AtmosphereSettings::default();
+        assert_eq!(settings.sun_angle(1000.0), 0.0);
+    }
+}
@@ -0,0 +1,593 @@
+use std::path::{Path, PathBuf};
+
+use ash::vk;
+use vent_rendering::{
+    any_as_u8_slice, begin_single_time_command,
+    descriptor::{DescriptorAllocator, DescriptorWriter},
+    end_single_time_command,
+    image::VulkanImage,
+    instance::VulkanInstance,
+    pipeline::{ComputePipeline, HDR_FORMAT},
+};
+
+pub(super) const SHADER_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/assets/shaders/app/3D");
+
+/// Must match `IBL_GROUP_SIZE` in ibl_sampling.glsl
+pub(super) const IBL_GROUP_SIZE: u32 = 8;
+
+/// Also what the Skybox shows for equirectangular sources
+const ENVIRONMENT_SIZE: u32 = 1024;
+const IRRADIANCE_SIZE: u32 = 32;
+const PREFILTERED_SIZE: u32 = 256;
+/// Roughness goes from 0 in the first mip to 1 in the last one, The smallest mip is 8x8
+const PREFILTERED_MIPS: u32 = 6;
+const BRDF_LUT_SIZE: u32 = 256;
+
+/// Where the maps are in the Frame set of the Fragment Shader, Must match ibl.glsl
+pub const IRRADIANCE_BINDING: u32 = 6;
+pub const PREFILTERED_BINDING: u32 = 7;
+pub const BRDF_LUT_BINDING: u32 = 8;
+
+/// What the Image Based Lighting is computed from
+pub enum EnvironmentSource<'a> {
+    Cubemap(&'a VulkanImage),
+    /// An equirectangular (latitude-longitude) image, Usually HDR
+    Equirect(&'a VulkanImage),
+}
+
+/// Push Constants of prefilter.comp
+#[repr(C)]
+struct PrefilterData {
+    roughness: f32,
+}
+
+/// Image Based Lighting, The environment lights the scene as ambient light.
+///
+/// Compute passes turn the environment into a diffuse irradiance cubemap and a GGX prefiltered cubemap with one roughness per mip.
+/// Together with the BRDF LUT the Fragment Shader gets the split sum approximation of the specular part.
+/// Everything is precomputed in one go, Only a new environment needs `generate` again
+pub struct ImageBasedLighting {
+    environment_pipeline: ComputePipeline,
+    equirect_pipeline: ComputePipeline,
+    irradiance_pipeline: ComputePipeline,
+    prefilter_pipeline: ComputePipeline,
+    brdf_pipeline: ComputePipeline,
+
+    /// The source as HDR cubemap with mips, Which the other maps are sampled from
+    environment: VulkanImage,
+    irradiance: VulkanImage,
+    prefiltered: VulkanImage,
+    brdf_lut: VulkanImage,
+}
+
+impl ImageBasedLighting {
+    pub fn new(instance: &VulkanInstance, source: EnvironmentSource) -> Self {
+        let create_pipeline = |name: &str| {
+            ComputePipeline::new(instance, &Path::new(SHADER_DIR).join(name))
+                .expect("Failed to create Image Based Lighting Pipeline")
+        };
+        let cube_size = |size: u32| vk::Extent2D {
+            width: size,
+            height: size,
+        };
+        let ibl = Self {
+            environment_pipeline: create_pipeline("environment.comp.spv"),
+            equirect_pipeline: create_pipeline("environment.equirect.comp.spv"),
+            irradiance_pipeline: create_pipeline("irradiance.comp.spv"),
+            prefilter_pipeline: create_pipeline("prefilter.comp.spv"),
+            brdf_pipeline: create_pipeline("brdf_lut.comp.spv"),
+            environment: VulkanImage::new_storage(
+                instance,
+                HDR_FORMAT,
+                cube_size(ENVIRONMENT_SIZE),
+                ENVIRONMENT_SIZE.ilog2() + 1,
+                true,
+                Some("IBL Environment"),
+            ),
+            irradiance: VulkanImage::new_storage(
+                instance,
+                HDR_FORMAT,
+                cube_size(IRRADIANCE_SIZE),
+                1,
+                true,
+                Some("IBL Irradiance"),
+            ),
+            prefiltered: VulkanImage::new_storage(
+                instance,
+                HDR_FORMAT,
+                cube_size(PREFILTERED_SIZE),
+                PREFILTERED_MIPS,
+                true,
+                Some("IBL Prefiltered"),
+            ),
+            brdf_lut: VulkanImage::new_storage(
+                instance,
+                HDR_FORMAT,
+                cube_size(BRDF_LUT_SIZE),
+                1,
+                false,
+                Some("BRDF LUT"),
+            ),
+        };
+        // Does not depend on the environment, So it is only needed once
+        submit(instance, |command_buffer, allocator, _| {
+            ibl.record_brdf_lut(instance, command_buffer, allocator)
+        });
+        ibl.generate(instance, source);
+        ibl
+    }
+
+    /// Computes all maps from a new environment, Waits for the GPU to be idle
+    pub fn generate(&self, instance: &VulkanInstance, source: EnvironmentSource) {
+        unsafe { instance.device.device_wait_idle().unwrap() };
+        submit(instance, |command_buffer, allocator, views| {
+            self.record_environment(instance, command_buffer, allocator, views, &source);
+            self.record_irradiance(instance, command_buffer, allocator, views);
+            self.record_prefiltered(instance, command_buffer, allocator, views);
+        });
+    }
+
+    /// The source as HDR cubemap with mips
+    pub fn environment(&self) -> &VulkanImage {
+        &self.environment
+    }
+
+    /// Adds the maps to the Frame set of the Fragment Shader
+    pub fn write_frame_set(&self, writer: DescriptorWriter) -> DescriptorWriter {
+        let layout = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
+        writer
+            .image(
+                IRRADIANCE_BINDING,
+                self.irradiance.image_view,
+                self.irradiance.sampler,
+                layout,
+            )
+            .image(
+                PREFILTERED_BINDING,
+                self.prefiltered.image_view,
+                self.prefiltered.sampler,
+                layout,
+            )
+            .image(
+                BRDF_LUT_BINDING,
+                self.brdf_lut.image_view,
+                self.brdf_lut.sampler,
+                layout,
+            )
+    }
+
+    fn record_environment(
+        &self,
+        instance: &VulkanInstance,
+        command_buffer: vk::CommandBuffer,
+        allocator: &DescriptorAllocator,
+        views: &mut Vec<vk::ImageView>,
+        source: &EnvironmentSource,
+    ) {
+        let device = &instance.device;
+        let (pipeline, source) = match source {
+            EnvironmentSource::Cubemap(image) => (&self.environment_pipeline, image),
+            EnvironmentSource::Equirect(image) => (&self.equirect_pipeline, image),
+        };
+        let mip_levels = ENVIRONMENT_SIZE.ilog2() + 1;
+        let image = self.environment.image;
+        barrier(
+            device,
+            command_buffer,
+            &[transition(
+                image,
+                0..mip_levels,
+                6,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::GENERAL,
+            )],
+        );
+        let view = self.environment.create_mip_view(device, HDR_FORMAT, 0, 6);
+        views.push(view);
+        let set = allocator
+            .allocate(device, pipeline.descriptor_set_layouts[0])
+            .expect("Failed to allocate Descriptor Set");
+        DescriptorWriter::new()
+            .image(
+                0,
+                source.image_view,
+                source.sampler,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            )
+            .image_of_type(
+                1,
+                view,
+                vk::Sampler::null(),
+                vk::ImageLayout::GENERAL,
+                vk::DescriptorType::STORAGE_IMAGE,
+            )
+            .update(device, set);
+        dispatch_faces(device, command_buffer, pipeline, set, ENVIRONMENT_SIZE, &[]);
+
+        // Every mip is blitted from the one before, The Prefilter Pass reads them for wide lobes
+        barrier(
+            device,
+            command_buffer,
+            &[
+                transition(
+                    image,
+                    0..1,
+                    6,
+                    vk::ImageLayout::GENERAL,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                ),
+                transition(
+                    image,
+                    1..mip_levels,
+                    6,
+                    vk::ImageLayout::GENERAL,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                ),
+            ],
+        );
+        let layers = |mip_level: u32| {
+            vk::ImageSubresourceLayers::default()
+                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                .mip_level(mip_level)
+                .layer_count(6)
+        };
+        let corner = |mip_level: u32| {
+            let size = (ENVIRONMENT_SIZE >> mip_level) as i32;
+            vk::Offset3D {
+                x: size,
+                y: size,
+                z: 1,
+            }
+        };
+        for mip_level in 1..mip_levels {
+            let blit = [vk::ImageBlit2::default()
+                .src_subresource(layers(mip_level - 1))
+                .src_offsets([vk::Offset3D::default(), corner(mip_level - 1)])
+                .dst_subresource(layers(mip_level))
+                .dst_offsets([vk::Offset3D::default(), corner(mip_level)])];
+            let blit_info = vk::BlitImageInfo2::default()
+                .src_image(image)
+                .src_image_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                .dst_image(image)
+                .dst_image_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                .regions(&blit)
+                .filter(vk::Filter::LINEAR);
+            unsafe { device.cmd_blit_image2(command_buffer, &blit_info) };
+            barrier(
+                device,
+                command_buffer,
+                &[transition(
+                    image,
+                    mip_level..mip_level + 1,
+                    6,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                )],
+            );
+        }
+        barrier(
+            device,
+            command_buffer,
+            &[transition(
+                image,
+                0..mip_levels,
+                6,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            )],
+        );
+    }
+
+    fn record_irradiance(
+        &self,
+        instance: &VulkanInstance,
+        command_buffer: vk::CommandBuffer,
+        allocator: &DescriptorAllocator,
+        views: &mut Vec<vk::ImageView>,
+    ) {
+        let device = &instance.device;
+        let pipeline = &self.irradiance_pipeline;
+        let image = self.irradiance.image;
+        barrier(
+            device,
+            command_buffer,
+            &[transition(
+                image,
+                0..1,
+                6,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::GENERAL,
+            )],
+        );
+        let view = self.irradiance.create_mip_view(device, HDR_FORMAT, 0, 6);
+        views.push(view);
+        let set = self.environment_set(device, allocator, pipeline, view);
+        dispatch_faces(device, command_buffer, pipeline, set, IRRADIANCE_SIZE, &[]);
+        barrier(
+            device,
+            command_buffer,
+            &[transition(
+                image,
+                0..1,
+                6,
+                vk::ImageLayout::GENERAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            )],
+        );
+    }
+
+    fn record_prefiltered(
+        &self,
+        instance: &VulkanInstance,
+        command_buffer: vk::CommandBuffer,
+        allocator: &DescriptorAllocator,
+        views: &mut Vec<vk::ImageView>,
+    ) {
+        let device = &instance.device;
+        let pipeline = &self.prefilter_pipeline;
+        let image = self.prefiltered.image;
+        barrier(
+            device,
+            command_buffer,
+            &[transition(
+                image,
+                0..PREFILTERED_MIPS,
+                6,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::GENERAL,
+            )],
+        );
+        for mip_level in 0..PREFILTERED_MIPS {
+            let view = self
+                .prefiltered
+                .create_mip_view(device, HDR_FORMAT, mip_level, 6);
+            views.push(view);
+            let set = self.environment_set(device, allocator, pipeline, view);
+            let prefilter = PrefilterData {
+                roughness: mip_level as f32 / (PREFILTERED_MIPS - 1) as f32,
+            };
+            dispatch_faces(
+                device,
+                command_buffer,
+                pipeline,
+                set,
+                PREFILTERED_SIZE >> mip_level,
+                any_as_u8_slice(&prefilter),
+            );
+        }
+        barrier(
+            device,
+            command_buffer,
+            &[transition(
+                image,
+                0..PREFILTERED_MIPS,
+                6,
+                vk::ImageLayout::GENERAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            )],
+        );
+    }
+
+    fn record_brdf_lut(
+        &self,
+        instance: &VulkanInstance,
+        command_buffer: vk::CommandBuffer,
+        allocator: &DescriptorAllocator,
+    ) {
+        let device = &instance.device;
+        let pipeline = &self.brdf_pipeline;
+        let image = self.brdf_lut.image;
+        barrier(
+            device,
+            command_buffer,
+            &[transition(
+                image,
+                0..1,
+                1,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::GENERAL,
+            )],
+        );
+        let set = allocator
+            .allocate(device, pipeline.descriptor_set_layouts[0])
+            .expect("Failed to allocate Descriptor Set");
+        DescriptorWriter::new()
+            .image_of_type(
+                0,
+                self.brdf_lut.image_view,
+                vk::Sampler::null(),
+                vk::ImageLayout::GENERAL,
+                vk::DescriptorType::STORAGE_IMAGE,
+            )
+            .update(device, set);
+        unsafe {
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                pipeline.pipeline_layout,
+                0,
+                &[set],
+                &[],
+            );
+        }
+        pipeline.dispatch_2d(
+            device,
+            command_buffer,
+            vk::Extent2D {
+                width: BRDF_LUT_SIZE,
+                height: BRDF_LUT_SIZE,
+            },
+            (IBL_GROUP_SIZE, IBL_GROUP_SIZE),
+        );
+        barrier(
+            device,
+            command_buffer,
+            &[transition(
+                image,
+                0..1,
+                1,
+                vk::ImageLayout::GENERAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            )],
+        );
+    }
+
+    /// Set of the passes reading the environment and writing into `target`
+    fn environment_set(
+        &self,
+        device: &ash::Device,
+        allocator: &DescriptorAllocator,
+        pipeline: &ComputePipeline,
+        target: vk::ImageView,
+    ) -> vk::DescriptorSet {
+        let set = allocator
+            .allocate(device, pipeline.descriptor_set_layouts[0])
+            .expect("Failed to allocate Descriptor Set");
+        DescriptorWriter::new()
+            .image(
+                0,
+                self.environment.image_view,
+                self.environment.sampler,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            )
+            .image_of_type(
+                1,
+                target,
+                vk::Sampler::null(),
+                vk::ImageLayout::GENERAL,
+                vk::DescriptorType::STORAGE_IMAGE,
+            )
+            .update(device, set);
+        set
+    }
+
+    /// Rebuilds the Pipelines using one of the changed SPIR-V files, The maps stay as they are until the next `generate`
+    pub fn reload_shaders(&mut self, instance: &VulkanInstance, changed: &[PathBuf]) {
+        for (name, pipeline) in [
+            ("IBL Environment", &mut self.environment_pipeline),
+            ("IBL Equirect", &mut self.equirect_pipeline),
+            ("IBL Irradiance", &mut self.irradiance_pipeline),
+            ("IBL Prefilter", &mut self.prefilter_pipeline),
+            ("BRDF LUT", &mut self.brdf_pipeline),
+        ] {
+            if !changed.iter().any(|file| pipeline.uses_shader(file)) {
+                continue;
+            }
+            match pipeline.reload(instance) {
+                Ok(()) => log::info!("Reloaded {name} Pipeline"),
+                Err(err) => {
+                    log::error!("Failed to reload {name} Pipeline, Keeping the old one: {err}")
+                }
+            }
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        self.environment_pipeline.destroy(device);
+        self.equirect_pipeline.destroy(device);
+        self.irradiance_pipeline.destroy(device);
+        self.prefilter_pipeline.destroy(device);
+        self.brdf_pipeline.destroy(device);
+        self.environment.destroy(device);
+        self.irradiance.destroy(device);
+        self.prefiltered.destroy(device);
+        self.brdf_lut.destroy(device);
+    }
+}
+
+/// Records everything into a single time Command Buffer, Descriptor Sets and views only live until it finished
+pub(super) fn submit(
+    instance: &VulkanInstance,
+    record: impl FnOnce(vk::CommandBuffer, &DescriptorAllocator, &mut Vec<vk::ImageView>),
+) {
+    let device = &instance.device;
+    let mut allocator = DescriptorAllocator::new(&DescriptorAllocator::default_ratios());
+    let mut views = Vec::new();
+    let command_buffer = begin_single_time_command(device, instance.global_command_pool);
+    record(command_buffer, &allocator, &mut views);
+    end_single_time_command(
+        device,
+        instance.global_command_pool,
+        instance.graphics_queue,
+        command_buffer,
+    );
+    for view in views {
+        unsafe { device.destroy_image_view(view, None) };
+    }
+    allocator.destroy(device);
+}
+
+/// Dispatches one invocation per texel of all 6 faces
+pub(super) fn dispatch_faces(
+    device: &ash::Device,
+    command_buffer: vk::CommandBuffer,
+    pipeline: &ComputePipeline,
+    set: vk::DescriptorSet,
+    size: u32,
+    push_constants: &[u8],
+) {
+    let groups = size.div_ceil(IBL_GROUP_SIZE);
+    unsafe {
+        device.cmd_bind_pipeline(
+            command_buffer,
+            vk::PipelineBindPoint::COMPUTE,
+            pipeline.pipeline,
+        );
+        device.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PipelineBindPoint::COMPUTE,
+            pipeline.pipeline_layout,
+            0,
+            &[set],
+            &[],
+        );
+        if !push_constants.is_empty() {
+            device.cmd_push_constants(
+                command_buffer,
+                pipeline.pipeline_layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                push_constants,
+            );
+        }
+        device.cmd_dispatch(command_buffer, groups, groups, 6);
+    }
+}
+
+/// Layout transition which waits for everything before, Good enough for work done once
+pub(super) fn transition(
+    image: vk::Image,
+    mip_levels: std::ops::Range<u32>,
+    layer_count: u32,
+    old_layout: vk::ImageLayout,
+    new_layout: vk::ImageLayout,
+) -> vk::ImageMemoryBarrier2<'static> {
+    vk::ImageMemoryBarrier2::default()
+        .src_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+        .src_access_mask(vk::AccessFlags2::MEMORY_WRITE)
+        .dst_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+        .dst_access_mask(vk::AccessFlags2::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE)
+        .old_layout(old_layout)
+        .new_layout(new_layout)
+        .image(image)
+        .subresource_range(
+            vk::ImageSubresourceRange::default()
+                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                .base_mip_level(mip_levels.start)
+                .level_count(mip_levels.len() as u32)
+                .layer_count(layer_count),
+        )
+}
+
+pub(super) fn barrier(
+    device: &ash::Device,
+    command_buffer: vk::CommandBuffer,
+    barriers: &[vk::ImageMemoryBarrier2],
+) {
+    unsafe {
+        device.cmd_pipeline_barrier2(
+            command_buffer,
+            &vk::DependencyInfo::default().image_memory_barriers(barriers),
+        )
+    };
+}
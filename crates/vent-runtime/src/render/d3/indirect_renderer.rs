@@ -0,0 +1,541 @@
+use std::{
+    mem::{size_of, size_of_val},
+    path::PathBuf,
+};
+
+use ash::vk;
+use vent_assets::PooledMesh;
+use vent_math::scalar::mat4::Mat4;
+use vent_rendering::{
+    any_as_u8_slice,
+    buffer::VulkanBuffer,
+    descriptor::DescriptorWriter,
+    geometry::GeometryPool,
+    instance::{VulkanInstance, MAX_FRAMES_IN_FLIGHT},
+    pipeline::ComputePipeline,
+    render_graph::{BufferAccess, BufferHandle, PassContext, PassHandle, PassType, RenderGraph},
+    vertex::Vertex3D,
+    RenderQueue,
+};
+
+use crate::render::{
+    camera::Camera3D,
+    model_renderer::{view_depth, ModelRenderer3D},
+};
+
+/// Enough for Sponza and a few more Models
+const POOL_VERTEX_CAPACITY: u32 = 2 * 1024 * 1024;
+const POOL_INDEX_CAPACITY: u32 = 8 * 1024 * 1024;
+
+/// Must match `local_size_x` in cull.comp
+const CULL_GROUP_SIZE: u32 = 64;
+
+/// Objects every Object Buffer can hold at first, They grow when more are drawn
+const INITIAL_OBJECT_CAPACITY: u32 = 1024;
+
+/// Where the Objects are in the Frame set of the Vertex Shader
+pub const OBJECTS_BINDING: u32 = 0;
+
+/// Everything the GPU needs to cull and draw a single Mesh, Must match `Object` in object.glsl (std430)
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct GpuObject {
+    transform: Mat4,
+    bounds_min: [f32; 4],
+    bounds_max: [f32; 4],
+    material_id: u32,
+    first_index: u32,
+    index_count: u32,
+    vertex_offset: i32,
+    batch: u32,
+    first_command: u32,
+    /// 1 for sorted Transparent Objects, They keep their place in the command buffer
+    ordered: u32,
+    _padding: u32,
+}
+
+/// Push Constants of cull.comp
+#[repr(C)]
+struct CullingData {
+    frustum_planes: [[f32; 4]; 6],
+    object_count: u32,
+}
+
+/// All Meshes drawn with the same Pipeline, They get their own range in the command buffer and their own counter
+struct DrawBatch {
+    pipeline: vk::Pipeline,
+    queue: RenderQueue,
+    first_command: u32,
+    max_draws: u32,
+}
+
+/// The Objects of a Frame in flight, Written by the CPU every Frame
+struct ObjectBuffer {
+    buffer: VulkanBuffer,
+    capacity: u32,
+}
+
+impl ObjectBuffer {
+    fn new(instance: &VulkanInstance, capacity: u32) -> Self {
+        Self {
+            buffer: VulkanBuffer::new(
+                instance,
+                capacity as vk::DeviceSize * size_of::<GpuObject>() as vk::DeviceSize,
+                vk::BufferUsageFlags::STORAGE_BUFFER,
+                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+                Some("Objects"),
+            ),
+            capacity,
+        }
+    }
+}
+
+/// Draws all Models with a few `cmd_draw_indexed_indirect_count`, One per Pipeline.
+///
+/// Every Mesh lives in one shared `GeometryPool`. Each Frame the transforms and bounds of all Meshes are uploaded,
+/// a compute pass culls them against the camera frustum and writes the draw commands of the visible ones.
+/// Transparent Meshes are sorted back to front on the CPU, Runs of the same Pipeline share a batch.
+/// Needs Bindless Descriptors, As the Material can not be switched between the draws
+pub struct IndirectRenderer {
+    pub geometry: GeometryPool,
+    cull_pipeline: ComputePipeline,
+    // What the draw buffers of the Graph were sized for
+    max_objects: u32,
+    max_batches: u32,
+    object_buffers: Vec<ObjectBuffer>,
+
+    reset_pass: Option<PassHandle>,
+    cull_pass: Option<PassHandle>,
+    draw_commands: Option<BufferHandle>,
+    draw_counts: Option<BufferHandle>,
+
+    // Written by the cull pass, Used by the draw of the same Frame
+    batches: Vec<DrawBatch>,
+    objects: Option<(vk::Buffer, vk::DeviceSize)>,
+}
+
+impl IndirectRenderer {
+    pub fn new(instance: &VulkanInstance) -> Self {
+        let cull_shader = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/assets/shaders/app/3D/cull.comp.spv"
+        );
+        let cull_pipeline = ComputePipeline::new(instance, cull_shader.as_ref())
+            .expect("Failed to create Culling Pipeline");
+        Self {
+            geometry: GeometryPool::new(
+                instance,
+                size_of::<Vertex3D>() as vk::DeviceSize,
+                POOL_VERTEX_CAPACITY,
+                POOL_INDEX_CAPACITY,
+            ),
+            cull_pipeline,
+            max_objects: 0,
+            max_batches: 0,
+            object_buffers: (0..MAX_FRAMES_IN_FLIGHT)
+                .map(|_| ObjectBuffer::new(instance, INITIAL_OBJECT_CAPACITY))
+                .collect(),
+            reset_pass: None,
+            cull_pass: None,
+            draw_commands: None,
+            draw_counts: None,
+            batches: Vec::new(),
+            objects: None,
+        }
+    }
+
+    /// Adds the passes which fill the draw buffers, Returns them so the pass drawing with `draw` can read them as `BufferAccess::Indirect`.
+    /// The buffers are sized for the Models added so far, With some room for more. See `needs_rebuild`
+    pub fn setup_passes(
+        &mut self,
+        graph: &mut RenderGraph,
+        models: &ModelRenderer3D,
+    ) -> (BufferHandle, BufferHandle) {
+        let (object_count, batch_count) = Self::count_draws(models);
+        // So adding a few Models does not rebuild the Graph every time
+        self.max_objects = object_count.max(1).next_power_of_two();
+        self.max_batches = batch_count.max(1).next_power_of_two();
+
+        let draw_commands = graph.create_buffer(
+            "Draw Commands",
+            (self.max_objects as usize * size_of::<vk::DrawIndexedIndirectCommand>())
+                as vk::DeviceSize,
+        );
+        let draw_counts = graph.create_buffer(
+            "Draw Counts",
+            (self.max_batches as usize * size_of::<u32>()) as vk::DeviceSize,
+        );
+        self.reset_pass = Some(
+            graph
+                .add_pass("Reset Draw Counts", PassType::Transfer)
+                .buffer(draw_counts, BufferAccess::TransferDst)
+                .build(),
+        );
+        self.cull_pass = Some(
+            graph
+                .add_pass("Culling", PassType::Compute)
+                .buffer(draw_commands, BufferAccess::StorageWrite)
+                .buffer(draw_counts, BufferAccess::StorageWrite)
+                .build(),
+        );
+        self.draw_commands = Some(draw_commands);
+        self.draw_counts = Some(draw_counts);
+        (draw_commands, draw_counts)
+    }
+
+    /// Whether more Models were added than the draw buffers of the Graph can hold, The Graph has to be built again then
+    pub fn needs_rebuild(&self, models: &ModelRenderer3D) -> bool {
+        let (object_count, batch_count) = Self::count_draws(models);
+        object_count > self.max_objects || batch_count > self.max_batches
+    }
+
+    /// How many Objects and batches the Models need at most
+    fn count_draws(models: &ModelRenderer3D) -> (u32, u32) {
+        let mut object_count = 0;
+        let mut batch_count = 0;
+        for (_, entity) in models.iter() {
+            for pipeline in models.model(entity.model).pipelines.iter() {
+                let meshes = pipeline
+                    .materials
+                    .iter()
+                    .map(|material| material.pooled_meshes.len() as u32)
+                    .sum::<u32>();
+                // After sorting every Transparent Mesh can end up in its own batch
+                batch_count += if pipeline.info.queue == RenderQueue::Transparent {
+                    meshes
+                } else {
+                    1
+                };
+                object_count += meshes;
+            }
+        }
+        (object_count, batch_count)
+    }
+
+    /// Records the pass if it is one of ours, Returns false otherwise
+    pub fn execute_pass(
+        &mut self,
+        instance: &VulkanInstance,
+        pass: PassHandle,
+        context: &PassContext,
+        models: &ModelRenderer3D,
+        camera: &mut Camera3D,
+    ) -> bool {
+        if Some(pass) == self.reset_pass {
+            let counts = context.buffer(self.draw_counts.unwrap());
+            unsafe {
+                instance.device.cmd_fill_buffer(
+                    context.command_buffer,
+                    counts,
+                    0,
+                    vk::WHOLE_SIZE,
+                    0,
+                )
+            };
+            true
+        } else if Some(pass) == self.cull_pass {
+            self.cull(instance, context, models, camera);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn cull(
+        &mut self,
+        instance: &VulkanInstance,
+        context: &PassContext,
+        models: &ModelRenderer3D,
+        camera: &mut Camera3D,
+    ) {
+        let device = &instance.device;
+        let command_buffer = context.command_buffer;
+        let objects = self.collect_objects(models, camera);
+        if objects.len() as u32 > self.max_objects || self.batches.len() as u32 > self.max_batches {
+            // Only happens when Models get added in between `needs_rebuild` and this Frame
+            log::warn!(
+                "Draw buffers are too small for {} Objects, Skipping them until the Graph is rebuilt",
+                objects.len()
+            );
+            self.batches.clear();
+        }
+        let object_count = if self.batches.is_empty() {
+            0
+        } else {
+            objects.len() as u32
+        };
+
+        // The GPU is done with the buffer of this Frame, We waited on its fence when the Frame began
+        let object_buffer = &mut self.object_buffers[instance.frame_index()];
+        if object_buffer.capacity < object_count {
+            object_buffer.buffer.destroy(device);
+            *object_buffer = ObjectBuffer::new(instance, object_count.next_power_of_two());
+        }
+        let objects_size =
+            object_buffer.capacity as vk::DeviceSize * size_of::<GpuObject>() as vk::DeviceSize;
+        if object_count > 0 {
+            let size = size_of_val(objects.as_slice()) as vk::DeviceSize;
+            let memory = object_buffer.buffer.map(device, size);
+            unsafe { object_buffer.buffer.upload_data(memory, &objects, size) };
+            object_buffer.buffer.unmap(device);
+        }
+
+        let descriptor_allocator = &context.frame().descriptor_allocator;
+        let cull_set = descriptor_allocator
+            .allocate(device, self.cull_pipeline.descriptor_set_layouts[0])
+            .expect("Failed to allocate Descriptor Set");
+        DescriptorWriter::new()
+            .buffer(
+                0,
+                *object_buffer.buffer,
+                0,
+                objects_size,
+                vk::DescriptorType::STORAGE_BUFFER,
+            )
+            .buffer(
+                1,
+                context.buffer(self.draw_commands.unwrap()),
+                0,
+                vk::WHOLE_SIZE,
+                vk::DescriptorType::STORAGE_BUFFER,
+            )
+            .buffer(
+                2,
+                context.buffer(self.draw_counts.unwrap()),
+                0,
+                vk::WHOLE_SIZE,
+                vk::DescriptorType::STORAGE_BUFFER,
+            )
+            .update(device, cull_set);
+
+        // The Vertex Shader reads the same Objects, Indexed by the first instance of the draw
+        self.objects = Some((*object_buffer.buffer, objects_size));
+
+        // The transforms are in the Objects, So the camera only has projection and view
+        camera.transformation = Mat4::IDENTITY;
+        camera.calc_matrix();
+        let culling = CullingData {
+            frustum_planes: frustum_planes(&camera.ubo.proj_view_trans),
+            object_count,
+        };
+
+        unsafe {
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.cull_pipeline.pipeline_layout,
+                0,
+                &[cull_set],
+                &[],
+            );
+            device.cmd_push_constants(
+                command_buffer,
+                self.cull_pipeline.pipeline_layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                any_as_u8_slice(&culling),
+            );
+        }
+        self.cull_pipeline
+            .dispatch(device, command_buffer, object_count, CULL_GROUP_SIZE);
+    }
+
+    /// Builds one Object for every pooled Mesh, Grouped into batches by Pipeline.
+    /// Transparent ones come last, Sorted back to front
+    fn collect_objects(&mut self, models: &ModelRenderer3D, camera: &Camera3D) -> Vec<GpuObject> {
+        let mut objects = Vec::with_capacity(self.max_objects as usize);
+        let mut transparent = Vec::new();
+        self.batches.clear();
+        for (_, entity) in models.iter() {
+            let model = models.model(entity.model);
+            let transform = entity.calc_trans_matrix();
+            for pipeline in model.pipelines.iter() {
+                if pipeline.info.queue == RenderQueue::Transparent {
+                    for material in pipeline.materials.iter() {
+                        let material_id = model.materials[material.material_index]
+                            .bindless_id
+                            .expect("Material was not added to the Bindless Descriptors");
+                        for mesh in material.pooled_meshes.iter() {
+                            let depth = view_depth(&camera.view, &transform, &mesh.bounds);
+                            let object = gpu_object(transform, material_id, mesh);
+                            transparent.push((depth, pipeline.pipeline, object));
+                        }
+                    }
+                    continue;
+                }
+                let batch = self.batches.len() as u32;
+                let first_command = objects.len() as u32;
+                for material in pipeline.materials.iter() {
+                    let material_id = model.materials[material.material_index]
+                        .bindless_id
+                        .expect("Material was not added to the Bindless Descriptors");
+                    for mesh in material.pooled_meshes.iter() {
+                        objects.push(GpuObject {
+                            batch,
+                            first_command,
+                            ..gpu_object(transform, material_id, mesh)
+                        });
+                    }
+                }
+                self.batches.push(DrawBatch {
+                    pipeline: pipeline.pipeline,
+                    queue: pipeline.info.queue,
+                    first_command,
+                    max_draws: objects.len() as u32 - first_command,
+                });
+            }
+        }
+
+        // Farthest first, The camera looks along -Z
+        transparent.sort_by(|(a, _, _), (b, _, _)| a.total_cmp(b));
+        for (_, pipeline, object) in transparent {
+            if !self.batches.last().is_some_and(|batch| {
+                batch.pipeline == pipeline && batch.queue == RenderQueue::Transparent
+            }) {
+                self.batches.push(DrawBatch {
+                    pipeline,
+                    queue: RenderQueue::Transparent,
+                    first_command: objects.len() as u32,
+                    max_draws: 0,
+                });
+            }
+            let batch = self.batches.len() - 1;
+            objects.push(GpuObject {
+                batch: batch as u32,
+                first_command: self.batches[batch].first_command,
+                ordered: 1,
+                ..object
+            });
+            self.batches[batch].max_draws += 1;
+        }
+        objects
+    }
+
+    /// Adds the Objects written by the cull pass of this Frame to the Frame set of the Vertex Shader
+    pub fn write_objects(&self, writer: DescriptorWriter) -> DescriptorWriter {
+        let (buffer, size) = self.objects.expect("The cull pass did not run");
+        writer.buffer(
+            OBJECTS_BINDING,
+            buffer,
+            0,
+            size,
+            vk::DescriptorType::STORAGE_BUFFER,
+        )
+    }
+
+    /// Draws everything of the `queues` the cull pass left visible, The Bindless Descriptors have to be bound at set 0 and the Frame set with `write_objects` at set 1
+    pub fn draw(
+        &self,
+        instance: &VulkanInstance,
+        context: &PassContext,
+        pipeline_layout: vk::PipelineLayout,
+        camera: &mut Camera3D,
+        queues: &[RenderQueue],
+    ) {
+        let device = &instance.device;
+        let command_buffer = context.command_buffer;
+        let draw_commands = context.buffer(self.draw_commands.unwrap());
+        let draw_counts = context.buffer(self.draw_counts.unwrap());
+
+        camera.transformation = Mat4::IDENTITY;
+        camera.calc_matrix();
+        camera.write(instance, pipeline_layout, command_buffer);
+        self.geometry.bind(device, command_buffer);
+        let stride = size_of::<vk::DrawIndexedIndirectCommand>() as u32;
+        for (i, batch) in self.batches.iter().enumerate() {
+            if batch.max_draws == 0 || !queues.contains(&batch.queue) {
+                continue;
+            }
+            unsafe {
+                device.cmd_bind_pipeline(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    batch.pipeline,
+                );
+                device.cmd_draw_indexed_indirect_count(
+                    command_buffer,
+                    draw_commands,
+                    batch.first_command as vk::DeviceSize * stride as vk::DeviceSize,
+                    draw_counts,
+                    (i * size_of::<u32>()) as vk::DeviceSize,
+                    batch.max_draws,
+                    stride,
+                );
+            }
+        }
+    }
+
+    pub fn reload_shaders(&mut self, instance: &VulkanInstance, changed: &[PathBuf]) {
+        if !changed
+            .iter()
+            .any(|file| self.cull_pipeline.uses_shader(file))
+        {
+            return;
+        }
+        match self.cull_pipeline.reload(instance) {
+            Ok(()) => log::info!("Reloaded Culling Pipeline"),
+            Err(err) => {
+                log::error!("Failed to reload Culling Pipeline, Keeping the old one: {err}")
+            }
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        for object_buffer in self.object_buffers.iter_mut() {
+            object_buffer.buffer.destroy(device);
+        }
+        self.geometry.destroy(device);
+        self.cull_pipeline.destroy(device);
+    }
+}
+
+/// The Object of a pooled Mesh, Batch and command range are filled in by the caller
+fn gpu_object(transform: Mat4, material_id: u32, mesh: &PooledMesh) -> GpuObject {
+    let [min_x, min_y, min_z] = mesh.bounds.min;
+    let [max_x, max_y, max_z] = mesh.bounds.max;
+    GpuObject {
+        transform,
+        bounds_min: [min_x, min_y, min_z, 0.0],
+        bounds_max: [max_x, max_y, max_z, 0.0],
+        material_id,
+        first_index: mesh.range.first_index,
+        index_count: mesh.range.index_count,
+        vertex_offset: mesh.range.vertex_offset,
+        batch: 0,
+        first_command: 0,
+        ordered: 0,
+        _padding: 0,
+    }
+}
+
+/// Extracts the 6 frustum planes from the projection * view matrix, Their normals point inside.
+///
+/// The near plane is the one of an OpenGL depth range (-w..w), Which is a bit behind the real one and never culls too much
+fn frustum_planes(proj_view: &Mat4) -> [[f32; 4]; 6] {
+    let columns: [[f32; 4]; 4] = [
+        proj_view.x_axis.into(),
+        proj_view.y_axis.into(),
+        proj_view.z_axis.into(),
+        proj_view.w_axis.into(),
+    ];
+    let row = |i: usize| [columns[0][i], columns[1][i], columns[2][i], columns[3][i]];
+    let combine = |a: [f32; 4], b: [f32; 4], sign: f32| {
+        let plane = [
+            a[0] + sign * b[0],
+            a[1] + sign * b[1],
+            a[2] + sign * b[2],
+            a[3] + sign * b[3],
+        ];
+        let length = (plane[0] * plane[0] + plane[1] * plane[1] + plane[2] * plane[2]).sqrt();
+        plane.map(|v| v / length)
+    };
+    let w = row(3);
+    [
+        combine(w, row(0), 1.0),  // Left
+        combine(w, row(0), -1.0), // Right
+        combine(w, row(1), 1.0),  // Bottom
+        combine(w, row(1), -1.0), // Top
+        combine(w, row(2), 1.0),  // Near
+        combine(w, row(2), -1.0), // Far
+    ]
+}
@@ -0,0 +1,214 @@
+use std::{mem::size_of, path::PathBuf};
+
+use ash::vk;
+use vent_math::scalar::mat4::Mat4;
+use vent_rendering::{
+    descriptor::DescriptorWriter,
+    frame::UploadAllocation,
+    instance::VulkanInstance,
+    pipeline::ComputePipeline,
+    render_graph::{BufferAccess, BufferHandle, PassContext, PassHandle, PassType, RenderGraph},
+};
+
+use crate::render::camera::Camera3D;
+
+use super::light_renderer::LightRenderer;
+
+/// Must match clusters.glsl
+const CLUSTERS_X: u32 = 16;
+const CLUSTERS_Y: u32 = 9;
+const CLUSTERS_Z: u32 = 24;
+const CLUSTER_COUNT: u32 = CLUSTERS_X * CLUSTERS_Y * CLUSTERS_Z;
+/// Lights beyond this are dropped from a Cluster
+const MAX_LIGHTS_PER_CLUSTER: u32 = 128;
+
+/// Must match `local_size_x` in cluster.comp
+const CLUSTER_GROUP_SIZE: u32 = 64;
+
+/// Where the Lights and Clusters are in the Frame set of the Fragment Shader, cluster.comp uses the same bindings in set 0
+pub const LIGHTS_BINDING: u32 = 1;
+pub const CLUSTERS_BINDING: u32 = 4;
+pub const CLUSTER_LIGHTS_BINDING: u32 = 5;
+
+/// Must match `Clusters` in clusters.glsl (std140)
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ClusterData {
+    view: Mat4,
+    screen_size: [f32; 2],
+    z_near: f32,
+    z_far: f32,
+    tan_half_fov: [f32; 2],
+    _padding: [f32; 2],
+}
+
+/// Clustered forward Lighting.
+///
+/// The view frustum is split into froxels, A compute pass writes the Lights touching each of them into a list.
+/// The Fragment Shader then only loops over the list of its own Cluster, So thousands of small Lights stay cheap
+pub struct LightClusters {
+    pipeline: ComputePipeline,
+
+    pass: Option<PassHandle>,
+    cluster_lights: Option<BufferHandle>,
+
+    // Written by the cluster pass, Used by the Fragment Shader of the same Frame
+    lights: Option<UploadAllocation>,
+    clusters: Option<UploadAllocation>,
+}
+
+impl LightClusters {
+    pub fn new(instance: &VulkanInstance) -> Self {
+        let cluster_shader = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/assets/shaders/app/3D/cluster.comp.spv"
+        );
+        let pipeline = ComputePipeline::new(instance, cluster_shader.as_ref())
+            .expect("Failed to create Light Cluster Pipeline");
+        Self {
+            pipeline,
+            pass: None,
+            cluster_lights: None,
+            lights: None,
+            clusters: None,
+        }
+    }
+
+    /// Adds the pass building the Light lists, The returned buffer has to be read as `BufferAccess::StorageRead` by the pass using `write_frame_set`
+    pub fn setup_pass(&mut self, graph: &mut RenderGraph) -> BufferHandle {
+        // A count and the indices for every Cluster
+        let cluster_size = (1 + MAX_LIGHTS_PER_CLUSTER) as usize * size_of::<u32>();
+        let cluster_lights = graph.create_buffer(
+            "Cluster Lights",
+            (CLUSTER_COUNT as usize * cluster_size) as vk::DeviceSize,
+        );
+        self.pass = Some(
+            graph
+                .add_pass("Light Clusters", PassType::Compute)
+                .buffer(cluster_lights, BufferAccess::StorageWrite)
+                .build(),
+        );
+        self.cluster_lights = Some(cluster_lights);
+        cluster_lights
+    }
+
+    /// Records the pass if it is ours, Returns false otherwise
+    pub fn execute_pass(
+        &mut self,
+        instance: &VulkanInstance,
+        pass: PassHandle,
+        context: &PassContext,
+        lights: &LightRenderer,
+        camera: &Camera3D,
+    ) -> bool {
+        if Some(pass) != self.pass {
+            return false;
+        }
+        let device = &instance.device;
+        let command_buffer = context.command_buffer;
+        let arena = &context.frame().upload_arena;
+
+        let tan_half_fov = (camera.fovy().to_radians() * 0.5).tan();
+        let cluster_data = ClusterData {
+            view: camera.view,
+            screen_size: [context.extent.width as f32, context.extent.height as f32],
+            z_near: camera.znear(),
+            z_far: camera.zfar(),
+            tan_half_fov: [tan_half_fov * camera.aspect_ratio(), tan_half_fov],
+            _padding: [0.0; 2],
+        };
+        let clusters = arena
+            .upload(&[cluster_data])
+            .expect("Upload Arena is too small for the Clusters");
+        let lights = lights.upload(arena);
+
+        let cluster_set = context
+            .frame()
+            .descriptor_allocator
+            .allocate(device, self.pipeline.descriptor_set_layouts[0])
+            .expect("Failed to allocate Descriptor Set");
+        Self::write_bindings(
+            DescriptorWriter::new(),
+            lights,
+            clusters,
+            context.buffer(self.cluster_lights.unwrap()),
+        )
+        .update(device, cluster_set);
+        self.lights = Some(lights);
+        self.clusters = Some(clusters);
+
+        unsafe {
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline.pipeline_layout,
+                0,
+                &[cluster_set],
+                &[],
+            );
+        }
+        self.pipeline
+            .dispatch(device, command_buffer, CLUSTER_COUNT, CLUSTER_GROUP_SIZE);
+        true
+    }
+
+    /// Adds the Lights and the Clusters of this Frame to the Frame set
+    pub fn write_frame_set(
+        &self,
+        writer: DescriptorWriter,
+        context: &PassContext,
+    ) -> DescriptorWriter {
+        Self::write_bindings(
+            writer,
+            self.lights.expect("The cluster pass did not run"),
+            self.clusters.expect("The cluster pass did not run"),
+            context.buffer(self.cluster_lights.unwrap()),
+        )
+    }
+
+    fn write_bindings(
+        writer: DescriptorWriter,
+        lights: UploadAllocation,
+        clusters: UploadAllocation,
+        cluster_lights: vk::Buffer,
+    ) -> DescriptorWriter {
+        writer
+            .buffer(
+                LIGHTS_BINDING,
+                lights.buffer,
+                lights.offset,
+                lights.size,
+                vk::DescriptorType::STORAGE_BUFFER,
+            )
+            .buffer(
+                CLUSTERS_BINDING,
+                clusters.buffer,
+                clusters.offset,
+                clusters.size,
+                vk::DescriptorType::UNIFORM_BUFFER,
+            )
+            .buffer(
+                CLUSTER_LIGHTS_BINDING,
+                cluster_lights,
+                0,
+                vk::WHOLE_SIZE,
+                vk::DescriptorType::STORAGE_BUFFER,
+            )
+    }
+
+    pub fn reload_shaders(&mut self, instance: &VulkanInstance, changed: &[PathBuf]) {
+        if !changed.iter().any(|file| self.pipeline.uses_shader(file)) {
+            return;
+        }
+        match self.pipeline.reload(instance) {
+            Ok(()) => log::info!("Reloaded Light Cluster Pipeline"),
+            Err(err) => {
+                log::error!("Failed to reload Light Cluster Pipeline, Keeping the old one: {err}")
+            }
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        self.pipeline.destroy(device);
+    }
+}
@@ -1,16 +1,119 @@
+use std::{collections::HashMap, path::PathBuf};
+
 use ash::vk;
-use vent_math::vec::vec3::Vec3;
-use vent_rendering::{instance::VulkanInstance, mesh::Mesh3D, pipeline::VulkanPipeline, vertex::Vertex3D};
+use vent_ecs::entity::Entity;
+use vent_math::{scalar::mat4::Mat4, vec::vec3::Vec3};
+use vent_rendering::{
+    any_as_u8_slice,
+    frame::{UploadAllocation, UploadArena},
+    instance::VulkanInstance,
+    mesh::Mesh3D,
+    pipeline::{PipelineError, RenderingFormats, VulkanPipeline},
+    vertex::VertexPos3D,
+};
 
-#[allow(dead_code)]
+use crate::render::{
+    camera::Camera3D,
+    d3::create_simple_cube,
+    light::{DirectionalLight, Light, PointLight, SpotLight},
+};
+
+/// Must match the `LIGHT_*` defines in lights.glsl
+const LIGHT_DIRECTIONAL: u32 = 0;
+const LIGHT_POINT: u32 = 1;
+const LIGHT_SPOT: u32 = 2;
+
+/// Size of the cubes drawn at the Light positions
+const GIZMO_SCALE: f32 = 0.1;
+
+/// The Sun is always the first Light in the buffer, So the Shadows can find it
+pub const SUN_INDEX: i32 = 0;
+
+/// A single Light as the Shaders see it, Must match `Light` in lights.glsl (std430)
 #[repr(C)]
-pub struct LightUBO {
-    pub position: Vec3,
-    pub color: Vec3,
+#[derive(Clone, Copy, Default)]
+pub struct GpuLight {
+    pub position: [f32; 3],
+    pub range: f32,
+    pub direction: [f32; 3],
+    pub kind: u32,
+    pub color: [f32; 3],
+    pub intensity: f32,
+    /// The cone falloff is `clamp(cos(angle) * spot_scale + spot_offset, 0, 1)`
+    pub spot_scale: f32,
+    pub spot_offset: f32,
+    pub _padding: [f32; 2],
 }
 
+impl From<&Light> for GpuLight {
+    fn from(light: &Light) -> Self {
+        match *light {
+            Light::Directional(DirectionalLight {
+                direction,
+                color,
+                intensity,
+            }) => Self {
+                direction: Vec3::from(direction).normalize().into(),
+                kind: LIGHT_DIRECTIONAL,
+                color,
+                intensity,
+                ..Default::default()
+            },
+            Light::Point(PointLight {
+                position,
+                color,
+                intensity,
+                range,
+            }) => Self {
+                position,
+                range,
+                kind: LIGHT_POINT,
+                color,
+                intensity,
+                ..Default::default()
+            },
+            Light::Spot(SpotLight {
+                position,
+                direction,
+                color,
+                intensity,
+                range,
+                inner_cone_angle,
+                outer_cone_angle,
+            }) => {
+                // Same as KHR_lights_punctual, So the Shader only needs a multiply add
+                let cos_outer = outer_cone_angle.cos();
+                let spot_scale = 1.0 / (inner_cone_angle.cos() - cos_outer).max(0.001);
+                Self {
+                    position,
+                    range,
+                    direction: Vec3::from(direction).normalize().into(),
+                    kind: LIGHT_SPOT,
+                    color,
+                    intensity,
+                    spot_scale,
+                    spot_offset: -cos_outer * spot_scale,
+                    ..Default::default()
+                }
+            }
+        }
+    }
+}
+
+/// Push Constants of light.vert
+#[repr(C)]
+struct GizmoData {
+    proj_view: Mat4,
+    position_scale: [f32; 4],
+    color: [f32; 4],
+}
+
+/// Keeps all Lights in the World, Uploads them every Frame and draws a small cube at every Light with a position
 pub struct LightRenderer {
+    lights: HashMap<Entity, Light>,
     pipeline: VulkanPipeline,
+    cube: Mesh3D,
+    pub draw_gizmos: bool,
 }
 
 #[allow(dead_code)]
@@ -25,57 +128,157 @@ impl LightRenderer {
             "/assets/shaders/app/3D/light.frag.spv"
         );
 
-        let desc_layout_bindings = [
-            vk::DescriptorSetLayoutBinding {
-                binding: 0,
-                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
-                descriptor_count: 1,
-                stage_flags: vk::ShaderStageFlags::VERTEX,
-                ..Default::default()
-            },
-            vk::DescriptorSetLayoutBinding {
-                binding: 1,
-                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
-                descriptor_count: 1,
-                stage_flags: vk::ShaderStageFlags::VERTEX,
-                ..Default::default()
-            },
-        ];
-
         let pipeline = VulkanPipeline::create_simple_pipeline(
             instance,
             vertex_shader.as_ref(),
             fragment_shader.as_ref(),
-            &[Vertex3D::binding_description()],
-            &Vertex3D::input_descriptions(),
+            &[VertexPos3D::binding_description()],
+            &VertexPos3D::input_descriptions(),
             instance.surface_resolution,
-            &[],
-            &desc_layout_bindings,
-        );
+            &RenderingFormats::hdr(instance),
+        )
+        .expect("Invalid Light Shaders");
 
-        Self { pipeline }
+        Self {
+            lights: HashMap::new(),
+            pipeline,
+            cube: create_simple_cube(instance),
+            draw_gizmos: cfg!(debug_assertions),
+        }
     }
 
-    pub fn render(
-        &self,
-        instance: &VulkanInstance,
-        command_buffer: vk::CommandBuffer,
-        _buffer_index: usize,
-        mesh: &Mesh3D,
-    ) {
+    #[inline]
+    pub fn insert(&mut self, entity: Entity, light: impl Into<Light>) {
+        self.lights.insert(entity, light.into());
+    }
+
+    #[inline]
+    pub fn remove(&mut self, entity: Entity) {
+        self.lights.remove(&entity);
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn get(&self, entity: Entity) -> Option<&Light> {
+        self.lights.get(&entity)
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn get_mut(&mut self, entity: Entity) -> Option<&mut Light> {
+        self.lights.get_mut(&entity)
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn iter(&self) -> std::collections::hash_map::Iter<'_, Entity, Light> {
+        self.lights.iter()
+    }
+
+    /// The Directional Light casting Shadows, The brightest one when there are multiple
+    #[must_use]
+    pub fn sun(&self) -> Option<&DirectionalLight> {
+        self.sun_entry().map(|(_, sun)| sun)
+    }
+
+    /// Lets e.g. the Atmosphere move and tint the Sun
+    pub fn sun_mut(&mut self) -> Option<&mut DirectionalLight> {
+        let (entity, _) = self.sun_entry()?;
+        match self.lights.get_mut(&entity) {
+            Some(Light::Directional(sun)) => Some(sun),
+            _ => None,
+        }
+    }
+
+    fn sun_entry(&self) -> Option<(Entity, &DirectionalLight)> {
+        self.lights
+            .iter()
+            .filter_map(|(entity, light)| match light {
+                Light::Directional(directional) => Some((*entity, directional)),
+                _ => None,
+            })
+            .max_by(|(_, a), (_, b)| a.intensity.total_cmp(&b.intensity))
+    }
+
+    /// Writes all Lights into the Arena with the Sun at `SUN_INDEX`, The Shaders get the count from the length of the buffer
+    pub fn upload(&self, arena: &UploadArena) -> UploadAllocation {
+        let sun = self.sun_entry().map(|(entity, _)| entity);
+        let mut sorted: Vec<_> = self.lights.iter().collect();
+        sorted.sort_by_key(|(entity, _)| Some(**entity) != sun);
+        let mut lights: Vec<GpuLight> = sorted
+            .into_iter()
+            .map(|(_, light)| GpuLight::from(light))
+            .collect();
+        if lights.is_empty() {
+            // An empty buffer can not be bound, A black Light does nothing
+            lights.push(GpuLight::default());
+        }
+        arena
+            .upload(&lights)
+            .expect("Upload Arena is too small for all Lights")
+    }
+
+    /// Draws the gizmos on top of everything, Nothing is drawn when `draw_gizmos` is false
+    pub fn draw(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, camera: &Camera3D) {
+        if !self.draw_gizmos {
+            return;
+        }
+        let proj_view = camera.projection * camera.view;
         unsafe {
-            instance.device.cmd_bind_pipeline(
+            device.cmd_bind_pipeline(
                 command_buffer,
                 vk::PipelineBindPoint::GRAPHICS,
                 self.pipeline.pipeline,
             )
         };
+        self.cube.bind(device, command_buffer);
+        for light in self.lights.values() {
+            let Some([x, y, z]) = light.position() else {
+                continue;
+            };
+            let [r, g, b] = light.color();
+            let gizmo = GizmoData {
+                proj_view,
+                position_scale: [x, y, z, GIZMO_SCALE],
+                color: [r, g, b, 1.0],
+            };
+            unsafe {
+                device.cmd_push_constants(
+                    command_buffer,
+                    self.pipeline.pipeline_layout,
+                    vk::ShaderStageFlags::VERTEX,
+                    0,
+                    any_as_u8_slice(&gizmo),
+                )
+            };
+            self.cube.draw(device, command_buffer);
+        }
+    }
 
-        mesh.bind(&instance.device, command_buffer);
-        mesh.draw(&instance.device, command_buffer);
+    /// Rebuilds the Pipeline when one of the changed SPIR-V files belongs to it, The GPU has to be idle
+    pub fn reload_shaders(&mut self, instance: &VulkanInstance, changed: &[PathBuf]) {
+        if !changed.iter().any(|file| self.pipeline.uses_shader(file)) {
+            return;
+        }
+        match self.pipeline.reload(instance) {
+            Ok(()) => log::info!("Reloaded Light Gizmo Pipeline"),
+            Err(err) => {
+                log::error!("Failed to reload Light Gizmo Pipeline, Keeping the old one: {err}")
+            }
+        }
+    }
+
+    /// Rebuilds the Pipeline for another MSAA sample count, The GPU has to be idle
+    pub fn set_samples(
+        &mut self,
+        instance: &VulkanInstance,
+        samples: vk::SampleCountFlags,
+    ) -> Result<(), PipelineError> {
+        self.pipeline.set_samples(instance, samples)
     }
 
     pub fn destroy(&mut self, device: &ash::Device) {
         self.pipeline.destroy(device);
+        self.cube.destroy(device);
     }
 }
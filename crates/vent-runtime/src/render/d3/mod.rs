@@ -1,35 +1,97 @@
-use std::mem::size_of;
+use std::{mem::size_of, path::PathBuf};
 
 use ash::vk;
 use pollster::FutureExt;
+use vent_assets::{GeneratedNormals, MaterialBinding, TransparentPipelines};
 
-use skybox_renderer::SkyBoxRenderer;
+use atmosphere::{Atmosphere, AtmosphereSettings};
+use ibl::{EnvironmentSource, ImageBasedLighting};
+use indirect_renderer::IndirectRenderer;
+use skybox_renderer::{SkyBoxRenderer, SkyboxImage, SkyboxSource};
+use transparency::WeightedBlendedOit;
 use vent_ecs::world::World;
 use vent_math::{
     scalar::mat4::Mat4,
     vec::{vec3::Vec3, vec4::Vec4},
 };
+use vent_rendering::render_graph::{
+    AttachmentLoad, BufferAccess, BufferHandle, ImageAccess, ImageHandle, PassBuilder, PassContext,
+    PassHandle, PassType, RenderGraph,
+};
+use vent_rendering::RenderQueue;
 use vent_rendering::{
-    any_as_u8_slice, buffer::VulkanBuffer, image::SkyBoxImages, instance::VulkanInstance, mesh::Mesh3D, vertex::VertexPos3D
+    any_as_u8_slice,
+    bindless::BindlessDescriptors,
+    buffer::VulkanBuffer,
+    descriptor::DescriptorWriter,
+    image::VulkanImage,
+    instance::VulkanInstance,
+    mesh::Mesh3D,
+    pipeline::{PipelineError, RenderingFormats},
+    reflection::PipelineReflection,
+    vertex::{InstanceData, Vertex3D, VertexPos3D},
 };
 
+use crate::project::RenderSettings;
+
 use super::{
     camera::{Camera, Camera3D},
+    light::{DirectionalLight, PointLight, SpotLight},
     model::Entity3D,
     model_renderer::ModelRenderer3D,
-    Renderer,
+    GraphTargets, Renderer, Transparency,
 };
 
+use light_clusters::LightClusters;
+use light_renderer::LightRenderer;
+use shadow_renderer::ShadowRenderer;
+
+pub mod atmosphere;
+pub mod ibl;
+pub mod indirect_renderer;
+pub mod light_clusters;
 pub mod light_renderer;
+pub mod shadow_renderer;
 pub mod skybox_renderer;
+pub mod transparency;
 
+/// Must match `Material` in the Shader (std140)
 #[repr(C)]
 pub struct MaterialUBO {
     pub base_color: Vec4,
+    pub emissive_factor: [f32; 3],
+    pub alpha_mode: u32,
+    pub alpha_cutoff: f32,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    pub occlusion_strength: f32,
+}
+
+/// `MaterialUBO` as it is stored in the Bindless Material buffer, Must match `Material` in the Shader (std430)
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct BindlessMaterial {
+    pub base_color: Vec4,
+    pub emissive_factor: [f32; 3],
     pub alpha_mode: u32,
     pub alpha_cutoff: f32,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    pub occlusion_strength: f32,
+    pub diffuse_texture: u32,
+    pub normal_texture: u32,
+    pub metallic_roughness_texture: u32,
+    pub occlusion_texture: u32,
+    pub emissive_texture: u32,
+    pub _padding: [u32; 3],
 }
 
+/// Per Frame data, The Lights, Shadows and Image Based Lighting for the Fragment Shader and the Objects of the indirect Vertex Shader
+const FRAME_SET: u32 = 1;
+
+/// Where the Bindless Shaders expect the Material id, Right after `Camera3DData`
+const MATERIAL_ID_OFFSET: u32 = size_of::<Camera3DData>() as u32;
+
 #[repr(C)] // This fixed everthing... #[repr(C)]
 /// We calculate all values on the CPU, This will save us alot of memory, Push constants only guarante us 128 bytes
 pub struct Camera3DData {
@@ -49,93 +111,107 @@ impl Default for Camera3DData {
 pub struct Renderer3D {
     mesh_renderer: ModelRenderer3D,
     skybox_renderer: SkyBoxRenderer,
-    //light_renderer: LightRenderer,
-    tmp_light_mesh: Mesh3D,
+    ibl: ImageBasedLighting,
+    /// Renders the environment of `ibl` when the Skybox is an Atmosphere
+    atmosphere: Option<Atmosphere>,
+    light_renderer: LightRenderer,
+    light_clusters: LightClusters,
+    shadow_renderer: ShadowRenderer,
     pipeline_layout: vk::PipelineLayout,
-    descriptor_set_layout: vk::DescriptorSetLayout,
+    /// Layout of `FRAME_SET`, A new set is allocated every Frame
+    frame_layout: vk::DescriptorSetLayout,
+    /// None when the Device does not support descriptor indexing, Then every Material has its own descriptor set
+    bindless: Option<BindlessDescriptors>,
+    /// GPU culling and indirect draws, Only with Bindless Descriptors and `draw_indirect_count`
+    indirect: Option<IndirectRenderer>,
+    /// None draws the Transparent Meshes sorted at the end of the Model pass
+    oit: Option<WeightedBlendedOit>,
 
     material_ubos: Vec<VulkanBuffer>,
-    light_ubos: Vec<VulkanBuffer>,
+
+    skybox_pass: Option<PassHandle>,
+    model_pass: Option<PassHandle>,
 }
 
 impl Renderer for Renderer3D {
-    fn init(instance: &mut VulkanInstance, _camera: &mut dyn Camera) -> Self
+    fn init(
+        instance: &mut VulkanInstance,
+        settings: &RenderSettings,
+        _camera: &mut dyn Camera,
+    ) -> Self
     where
         Self: Sized,
     {
         //  let _camera: &Camera3D = camera.downcast_ref().unwrap();
 
-        let skybox_renderer = SkyBoxRenderer::new(
-            instance,
-            SkyBoxImages {
-                right: concat!(
+        let mut bindless = instance.capabilities.descriptor_indexing.then(|| {
+            BindlessDescriptors::new(instance, size_of::<BindlessMaterial>() as vk::DeviceSize)
+        });
+
+        let gpu_driven = bindless.is_some() && instance.capabilities.draw_indirect_count;
+
+        let (vertex_shader, fragment_shader) = if gpu_driven {
+            (
+                concat!(
                     env!("CARGO_MANIFEST_DIR"),
-                    "/assets/textures/skybox/right.jpg"
-                )
-                .into(),
-                left: concat!(
+                    "/assets/shaders/app/3D/shader.indirect.vert.spv"
+                ),
+                concat!(
                     env!("CARGO_MANIFEST_DIR"),
-                    "/assets/textures/skybox/left.jpg"
-                )
-                .into(),
-                top: concat!(
+                    "/assets/shaders/app/3D/shader.bindless.frag.spv"
+                ),
+            )
+        } else if bindless.is_some() {
+            (
+                concat!(
                     env!("CARGO_MANIFEST_DIR"),
-                    "/assets/textures/skybox/top.jpg"
-                )
-                .into(),
-                bottom: concat!(
+                    "/assets/shaders/app/3D/shader.bindless.vert.spv"
+                ),
+                concat!(
                     env!("CARGO_MANIFEST_DIR"),
-                    "/assets/textures/skybox/bottom.jpg"
-                )
-                .into(),
-                front: concat!(
+                    "/assets/shaders/app/3D/shader.bindless.frag.spv"
+                ),
+            )
+        } else {
+            (
+                concat!(
                     env!("CARGO_MANIFEST_DIR"),
-                    "/assets/textures/skybox/front.jpg"
-                )
-                .into(),
-                back: concat!(
+                    "/assets/shaders/app/3D/shader.vert.spv"
+                ),
+                concat!(
                     env!("CARGO_MANIFEST_DIR"),
-                    "/assets/textures/skybox/back.jpg"
-                )
-                .into(),
-            },
-        );
+                    "/assets/shaders/app/3D/shader.frag.spv"
+                ),
+            )
+        };
 
-        let push_constant_range = vk::PushConstantRange::default()
-            .size(size_of::<Camera3DData>() as u32)
-            .stage_flags(vk::ShaderStageFlags::VERTEX);
-
-        let desc_layout_bindings = [
-            // Fragment
-            vk::DescriptorSetLayoutBinding {
-                binding: 0,
-                descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-                descriptor_count: 1,
-                stage_flags: vk::ShaderStageFlags::FRAGMENT,
-                ..Default::default()
-            },
-            vk::DescriptorSetLayoutBinding {
-                binding: 1,
-                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
-                descriptor_count: 1,
-                stage_flags: vk::ShaderStageFlags::FRAGMENT,
-                ..Default::default()
-            },
-            // vk::DescriptorSetLayoutBinding {
-            //     binding: 2,
-            //     descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
-            //     descriptor_count: 1,
-            //     stage_flags: vk::ShaderStageFlags::FRAGMENT,
-            //     ..Default::default()
-            // },
-        ];
-        let info = vk::DescriptorSetLayoutCreateInfo::default().bindings(&desc_layout_bindings);
-
-        let descriptor_set_layout =
-            unsafe { instance.device.create_descriptor_set_layout(&info, None) }.unwrap();
+        // Layouts are read from the Shaders, So they can not drift apart
+        let reflection =
+            PipelineReflection::from_files(&[vertex_shader.as_ref(), fragment_shader.as_ref()])
+                .expect("Invalid Model Shaders");
+        let mut vertex_attributes = Vertex3D::input_descriptions().to_vec();
+        if !gpu_driven {
+            vertex_attributes.extend(InstanceData::input_descriptions());
+        }
+        reflection
+            .validate_vertex_input(&vertex_attributes)
+            .expect("Model Shader does not match Vertex3D");
+        let descriptor_set_layout = match &bindless {
+            Some(bindless) => {
+                bindless
+                    .validate(&reflection, 0)
+                    .expect("Model Shader does not match the Bindless Descriptors");
+                bindless.layout
+            }
+            None => reflection.create_descriptor_set_layout(instance, 0),
+        };
+
+        let frame_layout = reflection.create_descriptor_set_layout(instance, FRAME_SET);
+        let set_layouts = [descriptor_set_layout, frame_layout];
+        let mut indirect = gpu_driven.then(|| IndirectRenderer::new(instance));
 
         let pipeline_layout =
-            instance.create_pipeline_layout(&[push_constant_range], &[descriptor_set_layout]);
+            instance.create_pipeline_layout(&reflection.push_constant_ranges, &set_layouts);
 
         let mut mesh_renderer = ModelRenderer3D::default();
 
@@ -151,133 +227,244 @@ impl Renderer for Renderer3D {
         // bistro_outside.glb
 
         let mut material_ubos = vec![];
-        let light_ubos = vec![];
 
-        let vertex_shader = concat!(
-            env!("CARGO_MANIFEST_DIR"),
-            "/assets/shaders/app/3D/shader.vert.spv"
-        );
-        let fragment_shader = concat!(
-            env!("CARGO_MANIFEST_DIR"),
-            "/assets/shaders/app/3D/shader.frag.spv"
-        );
-
-        let mut mesh = Entity3D::new(
+        let model = mesh_renderer.add_model(
             vent_assets::Model3D::load(
                 instance,
                 vertex_shader,
                 fragment_shader,
                 pipeline_layout,
+                &RenderingFormats::hdr(instance),
                 model,
+                indirect.as_mut().map(|indirect| &mut indirect.geometry),
+                GeneratedNormals::default(),
             )
             .block_on(),
         );
-        for material in mesh.model.materials.iter_mut() {
-            let descriptor_sets = VulkanInstance::allocate_descriptor_sets(
-                &instance.device,
-                mesh.model.descriptor_pool,
-                descriptor_set_layout,
-                instance.swapchain_images.len(),
-            );
-
-            for &descriptor_set in descriptor_sets.iter() {
-                let diffuse_texture = &material.diffuse_texture;
-
-                let matieral_buffer = VulkanBuffer::new_init(
+        let oit = (settings.transparency == Transparency::WeightedBlended).then(|| {
+            mesh_renderer
+                .model_mut(model)
+                .set_transparent_pipelines(
                     instance,
-                    size_of::<MaterialUBO>() as vk::DeviceSize,
-                    vk::BufferUsageFlags::UNIFORM_BUFFER,
-                    any_as_u8_slice(&MaterialUBO {
-                        base_color: Vec4::from_array(material.base_color),
-                        alpha_mode: material.alpha_mode as u32,
-                        alpha_cutoff: material.alpha_cut,
-                    }),
-                    vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::DEVICE_LOCAL,
-                    None,
-                );
-                // let light_buffer = VulkanBuffer::new_init(
-                //     instance,
-                //     size_of::<LightUBO>() as vk::DeviceSize,
-                //     vk::BufferUsageFlags::UNIFORM_BUFFER,
-                //     any_as_u8_slice(&LightUBO {
-                //         position: Vec3::new(2.0, 100.0, 2.0),
-                //         color: Vec3::new(1.0, 1.0, 1.0),
-                //     }),
-                //     vk::MemoryPropertyFlags::HOST_VISIBLE
-                //         | vk::MemoryPropertyFlags::DEVICE_LOCAL,
-                //     None,
-                // );
-
-                let image_info = vk::DescriptorImageInfo::default()
-                    .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
-                    .image_view(diffuse_texture.image_view)
-                    .sampler(diffuse_texture.sampler);
-
-                let material_buffer_info = vk::DescriptorBufferInfo::default()
-                    .buffer(*matieral_buffer)
-                    .offset(0)
-                    .range(size_of::<MaterialUBO>() as vk::DeviceSize);
-
-                // let light_buffer_info = vk::DescriptorBufferInfo::default()
-                //     .buffer(*light_buffer)
-                //     .offset(0)
-                //     .range(size_of::<LightUBO>() as vk::DeviceSize);
-
-                let desc_sets = [
-                    vk::WriteDescriptorSet {
-                        dst_set: descriptor_set,
-                        dst_binding: 0, // From DescriptorSetLayoutBinding
-                        descriptor_count: 1,
-                        descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-                        p_image_info: &image_info,
-                        ..Default::default()
-                    },
-                    vk::WriteDescriptorSet {
-                        dst_set: descriptor_set,
-                        dst_binding: 1,
-                        descriptor_count: 1,
-                        descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
-                        p_buffer_info: &material_buffer_info,
-                        ..Default::default()
+                    TransparentPipelines::WeightedBlended {
+                        fragment_shader: WeightedBlendedOit::model_fragment_shader(
+                            bindless.is_some(),
+                        ),
+                        formats: RenderingFormats::weighted_blended(instance),
                     },
-                    // vk::WriteDescriptorSet {
-                    //     dst_set: descriptor_set,
-                    //     dst_binding: 2,
-                    //     descriptor_count: 1,
-                    //     descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
-                    //     p_buffer_info: &light_buffer_info,
-                    //     ..Default::default()
-                    // },
-                ];
-
-                unsafe {
-                    instance.device.update_descriptor_sets(&desc_sets, &[]);
-                }
+                )
+                .expect("Failed to create the Weighted Blended Pipelines");
+            WeightedBlendedOit::new(instance)
+        });
+        for material in mesh_renderer.model_mut(model).materials.iter_mut() {
+            if let Some(bindless) = &mut bindless {
+                let mut add_texture = |texture: &VulkanImage| {
+                    bindless.add_texture(&instance.device, texture.image_view, texture.sampler)
+                };
+                let diffuse_texture = add_texture(&material.diffuse_texture);
+                let normal_texture = add_texture(&material.normal_texture);
+                let metallic_roughness_texture = add_texture(&material.metallic_roughness_texture);
+                let occlusion_texture = add_texture(&material.occlusion_texture);
+                let emissive_texture = add_texture(&material.emissive_texture);
+                material.bindless_id = Some(bindless.add_material(&BindlessMaterial {
+                    base_color: Vec4::from_array(material.base_color),
+                    emissive_factor: material.emissive_factor,
+                    alpha_mode: material.alpha_mode as u32,
+                    alpha_cutoff: material.alpha_cut,
+                    metallic_factor: material.metallic_factor,
+                    roughness_factor: material.roughness_factor,
+                    occlusion_strength: material.occlusion_strength,
+                    diffuse_texture,
+                    normal_texture,
+                    metallic_roughness_texture,
+                    occlusion_texture,
+                    emissive_texture,
+                    _padding: [0; 3],
+                }));
+                continue;
+            }
+            // Materials do not change while rendering, So one UBO and descriptor set is shared by all frames
+            let descriptor_set = instance
+                .descriptor_allocator
+                .allocate(&instance.device, descriptor_set_layout)
+                .expect("Failed to allocate Descriptor Set");
 
-                material_ubos.push(matieral_buffer);
-                //  light_ubos.push(light_buffer);
+            let matieral_buffer = VulkanBuffer::new_init(
+                instance,
+                size_of::<MaterialUBO>() as vk::DeviceSize,
+                vk::BufferUsageFlags::UNIFORM_BUFFER,
+                any_as_u8_slice(&MaterialUBO {
+                    base_color: Vec4::from_array(material.base_color),
+                    emissive_factor: material.emissive_factor,
+                    alpha_mode: material.alpha_mode as u32,
+                    alpha_cutoff: material.alpha_cut,
+                    metallic_factor: material.metallic_factor,
+                    roughness_factor: material.roughness_factor,
+                    occlusion_strength: material.occlusion_strength,
+                }),
+                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                None,
+            );
+            let mut writer = DescriptorWriter::new().buffer(
+                1,
+                *matieral_buffer,
+                0,
+                size_of::<MaterialUBO>() as vk::DeviceSize,
+                vk::DescriptorType::UNIFORM_BUFFER,
+            );
+            let textures = [
+                (0, &material.diffuse_texture),
+                (2, &material.normal_texture),
+                (3, &material.metallic_roughness_texture),
+                (4, &material.occlusion_texture),
+                (5, &material.emissive_texture),
+            ];
+            for (binding, texture) in textures {
+                writer = writer.image(
+                    binding,
+                    texture.image_view,
+                    texture.sampler,
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                );
             }
-            material.descriptor_set = Some(descriptor_sets);
+            writer.update(&instance.device, descriptor_set);
+
+            material_ubos.push(matieral_buffer);
+            material.descriptor_set = Some(descriptor_set);
         }
 
+        let mesh = Entity3D::new(model, mesh_renderer.model(model));
         mesh_renderer.insert(world.create_entity(), mesh);
 
-        let tmp_light_mesh = create_simple_cube(instance);
-        //  let light_renderer = LightRenderer::new(instance);
+        let mut light_renderer = LightRenderer::new(instance);
+        light_renderer.insert(
+            world.create_entity(),
+            DirectionalLight {
+                direction: [-0.3, -1.0, -0.2],
+                color: [1.0, 0.95, 0.9],
+                intensity: 3.0,
+            },
+        );
+        for (position, color) in [
+            ([-5.0, 1.5, 0.0], [1.0, 0.4, 0.2]),
+            ([5.0, 1.5, 0.0], [0.2, 0.4, 1.0]),
+        ] {
+            light_renderer.insert(
+                world.create_entity(),
+                PointLight {
+                    position,
+                    color,
+                    intensity: 5.0,
+                    range: 10.0,
+                },
+            );
+        }
+        light_renderer.insert(
+            world.create_entity(),
+            SpotLight {
+                position: [0.0, 4.0, 0.0],
+                direction: [0.0, -1.0, 0.0],
+                intensity: 20.0,
+                range: 15.0,
+                inner_cone_angle: 0.3,
+                outer_cone_angle: 0.5,
+                ..Default::default()
+            },
+        );
+
+        // A wrong Skybox in the project settings should not stop the runtime, The Atmosphere needs no assets
+        let (skybox_source, skybox_image) = match SkyboxImage::load(instance, &settings.skybox) {
+            Ok(image) => (settings.skybox.clone(), image),
+            Err(err) => {
+                log::error!("{err}, Falling back to the Atmosphere");
+                let source = SkyboxSource::Atmosphere(AtmosphereSettings::default());
+                (source, SkyboxImage::Environment)
+            }
+        };
+        // The Atmosphere needs the Sun, So it comes after the Lights
+        let mut atmosphere = match &skybox_source {
+            SkyboxSource::Atmosphere(atmosphere) => Some(Atmosphere::new(instance, atmosphere)),
+            _ => None,
+        };
+        let environment = match &mut atmosphere {
+            Some(atmosphere) => {
+                atmosphere.update(instance, &mut light_renderer, 0.0);
+                EnvironmentSource::Cubemap(atmosphere.sky())
+            }
+            None => skybox_image
+                .environment_source()
+                .expect("Skybox has no environment"),
+        };
+        let ibl = ImageBasedLighting::new(instance, environment);
+        let skybox_renderer = SkyBoxRenderer::new(instance, skybox_image, &ibl);
 
         Self {
             mesh_renderer,
             skybox_renderer,
-            descriptor_set_layout,
-            //   light_renderer,
-            tmp_light_mesh,
+            ibl,
+            atmosphere,
+            light_renderer,
+            light_clusters: LightClusters::new(instance),
+            shadow_renderer: ShadowRenderer::new(instance),
             pipeline_layout,
+            frame_layout,
+            bindless,
+            indirect,
+            oit,
             material_ubos,
-            light_ubos,
+            skybox_pass: None,
+            model_pass: None,
             // pipeline_wire,
         }
     }
 
+    fn setup_passes(&mut self, graph: &mut RenderGraph, targets: &GraphTargets) {
+        let clear_color = vk::ClearValue {
+            color: vk::ClearColorValue {
+                float32: [0.2, 0.9, 1.0, 1.0],
+            },
+        };
+        let clear_depth = vk::ClearValue {
+            depth_stencil: vk::ClearDepthStencilValue {
+                depth: 1.0,
+                stencil: 0,
+            },
+        };
+        // Culling has to run before the Models are drawn
+        let draw_buffers = self
+            .indirect
+            .as_mut()
+            .map(|indirect| indirect.setup_passes(graph, &self.mesh_renderer));
+        let cluster_lights = self.light_clusters.setup_pass(graph);
+        let shadow_map = self.shadow_renderer.setup_pass(graph);
+        self.skybox_pass = Some(
+            targets
+                .attach(
+                    graph.add_pass("Skybox", PassType::Graphics),
+                    AttachmentLoad::Clear(clear_color),
+                    AttachmentLoad::Clear(clear_depth),
+                    false,
+                )
+                .build(),
+        );
+        let inputs = ModelInputs {
+            shadow_map,
+            cluster_lights,
+            draw_buffers,
+        };
+        // The last pass drawing into the multisampled targets resolves them
+        let model_pass = targets.attach(
+            graph.add_pass("Models", PassType::Graphics),
+            AttachmentLoad::Load,
+            AttachmentLoad::Load,
+            true,
+        );
+        self.model_pass = Some(inputs.read(model_pass).build());
+        if let Some(oit) = &mut self.oit {
+            oit.setup_passes(graph, targets, |pass| inputs.read(pass));
+        }
+    }
+
     fn resize(
         &mut self,
         _instance: &mut VulkanInstance,
@@ -286,50 +473,136 @@ impl Renderer for Renderer3D {
     ) {
     }
 
-    fn render(
+    fn reload_shaders(&mut self, instance: &VulkanInstance, changed: &[PathBuf]) {
+        self.skybox_renderer.reload_shaders(instance, changed);
+        self.ibl.reload_shaders(instance, changed);
+        if let Some(atmosphere) = &mut self.atmosphere {
+            atmosphere.reload_shaders(instance, changed);
+        }
+        self.light_renderer.reload_shaders(instance, changed);
+        self.light_clusters.reload_shaders(instance, changed);
+        self.shadow_renderer.reload_shaders(instance, changed);
+        if let Some(indirect) = &mut self.indirect {
+            indirect.reload_shaders(instance, changed);
+        }
+        if let Some(oit) = &mut self.oit {
+            oit.reload_shaders(instance, changed);
+        }
+        for model in self.mesh_renderer.models_mut() {
+            if !changed.iter().any(|file| model.uses_shader(file)) {
+                continue;
+            }
+            match model.reload_pipelines(instance) {
+                Ok(()) => log::info!("Reloaded {} Model Pipelines", model.pipelines.len()),
+                Err(err) => {
+                    log::error!("Failed to reload Model Pipelines, Keeping the old ones: {err}")
+                }
+            }
+        }
+    }
+
+    fn set_msaa_samples(
         &mut self,
         instance: &VulkanInstance,
-        image_index: u32,
-        command_buffer: vk::CommandBuffer,
+        samples: vk::SampleCountFlags,
+    ) -> Result<(), PipelineError> {
+        let result = self.set_pipeline_samples(instance, samples);
+        if result.is_err() {
+            // Some Pipelines may already use the new count, They have to match the old Graph again
+            if let Err(err) = self.set_pipeline_samples(instance, instance.msaa_samples) {
+                log::error!("Failed to restore the samples of the Pipelines: {err}");
+            }
+        }
+        result
+    }
+
+    fn needs_rebuild(&self) -> bool {
+        self.indirect
+            .as_ref()
+            .is_some_and(|indirect| indirect.needs_rebuild(&self.mesh_renderer))
+    }
+
+    fn update(&mut self, instance: &VulkanInstance, delta_time: f32) {
+        if let Some(atmosphere) = &mut self.atmosphere {
+            if atmosphere.update(instance, &mut self.light_renderer, delta_time) {
+                self.ibl
+                    .generate(instance, EnvironmentSource::Cubemap(atmosphere.sky()));
+            }
+        }
+    }
+
+    fn toggle_debug_view(&mut self) {
+        self.shadow_renderer.debug_cascades = !self.shadow_renderer.debug_cascades;
+        log::info!(
+            "Shadow Cascade view {}",
+            if self.shadow_renderer.debug_cascades {
+                "on"
+            } else {
+                "off"
+            }
+        );
+    }
+
+    fn execute_pass(
+        &mut self,
+        instance: &VulkanInstance,
+        pass: PassHandle,
+        context: &PassContext,
         camera: &mut dyn Camera,
     ) {
         let camera: &mut Camera3D = camera.downcast_mut().unwrap();
 
-        let image_index = image_index as usize;
+        let command_buffer = context.command_buffer;
 
-        let render_area = vk::Rect2D::default()
-            .offset(vk::Offset2D::default())
-            .extent(instance.surface_resolution);
-
-        let viewport = vk::Viewport::default()
-            .width(instance.surface_resolution.width as f32)
-            .height(instance.surface_resolution.height as f32)
-            .max_depth(1.0);
+        if let Some(indirect) = &mut self.indirect {
+            if indirect.execute_pass(instance, pass, context, &self.mesh_renderer, camera) {
+                return;
+            }
+        }
+        if self
+            .light_clusters
+            .execute_pass(instance, pass, context, &self.light_renderer, camera)
+        {
+            return;
+        }
+        if self.shadow_renderer.execute_pass(
+            instance,
+            pass,
+            context,
+            &self.mesh_renderer,
+            self.indirect.as_ref().map(|indirect| &indirect.geometry),
+            camera,
+            self.light_renderer.sun(),
+        ) {
+            return;
+        }
 
-        unsafe {
-            instance
-                .device
-                .cmd_set_scissor(command_buffer, 0, &[render_area]);
-            instance
-                .device
-                .cmd_set_viewport(command_buffer, 0, &[viewport]);
+        if let Some(oit) = &self.oit {
+            if oit.execute_pass(instance, pass, context) {
+                return;
+            }
+            if oit.is_transparent_pass(pass) {
+                self.draw_models(instance, context, camera, &[RenderQueue::Transparent]);
+                return;
+            }
+        }
 
+        if Some(pass) == self.skybox_pass {
             self.skybox_renderer
-                .draw(&instance.device, command_buffer, camera, image_index);
-
-            self.mesh_renderer.record_buffer(
+                .draw(&instance.device, command_buffer, camera);
+        } else if Some(pass) == self.model_pass {
+            self.draw_models(
                 instance,
-                command_buffer,
-                image_index,
-                self.pipeline_layout,
+                context,
                 camera,
+                &[RenderQueue::Opaque, RenderQueue::Mask],
             );
-
-            //    self.light_renderer.render(instance, command_buffer, image_index, &self.tmp_light_mesh);
-
-            //  camera.write(instance, self.pipeline_layout, command_buffer);
-
-            // END
+            // Light gizmos are opaque, So they go before anything Transparent
+            self.light_renderer
+                .draw(&instance.device, command_buffer, camera);
+            if self.oit.is_none() {
+                self.draw_sorted_transparent(instance, context, camera);
+            }
         }
     }
 
@@ -337,20 +610,27 @@ impl Renderer for Renderer3D {
         unsafe { instance.device.device_wait_idle().unwrap() };
         self.mesh_renderer.destroy_all(&instance.device);
         self.skybox_renderer.destroy(&instance.device);
-        unsafe {
-            instance
-                .device
-                .destroy_descriptor_set_layout(self.descriptor_set_layout, None)
-        };
-        //self.light_renderer.destroy(&instance.device);
+        self.ibl.destroy(&instance.device);
+        if let Some(atmosphere) = &mut self.atmosphere {
+            atmosphere.destroy(&instance.device);
+        }
+        self.light_renderer.destroy(&instance.device);
+        self.light_clusters.destroy(&instance.device);
+        self.shadow_renderer.destroy(&instance.device);
         self.material_ubos
             .drain(..)
             .for_each(|mut ubo| ubo.destroy(&instance.device));
-        self.light_ubos
-            .drain(..)
-            .for_each(|mut ubo| ubo.destroy(&instance.device));
 
-        self.tmp_light_mesh.destroy(&instance.device);
+        if let Some(bindless) = &mut self.bindless {
+            bindless.destroy(&instance.device);
+        }
+        if let Some(indirect) = &mut self.indirect {
+            indirect.destroy(&instance.device);
+        }
+        if let Some(oit) = &mut self.oit {
+            oit.destroy(&instance.device);
+        }
+
         unsafe {
             instance
                 .device
@@ -359,6 +639,158 @@ impl Renderer for Renderer3D {
     }
 }
 
+/// Everything the Model Shaders read, Shared by every pass drawing Models
+#[derive(Clone, Copy)]
+struct ModelInputs {
+    shadow_map: ImageHandle,
+    cluster_lights: BufferHandle,
+    draw_buffers: Option<(BufferHandle, BufferHandle)>,
+}
+
+impl ModelInputs {
+    fn read<'a>(&self, pass: PassBuilder<'a>) -> PassBuilder<'a> {
+        let pass = pass
+            .image(self.shadow_map, ImageAccess::Sampled)
+            .buffer(self.cluster_lights, BufferAccess::StorageRead);
+        match self.draw_buffers {
+            Some((draw_commands, draw_counts)) => pass
+                .buffer(draw_commands, BufferAccess::Indirect)
+                .buffer(draw_counts, BufferAccess::Indirect),
+            None => pass,
+        }
+    }
+}
+
+impl Renderer3D {
+    fn set_pipeline_samples(
+        &mut self,
+        instance: &VulkanInstance,
+        samples: vk::SampleCountFlags,
+    ) -> Result<(), PipelineError> {
+        self.skybox_renderer.set_samples(instance, samples)?;
+        self.light_renderer.set_samples(instance, samples)?;
+        for model in self.mesh_renderer.models_mut() {
+            model.set_samples(instance, samples)?;
+        }
+        Ok(())
+    }
+
+    /// Binds everything the Model Shaders need and draws the `queues` of all Models, Transparent ones unsorted
+    fn draw_models(
+        &self,
+        instance: &VulkanInstance,
+        context: &PassContext,
+        camera: &mut Camera3D,
+        queues: &[RenderQueue],
+    ) {
+        self.bind_frame_set(instance, context);
+        if let (Some(indirect), Some(bindless)) = (&self.indirect, &self.bindless) {
+            bindless.bind(
+                &instance.device,
+                context.command_buffer,
+                self.pipeline_layout,
+                0,
+            );
+            indirect.draw(instance, context, self.pipeline_layout, camera, queues);
+            return;
+        }
+        self.mesh_renderer.record_buffer(
+            instance,
+            context.command_buffer,
+            self.pipeline_layout,
+            &context.frame().upload_arena,
+            camera,
+            self.bind_materials(instance, context.command_buffer),
+            queues,
+        );
+    }
+
+    /// Draws the Transparent Meshes back to front, Right after the Opaque ones in the same pass
+    fn draw_sorted_transparent(
+        &self,
+        instance: &VulkanInstance,
+        context: &PassContext,
+        camera: &mut Camera3D,
+    ) {
+        // The light gizmos have their own layout, So everything gets bound again
+        self.bind_frame_set(instance, context);
+        if let (Some(indirect), Some(bindless)) = (&self.indirect, &self.bindless) {
+            // The cull pass already sorted them
+            bindless.bind(
+                &instance.device,
+                context.command_buffer,
+                self.pipeline_layout,
+                0,
+            );
+            indirect.draw(
+                instance,
+                context,
+                self.pipeline_layout,
+                camera,
+                &[RenderQueue::Transparent],
+            );
+            return;
+        }
+        self.mesh_renderer.record_transparent(
+            instance,
+            context.command_buffer,
+            self.pipeline_layout,
+            &context.frame().upload_arena,
+            camera,
+            self.bind_materials(instance, context.command_buffer),
+        );
+    }
+
+    /// Binds the Bindless Descriptors if there are any, Returns how the Models bind their Materials
+    fn bind_materials(
+        &self,
+        instance: &VulkanInstance,
+        command_buffer: vk::CommandBuffer,
+    ) -> MaterialBinding {
+        match &self.bindless {
+            Some(bindless) => {
+                // Bound once, Every Material is just an index into it
+                bindless.bind(&instance.device, command_buffer, self.pipeline_layout, 0);
+                MaterialBinding::Bindless {
+                    material_id_offset: MATERIAL_ID_OFFSET,
+                }
+            }
+            None => MaterialBinding::DescriptorSets,
+        }
+    }
+
+    /// Binds the Lights, Clusters, Shadows and Image Based Lighting of this Frame together with the culled Objects at `FRAME_SET`
+    fn bind_frame_set(&self, instance: &VulkanInstance, context: &PassContext) {
+        let device = &instance.device;
+        let frame = context.frame();
+        let mut writer = self
+            .light_clusters
+            .write_frame_set(DescriptorWriter::new(), context);
+        writer = self
+            .shadow_renderer
+            .write_frame_set(writer, context, &frame.upload_arena);
+        writer = self.ibl.write_frame_set(writer);
+        if let Some(indirect) = &self.indirect {
+            writer = indirect.write_objects(writer);
+        }
+        let frame_set = frame
+            .descriptor_allocator
+            .allocate(device, self.frame_layout)
+            .expect("Failed to allocate Descriptor Set");
+        writer.update(device, frame_set);
+        unsafe {
+            device.cmd_bind_descriptor_sets(
+                context.command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                FRAME_SET,
+                &[frame_set],
+                &[],
+            )
+        };
+    }
+}
+
 fn create_simple_cube(instance: &VulkanInstance) -> Mesh3D {
     let indices = [
         //Top
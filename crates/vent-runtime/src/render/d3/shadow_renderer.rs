@@ -0,0 +1,359 @@
+use std::path::PathBuf;
+
+use ash::vk;
+use vent_math::{
+    scalar::mat4::Mat4,
+    vec::{vec3::Vec3, vec4::Vec4},
+};
+use vent_rendering::{
+    any_as_u8_slice,
+    descriptor::DescriptorWriter,
+    frame::UploadArena,
+    geometry::GeometryPool,
+    instance::VulkanInstance,
+    pipeline::DepthPipeline,
+    render_graph::{
+        AttachmentLoad, ImageDesc, ImageHandle, ImageSize, PassContext, PassHandle, PassType,
+        RenderGraph,
+    },
+    vertex::{InstanceData, Vertex3D},
+};
+
+use crate::render::{camera::Camera3D, light::DirectionalLight, model_renderer::ModelRenderer3D};
+
+use super::light_renderer::SUN_INDEX;
+
+/// Must match `CASCADE_COUNT` in shadows.glsl
+pub const CASCADE_COUNT: usize = 4;
+/// Size of a single Cascade, The atlas holds 2x2 of them
+const CASCADE_RESOLUTION: u32 = 2048;
+const SHADOW_FORMAT: vk::Format = vk::Format::D32_SFLOAT;
+
+/// Nothing further away from the Camera gets Shadows
+const SHADOW_DISTANCE: f32 = 150.0;
+/// Blend between uniform (0.0) and logarithmic (1.0) Cascade splits
+const SPLIT_LAMBDA: f32 = 0.8;
+/// How far behind a Cascade Objects still cast Shadows into it
+const CASTER_MARGIN: f32 = 100.0;
+/// Constant and slope factor, Against Shadow acne
+const DEPTH_BIAS: (f32, f32) = (1.25, 1.75);
+
+/// Must match shadows.glsl
+pub const SHADOW_MAP_BINDING: u32 = 2;
+pub const SHADOWS_BINDING: u32 = 3;
+
+/// Must match `Shadows` in shadows.glsl (std140)
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ShadowData {
+    cascade_view_proj: [Mat4; CASCADE_COUNT],
+    texel_sizes: [f32; CASCADE_COUNT],
+    light_index: i32,
+    debug_cascades: u32,
+    _padding: [u32; 2],
+}
+
+impl Default for ShadowData {
+    fn default() -> Self {
+        Self {
+            cascade_view_proj: [Mat4::IDENTITY; CASCADE_COUNT],
+            texel_sizes: [0.0; CASCADE_COUNT],
+            light_index: -1,
+            debug_cascades: 0,
+            _padding: [0; 2],
+        }
+    }
+}
+
+/// Cascaded Shadow Maps for the Sun.
+///
+/// The view frustum of the Camera is split into `CASCADE_COUNT` slices, Each one gets its own orthographic Shadow Map
+/// fitted around it. All Cascades share one depth atlas, Which the Model Shaders sample with hardware PCF
+pub struct ShadowRenderer {
+    pipeline: DepthPipeline,
+    /// Comparison sampler, Every lookup already filters 2x2 texels
+    sampler: vk::Sampler,
+
+    shadow_map: Option<ImageHandle>,
+    pass: Option<PassHandle>,
+    /// Computed by the Shadow pass, Used by the Models of the same Frame
+    data: ShadowData,
+
+    /// Colors every pixel by the Cascade it uses
+    pub debug_cascades: bool,
+}
+
+impl ShadowRenderer {
+    pub fn new(instance: &VulkanInstance) -> Self {
+        let vertex_shader = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/assets/shaders/app/3D/shadow.vert.spv"
+        );
+        let mut attributes = Vertex3D::input_descriptions().to_vec();
+        attributes.extend(InstanceData::input_descriptions());
+        let pipeline = DepthPipeline::new(
+            instance,
+            vertex_shader.as_ref(),
+            &[
+                Vertex3D::binding_description(),
+                InstanceData::binding_description(),
+            ],
+            &attributes,
+            SHADOW_FORMAT,
+            DEPTH_BIAS,
+        )
+        .expect("Invalid Shadow Shader");
+
+        let sampler_info = vk::SamplerCreateInfo::default()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .compare_enable(true)
+            .compare_op(vk::CompareOp::LESS_OR_EQUAL)
+            .border_color(vk::BorderColor::FLOAT_OPAQUE_WHITE);
+        let sampler = unsafe { instance.device.create_sampler(&sampler_info, None) }.unwrap();
+
+        Self {
+            pipeline,
+            sampler,
+            shadow_map: None,
+            pass: None,
+            data: ShadowData::default(),
+            debug_cascades: false,
+        }
+    }
+
+    /// Adds the Shadow pass, The returned Shadow Map has to be sampled by the passes using `write_frame_set`
+    pub fn setup_pass(&mut self, graph: &mut RenderGraph) -> ImageHandle {
+        let shadow_map = graph.create_image(
+            "Shadow Map",
+            ImageDesc {
+                format: SHADOW_FORMAT,
+                size: ImageSize::Fixed(vk::Extent2D {
+                    width: CASCADE_RESOLUTION * 2,
+                    height: CASCADE_RESOLUTION * 2,
+                }),
+                samples: vk::SampleCountFlags::TYPE_1,
+            },
+        );
+        let clear_depth = vk::ClearValue {
+            depth_stencil: vk::ClearDepthStencilValue {
+                depth: 1.0,
+                stencil: 0,
+            },
+        };
+        self.pass = Some(
+            graph
+                .add_pass("Shadows", PassType::Graphics)
+                .depth_attachment(shadow_map, AttachmentLoad::Clear(clear_depth))
+                .build(),
+        );
+        self.shadow_map = Some(shadow_map);
+        shadow_map
+    }
+
+    /// Records the pass if it is ours, Returns false otherwise.
+    /// With GPU culling the Meshes live in the `GeometryPool`, Which is bound before drawing
+    #[allow(clippy::too_many_arguments)]
+    pub fn execute_pass(
+        &mut self,
+        instance: &VulkanInstance,
+        pass: PassHandle,
+        context: &PassContext,
+        models: &ModelRenderer3D,
+        geometry: Option<&GeometryPool>,
+        camera: &Camera3D,
+        sun: Option<&DirectionalLight>,
+    ) -> bool {
+        if Some(pass) != self.pass {
+            return false;
+        }
+        self.data = ShadowData {
+            debug_cascades: self.debug_cascades as u32,
+            ..Default::default()
+        };
+        let Some(sun) = sun else {
+            // Only cleared, The Shaders skip the lookup
+            return true;
+        };
+        self.update_cascades(camera, sun);
+        self.draw(instance, context, models, geometry);
+        true
+    }
+
+    /// Fits every Cascade around its slice of the Camera frustum
+    fn update_cascades(&mut self, camera: &Camera3D, sun: &DirectionalLight) {
+        let near = camera.znear();
+        let far = camera.zfar().min(SHADOW_DISTANCE);
+        let splits: [f32; CASCADE_COUNT + 1] = std::array::from_fn(|i| {
+            let p = i as f32 / CASCADE_COUNT as f32;
+            let log = near * (far / near).powf(p);
+            let uniform = near + (far - near) * p;
+            uniform + (log - uniform) * SPLIT_LAMBDA
+        });
+
+        let light_direction = Vec3::from(sun.direction).normalize();
+        let up = if light_direction.y.abs() > 0.99 {
+            Vec3::Z
+        } else {
+            Vec3::Y
+        };
+
+        for i in 0..CASCADE_COUNT {
+            let (center, radius) = frustum_slice_sphere(camera, splits[i], splits[i + 1]);
+            let eye = center - light_direction * (radius + CASTER_MARGIN);
+            let view = Mat4::look_at_rh(eye, center, up);
+            let mut proj = Mat4::orthographic_rh(
+                -radius,
+                radius,
+                -radius,
+                radius,
+                0.0,
+                radius * 2.0 + CASTER_MARGIN,
+            );
+
+            // Snap to whole Texels, Otherwise the Shadow edges shimmer when the Camera moves
+            let half_resolution = CASCADE_RESOLUTION as f32 * 0.5;
+            let origin = (proj * view) * Vec4::new(0.0, 0.0, 0.0, 1.0);
+            let (x, y) = (origin.x * half_resolution, origin.y * half_resolution);
+            proj.w_axis.x += (x.round() - x) / half_resolution;
+            proj.w_axis.y += (y.round() - y) / half_resolution;
+
+            self.data.cascade_view_proj[i] = proj * view;
+            self.data.texel_sizes[i] = radius * 2.0 / CASCADE_RESOLUTION as f32;
+        }
+        self.data.light_index = SUN_INDEX;
+    }
+
+    fn draw(
+        &self,
+        instance: &VulkanInstance,
+        context: &PassContext,
+        models: &ModelRenderer3D,
+        geometry: Option<&GeometryPool>,
+    ) {
+        let device = &instance.device;
+        let command_buffer = context.command_buffer;
+        unsafe {
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline.pipeline,
+            )
+        };
+        if let Some(geometry) = geometry {
+            geometry.bind(device, command_buffer);
+        }
+        for batch in models.instance_batches() {
+            if !batch.bind_instances(device, command_buffer, &context.frame().upload_arena) {
+                continue;
+            }
+            for (i, view_proj) in self.data.cascade_view_proj.iter().enumerate() {
+                let offset = vk::Offset2D {
+                    x: (i as u32 % 2 * CASCADE_RESOLUTION) as i32,
+                    y: (i as u32 / 2 * CASCADE_RESOLUTION) as i32,
+                };
+                let extent = vk::Extent2D {
+                    width: CASCADE_RESOLUTION,
+                    height: CASCADE_RESOLUTION,
+                };
+                unsafe {
+                    device.cmd_set_viewport(
+                        command_buffer,
+                        0,
+                        &[vk::Viewport {
+                            x: offset.x as f32,
+                            y: offset.y as f32,
+                            width: extent.width as f32,
+                            height: extent.height as f32,
+                            min_depth: 0.0,
+                            max_depth: 1.0,
+                        }],
+                    );
+                    device.cmd_set_scissor(command_buffer, 0, &[vk::Rect2D { offset, extent }]);
+                    device.cmd_push_constants(
+                        command_buffer,
+                        self.pipeline.pipeline_layout,
+                        vk::ShaderStageFlags::VERTEX,
+                        0,
+                        any_as_u8_slice(view_proj),
+                    );
+                }
+                batch
+                    .mesh
+                    .draw_meshes(device, command_buffer, batch.instance_count());
+            }
+        }
+    }
+
+    /// Adds the Shadow Map and the Cascades of this Frame to the Frame set
+    pub fn write_frame_set(
+        &self,
+        writer: DescriptorWriter,
+        context: &PassContext,
+        arena: &UploadArena,
+    ) -> DescriptorWriter {
+        let shadows = arena
+            .upload(&[self.data])
+            .expect("Upload Arena is too small for the Shadows");
+        writer
+            .image(
+                SHADOW_MAP_BINDING,
+                context.image_view(self.shadow_map.unwrap()),
+                self.sampler,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            )
+            .buffer(
+                SHADOWS_BINDING,
+                shadows.buffer,
+                shadows.offset,
+                shadows.size,
+                vk::DescriptorType::UNIFORM_BUFFER,
+            )
+    }
+
+    pub fn reload_shaders(&mut self, instance: &VulkanInstance, changed: &[PathBuf]) {
+        if !changed.iter().any(|file| self.pipeline.uses_shader(file)) {
+            return;
+        }
+        match self.pipeline.reload(instance) {
+            Ok(()) => log::info!("Reloaded Shadow Pipeline"),
+            Err(err) => log::error!("Failed to reload Shadow Pipeline, Keeping the old one: {err}"),
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        self.pipeline.destroy(device);
+        unsafe { device.destroy_sampler(self.sampler, None) };
+    }
+}
+
+/// Bounding sphere of the Camera frustum between `near` and `far`.
+/// A sphere does not change its size when the Camera rotates, So the Texel size stays the same
+fn frustum_slice_sphere(camera: &Camera3D, near: f32, far: f32) -> (Vec3, f32) {
+    let forward = camera.direction.normalize();
+    let right = forward.cross(Vec3::Y).normalize();
+    let up = right.cross(forward);
+    let tan_half_fov = (camera.fovy().to_radians() * 0.5).tan();
+
+    let mut corners = [Vec3::ZERO; 8];
+    for (i, distance) in [near, far].into_iter().enumerate() {
+        let center = camera.position + forward * distance;
+        let half_height = distance * tan_half_fov;
+        let half_width = half_height * camera.aspect_ratio();
+        corners[i * 4] = center + right * half_width + up * half_height;
+        corners[i * 4 + 1] = center - right * half_width + up * half_height;
+        corners[i * 4 + 2] = center + right * half_width - up * half_height;
+        corners[i * 4 + 3] = center - right * half_width - up * half_height;
+    }
+    let center = corners.iter().fold(Vec3::ZERO, |sum, corner| sum + *corner) * (1.0 / 8.0);
+    let radius = corners
+        .iter()
+        .map(|corner| (*corner - center).length())
+        .fold(0.0, f32::max);
+    // Rounded up, So small float errors do not change the Texel size every Frame
+    (center, (radius * 16.0).ceil() / 16.0)
+}
@@ -1,21 +1,156 @@
-use std::mem::size_of;
+use std::{fmt, path::PathBuf};
 
 use ash::vk;
 use image::GenericImageView;
+use serde::{Deserialize, Serialize};
 use vent_math::scalar::mat4::Mat4;
 use vent_rendering::{
-    any_as_u8_slice, image::{SkyBoxImages, VulkanImage}, instance::VulkanInstance, mesh::Mesh3D, pipeline::VulkanPipeline, vertex::VertexPos3D
+    any_as_u8_slice,
+    descriptor::DescriptorWriter,
+    image::VulkanImage,
+    instance::VulkanInstance,
+    ktx2::{Ktx2Error, Ktx2Texture},
+    mesh::Mesh3D,
+    pipeline::{PipelineError, RenderingFormats, VulkanPipeline},
+    vertex::VertexPos3D,
 };
 
 use crate::render::{camera::Camera3D, d3::create_simple_cube};
 
-#[allow(dead_code)]
+use super::{
+    atmosphere::AtmosphereSettings,
+    ibl::{EnvironmentSource, ImageBasedLighting},
+};
+
+/// Where the Skybox comes from, It also lights the scene. Paths are relative to the working directory
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum SkyboxSource {
+    /// Six images, One for every face
+    Faces {
+        right: String,
+        left: String,
+        top: String,
+        bottom: String,
+        front: String,
+        back: String,
+    },
+    /// A single equirectangular (latitude-longitude) image, Usually `.hdr` or `.exr`. Converted into a cubemap on the GPU
+    Equirect(String),
+    /// A `.ktx2` cubemap, Its mips are used as they are
+    Ktx2(String),
+    /// No asset at all, The sky is rendered from the sun Directional Light
+    Atmosphere(AtmosphereSettings),
+}
+
+impl Default for SkyboxSource {
+    fn default() -> Self {
+        let face = |name: &str| {
+            format!(
+                "{}/assets/textures/skybox/{name}.jpg",
+                env!("CARGO_MANIFEST_DIR")
+            )
+        };
+        Self::Faces {
+            right: face("right"),
+            left: face("left"),
+            top: face("top"),
+            bottom: face("bottom"),
+            front: face("front"),
+            back: face("back"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SkyboxError {
+    Image(String, image::ImageError),
+    Ktx2(String, Ktx2Error),
+    /// The KTX2 file has no six faces
+    NotCubemap(String),
+}
+
+impl fmt::Display for SkyboxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SkyboxError::Image(path, err) => write!(f, "Failed to load Skybox {path}: {err}"),
+            SkyboxError::Ktx2(path, err) => write!(f, "Failed to load Skybox {path}: {err}"),
+            SkyboxError::NotCubemap(path) => write!(f, "Skybox {path} is not a cubemap"),
+        }
+    }
+}
+
+impl std::error::Error for SkyboxError {}
+
+/// A loaded `SkyboxSource`
+pub enum SkyboxImage {
+    Cubemap(VulkanImage),
+    /// Only needed until the Image Based Lighting made a cubemap out of it
+    Equirect(VulkanImage),
+    /// Nothing to load, Something else renders the environment of the Image Based Lighting like the Atmosphere
+    Environment,
+}
+
+impl SkyboxImage {
+    pub fn load(instance: &VulkanInstance, source: &SkyboxSource) -> Result<Self, SkyboxError> {
+        let open =
+            |path: &String| image::open(path).map_err(|err| SkyboxError::Image(path.clone(), err));
+        let image = match source {
+            SkyboxSource::Faces {
+                right,
+                left,
+                top,
+                bottom,
+                front,
+                back,
+            } => {
+                let [right, left, top, bottom, front, back] =
+                    [right, left, top, bottom, front, back].map(open);
+                let images = [right?, left?, top?, bottom?, front?, back?];
+                let image_size = images[0].dimensions();
+                Self::Cubemap(VulkanImage::load_cubemap(
+                    instance,
+                    images,
+                    vk::Extent2D {
+                        width: image_size.0,
+                        height: image_size.1,
+                    },
+                ))
+            }
+            SkyboxSource::Equirect(path) => {
+                let image = open(path)?;
+                Self::Equirect(VulkanImage::from_hdr(instance, image, Some(path)))
+            }
+            SkyboxSource::Ktx2(path) => {
+                let ktx2_error = |err| SkyboxError::Ktx2(path.clone(), err);
+                let texture = Ktx2Texture::load(path.as_ref()).map_err(ktx2_error)?;
+                if !texture.is_cubemap() {
+                    return Err(SkyboxError::NotCubemap(path.clone()));
+                }
+                Self::Cubemap(
+                    VulkanImage::from_ktx2(instance, &texture, Some(path)).map_err(ktx2_error)?,
+                )
+            }
+            SkyboxSource::Atmosphere(_) => Self::Environment,
+        };
+        Ok(image)
+    }
+
+    /// None when there is nothing loaded to light the scene with
+    pub fn environment_source(&self) -> Option<EnvironmentSource<'_>> {
+        match self {
+            SkyboxImage::Cubemap(image) => Some(EnvironmentSource::Cubemap(image)),
+            SkyboxImage::Equirect(image) => Some(EnvironmentSource::Equirect(image)),
+            SkyboxImage::Environment => None,
+        }
+    }
+}
+
 pub struct SkyBoxRenderer {
     pipeline: VulkanPipeline,
-    image: VulkanImage,
-    descriptor_pool: vk::DescriptorPool,
+    /// None when the Skybox shows the environment of the Image Based Lighting
+    image: Option<VulkanImage>,
     push_constants: SkyBoxUBO,
-    descriptor_sets: Vec<vk::DescriptorSet>,
+    descriptor_set: vk::DescriptorSet,
     cube: Mesh3D,
 }
 
@@ -26,7 +161,8 @@ pub struct SkyBoxUBO {
 }
 
 impl SkyBoxRenderer {
-    pub fn new(instance: &VulkanInstance, images: SkyBoxImages) -> Self {
+    /// Everything but cubemaps is shown through the environment of `ibl`, Which has to be generated from them
+    pub fn new(instance: &VulkanInstance, image: SkyboxImage, ibl: &ImageBasedLighting) -> Self {
         log::debug!("Creating skybox");
         let vertex_shader = concat!(
             env!("CARGO_MANIFEST_DIR"),
@@ -37,18 +173,6 @@ impl SkyBoxRenderer {
             "/assets/shaders/app/3D/skybox.frag.spv"
         );
 
-        let desc_layout_bindings = [vk::DescriptorSetLayoutBinding {
-            binding: 0,
-            descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-            descriptor_count: 1,
-            stage_flags: vk::ShaderStageFlags::FRAGMENT,
-            ..Default::default()
-        }];
-
-        let push_constant_range = vk::PushConstantRange::default()
-            .size(size_of::<SkyBoxUBO>() as u32)
-            .stage_flags(vk::ShaderStageFlags::VERTEX);
-
         let pipeline = VulkanPipeline::create_simple_pipeline(
             instance,
             vertex_shader.as_ref(),
@@ -56,98 +180,54 @@ impl SkyBoxRenderer {
             &[VertexPos3D::binding_description()],
             &VertexPos3D::input_descriptions(),
             instance.surface_resolution,
-            &[push_constant_range],
-            &desc_layout_bindings,
-        );
+            &RenderingFormats::hdr(instance),
+        )
+        .expect("Invalid Skybox Shaders");
         let cube = create_simple_cube(instance);
         let push_constants = SkyBoxUBO {
             projection: Mat4::IDENTITY,
             model: Mat4::IDENTITY,
         };
 
-        let descriptor_pool =
-            Self::create_descriptor_pool(instance.swapchain_images.len() as u32, &instance.device);
-
-        let descriptor_sets = VulkanInstance::allocate_descriptor_sets(
-            &instance.device,
-            descriptor_pool,
-            pipeline.descriptor_set_layout,
-            instance.swapchain_images.len(),
-        );
+        // The cubemap never changes, So all frames share one descriptor set
+        let descriptor_set = instance
+            .descriptor_allocator
+            .allocate(&instance.device, pipeline.descriptor_set_layout)
+            .expect("Failed to allocate Descriptor Set");
 
-        let images = [
-            image::open(images.right).unwrap(),
-            image::open(images.left).unwrap(),
-            image::open(images.top).unwrap(),
-            image::open(images.bottom).unwrap(),
-            image::open(images.front).unwrap(),
-            image::open(images.back).unwrap(),
-        ];
-        let image_size = images[0].dimensions();
-
-        let image = VulkanImage::load_cubemap(
-            instance,
-            images,
-            vk::Extent2D {
-                width: image_size.0,
-                height: image_size.1,
-            },
-        );
-
-        for &descriptor_set in descriptor_sets.iter() {
-            let diffuse_texture = &image;
-
-            let image_info = vk::DescriptorImageInfo::default()
-                .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
-                .image_view(diffuse_texture.image_view)
-                .sampler(diffuse_texture.sampler);
-
-            let desc_sets = [vk::WriteDescriptorSet {
-                dst_set: descriptor_set,
-                dst_binding: 0, // From DescriptorSetLayoutBinding
-                descriptor_count: 1,
-                descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-                p_image_info: &image_info,
-                ..Default::default()
-            }];
-
-            unsafe {
-                instance.device.update_descriptor_sets(&desc_sets, &[]);
+        let image = match image {
+            SkyboxImage::Cubemap(image) => Some(image),
+            SkyboxImage::Equirect(mut image) => {
+                image.destroy(&instance.device);
+                None
             }
-        }
+            SkyboxImage::Environment => None,
+        };
+        let cubemap = image.as_ref().unwrap_or(ibl.environment());
+        DescriptorWriter::new()
+            .image(
+                0,
+                cubemap.image_view,
+                cubemap.sampler,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            )
+            .update(&instance.device, descriptor_set);
 
         Self {
             pipeline,
             cube,
             image,
             push_constants,
-            descriptor_pool,
-            descriptor_sets,
+            descriptor_set,
         }
     }
 
-    pub fn create_descriptor_pool(
-        swapchain_count: u32,
-        device: &ash::Device,
-    ) -> vk::DescriptorPool {
-        let pool_sizes = [vk::DescriptorPoolSize {
-            ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-            descriptor_count: 2 * swapchain_count,
-        }];
-
-        let create_info = vk::DescriptorPoolCreateInfo::default()
-            .pool_sizes(&pool_sizes)
-            .max_sets(2 * swapchain_count);
-
-        unsafe { device.create_descriptor_pool(&create_info, None) }.unwrap()
-    }
     #[allow(dead_code)]
     pub fn draw(
         &mut self,
         device: &ash::Device,
         command_buffer: vk::CommandBuffer,
         camera: &Camera3D,
-        buffer_index: usize,
     ) {
         self.push_constants = SkyBoxUBO {
             projection: camera.projection,
@@ -171,7 +251,7 @@ impl SkyBoxRenderer {
                 vk::PipelineBindPoint::GRAPHICS,
                 self.pipeline.pipeline_layout,
                 0,
-                &self.descriptor_sets[buffer_index..=buffer_index],
+                &[self.descriptor_set],
                 &[],
             )
         };
@@ -179,12 +259,31 @@ impl SkyBoxRenderer {
         self.cube.draw(device, command_buffer);
     }
 
+    /// Rebuilds the Pipeline when one of the changed SPIR-V files belongs to it, The GPU has to be idle
+    pub fn reload_shaders(&mut self, instance: &VulkanInstance, changed: &[PathBuf]) {
+        if !changed.iter().any(|file| self.pipeline.uses_shader(file)) {
+            return;
+        }
+        match self.pipeline.reload(instance) {
+            Ok(()) => log::info!("Reloaded Skybox Pipeline"),
+            Err(err) => log::error!("Failed to reload Skybox Pipeline, Keeping the old one: {err}"),
+        }
+    }
+
+    /// Rebuilds the Pipeline for another MSAA sample count, The GPU has to be idle
+    pub fn set_samples(
+        &mut self,
+        instance: &VulkanInstance,
+        samples: vk::SampleCountFlags,
+    ) -> Result<(), PipelineError> {
+        self.pipeline.set_samples(instance, samples)
+    }
+
     pub fn destroy(&mut self, device: &ash::Device) {
         self.pipeline.destroy(device);
         self.cube.destroy(device);
-        self.image.destroy(device);
-        unsafe {
-            device.destroy_descriptor_pool(self.descriptor_pool, None);
+        if let Some(image) = &mut self.image {
+            image.destroy(device);
         }
     }
 }
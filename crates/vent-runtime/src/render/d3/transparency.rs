@@ -0,0 +1,227 @@
+use std::path::PathBuf;
+
+use ash::vk;
+use vent_rendering::{
+    descriptor::DescriptorWriter,
+    instance::VulkanInstance,
+    pipeline::{RenderingFormats, VulkanPipeline, OIT_ACCUMULATION_FORMAT, OIT_REVEALAGE_FORMAT},
+    render_graph::{
+        AttachmentLoad, ImageAccess, ImageDesc, ImageHandle, ImageSize, PassBuilder, PassContext,
+        PassHandle, PassType, RenderGraph,
+    },
+};
+
+use crate::render::GraphTargets;
+
+/// Weighted Blended Order Independent Transparency.
+///
+/// The Transparent Meshes are drawn unsorted into an accumulation and a revealage target,
+/// Which get composited onto the resolved scene color afterwards. Always single sampled
+pub struct WeightedBlendedOit {
+    composite_pipeline: VulkanPipeline,
+    sampler: vk::Sampler,
+
+    accumulation: Option<ImageHandle>,
+    revealage: Option<ImageHandle>,
+    transparent_pass: Option<PassHandle>,
+    composite_pass: Option<PassHandle>,
+}
+
+impl WeightedBlendedOit {
+    pub fn new(instance: &VulkanInstance) -> Self {
+        let vertex_shader = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/assets/shaders/app/post/fullscreen.vert.spv"
+        );
+        let fragment_shader = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/assets/shaders/app/3D/oit_composite.frag.spv"
+        );
+        let mut composite_pipeline = VulkanPipeline::create_simple_pipeline(
+            instance,
+            vertex_shader.as_ref(),
+            fragment_shader.as_ref(),
+            &[],
+            &[],
+            instance.surface_resolution,
+            &RenderingFormats::hdr_color(),
+        )
+        .expect("Invalid OIT Composite Shaders");
+        composite_pipeline
+            .set_blend(
+                instance,
+                Some(vk::PipelineColorBlendAttachmentState {
+                    blend_enable: vk::TRUE,
+                    src_color_blend_factor: vk::BlendFactor::SRC_ALPHA,
+                    dst_color_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+                    color_blend_op: vk::BlendOp::ADD,
+                    src_alpha_blend_factor: vk::BlendFactor::ONE,
+                    dst_alpha_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+                    alpha_blend_op: vk::BlendOp::ADD,
+                    color_write_mask: vk::ColorComponentFlags::RGBA,
+                }),
+            )
+            .expect("Failed to enable blending of the OIT Composite");
+
+        // Only read with texelFetch
+        let sampler_info = vk::SamplerCreateInfo::default()
+            .mag_filter(vk::Filter::NEAREST)
+            .min_filter(vk::Filter::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE);
+        let sampler = unsafe { instance.device.create_sampler(&sampler_info, None) }.unwrap();
+
+        Self {
+            composite_pipeline,
+            sampler,
+            accumulation: None,
+            revealage: None,
+            transparent_pass: None,
+            composite_pass: None,
+        }
+    }
+
+    /// The permutation of the Model Fragment Shader which writes into both OIT targets
+    pub fn model_fragment_shader(bindless: bool) -> PathBuf {
+        if bindless {
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/assets/shaders/app/3D/shader.bindless_oit.frag.spv"
+            )
+            .into()
+        } else {
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/assets/shaders/app/3D/shader.oit.frag.spv"
+            )
+            .into()
+        }
+    }
+
+    /// Adds the pass drawing the Transparent Meshes and the one compositing them, Both after the resolve of the scene targets.
+    /// `inputs` adds everything else the Model Shaders read
+    pub fn setup_passes(
+        &mut self,
+        graph: &mut RenderGraph,
+        targets: &GraphTargets,
+        inputs: impl for<'a> FnOnce(PassBuilder<'a>) -> PassBuilder<'a>,
+    ) {
+        let accumulation = graph.create_image(
+            "OIT Accumulation",
+            ImageDesc {
+                format: OIT_ACCUMULATION_FORMAT,
+                size: ImageSize::Swapchain,
+                samples: vk::SampleCountFlags::TYPE_1,
+            },
+        );
+        let revealage = graph.create_image(
+            "OIT Revealage",
+            ImageDesc {
+                format: OIT_REVEALAGE_FORMAT,
+                size: ImageSize::Swapchain,
+                samples: vk::SampleCountFlags::TYPE_1,
+            },
+        );
+        let clear = |value| {
+            AttachmentLoad::Clear(vk::ClearValue {
+                color: vk::ClearColorValue {
+                    float32: [value; 4],
+                },
+            })
+        };
+        let transparent_pass = graph
+            .add_pass("Transparent", PassType::Graphics)
+            .color_attachment(accumulation, clear(0.0))
+            .color_attachment(revealage, clear(1.0))
+            .depth_read(targets.depth);
+        self.transparent_pass = Some(inputs(transparent_pass).build());
+        self.composite_pass = Some(
+            graph
+                .add_pass("Transparent Composite", PassType::Graphics)
+                .color_attachment(targets.scene_color, AttachmentLoad::Load)
+                .image(accumulation, ImageAccess::Sampled)
+                .image(revealage, ImageAccess::Sampled)
+                .build(),
+        );
+        self.accumulation = Some(accumulation);
+        self.revealage = Some(revealage);
+    }
+
+    pub fn is_transparent_pass(&self, pass: PassHandle) -> bool {
+        Some(pass) == self.transparent_pass
+    }
+
+    /// Records the composite pass if it is ours, Returns false otherwise. The Transparent pass is drawn by the Renderer
+    pub fn execute_pass(
+        &self,
+        instance: &VulkanInstance,
+        pass: PassHandle,
+        context: &PassContext,
+    ) -> bool {
+        if Some(pass) != self.composite_pass {
+            return false;
+        }
+        let device = &instance.device;
+        let command_buffer = context.command_buffer;
+        let pipeline = &self.composite_pipeline;
+
+        let set = context
+            .frame()
+            .descriptor_allocator
+            .allocate(device, pipeline.descriptor_set_layout)
+            .expect("Failed to allocate Descriptor Set");
+        DescriptorWriter::new()
+            .image(
+                0,
+                context.image_view(self.accumulation.unwrap()),
+                self.sampler,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            )
+            .image(
+                1,
+                context.image_view(self.revealage.unwrap()),
+                self.sampler,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            )
+            .update(device, set);
+
+        unsafe {
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                pipeline.pipeline,
+            );
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                pipeline.pipeline_layout,
+                0,
+                &[set],
+                &[],
+            );
+            device.cmd_draw(command_buffer, 3, 1, 0, 0);
+        }
+        true
+    }
+
+    pub fn reload_shaders(&mut self, instance: &VulkanInstance, changed: &[PathBuf]) {
+        if !changed
+            .iter()
+            .any(|file| self.composite_pipeline.uses_shader(file))
+        {
+            return;
+        }
+        match self.composite_pipeline.reload(instance) {
+            Ok(()) => log::info!("Reloaded OIT Composite Pipeline"),
+            Err(err) => {
+                log::error!("Failed to reload OIT Composite Pipeline, Keeping the old one: {err}")
+            }
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        self.composite_pipeline.destroy(device);
+        unsafe { device.destroy_sampler(self.sampler, None) };
+    }
+}
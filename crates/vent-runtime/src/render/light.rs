@@ -0,0 +1,123 @@
+use vent_ecs::component::Component;
+
+/// Light from an infinitely far away source like the Sun, Only the direction matters
+#[derive(Clone, Copy, Debug)]
+pub struct DirectionalLight {
+    /// Where the Light shines to, Does not have to be normalized
+    pub direction: [f32; 3],
+    /// Linear RGB
+    pub color: [f32; 3],
+    /// In lux
+    pub intensity: f32,
+}
+
+/// Light shining from a single point into all directions
+#[derive(Clone, Copy, Debug)]
+pub struct PointLight {
+    pub position: [f32; 3],
+    pub color: [f32; 3],
+    /// In candela
+    pub intensity: f32,
+    /// The Light fades out smoothly until this distance, 0.0 means infinite
+    pub range: f32,
+}
+
+/// A Point Light limited to a cone, Full intensity inside the inner cone fading out until the outer cone
+#[derive(Clone, Copy, Debug)]
+pub struct SpotLight {
+    pub position: [f32; 3],
+    pub direction: [f32; 3],
+    pub color: [f32; 3],
+    /// In candela
+    pub intensity: f32,
+    /// The Light fades out smoothly until this distance, 0.0 means infinite
+    pub range: f32,
+    /// Angle from the direction in radians
+    pub inner_cone_angle: f32,
+    /// Angle from the direction in radians, Must be larger than `inner_cone_angle`
+    pub outer_cone_angle: f32,
+}
+
+impl Component for DirectionalLight {}
+impl Component for PointLight {}
+impl Component for SpotLight {}
+
+impl Default for DirectionalLight {
+    fn default() -> Self {
+        Self {
+            direction: [0.0, -1.0, 0.0],
+            color: [1.0, 1.0, 1.0],
+            intensity: 1.0,
+        }
+    }
+}
+
+impl Default for PointLight {
+    fn default() -> Self {
+        Self {
+            position: [0.0, 0.0, 0.0],
+            color: [1.0, 1.0, 1.0],
+            intensity: 1.0,
+            range: 0.0,
+        }
+    }
+}
+
+impl Default for SpotLight {
+    fn default() -> Self {
+        Self {
+            position: [0.0, 0.0, 0.0],
+            direction: [0.0, -1.0, 0.0],
+            color: [1.0, 1.0, 1.0],
+            intensity: 1.0,
+            range: 0.0,
+            inner_cone_angle: 0.0,
+            outer_cone_angle: std::f32::consts::FRAC_PI_4,
+        }
+    }
+}
+
+/// Any of the Light components, So the Renderer can keep all of them in one place
+#[derive(Clone, Copy, Debug)]
+pub enum Light {
+    Directional(DirectionalLight),
+    Point(PointLight),
+    Spot(SpotLight),
+}
+
+impl Light {
+    /// Directional Lights are everywhere, So they have no position
+    pub fn position(&self) -> Option<[f32; 3]> {
+        match self {
+            Light::Directional(_) => None,
+            Light::Point(light) => Some(light.position),
+            Light::Spot(light) => Some(light.position),
+        }
+    }
+
+    pub fn color(&self) -> [f32; 3] {
+        match self {
+            Light::Directional(light) => light.color,
+            Light::Point(light) => light.color,
+            Light::Spot(light) => light.color,
+        }
+    }
+}
+
+impl From<DirectionalLight> for Light {
+    fn from(light: DirectionalLight) -> Self {
+        Light::Directional(light)
+    }
+}
+
+impl From<PointLight> for Light {
+    fn from(light: PointLight) -> Self {
+        Light::Point(light)
+    }
+}
+
+impl From<SpotLight> for Light {
+    fn from(light: SpotLight) -> Self {
+        Light::Spot(light)
+    }
+}
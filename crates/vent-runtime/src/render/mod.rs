@@ -1,22 +1,39 @@
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
 
+use ash::prelude::VkResult;
 use ash::vk::{self};
+use chrono::{DateTime, Local};
 use serde::{Deserialize, Serialize};
 use vent_rendering::instance::VulkanInstance;
+use vent_rendering::pipeline::{PipelineError, HDR_FORMAT};
+use vent_rendering::render_graph::{
+    AttachmentLoad, ImageDesc, ImageHandle, ImageSize, PassBuilder, PassContext, PassHandle,
+    PassType, RenderGraph,
+};
 use vent_ui::renderer::GuiRenderer;
 
-use crate::project::VentApplicationProject;
+use crate::project::{RenderSettings, VentApplicationProject};
 
 use self::camera::{from_dimension, Camera};
 use self::d2::Renderer2D;
 use self::d3::Renderer3D;
 use self::gui::debug_gui::RenderData;
+use self::post::PostProcessing;
+
+pub use self::d3::atmosphere::AtmosphereSettings;
+pub use self::d3::skybox_renderer::SkyboxSource;
+use self::shader_reload::ShaderHotReload;
 
 pub mod camera;
 pub mod gui;
+pub mod light;
 pub mod model;
+pub mod post;
 
 mod model_renderer;
+mod shader_reload;
 
 mod d2;
 mod d3;
@@ -35,10 +52,14 @@ impl DefaultRuntimeRenderer {
             settings.render_settings.vsync,
             window,
         );
-        let dimension = &settings.render_settings.dimension;
+        let render_settings = &settings.render_settings;
         let window_size = window.size();
-        let mut camera = from_dimension(window_size.0 as f32 / window_size.1 as f32, dimension);
-        let runtime_renderer = RawRuntimeRenderer::new(dimension, &mut instance, camera.as_mut());
+        let mut camera = from_dimension(
+            window_size.0 as f32 / window_size.1 as f32,
+            &render_settings.dimension,
+        );
+        let runtime_renderer =
+            RawRuntimeRenderer::new(render_settings, &mut instance, camera.as_mut());
         Self {
             instance,
             runtime_renderer,
@@ -51,8 +72,51 @@ impl DefaultRuntimeRenderer {
     }
 
     pub(crate) fn render(&mut self) -> f32 {
-        self.runtime_renderer
-            .render(&mut self.instance, self.camera.as_mut())
+        let delta = self
+            .runtime_renderer
+            .render(&mut self.instance, self.camera.as_mut());
+        if let Some(capture) = self.instance.take_capture() {
+            Self::save_screenshot(capture);
+        }
+        delta
+    }
+
+    /// Captures the next rendered frame, It gets saved as PNG into the `screenshots` folder
+    pub(crate) fn request_screenshot(&mut self) {
+        if let Err(err) = self.instance.request_capture() {
+            log::error!("Failed to capture frame, {}", err);
+        }
+    }
+
+    fn save_screenshot(capture: VkResult<image::RgbaImage>) {
+        let image = match capture {
+            Ok(image) => image,
+            Err(err) => {
+                log::error!("Failed to capture frame, {}", err);
+                return;
+            }
+        };
+        let timestamp: DateTime<Local> = Local::now();
+        let file_name = format!(
+            "screenshots/screenshot_{}.png",
+            timestamp.format("%Y%m%d%H%M%S%3f")
+        );
+        if let Err(err) = fs::create_dir_all("screenshots") {
+            log::error!("Failed to create screenshots folder, {}", err);
+            return;
+        }
+        match image.save(&file_name) {
+            Ok(()) => log::info!("Saved screenshot to {}", file_name),
+            Err(err) => log::error!("Failed to save screenshot, {}", err),
+        }
+    }
+
+    pub(crate) fn set_msaa(&mut self, msaa: Msaa) -> Result<(), PipelineError> {
+        self.runtime_renderer.set_msaa(&mut self.instance, msaa)
+    }
+
+    pub(crate) fn toggle_debug_view(&mut self) {
+        self.runtime_renderer.toggle_debug_view();
     }
 
     pub(crate) fn resize(&mut self, new_size: (u32, u32)) {
@@ -81,11 +145,97 @@ pub enum Dimension {
     D3,
 }
 
+/// Multisample Anti-Aliasing of the forward passes, Clamped to what the GPU supports
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Msaa {
+    #[default]
+    Off,
+    X2,
+    X4,
+    X8,
+}
+
+impl Msaa {
+    pub fn sample_count(self) -> vk::SampleCountFlags {
+        match self {
+            Msaa::Off => vk::SampleCountFlags::TYPE_1,
+            Msaa::X2 => vk::SampleCountFlags::TYPE_2,
+            Msaa::X4 => vk::SampleCountFlags::TYPE_4,
+            Msaa::X8 => vk::SampleCountFlags::TYPE_8,
+        }
+    }
+
+    /// Cycles through all options, Off comes after 8x
+    pub fn next(self) -> Self {
+        match self {
+            Msaa::Off => Msaa::X2,
+            Msaa::X2 => Msaa::X4,
+            Msaa::X4 => Msaa::X8,
+            Msaa::X8 => Msaa::Off,
+        }
+    }
+}
+
+/// How Meshes with a blended Material are drawn
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Transparency {
+    /// Back to front by view depth, Exact for anything that does not intersect
+    #[default]
+    Sorted,
+    /// Weighted Blended Order Independent Transparency, No sorting but only an approximation
+    WeightedBlended,
+}
+
+/// The images every renderer draws into
+pub struct GraphTargets {
+    /// HDR, Tonemapped into the backbuffer after all passes of the Renderer
+    pub scene_color: ImageHandle,
+    pub depth: ImageHandle,
+    /// Color and depth with `VulkanInstance::msaa_samples`, Only there when MSAA is on
+    pub multisampled: Option<(ImageHandle, ImageHandle)>,
+}
+
+impl GraphTargets {
+    /// Adds the color and depth target to the pass, The multisampled ones when MSAA is on.
+    /// With `resolve` they get resolved into `scene_color` and `depth` at the end of the pass
+    pub fn attach<'a>(
+        &self,
+        pass: PassBuilder<'a>,
+        color_load: AttachmentLoad,
+        depth_load: AttachmentLoad,
+        resolve: bool,
+    ) -> PassBuilder<'a> {
+        match self.multisampled {
+            Some((color, depth)) => {
+                let pass = pass
+                    .color_attachment(color, color_load)
+                    .depth_attachment(depth, depth_load);
+                if resolve {
+                    pass.color_resolve(self.scene_color)
+                        .depth_resolve(self.depth)
+                } else {
+                    pass
+                }
+            }
+            None => pass
+                .color_attachment(self.scene_color, color_load)
+                .depth_attachment(self.depth, depth_load),
+        }
+    }
+}
+
 pub trait Renderer {
-    fn init(instance: &mut VulkanInstance, camera: &mut dyn Camera) -> Self
+    fn init(
+        instance: &mut VulkanInstance,
+        settings: &RenderSettings,
+        camera: &mut dyn Camera,
+    ) -> Self
     where
         Self: Sized;
 
+    /// Adds all passes of the Renderer to the Graph, The first pass has to clear the targets
+    fn setup_passes(&mut self, graph: &mut RenderGraph, targets: &GraphTargets);
+
     fn resize(
         &mut self,
         instance: &mut VulkanInstance,
@@ -93,11 +243,36 @@ pub trait Renderer {
         camera: &mut dyn Camera,
     );
 
-    fn render(
+    /// Rebuilds all Pipelines which use one of the changed SPIR-V files, The GPU is idle when this gets called
+    fn reload_shaders(&mut self, instance: &VulkanInstance, changed: &[PathBuf]);
+
+    /// Creates all Pipelines drawing into the scene targets again with the new sample count, The GPU is idle when this gets called.
+    /// On failure all Pipelines keep the sample count of `instance.msaa_samples`
+    fn set_msaa_samples(
         &mut self,
         instance: &VulkanInstance,
-        image_index: u32,
-        command_buffer: vk::CommandBuffer,
+        samples: vk::SampleCountFlags,
+    ) -> Result<(), PipelineError>;
+
+    /// Called every Frame before the Graph is recorded, e.g. to regenerate precomputed data. Blocking work is fine here.
+    /// `delta_time` is the duration of the last Frame in milliseconds
+    fn update(&mut self, _instance: &VulkanInstance, _delta_time: f32) {}
+
+    /// Whether the passes added in `setup_passes` do not fit anymore, e.g. because their buffers are too small.
+    /// The Graph is built again before the next Frame then
+    fn needs_rebuild(&self) -> bool {
+        false
+    }
+
+    /// Switches the debug view of the Renderer on or off, e.g. coloring the shadow cascades
+    fn toggle_debug_view(&mut self) {}
+
+    /// Records the commands of one of the passes added in `setup_passes`
+    fn execute_pass(
+        &mut self,
+        instance: &VulkanInstance,
+        pass: PassHandle,
+        context: &PassContext,
         camera: &mut dyn Camera,
     );
 
@@ -109,6 +284,11 @@ pub struct RawRuntimeRenderer {
     //  gui_renderer: GuiRenderer,
     multi_renderer: Box<dyn Renderer>,
     gui_renderer: GuiRenderer,
+    post_processing: PostProcessing,
+    graph: RenderGraph,
+    gui_pass: PassHandle,
+    // Only in debug builds, The shader sources are not shipped
+    shader_reload: Option<ShaderHotReload>,
     current_data: RenderData,
 
     current_frames: u32,
@@ -118,15 +298,30 @@ pub struct RawRuntimeRenderer {
 
 impl RawRuntimeRenderer {
     pub fn new(
-        dimension: &Dimension,
+        settings: &RenderSettings,
         instance: &mut VulkanInstance,
         camera: &mut dyn Camera,
     ) -> Self {
+        let samples = instance
+            .capabilities
+            .clamp_msaa_samples(settings.msaa.sample_count());
+        if samples != settings.msaa.sample_count() {
+            log::warn!(
+                "{:?} is not supported, Falling back to {:?} samples",
+                settings.msaa,
+                samples
+            );
+        }
+        instance.msaa_samples = samples;
         let gui_renderer = GuiRenderer::new(instance);
-        let multi_renderer: Box<dyn Renderer> = match dimension {
-            Dimension::D2 => Box::new(Renderer2D::init(instance, camera)),
-            Dimension::D3 => Box::new(Renderer3D::init(instance, camera)),
+        let mut post_processing = PostProcessing::new(instance, settings);
+        let mut multi_renderer: Box<dyn Renderer> = match settings.dimension {
+            Dimension::D2 => Box::new(Renderer2D::init(instance, settings, camera)),
+            Dimension::D3 => Box::new(Renderer3D::init(instance, settings, camera)),
         };
+
+        let (graph, gui_pass) =
+            Self::build_graph(instance, multi_renderer.as_mut(), &mut post_processing);
         //     // TODO
         //     .add_gui(Box::new(DebugGUI::new(unsafe {
         //         instance
@@ -134,9 +329,20 @@ impl RawRuntimeRenderer {
         //             .get_physical_device_properties(instance.physical_device)
         //     })));
 
+        let shader_reload = cfg!(debug_assertions).then(|| {
+            ShaderHotReload::new(&[
+                Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/shaders")),
+                Path::new(vent_ui::renderer::SHADER_DIR),
+            ])
+        });
+
         Self {
             multi_renderer,
             gui_renderer,
+            post_processing,
+            graph,
+            gui_pass,
+            shader_reload,
             current_frames: 0,
             current_data: RenderData::default(),
             last_fps: Instant::now(),
@@ -144,21 +350,101 @@ impl RawRuntimeRenderer {
         }
     }
 
+    fn build_graph(
+        instance: &VulkanInstance,
+        multi_renderer: &mut dyn Renderer,
+        post_processing: &mut PostProcessing,
+    ) -> (RenderGraph, PassHandle) {
+        let mut graph = RenderGraph::new();
+        let backbuffer = graph.import_backbuffer();
+        let target_desc = |format, samples| ImageDesc {
+            format,
+            size: ImageSize::Swapchain,
+            samples,
+        };
+        let single = vk::SampleCountFlags::TYPE_1;
+        let multisampled = (instance.msaa_samples != single).then(|| {
+            (
+                graph.create_image(
+                    "Scene Color MSAA",
+                    target_desc(HDR_FORMAT, instance.msaa_samples),
+                ),
+                graph.create_image(
+                    "Depth MSAA",
+                    target_desc(instance.depth_format, instance.msaa_samples),
+                ),
+            )
+        });
+        let targets = GraphTargets {
+            scene_color: graph.create_image("Scene Color", target_desc(HDR_FORMAT, single)),
+            depth: graph.create_image("Depth", target_desc(instance.depth_format, single)),
+            multisampled,
+        };
+        multi_renderer.setup_passes(&mut graph, &targets);
+        post_processing.setup_passes(
+            &mut graph,
+            targets.scene_color,
+            backbuffer,
+            instance.surface_format.format,
+        );
+        // The GUI goes on top of everything
+        let gui_pass = graph
+            .add_pass("GUI", PassType::Graphics)
+            .color_attachment(backbuffer, AttachmentLoad::Load)
+            .build();
+        (graph, gui_pass)
+    }
+
+    /// Done between frames when the post effects changed, All passes are added again
+    fn rebuild_graph(&mut self, instance: &VulkanInstance) {
+        log::debug!("Rebuilding Render Graph");
+        // Its images may still be used by frames in flight
+        unsafe { instance.device.device_wait_idle().unwrap() };
+        self.graph.destroy(&instance.device);
+        (self.graph, self.gui_pass) = Self::build_graph(
+            instance,
+            self.multi_renderer.as_mut(),
+            &mut self.post_processing,
+        );
+    }
+
+    /// Switches MSAA between frames, Recreating the Pipelines and the Graph
+    pub fn set_msaa(
+        &mut self,
+        instance: &mut VulkanInstance,
+        msaa: Msaa,
+    ) -> Result<(), PipelineError> {
+        let samples = instance
+            .capabilities
+            .clamp_msaa_samples(msaa.sample_count());
+        if samples == instance.msaa_samples {
+            return Ok(());
+        }
+        log::info!("Switching MSAA to {:?} samples", samples);
+        unsafe { instance.device.device_wait_idle().unwrap() };
+        // Nothing changes when the Pipelines can not be created
+        self.multi_renderer.set_msaa_samples(instance, samples)?;
+        instance.msaa_samples = samples;
+        self.rebuild_graph(instance);
+        Ok(())
+    }
+
     pub fn render(&mut self, instance: &mut VulkanInstance, camera: &mut dyn Camera) -> f32 {
         let frame_start = Instant::now();
 
+        self.reload_shaders(instance);
+        self.post_processing.update(instance, self.delta_time);
+        self.multi_renderer.update(instance, self.delta_time);
+        if self.post_processing.needs_rebuild() || self.multi_renderer.needs_rebuild() {
+            self.rebuild_graph(instance);
+        }
+
         let image = instance.next_image();
 
         match image {
             Ok((image_index, _)) => {
-                let command_pool = instance.command_pools[image_index as usize];
-                unsafe {
-                    instance
-                        .device
-                        .reset_command_pool(command_pool, vk::CommandPoolResetFlags::empty())
-                        .unwrap();
-                }
-                let command_buffer = instance.command_buffers[image_index as usize];
+                // The command pool of the frame was already reset when acquiring
+                let command_buffer = instance.current_frame().command_buffer;
                 unsafe {
                     let info = vk::CommandBufferBeginInfo::default()
                         .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
@@ -168,28 +454,28 @@ impl RawRuntimeRenderer {
                         .begin_command_buffer(command_buffer, &info)
                         .unwrap();
                 }
-                self.cmd_renderpass(instance, command_buffer, image_index as usize);
-
-                self.multi_renderer
-                    .render(instance, image_index, command_buffer, camera);
-
-                self.gui_renderer.render_text(
-                    instance,
-                    command_buffer,
-                    image_index as usize,
-                    "Abc".to_string(),
-                    10.0,
-                    10.0,
-                    0.5,
-                    255255255,
-                );
-
-                let subpass_end_info = vk::SubpassEndInfo::default();
-                unsafe {
-                    instance
-                        .device
-                        .cmd_end_render_pass2(command_buffer, &subpass_end_info)
-                };
+                let gui_pass = self.gui_pass;
+                let gui_renderer = &mut self.gui_renderer;
+                let multi_renderer = &mut self.multi_renderer;
+                let post_processing = &self.post_processing;
+                self.graph
+                    .execute(instance, command_buffer, image_index, |pass, context| {
+                        if pass == gui_pass {
+                            gui_renderer.render_text(
+                                instance,
+                                context.command_buffer,
+                                context.frame(),
+                                "Abc".to_string(),
+                                10.0,
+                                10.0,
+                                0.5,
+                                255255255,
+                            );
+                        } else if !post_processing.execute_pass(instance, pass, context) {
+                            multi_renderer.execute_pass(instance, pass, context, camera);
+                        }
+                    });
+
                 unsafe { instance.device.end_command_buffer(command_buffer).unwrap() };
                 let result = instance.submit(image_index);
                 if let Err(vk::Result::ERROR_OUT_OF_DATE_KHR | vk::Result::SUBOPTIMAL_KHR) = result
@@ -233,42 +519,20 @@ impl RawRuntimeRenderer {
         self.delta_time
     }
 
-    fn cmd_renderpass(
-        &self,
-        instance: &VulkanInstance,
-        command_buffer: vk::CommandBuffer,
-        image_index: usize,
-    ) {
-        let render_area = vk::Rect2D::default()
-            .offset(vk::Offset2D::default())
-            .extent(instance.surface_resolution);
-        let color_clear_value = vk::ClearValue {
-            color: vk::ClearColorValue {
-                float32: [0.2, 0.9, 1.0, 1.0],
-            },
-        };
-
-        let depth_clear_value = vk::ClearValue {
-            depth_stencil: vk::ClearDepthStencilValue {
-                depth: 1.0,
-                stencil: 0,
-            },
-        };
-
-        let clear_values = &[color_clear_value, depth_clear_value];
-
-        let info = vk::RenderPassBeginInfo::default()
-            .render_pass(instance.render_pass)
-            .framebuffer(instance.frame_buffers[image_index])
-            .render_area(render_area)
-            .clear_values(clear_values);
-        let subpass_info = vk::SubpassBeginInfo::default().contents(vk::SubpassContents::INLINE);
-
-        unsafe {
-            instance
-                .device
-                .cmd_begin_render_pass2(command_buffer, &info, &subpass_info)
+    /// Done between frames, So no pipeline we replace is still recorded
+    fn reload_shaders(&mut self, instance: &VulkanInstance) {
+        let Some(shader_reload) = &mut self.shader_reload else {
+            return;
         };
+        let changed = shader_reload.poll();
+        if changed.is_empty() {
+            return;
+        }
+        // Old pipelines may still be used by frames in flight
+        unsafe { instance.device.device_wait_idle().unwrap() };
+        self.gui_renderer.reload_shaders(instance, &changed);
+        self.post_processing.reload_shaders(instance, &changed);
+        self.multi_renderer.reload_shaders(instance, &changed);
     }
 
     fn calc_render_data(&mut self, frame_start: Instant) -> RenderData {
@@ -293,6 +557,15 @@ impl RawRuntimeRenderer {
         // self.gui_renderer.progress_event(event);
     }
 
+    /// Tonemapping, Exposure and the post effects can be changed at any time, They are read every Frame
+    pub fn post_processing_mut(&mut self) -> &mut PostProcessing {
+        &mut self.post_processing
+    }
+
+    pub fn toggle_debug_view(&mut self) {
+        self.multi_renderer.toggle_debug_view();
+    }
+
     pub fn resize(
         &mut self,
         instance: &mut VulkanInstance,
@@ -306,7 +579,9 @@ impl RawRuntimeRenderer {
 
     pub fn destroy(&mut self, instance: &VulkanInstance) {
         self.multi_renderer.destroy(instance);
+        self.post_processing.destroy(&instance.device);
         self.gui_renderer.destroy(&instance.device);
+        self.graph.destroy(&instance.device);
         // TODO Egui destroy
     }
 }
@@ -1,24 +1,45 @@
 use vent_math::scalar::{mat4::Mat4, quat::Quat};
+use vent_rendering::vertex::InstanceData;
 
+use super::model_renderer::ModelHandle;
+
+/// An Instance of a Model in the World, Many Entities can share the same Model
 pub struct Entity3D {
-    pub model: vent_assets::Model3D,
-    pub transformation_matrix: Mat4,
+    pub model: ModelHandle,
+    pub position: [f32; 3],
+    pub rotation: [f32; 4],
+    pub scale: [f32; 3],
 }
 
 impl Entity3D {
-    pub fn new(model: vent_assets::Model3D) -> Self {
+    /// Starts with the transform the Model was loaded with
+    pub fn new(model: ModelHandle, loaded: &vent_assets::Model3D) -> Self {
         Self {
-            transformation_matrix: Self::calc_trans_matrix(&model),
             model,
+            position: loaded.position,
+            rotation: loaded.rotation,
+            scale: loaded.scale,
         }
     }
 
-    pub fn calc_trans_matrix(model: &vent_assets::Model3D) -> Mat4 {
-        let rotation_quat = Quat::from_scaled_axis(Quat::from_array(model.rotation).xyz());
+    pub fn calc_trans_matrix(&self) -> Mat4 {
+        let rotation_quat = Quat::from_scaled_axis(Quat::from_array(self.rotation).xyz());
         Mat4::from_scale_rotation_translation(
-            model.scale.into(),
+            self.scale.into(),
             rotation_quat,
-            model.position.into(),
+            self.position.into(),
         )
     }
+
+    pub fn instance_data(&self) -> InstanceData {
+        let transform = self.calc_trans_matrix();
+        InstanceData {
+            transform: [
+                transform.x_axis.into(),
+                transform.y_axis.into(),
+                transform.z_axis.into(),
+                transform.w_axis.into(),
+            ],
+        }
+    }
 }
@@ -1,17 +1,71 @@
 use ash::vk::{self};
 use std::collections::HashMap;
+use vent_assets::{MaterialBinding, ModelMaterial};
 use vent_ecs::entity::Entity;
-use vent_rendering::instance::VulkanInstance;
+use vent_math::{scalar::mat4::Mat4, vec::vec4::Vec4};
+use vent_rendering::{
+    frame::UploadArena,
+    geometry::Bounds,
+    instance::VulkanInstance,
+    mesh::{bind_instances, InstancedMesh},
+    vertex::InstanceData,
+    RenderQueue,
+};
 
 use super::{camera::Camera3D, model::Entity3D};
 
+/// Index of a Model owned by the `ModelRenderer3D`
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ModelHandle(usize);
+
+/// A single Transparent Mesh of an Entity, Sorted before drawing
+struct TransparentDraw<'a> {
+    depth: f32,
+    model: &'a vent_assets::Model3D,
+    pipeline: vk::Pipeline,
+    material: &'a ModelMaterial,
+    instance: InstanceData,
+}
+
+/// View space Z of the bounds center, More negative is farther away
+pub fn view_depth(view: &Mat4, transform: &Mat4, bounds: &Bounds) -> f32 {
+    let [x, y, z] = bounds.center();
+    (*view * *transform).mul_vec4(Vec4::new(x, y, z, 1.0)).z
+}
+
+/// Owns the Models and the Entities using them.
+///
+/// Entities sharing a Model are drawn together with a single instanced draw per Mesh
 #[derive(Default)]
 pub struct ModelRenderer3D {
+    models: Vec<vent_assets::Model3D>,
     map: HashMap<Entity, Entity3D>,
 }
 
 #[allow(dead_code)]
 impl ModelRenderer3D {
+    pub fn add_model(&mut self, model: vent_assets::Model3D) -> ModelHandle {
+        self.models.push(model);
+        ModelHandle(self.models.len() - 1)
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn model(&self, handle: ModelHandle) -> &vent_assets::Model3D {
+        &self.models[handle.0]
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn model_mut(&mut self, handle: ModelHandle) -> &mut vent_assets::Model3D {
+        &mut self.models[handle.0]
+    }
+
+    #[inline]
+    pub fn models_mut(&mut self) -> std::slice::IterMut<'_, vent_assets::Model3D> {
+        self.models.iter_mut()
+    }
+
     #[inline]
     pub fn insert(&mut self, entity: Entity, mesh: Entity3D) {
         self.map.insert(entity, mesh);
@@ -46,32 +100,121 @@ impl ModelRenderer3D {
         self.map.iter_mut()
     }
 
+    /// Groups the Entities by their Model, Models without any Entity are left out
+    pub fn instance_batches(&self) -> Vec<InstancedMesh<&vent_assets::Model3D>> {
+        // One batch per Model, The transforms are per instance now
+        let mut batches: Vec<_> = self.models.iter().map(InstancedMesh::new).collect();
+        for entity in self.map.values() {
+            batches[entity.model.0]
+                .instances
+                .push(entity.instance_data());
+        }
+        batches.retain(|batch| !batch.instances.is_empty());
+        batches
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn record_buffer(
         &self,
         instance: &VulkanInstance,
         command_buffer: vk::CommandBuffer,
-        buffer_index: usize,
         pipeline_layout: vk::PipelineLayout,
+        arena: &UploadArena,
         camera: &mut Camera3D,
+        material_binding: MaterialBinding,
+        queues: &[RenderQueue],
     ) {
-        for model in self.map.values() {
-            camera.transformation = Entity3D::calc_trans_matrix(&model.model);
-            camera.calc_matrix();
-            camera.write(instance, pipeline_layout, command_buffer);
+        camera.transformation = Mat4::IDENTITY;
+        camera.calc_matrix();
+        camera.write(instance, pipeline_layout, command_buffer);
+
+        for batch in self.instance_batches() {
+            if !batch.bind_instances(&instance.device, command_buffer, arena) {
+                continue;
+            }
+            batch.mesh.draw_instanced(
+                &instance.device,
+                pipeline_layout,
+                command_buffer,
+                material_binding,
+                batch.instance_count(),
+                queues,
+            );
+        }
+    }
 
-            model.model.draw(
+    /// Draws the Transparent Meshes of all Entities back to front, One draw per Mesh and Entity.
+    /// They are sorted by the center of their bounds, So intersecting Meshes can still blend in the wrong order
+    pub fn record_transparent(
+        &self,
+        instance: &VulkanInstance,
+        command_buffer: vk::CommandBuffer,
+        pipeline_layout: vk::PipelineLayout,
+        arena: &UploadArena,
+        camera: &mut Camera3D,
+        material_binding: MaterialBinding,
+    ) {
+        camera.transformation = Mat4::IDENTITY;
+        camera.calc_matrix();
+        camera.write(instance, pipeline_layout, command_buffer);
+
+        let mut draws: Vec<TransparentDraw> = Vec::new();
+        for entity in self.map.values() {
+            let model = &self.models[entity.model.0];
+            let transform = entity.calc_trans_matrix();
+            let instance_data = entity.instance_data();
+            for pipeline in model
+                .pipelines
+                .iter()
+                .filter(|pipeline| pipeline.info.queue == RenderQueue::Transparent)
+            {
+                for material in pipeline.materials.iter() {
+                    draws.push(TransparentDraw {
+                        depth: view_depth(&camera.view, &transform, &material.bounds),
+                        model,
+                        pipeline: pipeline.pipeline,
+                        material,
+                        instance: instance_data,
+                    });
+                }
+            }
+        }
+        // Farthest first, The camera looks along -Z
+        draws.sort_by(|a, b| a.depth.total_cmp(&b.depth));
+
+        let instances: Vec<InstanceData> = draws.iter().map(|draw| draw.instance).collect();
+        if !bind_instances(&instance.device, command_buffer, arena, &instances) {
+            return;
+        }
+        let mut bound_pipeline = vk::Pipeline::null();
+        for (i, draw) in draws.iter().enumerate() {
+            if draw.pipeline != bound_pipeline {
+                unsafe {
+                    instance.device.cmd_bind_pipeline(
+                        command_buffer,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        draw.pipeline,
+                    )
+                };
+                bound_pipeline = draw.pipeline;
+            }
+            draw.model.bind_material(
                 &instance.device,
                 pipeline_layout,
                 command_buffer,
-                buffer_index,
-                true,
+                material_binding,
+                draw.material,
             );
+            let i = i as u32;
+            draw.material
+                .draw(&instance.device, command_buffer, i..i + 1);
         }
     }
 
     pub fn destroy_all(&mut self, device: &ash::Device) {
-        for model in self.map.values_mut() {
-            model.model.destroy(device)
+        self.map.clear();
+        for model in self.models.iter_mut() {
+            model.destroy(device)
         }
     }
 }
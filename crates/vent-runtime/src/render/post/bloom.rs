@@ -0,0 +1,284 @@
+use std::path::PathBuf;
+
+use ash::vk;
+use serde::{Deserialize, Serialize};
+use vent_rendering::{
+    any_as_u8_slice,
+    descriptor::DescriptorWriter,
+    instance::VulkanInstance,
+    pipeline::{RenderingFormats, VulkanPipeline, HDR_FORMAT},
+    render_graph::{
+        AttachmentLoad, ImageAccess, ImageDesc, ImageHandle, ImageSize, PassContext, PassHandle,
+        PassType, RenderGraph,
+    },
+};
+
+use super::draw_fullscreen;
+
+/// Number of downsampled levels, The smallest one has 1/64 of the screen size
+const BLOOM_LEVELS: usize = 6;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(default)]
+pub struct BloomSettings {
+    /// How much of the scene color gets replaced by the blurred one, So no energy is added
+    pub intensity: f32,
+    /// In texels of every level, Larger values give a wider and softer glow
+    pub filter_radius: f32,
+}
+
+impl Default for BloomSettings {
+    fn default() -> Self {
+        Self {
+            intensity: 0.04,
+            filter_radius: 1.0,
+        }
+    }
+}
+
+/// Push Constants of bloom_downsample.frag
+#[repr(C)]
+struct DownsampleData {
+    karis_average: u32,
+}
+
+/// Push Constants of bloom_upsample.frag
+#[repr(C)]
+struct UpsampleData {
+    filter_radius: f32,
+    base_weight: f32,
+    bloom_weight: f32,
+}
+
+struct UpsamplePass {
+    pass: PassHandle,
+    /// The next smaller level, Already upsampled
+    bloom: ImageHandle,
+    /// The level of the same size, Or the scene color for the last pass
+    base: ImageHandle,
+    /// The last pass blends into the scene color instead of adding
+    composite: bool,
+}
+
+/// Physically based Bloom, Like in Call of Duty: Advanced Warfare.
+///
+/// The scene color is downsampled into a chain of levels, Which are then blurred back up and blended into the scene color.
+/// There is no threshold, Everything blooms a little and bright things a lot
+pub struct Bloom {
+    downsample_pipeline: VulkanPipeline,
+    upsample_pipeline: VulkanPipeline,
+
+    /// The pass and the image it reads
+    downsample_passes: Vec<(PassHandle, ImageHandle)>,
+    upsample_passes: Vec<UpsamplePass>,
+}
+
+impl Bloom {
+    pub fn new(instance: &VulkanInstance) -> Self {
+        let vertex_shader = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/assets/shaders/app/post/fullscreen.vert.spv"
+        );
+        let downsample_shader = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/assets/shaders/app/post/bloom_downsample.frag.spv"
+        );
+        let upsample_shader = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/assets/shaders/app/post/bloom_upsample.frag.spv"
+        );
+        let create_pipeline = |fragment_shader: &str| {
+            VulkanPipeline::create_simple_pipeline(
+                instance,
+                vertex_shader.as_ref(),
+                fragment_shader.as_ref(),
+                &[],
+                &[],
+                instance.surface_resolution,
+                &RenderingFormats::hdr_color(),
+            )
+            .expect("Invalid Bloom Shaders")
+        };
+        Self {
+            downsample_pipeline: create_pipeline(downsample_shader),
+            upsample_pipeline: create_pipeline(upsample_shader),
+            downsample_passes: Vec::new(),
+            upsample_passes: Vec::new(),
+        }
+    }
+
+    /// Adds all passes blooming `scene_color`, Returns the bloomed copy of it
+    pub fn setup_passes(
+        &mut self,
+        graph: &mut RenderGraph,
+        scene_color: ImageHandle,
+    ) -> ImageHandle {
+        self.downsample_passes.clear();
+        self.upsample_passes.clear();
+
+        let level_desc = |level: usize| ImageDesc {
+            format: HDR_FORMAT,
+            size: ImageSize::Scaled(0.5f32.powi(level as i32 + 1)),
+            samples: vk::SampleCountFlags::TYPE_1,
+        };
+        let mut levels = Vec::with_capacity(BLOOM_LEVELS);
+        let mut source = scene_color;
+        for level in 0..BLOOM_LEVELS {
+            let target = graph.create_image(&format!("Bloom Down {level}"), level_desc(level));
+            let pass = graph
+                .add_pass(&format!("Bloom Downsample {level}"), PassType::Graphics)
+                .color_attachment(target, AttachmentLoad::DontCare)
+                .image(source, ImageAccess::Sampled)
+                .build();
+            self.downsample_passes.push((pass, source));
+            levels.push(target);
+            source = target;
+        }
+
+        // The smallest level has nothing to add, So it already is its own upsampled version
+        let mut bloom = levels[BLOOM_LEVELS - 1];
+        for level in (0..BLOOM_LEVELS - 1).rev() {
+            let target = graph.create_image(&format!("Bloom Up {level}"), level_desc(level));
+            let pass = graph
+                .add_pass(&format!("Bloom Upsample {level}"), PassType::Graphics)
+                .color_attachment(target, AttachmentLoad::DontCare)
+                .image(bloom, ImageAccess::Sampled)
+                .image(levels[level], ImageAccess::Sampled)
+                .build();
+            self.upsample_passes.push(UpsamplePass {
+                pass,
+                bloom,
+                base: levels[level],
+                composite: false,
+            });
+            bloom = target;
+        }
+
+        let bloomed = graph.create_image(
+            "Bloomed Scene Color",
+            ImageDesc {
+                format: HDR_FORMAT,
+                size: ImageSize::Swapchain,
+                samples: vk::SampleCountFlags::TYPE_1,
+            },
+        );
+        let pass = graph
+            .add_pass("Bloom Composite", PassType::Graphics)
+            .color_attachment(bloomed, AttachmentLoad::DontCare)
+            .image(bloom, ImageAccess::Sampled)
+            .image(scene_color, ImageAccess::Sampled)
+            .build();
+        self.upsample_passes.push(UpsamplePass {
+            pass,
+            bloom,
+            base: scene_color,
+            composite: true,
+        });
+        bloomed
+    }
+
+    /// Records the pass if it is one of ours, Returns false otherwise
+    pub fn execute_pass(
+        &self,
+        instance: &VulkanInstance,
+        pass: PassHandle,
+        context: &PassContext,
+        settings: &BloomSettings,
+        sampler: vk::Sampler,
+    ) -> bool {
+        let device = &instance.device;
+        let allocate = |pipeline: &VulkanPipeline| {
+            context
+                .frame()
+                .descriptor_allocator
+                .allocate(device, pipeline.descriptor_set_layout)
+                .expect("Failed to allocate Descriptor Set")
+        };
+
+        if let Some(level) = self.downsample_passes.iter().position(|(p, _)| *p == pass) {
+            let pipeline = &self.downsample_pipeline;
+            let set = allocate(pipeline);
+            DescriptorWriter::new()
+                .image(
+                    0,
+                    context.image_view(self.downsample_passes[level].1),
+                    sampler,
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                )
+                .update(device, set);
+            let downsample = DownsampleData {
+                karis_average: (level == 0) as u32,
+            };
+            draw_fullscreen(
+                device,
+                context.command_buffer,
+                pipeline,
+                set,
+                any_as_u8_slice(&downsample),
+            );
+            return true;
+        }
+
+        let Some(upsample_pass) = self.upsample_passes.iter().find(|p| p.pass == pass) else {
+            return false;
+        };
+        let pipeline = &self.upsample_pipeline;
+        let set = allocate(pipeline);
+        DescriptorWriter::new()
+            .image(
+                0,
+                context.image_view(upsample_pass.bloom),
+                sampler,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            )
+            .image(
+                1,
+                context.image_view(upsample_pass.base),
+                sampler,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            )
+            .update(device, set);
+        // Every level adds its light on the way up, The average of them replaces a bit of the scene color
+        let (base_weight, bloom_weight) = if upsample_pass.composite {
+            let intensity = settings.intensity.clamp(0.0, 1.0);
+            (1.0 - intensity, intensity / BLOOM_LEVELS as f32)
+        } else {
+            (1.0, 1.0)
+        };
+        let upsample = UpsampleData {
+            filter_radius: settings.filter_radius,
+            base_weight,
+            bloom_weight,
+        };
+        draw_fullscreen(
+            device,
+            context.command_buffer,
+            pipeline,
+            set,
+            any_as_u8_slice(&upsample),
+        );
+        true
+    }
+
+    pub fn reload_shaders(&mut self, instance: &VulkanInstance, changed: &[PathBuf]) {
+        for (name, pipeline) in [
+            ("Bloom Downsample", &mut self.downsample_pipeline),
+            ("Bloom Upsample", &mut self.upsample_pipeline),
+        ] {
+            if !changed.iter().any(|file| pipeline.uses_shader(file)) {
+                continue;
+            }
+            match pipeline.reload(instance) {
+                Ok(()) => log::info!("Reloaded {name} Pipeline"),
+                Err(err) => {
+                    log::error!("Failed to reload {name} Pipeline, Keeping the old one: {err}")
+                }
+            }
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        self.downsample_pipeline.destroy(device);
+        self.upsample_pipeline.destroy(device);
+    }
+}
@@ -0,0 +1,397 @@
+use std::{fs, path::PathBuf};
+
+use ash::vk;
+use serde::{Deserialize, Serialize};
+use vent_rendering::{
+    any_as_u8_slice,
+    descriptor::DescriptorWriter,
+    image::VulkanImage,
+    instance::VulkanInstance,
+    pipeline::{RenderingFormats, VulkanPipeline},
+    render_graph::PassContext,
+};
+
+use super::{draw_fullscreen, PostEffect};
+
+/// Size of the LUT used when no file is set, The identity does not need more
+const IDENTITY_LUT_SIZE: u32 = 2;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(default)]
+pub struct FxaaSettings {
+    /// In pixels, How far along an edge gets blurred
+    pub span_max: f32,
+    /// Higher values blur less on low contrast edges
+    pub reduce_mul: f32,
+    pub reduce_min: f32,
+}
+
+impl Default for FxaaSettings {
+    fn default() -> Self {
+        Self {
+            span_max: 8.0,
+            reduce_mul: 1.0 / 8.0,
+            reduce_min: 1.0 / 128.0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ColorGradingSettings {
+    /// Path to a `.cube` 3D LUT, Without one the colors stay as they are
+    pub lut: Option<String>,
+    /// 0.0 keeps the original colors, 1.0 uses the graded ones
+    pub strength: f32,
+}
+
+impl Default for ColorGradingSettings {
+    fn default() -> Self {
+        Self {
+            lut: None,
+            strength: 1.0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(default)]
+pub struct VignetteSettings {
+    /// How dark the corners get, 1.0 is black
+    pub intensity: f32,
+    /// Distance from the center where the darkening starts, 1.0 are the corners
+    pub radius: f32,
+    /// Width of the transition
+    pub smoothness: f32,
+}
+
+impl Default for VignetteSettings {
+    fn default() -> Self {
+        Self {
+            intensity: 0.4,
+            radius: 0.5,
+            smoothness: 0.5,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(default)]
+pub struct FilmGrainSettings {
+    pub intensity: f32,
+    /// In pixels
+    pub grain_size: f32,
+}
+
+impl Default for FilmGrainSettings {
+    fn default() -> Self {
+        Self {
+            intensity: 0.05,
+            grain_size: 1.5,
+        }
+    }
+}
+
+/// Push Constants of fxaa.frag
+#[repr(C)]
+struct FxaaData {
+    span_max: f32,
+    reduce_mul: f32,
+    reduce_min: f32,
+    encode_srgb: u32,
+}
+
+/// Push Constants of color_grading.frag
+#[repr(C)]
+struct ColorGradingData {
+    strength: f32,
+    encode_srgb: u32,
+}
+
+/// Push Constants of vignette.frag
+#[repr(C)]
+struct VignetteData {
+    intensity: f32,
+    radius: f32,
+    smoothness: f32,
+    encode_srgb: u32,
+}
+
+/// Push Constants of film_grain.frag
+#[repr(C)]
+struct FilmGrainData {
+    intensity: f32,
+    grain_size: f32,
+    time: f32,
+    encode_srgb: u32,
+}
+
+/// Reads a `.cube` file, Returns its size and the colors with red changing fastest
+fn load_cube(path: &str) -> Result<(u32, Vec<[f32; 3]>), String> {
+    let source = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    let mut size = None;
+    let mut colors = Vec::new();
+    for line in source.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(value) = line.strip_prefix("LUT_3D_SIZE") {
+            size = Some(value.trim().parse::<u32>().map_err(|err| err.to_string())?);
+            continue;
+        }
+        // TITLE, DOMAIN_MIN, DOMAIN_MAX and the like, We only support the default domain of 0..1
+        if line.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            continue;
+        }
+        let values: Vec<f32> = line
+            .split_whitespace()
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .map_err(|err| format!("Invalid line `{line}`, {err}"))?;
+        let [r, g, b] = values[..] else {
+            return Err(format!("Expected 3 values in line `{line}`"));
+        };
+        colors.push([r, g, b]);
+    }
+    let size = size.ok_or("Missing LUT_3D_SIZE, Only 3D LUTs are supported")?;
+    if colors.len() != (size * size * size) as usize {
+        return Err(format!(
+            "Expected {} colors for a size of {size}, Got {}",
+            size * size * size,
+            colors.len()
+        ));
+    }
+    Ok((size, colors))
+}
+
+fn identity_lut() -> (u32, Vec<[f32; 3]>) {
+    let max = (IDENTITY_LUT_SIZE - 1) as f32;
+    let mut colors = Vec::new();
+    for b in 0..IDENTITY_LUT_SIZE {
+        for g in 0..IDENTITY_LUT_SIZE {
+            for r in 0..IDENTITY_LUT_SIZE {
+                colors.push([r as f32 / max, g as f32 / max, b as f32 / max]);
+            }
+        }
+    }
+    (IDENTITY_LUT_SIZE, colors)
+}
+
+/// 10 bits per channel, Always supported for sampling with linear filtering
+fn create_lut(instance: &VulkanInstance, size: u32, colors: &[[f32; 3]]) -> VulkanImage {
+    let packed: Vec<u8> = colors
+        .iter()
+        .flat_map(|color| {
+            let [r, g, b] = color.map(|c| (c.clamp(0.0, 1.0) * 1023.0).round() as u32);
+            (r | g << 10 | b << 20 | 3 << 30).to_ne_bytes()
+        })
+        .collect();
+    let sampler_info = vk::SamplerCreateInfo::default()
+        .mag_filter(vk::Filter::LINEAR)
+        .min_filter(vk::Filter::LINEAR)
+        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE);
+    VulkanImage::new_3d(
+        instance,
+        &packed,
+        vk::Extent3D {
+            width: size,
+            height: size,
+            depth: size,
+        },
+        vk::Format::A2B10G10R10_UNORM_PACK32,
+        Some(sampler_info),
+        Some("Color Grading LUT"),
+    )
+}
+
+/// The effects running after tonemapping, Each one is a single fullscreen pass from one LDR image into the next
+pub struct LdrEffects {
+    fxaa_pipeline: VulkanPipeline,
+    color_grading_pipeline: VulkanPipeline,
+    vignette_pipeline: VulkanPipeline,
+    film_grain_pipeline: VulkanPipeline,
+
+    lut: VulkanImage,
+    /// The file `lut` was loaded from, None for the identity
+    lut_path: Option<String>,
+}
+
+impl LdrEffects {
+    pub fn new(instance: &VulkanInstance) -> Self {
+        let vertex_shader = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/assets/shaders/app/post/fullscreen.vert.spv"
+        );
+        let create_pipeline = |fragment_shader: &str| {
+            VulkanPipeline::create_simple_pipeline(
+                instance,
+                vertex_shader.as_ref(),
+                fragment_shader.as_ref(),
+                &[],
+                &[],
+                instance.surface_resolution,
+                &RenderingFormats::swapchain_color(instance),
+            )
+            .expect("Invalid Post Effect Shaders")
+        };
+        let (size, colors) = identity_lut();
+
+        Self {
+            fxaa_pipeline: create_pipeline(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/assets/shaders/app/post/fxaa.frag.spv"
+            )),
+            color_grading_pipeline: create_pipeline(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/assets/shaders/app/post/color_grading.frag.spv"
+            )),
+            vignette_pipeline: create_pipeline(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/assets/shaders/app/post/vignette.frag.spv"
+            )),
+            film_grain_pipeline: create_pipeline(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/assets/shaders/app/post/film_grain.frag.spv"
+            )),
+            lut: create_lut(instance, size, &colors),
+            lut_path: None,
+        }
+    }
+
+    /// Loads the LUT when `path` changed, Invalid files fall back to the identity
+    pub fn update_lut(&mut self, instance: &VulkanInstance, path: Option<&str>) {
+        if self.lut_path.as_deref() == path {
+            return;
+        }
+        let (size, colors) = match path.map(load_cube) {
+            Some(Ok(lut)) => {
+                log::info!("Loaded color grading LUT {}", path.unwrap());
+                lut
+            }
+            Some(Err(err)) => {
+                log::error!("Failed to load LUT {}, {err}", path.unwrap());
+                identity_lut()
+            }
+            None => identity_lut(),
+        };
+        // The old LUT may still be used by frames in flight
+        unsafe { instance.device.device_wait_idle().unwrap() };
+        self.lut.destroy(&instance.device);
+        self.lut = create_lut(instance, size, &colors);
+        self.lut_path = path.map(str::to_owned);
+    }
+
+    /// Records `effect` reading from `source`, `time` is in seconds
+    #[allow(clippy::too_many_arguments)]
+    pub fn record(
+        &self,
+        instance: &VulkanInstance,
+        context: &PassContext,
+        effect: &PostEffect,
+        source: vk::ImageView,
+        sampler: vk::Sampler,
+        encode_srgb: bool,
+        time: f32,
+    ) {
+        let device = &instance.device;
+        let encode_srgb = encode_srgb as u32;
+        let mut writer = DescriptorWriter::new().image(
+            0,
+            source,
+            sampler,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        );
+        let (pipeline, push_constants) = match effect {
+            PostEffect::Fxaa(settings) => (
+                &self.fxaa_pipeline,
+                any_as_u8_slice(&FxaaData {
+                    span_max: settings.span_max,
+                    reduce_mul: settings.reduce_mul,
+                    reduce_min: settings.reduce_min,
+                    encode_srgb,
+                })
+                .to_vec(),
+            ),
+            PostEffect::ColorGrading(settings) => {
+                writer = writer.image(
+                    1,
+                    self.lut.image_view,
+                    self.lut.sampler,
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                );
+                (
+                    &self.color_grading_pipeline,
+                    any_as_u8_slice(&ColorGradingData {
+                        strength: settings.strength,
+                        encode_srgb,
+                    })
+                    .to_vec(),
+                )
+            }
+            PostEffect::Vignette(settings) => (
+                &self.vignette_pipeline,
+                any_as_u8_slice(&VignetteData {
+                    intensity: settings.intensity,
+                    radius: settings.radius,
+                    smoothness: settings.smoothness,
+                    encode_srgb,
+                })
+                .to_vec(),
+            ),
+            PostEffect::FilmGrain(settings) => (
+                &self.film_grain_pipeline,
+                any_as_u8_slice(&FilmGrainData {
+                    intensity: settings.intensity,
+                    grain_size: settings.grain_size,
+                    time,
+                    encode_srgb,
+                })
+                .to_vec(),
+            ),
+            PostEffect::Bloom(_) => unreachable!("Bloom runs before tonemapping"),
+        };
+
+        let set = context
+            .frame()
+            .descriptor_allocator
+            .allocate(device, pipeline.descriptor_set_layout)
+            .expect("Failed to allocate Descriptor Set");
+        writer.update(device, set);
+        draw_fullscreen(
+            device,
+            context.command_buffer,
+            pipeline,
+            set,
+            &push_constants,
+        );
+    }
+
+    pub fn reload_shaders(&mut self, instance: &VulkanInstance, changed: &[PathBuf]) {
+        for (name, pipeline) in [
+            ("FXAA", &mut self.fxaa_pipeline),
+            ("Color Grading", &mut self.color_grading_pipeline),
+            ("Vignette", &mut self.vignette_pipeline),
+            ("Film Grain", &mut self.film_grain_pipeline),
+        ] {
+            if !changed.iter().any(|file| pipeline.uses_shader(file)) {
+                continue;
+            }
+            match pipeline.reload(instance) {
+                Ok(()) => log::info!("Reloaded {name} Pipeline"),
+                Err(err) => {
+                    log::error!("Failed to reload {name} Pipeline, Keeping the old one: {err}")
+                }
+            }
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        self.fxaa_pipeline.destroy(device);
+        self.color_grading_pipeline.destroy(device);
+        self.vignette_pipeline.destroy(device);
+        self.film_grain_pipeline.destroy(device);
+        self.lut.destroy(device);
+    }
+}
@@ -0,0 +1,348 @@
+use std::{mem::size_of, path::PathBuf};
+
+use ash::vk;
+use vent_rendering::{
+    any_as_u8_slice,
+    buffer::VulkanBuffer,
+    descriptor::DescriptorWriter,
+    instance::VulkanInstance,
+    pipeline::ComputePipeline,
+    render_graph::{
+        BufferAccess, BufferHandle, ImageAccess, ImageHandle, PassContext, PassHandle, PassType,
+        RenderGraph,
+    },
+};
+
+use super::{ExposureMode, ExposureSettings};
+
+/// Must match exposure.glsl
+const HISTOGRAM_BINS: u32 = 256;
+/// Must match `local_size` in histogram.comp
+const HISTOGRAM_GROUP_SIZE: (u32, u32) = (16, 16);
+
+/// Must match `Exposure` in exposure.glsl (std430)
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct GpuExposure {
+    luminance: f32,
+    exposure: f32,
+}
+
+/// Push Constants of histogram.comp
+#[repr(C)]
+struct HistogramData {
+    min_log_luminance: f32,
+    inv_log_luminance_range: f32,
+}
+
+/// Push Constants of exposure.comp
+#[repr(C)]
+struct ExposureData {
+    min_log_luminance: f32,
+    log_luminance_range: f32,
+    pixel_count: f32,
+    delta_time: f32,
+    adaptation_speed: f32,
+    compensation: f32,
+}
+
+/// Automatic Exposure from a luminance histogram of the scene color.
+///
+/// Every Frame the histogram is built, Averaged and the Exposure slowly adapts towards it.
+/// The result stays in a buffer on the GPU, So there is no readback
+pub struct AutoExposure {
+    histogram_pipeline: ComputePipeline,
+    exposure_pipeline: ComputePipeline,
+    /// Lives across Frames, Imported into the Graph
+    exposure_buffer: VulkanBuffer,
+
+    scene_color: Option<ImageHandle>,
+    histogram: Option<BufferHandle>,
+    exposure: Option<BufferHandle>,
+    clear_pass: Option<PassHandle>,
+    histogram_pass: Option<PassHandle>,
+    exposure_pass: Option<PassHandle>,
+}
+
+impl AutoExposure {
+    pub fn new(instance: &VulkanInstance) -> Self {
+        let histogram_shader = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/assets/shaders/app/post/histogram.comp.spv"
+        );
+        let exposure_shader = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/assets/shaders/app/post/exposure.comp.spv"
+        );
+        let histogram_pipeline = ComputePipeline::new(instance, histogram_shader.as_ref())
+            .expect("Failed to create Histogram Pipeline");
+        let exposure_pipeline = ComputePipeline::new(instance, exposure_shader.as_ref())
+            .expect("Failed to create Exposure Pipeline");
+
+        // Starts at middle grey, So the first Frames are neither black nor white
+        let initial = GpuExposure {
+            luminance: 0.18,
+            exposure: 1.0,
+        };
+        let exposure_buffer = VulkanBuffer::new_init(
+            instance,
+            size_of::<GpuExposure>() as vk::DeviceSize,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            &[initial],
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            Some("Exposure"),
+        );
+
+        Self {
+            histogram_pipeline,
+            exposure_pipeline,
+            exposure_buffer,
+            scene_color: None,
+            histogram: None,
+            exposure: None,
+            clear_pass: None,
+            histogram_pass: None,
+            exposure_pass: None,
+        }
+    }
+
+    /// Adds the passes measuring `scene_color`, The returned buffer has to be read as `BufferAccess::StorageRead` by the tonemapping
+    pub fn setup_passes(
+        &mut self,
+        graph: &mut RenderGraph,
+        scene_color: ImageHandle,
+    ) -> BufferHandle {
+        let histogram = graph.create_buffer(
+            "Luminance Histogram",
+            (HISTOGRAM_BINS as usize * size_of::<u32>()) as vk::DeviceSize,
+        );
+        let exposure = graph.import_buffer("Exposure", *self.exposure_buffer);
+        self.clear_pass = Some(
+            graph
+                .add_pass("Clear Histogram", PassType::Transfer)
+                .buffer(histogram, BufferAccess::TransferDst)
+                .build(),
+        );
+        self.histogram_pass = Some(
+            graph
+                .add_pass("Luminance Histogram", PassType::Compute)
+                .image(scene_color, ImageAccess::Sampled)
+                .buffer(histogram, BufferAccess::StorageWrite)
+                .build(),
+        );
+        self.exposure_pass = Some(
+            graph
+                .add_pass("Exposure", PassType::Compute)
+                .buffer(histogram, BufferAccess::StorageRead)
+                .buffer(exposure, BufferAccess::StorageWrite)
+                .build(),
+        );
+        self.scene_color = Some(scene_color);
+        self.histogram = Some(histogram);
+        self.exposure = Some(exposure);
+        exposure
+    }
+
+    /// Records the pass if it is one of ours, Returns false otherwise.
+    /// With `ExposureMode::Manual` the passes record nothing
+    pub fn execute_pass(
+        &self,
+        instance: &VulkanInstance,
+        pass: PassHandle,
+        context: &PassContext,
+        settings: &ExposureSettings,
+        sampler: vk::Sampler,
+        delta_time: f32,
+    ) -> bool {
+        let ours = [self.clear_pass, self.histogram_pass, self.exposure_pass];
+        if !ours.contains(&Some(pass)) {
+            return false;
+        }
+        if settings.mode == ExposureMode::Manual {
+            return true;
+        }
+        let log_luminance_range = settings.max_log_luminance - settings.min_log_luminance;
+        if Some(pass) == self.clear_pass {
+            unsafe {
+                instance.device.cmd_fill_buffer(
+                    context.command_buffer,
+                    context.buffer(self.histogram.unwrap()),
+                    0,
+                    vk::WHOLE_SIZE,
+                    0,
+                )
+            };
+        } else if Some(pass) == self.histogram_pass {
+            let writer = DescriptorWriter::new().image(
+                0,
+                context.image_view(self.scene_color.unwrap()),
+                sampler,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            );
+            let histogram = HistogramData {
+                min_log_luminance: settings.min_log_luminance,
+                inv_log_luminance_range: 1.0 / log_luminance_range,
+            };
+            self.dispatch_histogram(instance, context, writer, any_as_u8_slice(&histogram));
+        } else {
+            let exposure = ExposureData {
+                min_log_luminance: settings.min_log_luminance,
+                log_luminance_range,
+                pixel_count: (context.extent.width * context.extent.height) as f32,
+                delta_time: delta_time / 1000.0,
+                adaptation_speed: settings.adaptation_speed,
+                compensation: settings.compensation,
+            };
+            self.dispatch_exposure(instance, context, any_as_u8_slice(&exposure));
+        }
+        true
+    }
+
+    fn dispatch_histogram(
+        &self,
+        instance: &VulkanInstance,
+        context: &PassContext,
+        writer: DescriptorWriter,
+        push_constants: &[u8],
+    ) {
+        let device = &instance.device;
+        let pipeline = &self.histogram_pipeline;
+        let set = context
+            .frame()
+            .descriptor_allocator
+            .allocate(device, pipeline.descriptor_set_layouts[0])
+            .expect("Failed to allocate Descriptor Set");
+        writer
+            .buffer(
+                1,
+                context.buffer(self.histogram.unwrap()),
+                0,
+                vk::WHOLE_SIZE,
+                vk::DescriptorType::STORAGE_BUFFER,
+            )
+            .update(device, set);
+        Self::bind(
+            device,
+            context.command_buffer,
+            pipeline,
+            set,
+            push_constants,
+        );
+        pipeline.dispatch_2d(
+            device,
+            context.command_buffer,
+            context.extent,
+            HISTOGRAM_GROUP_SIZE,
+        );
+    }
+
+    fn dispatch_exposure(
+        &self,
+        instance: &VulkanInstance,
+        context: &PassContext,
+        push_constants: &[u8],
+    ) {
+        let device = &instance.device;
+        let pipeline = &self.exposure_pipeline;
+        let set = context
+            .frame()
+            .descriptor_allocator
+            .allocate(device, pipeline.descriptor_set_layouts[0])
+            .expect("Failed to allocate Descriptor Set");
+        DescriptorWriter::new()
+            .buffer(
+                0,
+                context.buffer(self.histogram.unwrap()),
+                0,
+                vk::WHOLE_SIZE,
+                vk::DescriptorType::STORAGE_BUFFER,
+            )
+            .buffer(
+                1,
+                context.buffer(self.exposure.unwrap()),
+                0,
+                vk::WHOLE_SIZE,
+                vk::DescriptorType::STORAGE_BUFFER,
+            )
+            .update(device, set);
+        Self::bind(
+            device,
+            context.command_buffer,
+            pipeline,
+            set,
+            push_constants,
+        );
+        // A single group, One invocation per bin
+        pipeline.dispatch(
+            device,
+            context.command_buffer,
+            HISTOGRAM_BINS,
+            HISTOGRAM_BINS,
+        );
+    }
+
+    fn bind(
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        pipeline: &ComputePipeline,
+        set: vk::DescriptorSet,
+        push_constants: &[u8],
+    ) {
+        unsafe {
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                pipeline.pipeline_layout,
+                0,
+                &[set],
+                &[],
+            );
+            device.cmd_push_constants(
+                command_buffer,
+                pipeline.pipeline_layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                push_constants,
+            );
+        }
+    }
+
+    /// The Exposure measured by the last Frame, For the tonemapping
+    pub fn write_exposure(
+        &self,
+        writer: DescriptorWriter,
+        binding: u32,
+        context: &PassContext,
+    ) -> DescriptorWriter {
+        writer.buffer(
+            binding,
+            context.buffer(self.exposure.unwrap()),
+            0,
+            vk::WHOLE_SIZE,
+            vk::DescriptorType::STORAGE_BUFFER,
+        )
+    }
+
+    pub fn reload_shaders(&mut self, instance: &VulkanInstance, changed: &[PathBuf]) {
+        for (name, pipeline) in [
+            ("Histogram", &mut self.histogram_pipeline),
+            ("Exposure", &mut self.exposure_pipeline),
+        ] {
+            if !changed.iter().any(|file| pipeline.uses_shader(file)) {
+                continue;
+            }
+            match pipeline.reload(instance) {
+                Ok(()) => log::info!("Reloaded {name} Pipeline"),
+                Err(err) => {
+                    log::error!("Failed to reload {name} Pipeline, Keeping the old one: {err}")
+                }
+            }
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        self.histogram_pipeline.destroy(device);
+        self.exposure_pipeline.destroy(device);
+        self.exposure_buffer.destroy(device);
+    }
+}
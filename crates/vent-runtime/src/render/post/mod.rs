@@ -0,0 +1,500 @@
+use std::{
+    mem::{self, Discriminant},
+    path::PathBuf,
+};
+
+use ash::vk;
+use serde::{Deserialize, Serialize};
+use vent_rendering::{
+    any_as_u8_slice,
+    descriptor::DescriptorWriter,
+    instance::VulkanInstance,
+    pipeline::{RenderingFormats, VulkanPipeline},
+    render_graph::{
+        AttachmentLoad, BufferAccess, ImageAccess, ImageDesc, ImageHandle, ImageSize, PassContext,
+        PassHandle, PassType, RenderGraph,
+    },
+};
+
+use crate::project::RenderSettings;
+
+use self::{
+    bloom::{Bloom, BloomSettings},
+    effects::{
+        ColorGradingSettings, FilmGrainSettings, FxaaSettings, LdrEffects, VignetteSettings,
+    },
+    exposure::AutoExposure,
+};
+
+pub mod bloom;
+pub mod effects;
+mod exposure;
+
+/// The curve mapping the HDR scene color into the displayable range
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Tonemapper {
+    #[default]
+    Aces,
+    AgX,
+    Reinhard,
+}
+
+impl Tonemapper {
+    /// Must match the `TONEMAP_*` defines in tonemap.frag
+    const fn shader_id(self) -> u32 {
+        match self {
+            Tonemapper::Aces => 0,
+            Tonemapper::AgX => 1,
+            Tonemapper::Reinhard => 2,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ExposureMode {
+    /// Always `ExposureSettings::exposure`
+    Manual,
+    /// Measured from the luminance histogram of every Frame
+    #[default]
+    Automatic,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(default)]
+pub struct ExposureSettings {
+    pub mode: ExposureMode,
+    /// Multiplier of the scene color in `ExposureMode::Manual`
+    pub exposure: f32,
+    /// In stops, Applied on top in both modes
+    pub compensation: f32,
+    /// log2 luminance range of the histogram, Anything outside is clamped
+    pub min_log_luminance: f32,
+    pub max_log_luminance: f32,
+    /// How fast the automatic Exposure follows the scene, Higher is faster
+    pub adaptation_speed: f32,
+}
+
+impl Default for ExposureSettings {
+    fn default() -> Self {
+        Self {
+            mode: ExposureMode::default(),
+            exposure: 1.0,
+            compensation: 0.0,
+            min_log_luminance: -8.0,
+            max_log_luminance: 4.0,
+            adaptation_speed: 1.5,
+        }
+    }
+}
+
+/// A single effect of the post-processing stack
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum PostEffect {
+    /// Works on the HDR colors, So it always runs before tonemapping
+    Bloom(BloomSettings),
+    Fxaa(FxaaSettings),
+    ColorGrading(ColorGradingSettings),
+    Vignette(VignetteSettings),
+    FilmGrain(FilmGrainSettings),
+}
+
+impl PostEffect {
+    pub const fn name(&self) -> &'static str {
+        match self {
+            PostEffect::Bloom(_) => "Bloom",
+            PostEffect::Fxaa(_) => "FXAA",
+            PostEffect::ColorGrading(_) => "Color Grading",
+            PostEffect::Vignette(_) => "Vignette",
+            PostEffect::FilmGrain(_) => "Film Grain",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PostEffectSettings {
+    pub enabled: bool,
+    pub effect: PostEffect,
+}
+
+impl PostEffectSettings {
+    pub fn new(effect: PostEffect) -> Self {
+        Self {
+            enabled: true,
+            effect,
+        }
+    }
+
+    /// Every effect once with its default settings, Only Bloom and FXAA are enabled
+    pub fn default_stack() -> Vec<Self> {
+        let disabled = |effect| Self {
+            enabled: false,
+            effect,
+        };
+        vec![
+            Self::new(PostEffect::Bloom(BloomSettings::default())),
+            disabled(PostEffect::ColorGrading(ColorGradingSettings::default())),
+            Self::new(PostEffect::Fxaa(FxaaSettings::default())),
+            disabled(PostEffect::Vignette(VignetteSettings::default())),
+            disabled(PostEffect::FilmGrain(FilmGrainSettings::default())),
+        ]
+    }
+}
+
+/// Push Constants of tonemap.frag
+#[repr(C)]
+struct TonemapData {
+    tonemapper: u32,
+    automatic: u32,
+    exposure: f32,
+    encode_srgb: u32,
+}
+
+/// Where tonemap.frag expects the measured Exposure
+const EXPOSURE_BINDING: u32 = 1;
+
+/// Binds `pipeline` and draws a single triangle covering the target, For fullscreen.vert
+pub(super) fn draw_fullscreen(
+    device: &ash::Device,
+    command_buffer: vk::CommandBuffer,
+    pipeline: &VulkanPipeline,
+    set: vk::DescriptorSet,
+    push_constants: &[u8],
+) {
+    unsafe {
+        device.cmd_bind_pipeline(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            pipeline.pipeline,
+        );
+        device.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            pipeline.pipeline_layout,
+            0,
+            &[set],
+            &[],
+        );
+        device.cmd_push_constants(
+            command_buffer,
+            pipeline.pipeline_layout,
+            vk::ShaderStageFlags::FRAGMENT,
+            0,
+            push_constants,
+        );
+        device.cmd_draw(command_buffer, 3, 1, 0, 0);
+    }
+}
+
+struct LdrPass {
+    pass: PassHandle,
+    /// Index into `PostProcessing::effects`
+    effect: usize,
+    source: ImageHandle,
+}
+
+/// Everything between the HDR scene color and the Swapchain.
+///
+/// The Renderers draw into the scene color, Which gets bloomed, Exposed and tonemapped.
+/// The LDR effects then run in the order of `effects`, The last one writes into the backbuffer
+pub struct PostProcessing {
+    pub tonemapper: Tonemapper,
+    pub exposure: ExposureSettings,
+    /// Settings are read every Frame, Enabling, Disabling or reordering effects rebuilds the Graph before the next one
+    pub effects: Vec<PostEffectSettings>,
+
+    tonemap_pipeline: VulkanPipeline,
+    auto_exposure: AutoExposure,
+    bloom: Bloom,
+    ldr_effects: LdrEffects,
+    /// Linear and clamped, For reading the scene color
+    sampler: vk::Sampler,
+    /// The tonemapping outputs linear colors, Only sRGB Swapchains encode them for us
+    encode_srgb: bool,
+
+    /// The effects the Graph was built with, See `active_effects`
+    built_effects: Vec<(usize, Discriminant<PostEffect>)>,
+    /// The scene color, Or its bloomed copy
+    tonemap_input: Option<ImageHandle>,
+    tonemap_pass: Option<PassHandle>,
+    bloom_effect: Option<usize>,
+    ldr_passes: Vec<LdrPass>,
+
+    /// In milliseconds
+    delta_time: f32,
+    /// In seconds, Wraps around every hour
+    time: f32,
+}
+
+impl PostProcessing {
+    pub fn new(instance: &VulkanInstance, settings: &RenderSettings) -> Self {
+        let vertex_shader = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/assets/shaders/app/post/fullscreen.vert.spv"
+        );
+        let fragment_shader = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/assets/shaders/app/post/tonemap.frag.spv"
+        );
+        let tonemap_pipeline = VulkanPipeline::create_simple_pipeline(
+            instance,
+            vertex_shader.as_ref(),
+            fragment_shader.as_ref(),
+            &[],
+            &[],
+            instance.surface_resolution,
+            &RenderingFormats::swapchain_color(instance),
+        )
+        .expect("Invalid Tonemap Shaders");
+
+        let sampler_info = vk::SamplerCreateInfo::default()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE);
+        let sampler = unsafe { instance.device.create_sampler(&sampler_info, None) }.unwrap();
+
+        let encode_srgb = !matches!(
+            instance.surface_format.format,
+            vk::Format::R8G8B8A8_SRGB
+                | vk::Format::B8G8R8A8_SRGB
+                | vk::Format::A8B8G8R8_SRGB_PACK32
+        );
+
+        let mut ldr_effects = LdrEffects::new(instance);
+        ldr_effects.update_lut(instance, Self::lut_path(&settings.post_effects));
+
+        Self {
+            tonemapper: settings.tonemapper,
+            exposure: settings.exposure,
+            effects: settings.post_effects.clone(),
+            tonemap_pipeline,
+            auto_exposure: AutoExposure::new(instance),
+            bloom: Bloom::new(instance),
+            ldr_effects,
+            sampler,
+            encode_srgb,
+            built_effects: Vec::new(),
+            tonemap_input: None,
+            tonemap_pass: None,
+            bloom_effect: None,
+            ldr_passes: Vec::new(),
+            delta_time: 0.0,
+            time: 0.0,
+        }
+    }
+
+    /// The LUT of the first color grading effect
+    fn lut_path(effects: &[PostEffectSettings]) -> Option<&str> {
+        effects.iter().find_map(|entry| match &entry.effect {
+            PostEffect::ColorGrading(settings) => settings.lut.as_deref(),
+            _ => None,
+        })
+    }
+
+    /// The enabled effects with their index, Every kind of effect can only be used once
+    fn active_effects(&self) -> Vec<(usize, Discriminant<PostEffect>)> {
+        let mut active: Vec<(usize, Discriminant<PostEffect>)> = Vec::new();
+        for (index, entry) in self.effects.iter().enumerate() {
+            let kind = mem::discriminant(&entry.effect);
+            if entry.enabled && !active.iter().any(|(_, other)| *other == kind) {
+                active.push((index, kind));
+            }
+        }
+        active
+    }
+
+    /// Whether the effects changed since `setup_passes`, The Graph has to be built again then
+    pub fn needs_rebuild(&self) -> bool {
+        self.active_effects() != self.built_effects
+    }
+
+    /// Has to be called once before every Frame, `delta_time` is in milliseconds
+    pub fn update(&mut self, instance: &VulkanInstance, delta_time: f32) {
+        self.delta_time = delta_time;
+        self.time = (self.time + delta_time / 1000.0) % 3600.0;
+        self.ldr_effects
+            .update_lut(instance, Self::lut_path(&self.effects));
+    }
+
+    /// Adds all passes reading `scene_color` and writing into the `backbuffer`, Has to be called after the Renderer added its passes
+    pub fn setup_passes(
+        &mut self,
+        graph: &mut RenderGraph,
+        scene_color: ImageHandle,
+        backbuffer: ImageHandle,
+        ldr_format: vk::Format,
+    ) {
+        let active = self.active_effects();
+        if active.len() < self.effects.iter().filter(|entry| entry.enabled).count() {
+            log::warn!("Every post effect can only be used once, Skipping the duplicates");
+        }
+        let exposure = self.auto_exposure.setup_passes(graph, scene_color);
+
+        let mut tonemap_input = scene_color;
+        let mut ldr_effects = Vec::new();
+        self.bloom_effect = None;
+        for &(index, _) in &active {
+            match self.effects[index].effect {
+                PostEffect::Bloom(_) => {
+                    tonemap_input = self.bloom.setup_passes(graph, scene_color);
+                    self.bloom_effect = Some(index);
+                }
+                _ => ldr_effects.push(index),
+            }
+        }
+
+        // The LDR images have the format of the Swapchain, So all LDR Pipelines work with any of them
+        let ldr_desc = ImageDesc {
+            format: ldr_format,
+            size: ImageSize::Swapchain,
+            samples: vk::SampleCountFlags::TYPE_1,
+        };
+        let mut target = if ldr_effects.is_empty() {
+            backbuffer
+        } else {
+            graph.create_image("Tonemapped", ldr_desc)
+        };
+        self.tonemap_pass = Some(
+            graph
+                .add_pass("Tonemap", PassType::Graphics)
+                .color_attachment(target, AttachmentLoad::DontCare)
+                .image(tonemap_input, ImageAccess::Sampled)
+                .buffer(exposure, BufferAccess::StorageRead)
+                .build(),
+        );
+        self.tonemap_input = Some(tonemap_input);
+
+        self.ldr_passes.clear();
+        for (i, &index) in ldr_effects.iter().enumerate() {
+            let source = target;
+            let name = self.effects[index].effect.name();
+            target = if i + 1 == ldr_effects.len() {
+                backbuffer
+            } else {
+                graph.create_image(name, ldr_desc)
+            };
+            let pass = graph
+                .add_pass(name, PassType::Graphics)
+                .color_attachment(target, AttachmentLoad::DontCare)
+                .image(source, ImageAccess::Sampled)
+                .build();
+            self.ldr_passes.push(LdrPass {
+                pass,
+                effect: index,
+                source,
+            });
+        }
+        self.built_effects = active;
+    }
+
+    /// Records the pass if it is one of ours, Returns false otherwise
+    pub fn execute_pass(
+        &self,
+        instance: &VulkanInstance,
+        pass: PassHandle,
+        context: &PassContext,
+    ) -> bool {
+        if self.auto_exposure.execute_pass(
+            instance,
+            pass,
+            context,
+            &self.exposure,
+            self.sampler,
+            self.delta_time,
+        ) {
+            return true;
+        }
+        if let Some(index) = self.bloom_effect {
+            if let PostEffect::Bloom(settings) = &self.effects[index].effect {
+                if self
+                    .bloom
+                    .execute_pass(instance, pass, context, settings, self.sampler)
+                {
+                    return true;
+                }
+            }
+        }
+        if Some(pass) == self.tonemap_pass {
+            self.tonemap(instance, context);
+            return true;
+        }
+        let Some(ldr_pass) = self
+            .ldr_passes
+            .iter()
+            .find(|ldr_pass| ldr_pass.pass == pass)
+        else {
+            return false;
+        };
+        self.ldr_effects.record(
+            instance,
+            context,
+            &self.effects[ldr_pass.effect].effect,
+            context.image_view(ldr_pass.source),
+            self.sampler,
+            self.encode_srgb,
+            self.time,
+        );
+        true
+    }
+
+    fn tonemap(&self, instance: &VulkanInstance, context: &PassContext) {
+        let device = &instance.device;
+        let pipeline = &self.tonemap_pipeline;
+
+        let set = context
+            .frame()
+            .descriptor_allocator
+            .allocate(device, pipeline.descriptor_set_layout)
+            .expect("Failed to allocate Descriptor Set");
+        let writer = DescriptorWriter::new().image(
+            0,
+            context.image_view(self.tonemap_input.unwrap()),
+            self.sampler,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        );
+        self.auto_exposure
+            .write_exposure(writer, EXPOSURE_BINDING, context)
+            .update(device, set);
+
+        let tonemap = TonemapData {
+            tonemapper: self.tonemapper.shader_id(),
+            automatic: (self.exposure.mode == ExposureMode::Automatic) as u32,
+            exposure: self.exposure.exposure * self.exposure.compensation.exp2(),
+            encode_srgb: self.encode_srgb as u32,
+        };
+        draw_fullscreen(
+            device,
+            context.command_buffer,
+            pipeline,
+            set,
+            any_as_u8_slice(&tonemap),
+        );
+    }
+
+    pub fn reload_shaders(&mut self, instance: &VulkanInstance, changed: &[PathBuf]) {
+        self.auto_exposure.reload_shaders(instance, changed);
+        self.bloom.reload_shaders(instance, changed);
+        self.ldr_effects.reload_shaders(instance, changed);
+        if !changed
+            .iter()
+            .any(|file| self.tonemap_pipeline.uses_shader(file))
+        {
+            return;
+        }
+        match self.tonemap_pipeline.reload(instance) {
+            Ok(()) => log::info!("Reloaded Tonemap Pipeline"),
+            Err(err) => {
+                log::error!("Failed to reload Tonemap Pipeline, Keeping the old one: {err}")
+            }
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        self.tonemap_pipeline.destroy(device);
+        self.auto_exposure.destroy(device);
+        self.bloom.destroy(device);
+        self.ldr_effects.destroy(device);
+        unsafe { device.destroy_sampler(self.sampler, None) };
+    }
+}
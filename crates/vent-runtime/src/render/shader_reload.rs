@@ -0,0 +1,53 @@
+use std::path::{Path, PathBuf};
+
+use vent_shader::{watcher::ShaderWatcher, ShaderCompiler};
+
+/// Recompiles GLSL sources when they change, So Shaders can be changed without restarting the Runtime
+pub struct ShaderHotReload {
+    watcher: ShaderWatcher,
+    compiler: ShaderCompiler,
+}
+
+impl ShaderHotReload {
+    pub fn new(dirs: &[&Path]) -> Self {
+        log::debug!("Watching shaders in {:?}", dirs);
+        let mut compiler = ShaderCompiler::new();
+        for dir in dirs {
+            compiler.add_include_dir(dir);
+        }
+        Self {
+            watcher: ShaderWatcher::new(dirs),
+            compiler,
+        }
+    }
+
+    /// Compiles all changed sources and returns the SPIR-V files which got rebuilt.
+    ///
+    /// When a source fails to compile the error is logged and the old SPIR-V file stays untouched
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        let changed = self.watcher.changed_sources();
+
+        let mut spv_files = Vec::new();
+        for source in changed {
+            match self.compiler.compile_all_to_spv_files(&source) {
+                Ok(compiled) => {
+                    let mut includes = Vec::new();
+                    for (spv_file, shader) in compiled {
+                        for warning in shader.warnings {
+                            log::warn!("{warning}");
+                        }
+                        includes.extend(shader.includes);
+                        spv_files.push(spv_file);
+                    }
+                    // Permutations may include different files
+                    self.watcher.set_includes(&source, &includes);
+                    log::info!("Recompiled {}", source.display());
+                }
+                Err(err) => {
+                    log::error!("Failed to compile shader, Keeping the old one\n{err}");
+                }
+            }
+        }
+        spv_files
+    }
+}
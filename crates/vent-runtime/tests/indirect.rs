@@ -0,0 +1,75 @@
+use vent_rendering::instance::VulkanInstance;
+use vent_runtime::{
+    project::RenderSettings,
+    render::{
+        camera::{Camera, Camera3D},
+        post::{ExposureSettings, Tonemapper},
+        Dimension, Msaa, RawRuntimeRenderer, SkyboxSource, Transparency,
+    },
+};
+
+const SIZE: (u32, u32) = (128, 96);
+
+fn settings() -> RenderSettings {
+    // No time dependent effects, Both frames have to be rendered the same way
+    RenderSettings {
+        dimension: Dimension::D3,
+        vsync: false,
+        tonemapper: Tonemapper::default(),
+        exposure: ExposureSettings::default(),
+        post_effects: vec![],
+        msaa: Msaa::Off,
+        transparency: Transparency::default(),
+        skybox: SkyboxSource::default(),
+    }
+}
+
+/// Renders the first Frame of the Demo Scene, `None` when the Device can not draw GPU driven
+fn render_frame(gpu_driven: bool) -> Option<image::RgbaImage> {
+    let mut instance = VulkanInstance::new_headless(&"Indirect Test".to_owned(), 0, SIZE);
+    let capabilities = &mut instance.capabilities;
+    if !capabilities.descriptor_indexing || !capabilities.draw_indirect_count {
+        return None;
+    }
+    // The renderer picks its path from the capabilities
+    capabilities.draw_indirect_count = gpu_driven;
+
+    let mut camera = Camera3D::new(SIZE.0 as f32 / SIZE.1 as f32);
+    let mut renderer = RawRuntimeRenderer::new(&settings(), &mut instance, &mut camera);
+    instance.request_capture().unwrap();
+    renderer.render(&mut instance, &mut camera);
+    let capture = instance
+        .take_capture()
+        .expect("The rendered Frame was not captured")
+        .unwrap();
+
+    unsafe { instance.device.device_wait_idle().unwrap() };
+    renderer.destroy(&instance);
+    Some(capture)
+}
+
+#[test]
+fn gpu_culled_matches_direct_draws() {
+    if !VulkanInstance::headless_available() {
+        eprintln!("No Vulkan device, Skipping");
+        return;
+    }
+    let Some(indirect) = render_frame(true) else {
+        eprintln!("No GPU driven rendering support, Skipping");
+        return;
+    };
+    let direct = render_frame(false).unwrap();
+    assert_eq!(indirect.dimensions(), direct.dimensions());
+
+    // Draw order differs between the paths, So depth ties may resolve differently on a few pixels
+    let differing = indirect
+        .pixels()
+        .zip(direct.pixels())
+        .filter(|(a, b)| a.0.iter().zip(b.0).any(|(a, b)| a.abs_diff(b) > 2))
+        .count();
+    let total = (SIZE.0 * SIZE.1) as usize;
+    assert!(
+        differing * 100 <= total,
+        "{differing} of {total} pixels differ between the GPU culled and the direct path"
+    );
+}
@@ -0,0 +1,149 @@
+//! Helpers for the build scripts of crates which ship GLSL shaders
+
+use std::{
+    collections::BTreeSet,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{parse_permutations, permutation_spv_path, shader_kind, spv_path, ShaderCompiler};
+
+/// Returns true when `SKIP_SHADER_COMPILATION=true` is set, e.g. when no shaderc is available
+pub fn should_skip_shader_compilation() -> bool {
+    env::var("SKIP_SHADER_COMPILATION")
+        .map(|var| var.parse::<bool>().unwrap_or(false))
+        .unwrap_or(false)
+}
+
+/// Compiles every shader in `shader_dir` and all of its permutations, The directory is also used to search includes.
+///
+/// Cargo reruns the build script once a shader or one of the files it includes changes, Or a shader gets added.
+/// A shader which fails to compile fails the build, As the old SPIR-V would not match the source anymore.
+///
+/// SPIR-V is not tracked in git, When compilation is skipped every shader must have been compiled before
+pub fn compile_shaders(shader_dir: &Path) {
+    println!("cargo:rerun-if-env-changed=SKIP_SHADER_COMPILATION");
+    println!("cargo:rerun-if-changed={}", shader_dir.display());
+    if should_skip_shader_compilation() {
+        let mut missing = Vec::new();
+        missing_spv_files(shader_dir, &mut missing);
+        if !missing.is_empty() {
+            let missing: Vec<_> = missing
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect();
+            panic!(
+                "SKIP_SHADER_COMPILATION is set, But these shaders were never compiled:\n{}",
+                missing.join("\n")
+            );
+        }
+        return;
+    }
+    println!("Compiling shaders in {:?}", shader_dir.as_os_str());
+    let mut compiler = ShaderCompiler::new();
+    compiler.add_include_dir(shader_dir);
+
+    let mut dependencies = BTreeSet::new();
+    compile_dir(&compiler, shader_dir, &mut dependencies);
+    for dependency in dependencies {
+        println!("cargo:rerun-if-changed={}", dependency.display());
+    }
+}
+
+/// Lets Cargo rerun the build script when something in `assets_dir` changes, Except for the shaders in `shader_dir`.
+/// Those are tracked by `compile_shaders`
+pub fn rerun_if_assets_changed(assets_dir: &Path, shader_dir: &Path) {
+    let Ok(entries) = fs::read_dir(assets_dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path != shader_dir {
+            println!("cargo:rerun-if-changed={}", path.display());
+        }
+    }
+}
+
+fn compile_dir(compiler: &ShaderCompiler, dir: &Path, dependencies: &mut BTreeSet<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            compile_dir(compiler, &path, dependencies);
+            continue;
+        }
+        // Include files and already compiled SPIR-V
+        if shader_kind(&path).is_none() {
+            continue;
+        }
+
+        println!("Compiling {:?}", path.as_os_str());
+        match compiler.compile_all_to_spv_files(&path) {
+            Ok(compiled) => {
+                for (_, shader) in compiled {
+                    for warning in shader.warnings {
+                        println!("cargo:warning={warning}");
+                    }
+                    dependencies.extend(shader.includes);
+                }
+            }
+            Err(err) => panic!("Shader compilation failed.\n{err}"),
+        }
+        dependencies.insert(path);
+    }
+}
+
+/// SPIR-V files `compile_dir` would write which do not exist
+fn missing_spv_files(dir: &Path, missing: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            missing_spv_files(&path, missing);
+            continue;
+        }
+        if shader_kind(&path).is_none() {
+            continue;
+        }
+        let permutations = fs::read_to_string(&path)
+            .map(|source| parse_permutations(&source))
+            .unwrap_or_default();
+        let outputs = permutations
+            .iter()
+            .map(|permutation| permutation_spv_path(&path, &permutation.name));
+        missing.extend(
+            std::iter::once(spv_path(&path))
+                .chain(outputs)
+                .filter(|spv| !spv.is_file()),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_missing_spv_files() {
+        let root = std::env::temp_dir().join(format!("vent-shader-missing-{}", std::process::id()));
+        fs::create_dir_all(root.join("3D")).unwrap();
+        fs::write(
+            root.join("3D/shader.frag"),
+            "#version 450\n//! permutation masked ALPHA_MASK\nvoid main() {}\n",
+        )
+        .unwrap();
+        fs::write(root.join("3D/shader.frag.spv"), "").unwrap();
+        fs::write(root.join("shared.glsl"), "").unwrap();
+
+        let mut missing = Vec::new();
+        missing_spv_files(&root, &mut missing);
+        // Includes are never compiled on their own
+        assert_eq!(missing, vec![root.join("3D/shader.masked.frag.spv")]);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
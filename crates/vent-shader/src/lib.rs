@@ -0,0 +1,442 @@
+use std::{
+    cell::RefCell,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+pub mod build;
+pub mod watcher;
+
+/// Extension of files which are only meant to be `#include`d, They are not compiled on their own
+pub const INCLUDE_EXTENSION: &str = "glsl";
+
+#[derive(Debug)]
+pub enum ShaderError {
+    Io(PathBuf, io::Error),
+    /// The file extension does not tell us which stage the shader is for
+    UnknownStage(PathBuf),
+    Compile(Vec<Diagnostic>),
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShaderError::Io(path, err) => write!(f, "{}: {err}", path.display()),
+            ShaderError::UnknownStage(path) => {
+                write!(f, "{}: Unknown shader stage", path.display())
+            }
+            ShaderError::Compile(diagnostics) => {
+                for (i, diagnostic) in diagnostics.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "{diagnostic}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ShaderError {}
+
+/// A single error or warning, `file` is the file the problem is in which may be an included one
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub file: String,
+    pub line: Option<u32>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    /// Splits the messages of the compiler, They look like `path/shader.frag:12: error: ...`
+    fn parse_all(messages: &str) -> Vec<Self> {
+        messages
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            // The summary at the end, e.g. `2 errors generated.`
+            .filter(|line| !line.ends_with("generated."))
+            .map(Self::parse)
+            .collect()
+    }
+
+    fn parse(line: &str) -> Self {
+        // Search for `:<line>: ` so Paths containing `:` (Windows drives) do not break
+        let mut start = 0;
+        while let Some(offset) = line[start..].find(':') {
+            let colon = start + offset;
+            let rest = &line[colon + 1..];
+            let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+            if digits > 0 && rest[digits..].starts_with(':') {
+                return Self {
+                    file: line[..colon].to_owned(),
+                    line: rest[..digits].parse().ok(),
+                    message: rest[digits + 1..].trim().to_owned(),
+                };
+            }
+            start = colon + 1;
+        }
+        Self {
+            file: String::new(),
+            line: None,
+            message: line.trim().to_owned(),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.file.is_empty(), self.line) {
+            (true, _) => write!(f, "{}", self.message),
+            (false, Some(line)) => write!(f, "{}:{line}: {}", self.file, self.message),
+            (false, None) => write!(f, "{}: {}", self.file, self.message),
+        }
+    }
+}
+
+/// A Preprocessor define, `#define NAME VALUE`
+pub type Define<'a> = (&'a str, Option<&'a str>);
+
+pub struct CompiledShader {
+    pub code: Vec<u32>,
+    /// Every file pulled in by `#include`, Changes to them also require a recompile
+    pub includes: Vec<PathBuf>,
+    pub warnings: Vec<Diagnostic>,
+}
+
+/// A variant of a Shader declared in its source, See `ShaderCompiler::compile_all_to_spv_files`
+pub struct Permutation {
+    pub name: String,
+    pub defines: Vec<(String, Option<String>)>,
+}
+
+const PERMUTATION_PREFIX: &str = "//! permutation ";
+
+pub fn parse_permutations(source: &str) -> Vec<Permutation> {
+    source
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix(PERMUTATION_PREFIX))
+        .filter_map(|declaration| {
+            let mut parts = declaration.split_whitespace();
+            let name = parts.next()?.to_owned();
+            let defines = parts
+                .map(|define| match define.split_once('=') {
+                    Some((name, value)) => (name.to_owned(), Some(value.to_owned())),
+                    None => (define.to_owned(), None),
+                })
+                .collect();
+            Some(Permutation { name, defines })
+        })
+        .collect()
+}
+
+/// Returns the Shader Stage of a GLSL file by its extension, e.g. `shader.frag`
+pub fn shader_kind(path: &Path) -> Option<shaderc::ShaderKind> {
+    let kind = match path.extension()?.to_str()? {
+        "vert" => shaderc::ShaderKind::Vertex,
+        "frag" => shaderc::ShaderKind::Fragment,
+        "comp" => shaderc::ShaderKind::Compute,
+        "geom" => shaderc::ShaderKind::Geometry,
+        "tesc" => shaderc::ShaderKind::TessControl,
+        "tese" => shaderc::ShaderKind::TessEvaluation,
+        _ => return None,
+    };
+    Some(kind)
+}
+
+/// Returns true for files which can only be `#include`d, e.g. `common/lighting.glsl`
+pub fn is_include(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == INCLUDE_EXTENSION)
+}
+
+/// Returns where the compiled SPIR-V of a GLSL file goes, `shader.frag` -> `shader.frag.spv`
+pub fn spv_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".spv");
+    path.with_file_name(name)
+}
+
+/// Returns where the SPIR-V of an permutation goes, `shader.frag` + `alpha_mask` -> `shader.alpha_mask.frag.spv`
+pub fn permutation_spv_path(path: &Path, permutation: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path.extension().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{stem}.{permutation}.{extension}.spv"))
+}
+
+/// Compiles GLSL into SPIR-V in process, No external `glslangValidator` needed.
+///
+/// `#include "file"` is resolved relative to the including file, `#include <file>` is searched in the include directories
+pub struct ShaderCompiler {
+    compiler: shaderc::Compiler,
+    include_dirs: Vec<PathBuf>,
+    /// Added to every shader compiled by this compiler
+    defines: Vec<(String, Option<String>)>,
+}
+
+impl ShaderCompiler {
+    pub fn new() -> Self {
+        Self {
+            compiler: shaderc::Compiler::new().expect("Failed to create Shader Compiler"),
+            include_dirs: Vec::new(),
+            defines: Vec::new(),
+        }
+    }
+
+    pub fn add_include_dir(&mut self, dir: impl Into<PathBuf>) {
+        self.include_dirs.push(dir.into());
+    }
+
+    /// Defines a macro for all shaders, Use `compile_file` with defines for single permutations
+    pub fn define(&mut self, name: &str, value: Option<&str>) {
+        self.defines
+            .push((name.to_owned(), value.map(str::to_owned)));
+    }
+
+    pub fn compile_file(
+        &self,
+        path: &Path,
+        defines: &[Define],
+    ) -> Result<CompiledShader, ShaderError> {
+        let kind = shader_kind(path).ok_or_else(|| ShaderError::UnknownStage(path.to_owned()))?;
+        let source =
+            fs::read_to_string(path).map_err(|err| ShaderError::Io(path.to_owned(), err))?;
+
+        let includes = RefCell::new(Vec::new());
+        let mut options = shaderc::CompileOptions::new().expect("Failed to create Compile Options");
+        options.set_target_env(
+            shaderc::TargetEnv::Vulkan,
+            shaderc::EnvVersion::Vulkan1_3 as u32,
+        );
+        for (name, value) in self.defines.iter() {
+            options.add_macro_definition(name, value.as_deref());
+        }
+        for (name, value) in defines {
+            options.add_macro_definition(name, *value);
+        }
+        options.set_include_callback(|requested, include_type, requesting, _depth| {
+            let resolved = self.resolve_include(requested, include_type, Path::new(requesting))?;
+            let content = fs::read_to_string(&resolved)
+                .map_err(|err| format!("Failed to read {}: {err}", resolved.display()))?;
+            includes.borrow_mut().push(resolved.clone());
+            Ok(shaderc::ResolvedInclude {
+                // Used in error messages, So errors point to the included file
+                resolved_name: resolved.to_string_lossy().into_owned(),
+                content,
+            })
+        });
+
+        let artifact = self
+            .compiler
+            .compile_into_spirv(
+                &source,
+                kind,
+                &path.to_string_lossy(),
+                "main",
+                Some(&options),
+            )
+            .map_err(|err| match err {
+                shaderc::Error::CompilationError(_, messages) => {
+                    ShaderError::Compile(Diagnostic::parse_all(&messages))
+                }
+                err => ShaderError::Compile(vec![Diagnostic {
+                    file: path.to_string_lossy().into_owned(),
+                    line: None,
+                    message: err.to_string(),
+                }]),
+            })?;
+        let warnings = if artifact.get_num_warnings() > 0 {
+            Diagnostic::parse_all(&artifact.get_warning_messages())
+        } else {
+            Vec::new()
+        };
+        Ok(CompiledShader {
+            code: artifact.as_binary().to_vec(),
+            // The callback still borrows `includes`
+            includes: includes.take(),
+            warnings,
+        })
+    }
+
+    /// Compiles the GLSL file and writes the SPIR-V next to it
+    pub fn compile_to_spv_file(&self, path: &Path) -> Result<CompiledShader, ShaderError> {
+        self.compile_to(path, &spv_path(path), &[])
+    }
+
+    /// Compiles the GLSL file with extra defines, The SPIR-V is written to `permutation_spv_path`
+    pub fn compile_permutation(
+        &self,
+        path: &Path,
+        permutation: &str,
+        defines: &[Define],
+    ) -> Result<CompiledShader, ShaderError> {
+        self.compile_to(path, &permutation_spv_path(path, permutation), defines)
+    }
+
+    /// Compiles the GLSL file and every permutation it declares, Returns the written SPIR-V files.
+    ///
+    /// Permutations are declared in the source with `//! permutation <name> <DEFINE>[=<value>]...`,
+    /// e.g. `//! permutation bindless BINDLESS` writes `shader.bindless.frag.spv` compiled with `BINDLESS` defined
+    pub fn compile_all_to_spv_files(
+        &self,
+        path: &Path,
+    ) -> Result<Vec<(PathBuf, CompiledShader)>, ShaderError> {
+        let source =
+            fs::read_to_string(path).map_err(|err| ShaderError::Io(path.to_owned(), err))?;
+        let mut compiled = vec![(spv_path(path), self.compile_to_spv_file(path)?)];
+        for permutation in parse_permutations(&source) {
+            let defines: Vec<Define> = permutation
+                .defines
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_deref()))
+                .collect();
+            compiled.push((
+                permutation_spv_path(path, &permutation.name),
+                self.compile_permutation(path, &permutation.name, &defines)?,
+            ));
+        }
+        Ok(compiled)
+    }
+
+    fn compile_to(
+        &self,
+        path: &Path,
+        spv_path: &Path,
+        defines: &[Define],
+    ) -> Result<CompiledShader, ShaderError> {
+        let shader = self.compile_file(path, defines)?;
+        let bytes: Vec<u8> = shader
+            .code
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .collect();
+        // Unchanged SPIR-V keeps its modification time, So build scripts watching the directory settle
+        if fs::read(spv_path).ok().as_deref() != Some(bytes.as_slice()) {
+            fs::write(spv_path, bytes).map_err(|err| ShaderError::Io(spv_path.to_owned(), err))?;
+        }
+        Ok(shader)
+    }
+
+    fn resolve_include(
+        &self,
+        requested: &str,
+        include_type: shaderc::IncludeType,
+        requesting: &Path,
+    ) -> Result<PathBuf, String> {
+        if include_type == shaderc::IncludeType::Relative {
+            if let Some(parent) = requesting.parent() {
+                let path = parent.join(requested);
+                if path.is_file() {
+                    return Ok(path);
+                }
+            }
+        }
+        // `#include "file"` falls back to the include directories like a C compiler does
+        self.include_dirs
+            .iter()
+            .map(|dir| dir.join(requested))
+            .find(|path| path.is_file())
+            .ok_or_else(|| format!("Cannot find include file \"{requested}\""))
+    }
+}
+
+impl Default for ShaderCompiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_diagnostics() {
+        let diagnostic =
+            Diagnostic::parse("shaders/3D/shader.frag:12: error: 'foo' : undeclared identifier");
+        assert_eq!(diagnostic.file, "shaders/3D/shader.frag");
+        assert_eq!(diagnostic.line, Some(12));
+        assert_eq!(diagnostic.message, "error: 'foo' : undeclared identifier");
+
+        // The drive letter is not a line
+        let diagnostic = Diagnostic::parse(r"C:\shaders\shader.vert:3: warning: unused");
+        assert_eq!(diagnostic.file, r"C:\shaders\shader.vert");
+        assert_eq!(diagnostic.line, Some(3));
+        assert_eq!(diagnostic.message, "warning: unused");
+
+        let diagnostic = Diagnostic::parse("error: something went wrong ");
+        assert!(diagnostic.file.is_empty());
+        assert_eq!(diagnostic.line, None);
+        assert_eq!(diagnostic.message, "error: something went wrong");
+
+        let diagnostics = Diagnostic::parse_all(
+            "a.frag:1: error: first\n\nb.glsl:2: error: second\n2 errors generated.\n",
+        );
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[1].to_string(), "b.glsl:2: error: second");
+    }
+
+    #[test]
+    fn parses_permutations() {
+        let source = r#"#version 450
+//! permutation bindless BINDLESS
+    //! permutation masked ALPHA_MASK CUTOFF=0.5
+// permutation ignored IGNORED
+//! permutation
+void main() {}
+"#;
+        let permutations = parse_permutations(source);
+        assert_eq!(permutations.len(), 2);
+        assert_eq!(permutations[0].name, "bindless");
+        assert_eq!(permutations[0].defines, vec![("BINDLESS".to_owned(), None)]);
+        assert_eq!(permutations[1].name, "masked");
+        assert_eq!(
+            permutations[1].defines,
+            vec![
+                ("ALPHA_MASK".to_owned(), None),
+                ("CUTOFF".to_owned(), Some("0.5".to_owned()))
+            ]
+        );
+    }
+
+    #[test]
+    fn resolves_includes() {
+        let root =
+            std::env::temp_dir().join(format!("vent-shader-includes-{}", std::process::id()));
+        let shaders = root.join("shaders");
+        let common = root.join("common");
+        fs::create_dir_all(shaders.join("3D")).unwrap();
+        fs::create_dir_all(&common).unwrap();
+        fs::write(shaders.join("3D/local.glsl"), "").unwrap();
+        fs::write(shaders.join("shared.glsl"), "").unwrap();
+        fs::write(common.join("lighting.glsl"), "").unwrap();
+
+        let mut compiler = ShaderCompiler::new();
+        compiler.add_include_dir(&shaders);
+        compiler.add_include_dir(&common);
+        let requesting = shaders.join("3D/shader.frag");
+
+        // Relative to the including file
+        assert_eq!(
+            compiler.resolve_include("local.glsl", shaderc::IncludeType::Relative, &requesting),
+            Ok(shaders.join("3D/local.glsl"))
+        );
+        // Falls back to the include directories
+        assert_eq!(
+            compiler.resolve_include("lighting.glsl", shaderc::IncludeType::Relative, &requesting),
+            Ok(common.join("lighting.glsl"))
+        );
+        assert_eq!(
+            compiler.resolve_include("shared.glsl", shaderc::IncludeType::Standard, &requesting),
+            Ok(shaders.join("shared.glsl"))
+        );
+        // `<file>` never looks next to the including file
+        assert!(compiler
+            .resolve_include("local.glsl", shaderc::IncludeType::Standard, &requesting)
+            .is_err());
+        assert!(compiler
+            .resolve_include("missing.glsl", shaderc::IncludeType::Relative, &requesting)
+            .is_err());
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,137 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{is_include, shader_kind};
+
+/// How often we look at the files, Stat'ing every frame would be wasteful
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Watches directories for changed GLSL sources and include files.
+///
+/// This just compares modification times, It is polled from the render loop so changes are only
+/// picked up between frames. A changed include file reports the sources which include it, See `set_includes`
+pub struct ShaderWatcher {
+    dirs: Vec<PathBuf>,
+    modified: HashMap<PathBuf, SystemTime>,
+    last_poll: Instant,
+    /// Include file -> Sources which include it
+    dependents: HashMap<PathBuf, HashSet<PathBuf>>,
+    /// Sources whose includes are known
+    tracked: HashSet<PathBuf>,
+}
+
+impl ShaderWatcher {
+    pub fn new(dirs: &[&Path]) -> Self {
+        let mut watcher = Self {
+            dirs: dirs.iter().map(|dir| dir.to_path_buf()).collect(),
+            modified: HashMap::new(),
+            last_poll: Instant::now(),
+            dependents: HashMap::new(),
+            tracked: HashSet::new(),
+        };
+        // Everything which is already there is not a change
+        let mut files = Vec::new();
+        for dir in watcher.dirs.iter() {
+            collect_sources(dir, &mut files);
+        }
+        watcher.modified = files.into_iter().collect();
+        watcher
+    }
+
+    /// Returns all shader stages which are watched, Without include files
+    pub fn sources(&self) -> impl Iterator<Item = &Path> {
+        self.modified
+            .keys()
+            .map(PathBuf::as_path)
+            .filter(|path| shader_kind(path).is_some())
+    }
+
+    /// Remembers which files a source includes, Usually `CompiledShader::includes` after compiling it
+    pub fn set_includes(&mut self, source: &Path, includes: &[PathBuf]) {
+        let source = canonical(source);
+        for dependents in self.dependents.values_mut() {
+            dependents.remove(&source);
+        }
+        for include in includes {
+            self.dependents
+                .entry(canonical(include))
+                .or_default()
+                .insert(source.clone());
+        }
+        self.tracked.insert(source);
+    }
+
+    /// Returns all sources which have been created or modified since the last call,
+    /// Including the ones which include a modified file
+    pub fn changed_sources(&mut self) -> Vec<PathBuf> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return Vec::new();
+        }
+        self.last_poll = Instant::now();
+
+        let mut files = Vec::new();
+        for dir in self.dirs.iter() {
+            collect_sources(dir, &mut files);
+        }
+        let mut changed = Vec::new();
+        for (path, modified) in files {
+            if self.modified.get(&path) != Some(&modified) {
+                self.modified.insert(path.clone(), modified);
+                changed.push(path);
+            }
+        }
+        self.affected_sources(changed)
+    }
+
+    /// Replaces changed include files with the sources using them
+    fn affected_sources(&self, changed: Vec<PathBuf>) -> Vec<PathBuf> {
+        let mut sources = Vec::new();
+        let mut push = |path: &Path| {
+            if !sources.iter().any(|source: &PathBuf| source == path) {
+                sources.push(path.to_path_buf());
+            }
+        };
+        for path in changed {
+            if !is_include(&path) {
+                push(&path);
+                continue;
+            }
+            let dependents = self.dependents.get(&canonical(&path));
+            for source in self.sources() {
+                let source_key = canonical(source);
+                // Until a source was compiled once we do not know what it includes
+                if !self.tracked.contains(&source_key)
+                    || dependents.is_some_and(|dependents| dependents.contains(&source_key))
+                {
+                    push(source);
+                }
+            }
+        }
+        sources
+    }
+}
+
+/// Includes may be reached through different paths, e.g. `shaders/3D/../common.glsl`
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn collect_sources(dir: &Path, files: &mut Vec<(PathBuf, SystemTime)>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_sources(&path, files);
+        } else if shader_kind(&path).is_some() || is_include(&path) {
+            if let Ok(modified) = entry.metadata().and_then(|m| m.modified()) {
+                files.push((path, modified));
+            }
+        }
+    }
+}
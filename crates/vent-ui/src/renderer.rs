@@ -1,18 +1,24 @@
-use std::mem::size_of;
+use std::path::PathBuf;
 
 use ash::vk::{self};
 use vent_math::vec::vec2::Vec2;
 use vent_rendering::{
-    any_as_u8_slice, instance::VulkanInstance, pipeline::VulkanPipeline, vertex::Vertex2D,
+    any_as_u8_slice,
+    frame::FrameContext,
+    instance::VulkanInstance,
+    pipeline::{RenderingFormats, VulkanPipeline},
+    vertex::Vertex2D,
 };
 
 use crate::font::{ab_glyph::AbGlyphLoader, Font};
 
 use super::GUI;
 
+/// Where the GLSL sources of the GUI Shaders are, Used to watch them for changes
+pub const SHADER_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/assets/shaders");
+
 #[allow(dead_code)]
 pub struct GuiRenderer {
-    descriptor_pool: vk::DescriptorPool,
     pipeline: VulkanPipeline,
 
     // Font
@@ -41,14 +47,7 @@ impl GuiRenderer {
             translate: Vec2::ZERO,
         };
 
-        let descriptor_pool = Self::create_descriptor_pool(
-            1, // 1 Font
-            instance.swapchain_images.len() as u32,
-            &instance.device,
-        );
-
         let mut renderer = Self {
-            descriptor_pool,
             pipeline,
             push_constant,
             font: None,
@@ -64,21 +63,6 @@ impl GuiRenderer {
         let vertex_shader = concat!(env!("CARGO_MANIFEST_DIR"), "/assets/shaders/gui.vert.spv");
         let fragment_shader = concat!(env!("CARGO_MANIFEST_DIR"), "/assets/shaders/gui.frag.spv");
 
-        let desc_layout_bindings = [
-            // Fragment
-            vk::DescriptorSetLayoutBinding {
-                binding: 0,
-                descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-                descriptor_count: 1,
-                stage_flags: vk::ShaderStageFlags::FRAGMENT,
-                ..Default::default()
-            },
-        ];
-
-        let push_constant_range = vk::PushConstantRange::default()
-            .size(size_of::<PushConstant>() as u32)
-            .stage_flags(vk::ShaderStageFlags::VERTEX);
-
         VulkanPipeline::create_simple_pipeline(
             instance,
             vertex_shader.as_ref(),
@@ -86,26 +70,9 @@ impl GuiRenderer {
             &[Vertex2D::binding_description()],
             &Vertex2D::input_descriptions(),
             instance.surface_resolution,
-            &[push_constant_range],
-            &desc_layout_bindings,
+            &RenderingFormats::swapchain_color(instance),
         )
-    }
-
-    pub fn create_descriptor_pool(
-        material_count: u32,
-        swapchain_count: u32,
-        device: &ash::Device,
-    ) -> vk::DescriptorPool {
-        let pool_sizes = [vk::DescriptorPoolSize {
-            ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-            descriptor_count: material_count * swapchain_count,
-        }];
-
-        let create_info = vk::DescriptorPoolCreateInfo::default()
-            .pool_sizes(&pool_sizes)
-            .max_sets(material_count * swapchain_count);
-
-        unsafe { device.create_descriptor_pool(&create_info, None) }.unwrap()
+        .expect("Invalid GUI Shaders")
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -113,7 +80,7 @@ impl GuiRenderer {
         &mut self,
         instance: &VulkanInstance,
         command_buffer: vk::CommandBuffer,
-        buffer_index: usize,
+        frame: &FrameContext,
         text: String,
         x: f32,
         y: f32,
@@ -158,7 +125,7 @@ impl GuiRenderer {
                 instance,
                 command_buffer,
                 self.pipeline.pipeline_layout,
-                buffer_index,
+                frame,
                 text,
                 x,
                 y,
@@ -172,7 +139,6 @@ impl GuiRenderer {
         self.font = Some(AbGlyphLoader::load(
             path,
             self.pipeline.descriptor_set_layout,
-            self.descriptor_pool,
             instance,
         ));
     }
@@ -192,14 +158,22 @@ impl GuiRenderer {
     #[allow(dead_code)]
     pub fn register_texture(&mut self) {}
 
-    pub fn destroy(&mut self, device: &ash::Device) {
-        unsafe {
-            if let Some(font) = &mut self.font {
-                font.destroy(device);
-                self.font = None
+    /// Rebuilds the Pipeline when one of the changed SPIR-V files belongs to it, The GPU has to be idle
+    pub fn reload_shaders(&mut self, instance: &VulkanInstance, changed: &[PathBuf]) {
+        if !changed.iter().any(|file| self.pipeline.uses_shader(file)) {
+            return;
+        }
+        match self.pipeline.reload(instance) {
+            Ok(()) => log::info!(target: "ui", "Reloaded GUI Pipeline"),
+            Err(err) => {
+                log::error!(target: "ui", "Failed to reload GUI Pipeline, Keeping the old one: {err}")
             }
-            self.pipeline.destroy(device);
-            device.destroy_descriptor_pool(self.descriptor_pool, None);
         }
     }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        // The Font only owns a descriptor set, It belongs to the descriptor allocator of the Instance
+        self.font = None;
+        self.pipeline.destroy(device);
+    }
 }
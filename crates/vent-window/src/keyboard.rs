@@ -33,6 +33,9 @@ pub enum Key {
     Uparrow,
     Rightarrow,
     Downarrow,
+    F8,
+    F9,
+    F12,
     Unknown,
 }
 
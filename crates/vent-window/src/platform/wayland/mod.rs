@@ -778,6 +778,9 @@ fn convert_key(raw_key: xkeysym::RawKeysym) -> Key {
         xkeysym::key::uparrow => Key::Uparrow,
         xkeysym::key::rightarrow => Key::Rightarrow,
         xkeysym::key::downarrow => Key::Downarrow,
+        xkeysym::key::F8 => Key::F8,
+        xkeysym::key::F9 => Key::F9,
+        xkeysym::key::F12 => Key::F12,
 
         _ => {
             log::warn!("Unknown key {}", raw_key);
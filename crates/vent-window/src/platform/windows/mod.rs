@@ -363,6 +363,9 @@ fn convert_key(key: VIRTUAL_KEY) -> Key {
         VK_RIGHT => Key::Rightarrow,
         VK_UP => Key::Uparrow,
         VK_DOWN => Key::Downarrow,
+        VK_F8 => Key::F8,
+        VK_F9 => Key::F9,
+        VK_F12 => Key::F12,
         _ => Key::Unknown,
     }
 }